@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// duplicates which are intentional; every line of the file is either a glob or two tab separated paths
+pub struct AllowedDupes {
+    globs: Box<[Glob]>,
+    pairs: HashSet<(PathBuf, PathBuf)>,
+}
+
+impl AllowedDupes {
+    /// lines starting with '#' are ignored
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let mut globs = Vec::new();
+        let mut pairs = HashSet::new();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((a, b)) = line.split_once('\t') {
+                // the files of the sets are canonical
+                let canonical = |path: &str| {
+                    Path::new(path)
+                        .canonicalize()
+                        .unwrap_or_else(|_| PathBuf::from(path))
+                };
+                let (a, b) = (canonical(a), canonical(b));
+                pairs.insert((b.clone(), a.clone()));
+                pairs.insert((a, b));
+            } else {
+                globs.push(Glob::new(line));
+            }
+        }
+        Ok(Self {
+            globs: globs.into_boxed_slice(),
+            pairs,
+        })
+    }
+
+    /// whether the files are known to be duplicates of each other
+    pub fn is_allowed(&self, a: &Path, b: &Path) -> bool {
+        self.pairs.contains(&(a.to_path_buf(), b.to_path_buf()))
+            || self
+                .globs
+                .iter()
+                .any(|glob| glob.matches(a) && glob.matches(b))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum GlobToken {
+    Literal(char),
+    /// '?', any character but a separator
+    AnyChar,
+    /// '*', any number of characters but no separator
+    Star,
+    /// '**', any number of characters
+    DoubleStar,
+}
+
+/// a pattern without a separator is matched against the file name, otherwise against the whole path
+struct Glob {
+    tokens: Box<[GlobToken]>,
+    file_name_only: bool,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    GlobToken::DoubleStar
+                }
+                '*' => GlobToken::Star,
+                '?' => GlobToken::AnyChar,
+                c => GlobToken::Literal(c),
+            });
+        }
+        Self {
+            tokens: tokens.into_boxed_slice(),
+            file_name_only: !pattern.chars().any(std::path::is_separator),
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let text = if self.file_name_only {
+            let Some(file_name) = path.file_name() else { return false };
+            file_name.to_string_lossy()
+        } else {
+            path.to_string_lossy()
+        };
+        let text = text.chars().collect::<Vec<_>>();
+        // matched[i] is whether the tokens so far match the first i characters
+        let mut matched = vec![false; text.len() + 1];
+        matched[0] = true;
+        for token in &*self.tokens {
+            let mut next = vec![false; text.len() + 1];
+            for i in 0..=text.len() {
+                next[i] = match token {
+                    GlobToken::Literal(c) => i > 0 && matched[i - 1] && text[i - 1] == *c,
+                    GlobToken::AnyChar => {
+                        i > 0 && matched[i - 1] && !std::path::is_separator(text[i - 1])
+                    }
+                    GlobToken::Star => {
+                        matched[i]
+                            || (i > 0 && next[i - 1] && !std::path::is_separator(text[i - 1]))
+                    }
+                    GlobToken::DoubleStar => matched[i] || (i > 0 && next[i - 1]),
+                };
+            }
+            matched = next;
+        }
+        matched[text.len()]
+    }
+}
+
+#[test]
+fn test_glob() {
+    let matches = |pattern: &str, path: &str| Glob::new(pattern).matches(Path::new(path));
+    assert!(matches("LICENSE", "/a/b/LICENSE"));
+    assert!(!matches("LICENSE", "/a/LICENSE/b"));
+    assert!(matches("*.txt", "/a/b.txt"));
+    assert!(!matches("*.txt", "/a/b.txt.bak"));
+    assert!(matches("LICENSE-??", "/LICENSE-MI"));
+    assert!(matches("/a/*/c", "/a/b/c"));
+    assert!(!matches("/a/*/c", "/a/b/b/c"));
+    assert!(matches("/a/**/c", "/a/b/b/c"));
+    assert!(matches("/a/**", "/a/b/c"));
+    assert!(!matches("/a/**", "/b/a/c"));
+}
@@ -0,0 +1,63 @@
+use crate::plan::plan_can_represent;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// the answers given in interactive mode, each line is 'y' or 'n', a tab and the file the answer was about;
+/// a recorded answer is replayed when its file is asked about again, new answers are appended
+pub struct Answers {
+    /// the last answer recorded for each file
+    recorded: HashMap<PathBuf, bool>,
+    append: std::fs::File,
+}
+
+impl Answers {
+    /// read the recorded answers, the file is created if it does not exist yet; the answers about files which
+    /// no longer exist are left out
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut recorded = HashMap::new();
+        for (number, line) in std::io::BufReader::new(&file).lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let answer = match line.split_once('\t') {
+                Some(("y", path)) => (true, PathBuf::from(path)),
+                Some(("n", path)) => (false, PathBuf::from(path)),
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("line {} is not an answer", number + 1),
+                    ))
+                }
+            };
+            recorded.insert(answer.1, answer.0);
+        }
+        recorded.retain(|path, _| path.symlink_metadata().is_ok());
+        Ok(Self {
+            recorded,
+            append: file,
+        })
+    }
+
+    /// the recorded answer about the file, None if the user has to be asked
+    pub fn replay(&mut self, path: &Path) -> Option<bool> {
+        self.recorded.remove(path)
+    }
+
+    /// append the answer given by the user, returns false if the path of the file cannot be written
+    pub fn record(&mut self, path: &Path, answer: bool) -> std::io::Result<bool> {
+        let Some(path) = path.to_str().filter(|path| plan_can_represent(path)) else {
+            return Ok(false);
+        };
+        let answer = if answer { 'y' } else { 'n' };
+        self.append
+            .write_all(format!("{answer}\t{path}\n").as_bytes())
+            .map(|()| true)
+    }
+}
@@ -1,23 +1,38 @@
+use crate::allow_dupes::AllowedDupes;
+use crate::answers::Answers;
+use crate::error_handling::AlreadyReportedError;
 use crate::file_action::{FileConsumeAction, FileConsumeResult};
 use crate::file_filters::{
     ExtensionFilter, FileFilter, FileMetadataFilter, FileNameFilter, MaxSizeFileFilter,
-    MinSizeFileFilter, PathFilter,
+    MinSizeFileFilter, ModifiedTimeFileFilter, PathFilter,
 };
-use crate::input_source::{ChannelInputSink, DiscoveringInputSource, InputSource};
+use crate::file_set_refiner::{
+    CheckEqualsErrorOn, FileContentEquals, FileEqualsChecker, FileSetRefiners, FileWorkload,
+    RefinerLogic,
+};
+use crate::input_source::{
+    BudgetedInputSink, ChannelInputSink, DiscoveringInputSource, InputSink, InputSource,
+    StdInSource, SymlinkTreatment,
+};
+use crate::manifest::{diff_manifests, write_manifest_header, write_manifest_line};
+use crate::seen_db::SeenDb;
 use crate::set_consumer::{
-    FileSetConsumer, InteractiveEachChoice, MachineReadableEach, MachineReadableSet,
+    DryRun, FileSetConsumer, FilterAllowedDupes, InteractiveEachChoice, MachineReadableEach,
+    MachineReadableSet, RmBatch, SetSummary, SuppressSeen, SyncWriterSink, TreeReport,
     UnconditionalAction,
 };
 use crate::set_order::{
-    CreateTimeSetOrder, ModTimeSetOrder, NameAlphabeticSetOrder, NoopSetOrder, SetOrder,
+    CleanNameSetOrder, CreateTimeSetOrder, ModTimeSetOrder, NameAlphabeticSetOrder, NoopSetOrder,
+    SetOrder, SizeSetOrder,
 };
-use crate::util::LinkedPath;
-use crate::HashedFile;
+use crate::util::{LinkedPath, MemoryBudget, SizeFormat, StopSignal};
+use crate::{DuplicateSet, HashedFile, SetProvenance};
 use std::borrow::Cow;
 use std::collections::HashSet;
 use std::ffi::OsString;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -47,11 +62,38 @@ fn gather_hashed_files(files: &[&(std::fs::File, LinkedPath)]) -> Vec<HashedFile
         .into_iter()
         .map(|(file, path)| HashedFile {
             file_version_timestamp: file.metadata().unwrap().modified().ok(),
+            content_hash: crate::hash_content(&path.to_push_buf(), crate::HashAlgorithm::Xxh3)
+                .unwrap_or_default(),
             file_path: (*path).clone(),
+            identity: crate::os::link_identity(&path.to_push_buf()),
+            size: Some(file.metadata().unwrap().len()),
         })
         .collect()
 }
 
+/// a set of the files as hashing their content would find it
+fn duplicate_set(id: u128, files: Vec<HashedFile>) -> DuplicateSet {
+    DuplicateSet::new(id, files, SetProvenance::Content)
+}
+
+/// hash the entire files without a budget, manifest or progress
+fn worker_shared(stop: &StopSignal) -> crate::WorkerShared<'_> {
+    static NO_ERRORS: crate::error_handling::ErrorSummary =
+        crate::error_handling::ErrorSummary::new();
+    crate::WorkerShared {
+        budget: None,
+        stop,
+        errors: &NO_ERRORS,
+        hash_scope: crate::HashScope::Full,
+        hash_algorithm: crate::HashAlgorithm::Xxh3,
+        file_timeout: None,
+        cache: None,
+        manifest: None,
+        progress: None,
+        group_hard_links: false,
+    }
+}
+
 fn permute<T: Clone>(source: &[T], indices: &[usize]) -> Vec<T> {
     indices.iter().copied().map(|i| source[i].clone()).collect()
 }
@@ -67,11 +109,11 @@ impl FileConsumeAction for UnreachableFileConsumer {
         true
     }
 
-    fn short_name(&self) -> Cow<str> {
+    fn short_name(&self) -> Cow<'_, str> {
         Cow::Borrowed("fail")
     }
 
-    fn short_opposite(&self) -> Cow<str> {
+    fn short_opposite(&self) -> Cow<'_, str> {
         Cow::Borrowed("don't fail")
     }
 }
@@ -91,11 +133,11 @@ impl FileConsumeAction for ExpectingConsumeAction {
         false
     }
 
-    fn short_name(&self) -> Cow<str> {
+    fn short_name(&self) -> Cow<'_, str> {
         Cow::Borrowed("check for")
     }
 
-    fn short_opposite(&self) -> Cow<str> {
+    fn short_opposite(&self) -> Cow<'_, str> {
         Cow::Borrowed("ignore")
     }
 }
@@ -184,6 +226,8 @@ fn test_ordering() {
         &[2, 0, 3, 1],
         CreateTimeSetOrder::new(true),
     );
+    test_ordering(&files, &[0, 3, 2, 1], SizeSetOrder::new(false));
+    test_ordering(&files, &[1, 2, 3, 0], SizeSetOrder::new(true));
     test_ordering(&files, &[1, 3, 0, 2], NameAlphabeticSetOrder::new(false));
     test_ordering(&files, &[2, 0, 3, 1], NameAlphabeticSetOrder::new(true));
     test_ordering(&files, &[0, 1, 2, 3], NoopSetOrder::new());
@@ -193,6 +237,173 @@ fn test_ordering() {
     })
 }
 
+#[test]
+fn test_staleness_ordering() {
+    use crate::set_order::{StalenessSetOrder, StalenessWeights};
+    use std::fs::FileTimes;
+    use std::time::SystemTime;
+
+    let mut prefix = CommonPrefix::new("staleness_ordering_");
+    let files = [
+        prefix.create_file_auto(&[]),
+        prefix.create_file_auto(&[]),
+        prefix.create_file_auto(&[]),
+    ];
+    let hashed = gather_hashed_files(&files.iter().collect::<Vec<_>>());
+    // set after hashing, which may update the access times
+    let at = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000 + secs);
+    for ((file, _), (modified, accessed)) in
+        files.iter().zip([(1000, 5000), (3000, 1000), (2000, 2500)])
+    {
+        let times = FileTimes::new()
+            .set_modified(at(modified))
+            .set_accessed(at(accessed));
+        file.set_times(times).unwrap();
+    }
+    let ordered = |reverse: bool, modified: u32, accessed: u32| {
+        let weights = StalenessWeights { modified, accessed };
+        let mut ordered = hashed.clone();
+        StalenessSetOrder::new(reverse, weights)
+            .order(&mut ordered)
+            .unwrap();
+        ordered
+    };
+    assert_eq!(ordered(false, 1, 1), permute(&hashed, &[0, 2, 1]));
+    assert_eq!(ordered(true, 1, 1), permute(&hashed, &[1, 2, 0]));
+    assert_eq!(ordered(false, 1, 0), permute(&hashed, &[1, 2, 0]));
+    assert_eq!(ordered(false, 0, 1), permute(&hashed, &[0, 2, 1]));
+}
+
+#[test]
+fn test_stable_ordering() {
+    use crate::set_order::StableSetOrder;
+
+    let mut prefix = CommonPrefix::new("stable_ordering_");
+    let files = [
+        prefix.create_file_auto(b"abc"),
+        prefix.create_file_auto(b"abc"),
+        prefix.create_file_auto(b"abc"),
+    ];
+    let hashed = gather_hashed_files(&files.iter().collect::<Vec<_>>());
+    // the same files are ordered alike, no matter the order they were found in
+    let ordered = [[0, 1, 2], [2, 0, 1], [1, 2, 0]].map(|found| {
+        let mut ordered = permute(&hashed, &found);
+        StableSetOrder.order(&mut ordered).unwrap();
+        ordered
+    });
+    assert_eq!(ordered[0], ordered[1]);
+    assert_eq!(ordered[0], ordered[2]);
+    // a file without an identity is ordered by its path
+    let mut unidentified = hashed.clone();
+    for file in &mut unidentified {
+        file.identity = None;
+    }
+    let mut ordered = unidentified.clone();
+    ordered.reverse();
+    StableSetOrder.order(&mut ordered).unwrap();
+    assert_eq!(ordered, unidentified);
+}
+
+#[test]
+fn test_path_ordering() {
+    use crate::set_order::{PathDepthSetOrder, PathLengthSetOrder};
+
+    let root = LinkedPath::root("paths");
+    let path = |segments: &[&str]| {
+        let parent = segments[..segments.len() - 1]
+            .iter()
+            .fold(root.clone(), |parent, segment| {
+                Arc::new(LinkedPath::new_child(&parent, OsString::from(segment)))
+            });
+        LinkedPath::new_child(&parent, OsString::from(segments[segments.len() - 1]))
+    };
+    let files = [
+        path(&["a", "b", "c"]),
+        path(&["long_name"]),
+        path(&["d", "e"]),
+    ]
+    .map(|file_path| HashedFile {
+        file_version_timestamp: None,
+        content_hash: 0,
+        file_path,
+        identity: None,
+        size: None,
+    })
+    .to_vec();
+    let ordered = |mut orderer: Box<dyn SetOrder>| {
+        let mut ordered = files.clone();
+        orderer.order(&mut ordered).unwrap();
+        ordered
+    };
+    assert_eq!(
+        ordered(Box::new(PathDepthSetOrder::new(false))),
+        permute(&files, &[1, 2, 0])
+    );
+    assert_eq!(
+        ordered(Box::new(PathDepthSetOrder::new(true))),
+        permute(&files, &[0, 2, 1])
+    );
+    assert_eq!(
+        ordered(Box::new(PathLengthSetOrder::new(false))),
+        permute(&files, &[2, 0, 1])
+    );
+    assert_eq!(
+        ordered(Box::new(PathLengthSetOrder::new(true))),
+        permute(&files, &[1, 0, 2])
+    );
+}
+
+#[test]
+fn test_path_prefix_ordering() {
+    use crate::set_order::PathPrefixSetOrder;
+
+    let mut prefix = CommonPrefix::new("path_prefix_ordering/");
+    let file1 = prefix.create_file("a/x", b"ab");
+    let file2 = prefix.create_file("b/x", b"ab");
+    let files = gather_hashed_files(&[&file1, &file2]);
+    let ordered = |prefix: PathBuf| {
+        let mut ordered = files.clone();
+        PathPrefixSetOrder::new(prefix).order(&mut ordered).unwrap();
+        ordered
+    };
+    let dir = PathBuf::from("test_files/path_prefix_ordering/b");
+    assert_eq!(ordered(dir.clone()), permute(&files, &[1, 0]));
+    // the relative paths of the files match the canonical prefix
+    assert_eq!(
+        ordered(dir.canonicalize().unwrap()),
+        permute(&files, &[1, 0])
+    );
+    assert_eq!(ordered(PathBuf::from("no/such/dir")), files);
+}
+
+#[test]
+fn test_clean_name_ordering() {
+    let root = LinkedPath::root("clean");
+    let names = [
+        "a (1).txt",
+        "a.txt",
+        "a - Copy.txt",
+        "a_copy.txt",
+        "a.txt~",
+        "Copy of a.txt",
+        "a copy 2.txt",
+        "a2.txt",
+        "(1).txt",
+    ];
+    let files = names
+        .map(|name| HashedFile {
+            file_version_timestamp: None,
+            content_hash: 0,
+            file_path: LinkedPath::new_child(&root, OsString::from(name)),
+            identity: None,
+            size: None,
+        })
+        .to_vec();
+    let mut ordered = files.clone();
+    CleanNameSetOrder::new().order(&mut ordered).unwrap();
+    assert_eq!(ordered, permute(&files, &[1, 7, 8, 0, 2, 3, 4, 5, 6]));
+}
+
 #[test]
 fn test_file_filter() {
     let mut prefix = CommonPrefix::new("file_filter_");
@@ -201,7 +412,13 @@ fn test_file_filter() {
     let file2 = prefix.create_file_auto(b"bb");
     let file3 = prefix.create_file_auto(b"bbb");
     let file4 = prefix.create_file_auto(b"bbbb");
-    let files = gather_hashed_files(&[&file0, &file1, &file2, &file3, &file4])
+    let all = [&file0, &file1, &file2, &file3, &file4];
+    // the file i was last modified on day i + 1
+    let day = |i: u64| std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(i * 24 * 60 * 60);
+    for (i, file) in all.into_iter().enumerate() {
+        file.0.set_modified(day(i as u64 + 1)).unwrap();
+    }
+    let files = gather_hashed_files(&all)
         .into_iter()
         .map(|HashedFile { file_path, .. }| (file_path.to_push_buf(), file_path))
         .map(|(path, lpath)| (lpath, path.metadata().unwrap(), path))
@@ -231,6 +448,12 @@ fn test_file_filter() {
     test_filter(&files, &[0, 1, 2], MaxSizeFileFilter::new(3));
     test_filter(&files, &[0], MaxSizeFileFilter::new(1));
     test_filter(&files, &[], MaxSizeFileFilter::new(0));
+    let modified = |start: Option<u64>, end: Option<u64>| {
+        ModifiedTimeFileFilter::new(start.map(day), end.map(day))
+    };
+    test_filter(&files, &[1, 2], modified(Some(2), Some(4)));
+    test_filter(&files, &[0], modified(None, Some(2)));
+    test_filter(&files, &[3, 4], modified(Some(4), None));
 
     files
         .into_iter()
@@ -269,6 +492,20 @@ fn test_filter_extension() {
     let filterer =
         ExtensionFilter::new(HashSet::from(["ea", "ec"].map(OsString::from)), true, true);
     test_named_filter(&files, &[0, 2, 3], filterer);
+
+    let file4 = prefix.create_file("ext.EA", &[]);
+    let file5 = prefix.create_file("ext.Ec", &[]);
+    let files = [&file0, &file1, &file4, &file5]
+        .into_iter()
+        .map(|f| (f.1.clone(), f.1.to_push_buf()))
+        .collect::<Vec<_>>();
+    let filterer = ExtensionFilter::new(HashSet::from([OsString::from("eb")]), false, true)
+        .with_any_case(vec![OsString::from("ea"), OsString::from("ec")]);
+    test_named_filter(&files, &[0, 1, 2, 3], filterer);
+
+    let filterer = ExtensionFilter::new(HashSet::from([OsString::from("EB")]), false, true)
+        .with_any_case(vec![OsString::from("ea")]);
+    test_named_filter(&files, &[0, 2], filterer);
 }
 
 #[test]
@@ -345,9 +582,9 @@ fn test_deleted_original(prefix: &mut CommonPrefix, mut consumer: impl FileSetCo
     let path_1 = file1.1.to_push_buf();
     std::fs::remove_file(&path_1).unwrap();
     let files = gather_hashed_files(&[&file1, &file2]);
-    consumer.consume_set(files).unwrap();
+    consumer.consume_set(duplicate_set(0, files)).unwrap();
     let files = gather_hashed_files(&[&file2, &file1]);
-    consumer.consume_set(files).unwrap();
+    consumer.consume_set(duplicate_set(0, files)).unwrap();
 }
 
 #[test]
@@ -381,7 +618,7 @@ fn test_machine_readable_each() {
 
     let files = gather_hashed_files(&[&file1, &filec, &file2, &file3]);
 
-    mreadable.consume_set(files).unwrap();
+    mreadable.consume_set(duplicate_set(0, files)).unwrap();
 
     let result = String::from_utf8(target.clone()).unwrap();
     let expected = format!(
@@ -397,7 +634,10 @@ fn test_machine_readable_each() {
     let mut mreadable = MachineReadableEach::new(&mut target);
 
     mreadable
-        .consume_set(gather_hashed_files(&[&filec, &file1, &file2, &file3]))
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&filec, &file1, &file2, &file3]),
+        ))
         .unwrap();
 
     let result = String::from_utf8(target).unwrap();
@@ -407,7 +647,7 @@ fn test_machine_readable_each() {
     let mut mreadable = MachineReadableEach::new(empty_buf.as_mut_slice());
 
     mreadable
-        .consume_set(gather_hashed_files(&[&file1, &file2]))
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
         .unwrap_err();
 }
 
@@ -432,7 +672,7 @@ fn test_machine_readable_set() {
 
     let files = gather_hashed_files(&[&file1, &filec, &file2, &file3]);
 
-    mreadable.consume_set(files).unwrap();
+    mreadable.consume_set(duplicate_set(0, files)).unwrap();
 
     let result = String::from_utf8(target.clone()).unwrap();
     let expected = format!(
@@ -447,17 +687,40 @@ fn test_machine_readable_set() {
     let mut mreadable = MachineReadableSet::new(&mut target);
 
     mreadable
-        .consume_set(gather_hashed_files(&[&filec, &file1, &file2, &file3]))
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&filec, &file1, &file2, &file3]),
+        ))
         .unwrap();
 
     let result = String::from_utf8(target).unwrap();
 
     assert_eq!(result, expected);
+    let mut target = Vec::new();
+    let mut mreadable = MachineReadableSet::new(&mut target).with_set_ids();
+    mreadable
+        .consume_set(duplicate_set(0xab, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    mreadable
+        .consume_set(duplicate_set(0xcd, gather_hashed_files(&[&file2, &file3])))
+        .unwrap();
+    let result = String::from_utf8(target).unwrap();
+    let expected = format!(
+        "{:032x},{},{}\n{:032x},{},{}",
+        0xab,
+        file1p.display(),
+        file2p.display(),
+        0xcd,
+        file2p.display(),
+        file3p.display()
+    );
+    assert_eq!(result, expected);
+
     let mut empty_buf: [u8; 0] = [];
     let mut mreadable = MachineReadableSet::new(empty_buf.as_mut_slice());
 
     mreadable
-        .consume_set(gather_hashed_files(&[&file1, &file2]))
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
         .unwrap_err();
 }
 
@@ -492,7 +755,7 @@ fn test_interactive_set_action() {
     let read_source = b"y\nn".as_ref();
 
     let mut writer = InteractiveEachChoice::new(read_source, &mut write_sink, Box::new(expected()));
-    writer.consume_set(files).unwrap();
+    writer.consume_set(duplicate_set(0, files)).unwrap();
 
     let files = gather_hashed_files(&[&file1, &file3, &file2]);
 
@@ -500,7 +763,329 @@ fn test_interactive_set_action() {
     let read_source = b"no\nyes".as_slice();
 
     let mut writer = InteractiveEachChoice::new(read_source, &mut write_sink, Box::new(expected()));
-    writer.consume_set(files).unwrap();
+    writer.consume_set(duplicate_set(0, files)).unwrap();
+}
+
+#[test]
+fn test_interactive_set_choice() {
+    use crate::set_consumer::InteractiveSetChoice;
+
+    let mut prefix = CommonPrefix::new("interactive_set_choice");
+    let file1 = prefix.make_file_auto();
+    let file2 = prefix.make_file_auto();
+    let file3 = prefix.make_file_auto();
+    let path = |file: &CreateFileRet| file.1.to_push_buf();
+    let choose = |answers: &[u8], expected: ExpectingConsumeAction| {
+        let mut write_sink = Vec::new();
+        let mut interactive =
+            InteractiveSetChoice::new(answers, &mut write_sink, Box::new(expected));
+        let consumed = interactive.consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &file2, &file3]),
+        ));
+        drop(interactive);
+        consumed.map(|()| String::from_utf8(write_sink).unwrap())
+    };
+
+    // the first kept file is the original, unrecognised answers are asked again
+    let expected = ExpectingConsumeAction(HashSet::from([(path(&file1), Some(path(&file2)))]));
+    let Ok(prompts) = choose(
+        b"4
+x
+2, 3
+",
+        expected,
+    ) else {
+        panic!("the set should be consumed")
+    };
+    assert!(prompts.starts_with(&format!(
+        "[1] {}
+[2] {}
+[3] {}
+",
+        path(&file1).display(),
+        path(&file2).display(),
+        path(&file3).display()
+    )));
+    assert_eq!(prompts.matches("unrecognised answer").count(), 2);
+
+    let expected = ExpectingConsumeAction(HashSet::new());
+    assert!(choose(
+        b"all
+", expected
+    )
+    .is_ok());
+    let expected = ExpectingConsumeAction(HashSet::from([
+        (path(&file1), None),
+        (path(&file2), None),
+        (path(&file3), None),
+    ]));
+    assert!(choose(
+        b"none
+", expected
+    )
+    .is_ok());
+    // the input is closed before an answer is given
+    let expected = ExpectingConsumeAction(HashSet::new());
+    assert!(choose(
+        b"0
+", expected
+    )
+    .is_err());
+}
+
+#[test]
+fn test_interactive_default_answer() {
+    let mut prefix = CommonPrefix::new("interactive_default_answer");
+    let file1 = prefix.make_file_auto();
+    let file2 = prefix.make_file_auto();
+    let file3 = prefix.make_file_auto();
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        file2.1.to_push_buf(),
+        Some(file1.1.to_push_buf()),
+    )]));
+
+    let mut write_sink = Vec::new();
+    let mut interactive =
+        InteractiveEachChoice::new(b"\nn\n".as_slice(), &mut write_sink, Box::new(expected))
+            .with_default_answer(Some(true));
+    interactive
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &file2, &file3]),
+        ))
+        .unwrap();
+    drop(interactive);
+    let prompts = String::from_utf8(write_sink).unwrap();
+    assert_eq!(prompts.matches("? [Y/n]\n").count(), 2);
+
+    // without a default an empty line is not an answer
+    let mut write_sink = Vec::new();
+    let mut interactive = InteractiveEachChoice::new(
+        b"\n".as_slice(),
+        &mut write_sink,
+        Box::new(UnreachableFileConsumer),
+    );
+    interactive
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap_err();
+}
+
+#[test]
+fn test_answers() {
+    let mut prefix = CommonPrefix::new("answers_");
+    let answers_path = PathBuf::from("test_files/answers");
+    let _ = std::fs::remove_file(&answers_path);
+
+    let file1 = prefix.make_file_auto();
+    let file2 = prefix.make_file_auto();
+    let file3 = prefix.make_file_auto();
+    let expected = || {
+        ExpectingConsumeAction(HashSet::from([(
+            file3.1.to_push_buf(),
+            Some(file1.1.to_push_buf()),
+        )]))
+    };
+
+    let mut write_sink = Vec::new();
+    let mut interactive =
+        InteractiveEachChoice::new(b"n\ny\n".as_slice(), &mut write_sink, Box::new(expected()))
+            .with_answers(Answers::open(&answers_path).unwrap());
+    interactive
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &file2, &file3]),
+        ))
+        .unwrap();
+    drop(interactive);
+
+    // the recorded answers are replayed without asking
+    let mut interactive =
+        InteractiveEachChoice::new(b"".as_slice(), Vec::new(), Box::new(expected()))
+            .with_answers(Answers::open(&answers_path).unwrap());
+    interactive
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &file2, &file3]),
+        ))
+        .unwrap();
+    drop(interactive);
+
+    // the answers are found by their files in any order, the files without an answer are asked about
+    let file4 = prefix.make_file_auto();
+    let expected = ExpectingConsumeAction(HashSet::from([
+        (file3.1.to_push_buf(), Some(file1.1.to_push_buf())),
+        (file4.1.to_push_buf(), Some(file1.1.to_push_buf())),
+    ]));
+    let mut interactive =
+        InteractiveEachChoice::new(b"y\n".as_slice(), Vec::new(), Box::new(expected))
+            .with_answers(Answers::open(&answers_path).unwrap());
+    interactive
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &file3, &file4, &file2]),
+        ))
+        .unwrap();
+    drop(interactive);
+
+    // the answers about files which no longer exist are left out
+    let file2 = file2.1.to_push_buf();
+    std::fs::remove_file(&file2).unwrap();
+    let mut answers = Answers::open(&answers_path).unwrap();
+    assert_eq!(answers.replay(&file2), None);
+    assert_eq!(answers.replay(&file3.1.to_push_buf()), Some(true));
+    std::fs::remove_file(&answers_path).unwrap();
+}
+
+#[test]
+fn test_seen_db() {
+    let mut prefix = CommonPrefix::new("seen_db_");
+    let seen_db_path = PathBuf::from("test_files/seen_db");
+    let _ = std::fs::remove_file(&seen_db_path);
+
+    let file1 = prefix.make_file_auto();
+    let file2 = prefix.make_file_auto();
+    let file3 = prefix.make_file_auto();
+
+    let mut write_sink = Vec::new();
+    let mut interactive = InteractiveEachChoice::new(
+        b"maybe\ni\n".as_slice(),
+        &mut write_sink,
+        Box::new(UnreachableFileConsumer),
+    )
+    .with_seen_db(SeenDb::open(&seen_db_path).unwrap());
+    interactive
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &file2, &file3]),
+        ))
+        .unwrap();
+
+    // the dismissed set is not asked about again, even though the input is closed
+    let empty_read_buf: [u8; 0] = [];
+    let mut interactive = InteractiveEachChoice::new(
+        empty_read_buf.as_slice(),
+        Vec::new(),
+        Box::new(UnreachableFileConsumer),
+    )
+    .with_seen_db(SeenDb::open(&seen_db_path).unwrap());
+    interactive
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file3, &file1, &file2]),
+        ))
+        .unwrap();
+
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        file2.1.to_push_buf(),
+        Some(file1.1.to_push_buf()),
+    )]));
+    let mut suppress = SuppressSeen::new(
+        SeenDb::open(&seen_db_path).unwrap(),
+        Box::new(UnconditionalAction::new(Box::new(expected))),
+    );
+    suppress
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file2, &file3, &file1]),
+        ))
+        .unwrap();
+    // a set with other files is still reported
+    suppress
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+}
+
+#[test]
+fn test_seen_db_skip() {
+    let mut prefix = CommonPrefix::new("seen_db_skip_");
+    let seen_db_path = PathBuf::from("test_files/seen_db_skip");
+    let _ = std::fs::remove_file(&seen_db_path);
+
+    let file1 = prefix.make_file_auto();
+    let file2 = prefix.make_file_auto();
+    let file3 = prefix.make_file_auto();
+
+    let mut interactive = InteractiveEachChoice::new(
+        b"x\n".as_slice(),
+        Vec::new(),
+        Box::new(UnreachableFileConsumer),
+    )
+    .with_seen_db(SeenDb::open(&seen_db_path).unwrap());
+    interactive
+        .consume_set(duplicate_set(7, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+
+    // the skipped set is not reported again, even with other files
+    let mut suppress = SuppressSeen::new(
+        SeenDb::open(&seen_db_path).unwrap(),
+        Box::new(UnconditionalAction::new(Box::new(UnreachableFileConsumer))),
+    );
+    suppress
+        .consume_set(duplicate_set(
+            7,
+            gather_hashed_files(&[&file1, &file2, &file3]),
+        ))
+        .unwrap();
+    // another set with the same files is still reported
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        file2.1.to_push_buf(),
+        Some(file1.1.to_push_buf()),
+    )]));
+    let mut suppress = SuppressSeen::new(
+        SeenDb::open(&seen_db_path).unwrap(),
+        Box::new(UnconditionalAction::new(Box::new(expected))),
+    );
+    suppress
+        .consume_set(duplicate_set(8, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+}
+
+#[test]
+fn test_allow_dupes() {
+    let mut prefix = CommonPrefix::new("allow_dupes/");
+    let canonical = |file: &CreateFileRet| {
+        let path = file.1.to_push_buf().canonicalize().unwrap();
+        HashedFile {
+            file_version_timestamp: None,
+            content_hash: 0,
+            file_path: (*LinkedPath::from_path_buf(&path)).clone(),
+            identity: None,
+            size: None,
+        }
+    };
+    let license_a = canonical(&prefix.create_file("a/LICENSE", &[]));
+    let license_b = canonical(&prefix.create_file("b/LICENSE", &[]));
+    let copy = canonical(&prefix.create_file("copy", &[]));
+    let pair_a = canonical(&prefix.create_file("pair_a", &[]));
+    let pair_b = canonical(&prefix.create_file("pair_b", &[]));
+
+    let rules_path = PathBuf::from("test_files/allow_dupes_rules");
+    let rules = format!(
+        "# licenses are meant to be copied\nLICENSE\n{}\t{}\n",
+        pair_b.file_path.to_push_buf().display(),
+        pair_a.file_path.to_push_buf().display()
+    );
+    std::fs::write(&rules_path, rules).unwrap();
+
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        copy.file_path.to_push_buf(),
+        Some(license_a.file_path.to_push_buf()),
+    )]));
+    let mut filter = FilterAllowedDupes::new(
+        AllowedDupes::read(&rules_path).unwrap(),
+        Box::new(UnconditionalAction::new(Box::new(expected))),
+    );
+    filter
+        .consume_set(duplicate_set(0, vec![license_a.clone(), license_b.clone()]))
+        .unwrap();
+    filter
+        .consume_set(duplicate_set(0, vec![pair_a, pair_b]))
+        .unwrap();
+    // the copy is still a duplicate of the license kept
+    filter
+        .consume_set(duplicate_set(0, vec![license_a, license_b, copy]))
+        .unwrap();
 }
 
 #[test]
@@ -524,18 +1109,30 @@ fn test_discovery_source() {
 
         let mut sink = ChannelInputSink::new(s);
 
-        source.consume_all(&mut sink).unwrap();
+        source
+            .consume_all(&mut sink, &StopSignal::new(None, None))
+            .unwrap();
         drop(sink);
         let actual = r.iter().collect::<HashSet<_>>();
         let expected = expected.into_iter().collect::<HashSet<_>>();
         assert_eq!(expected, actual)
     }
 
-    let disc =
-        DiscoveringInputSource::new(false, false, vec![a_source.clone()], empty_filter.clone());
+    let disc = DiscoveringInputSource::new(
+        false,
+        false,
+        SymlinkTreatment::Skip,
+        vec![a_source.clone()],
+        empty_filter.clone(),
+    );
     test_input(permute(&files, &[0, 1]), disc);
-    let disc =
-        DiscoveringInputSource::new(true, false, vec![a_source.clone()], empty_filter.clone());
+    let disc = DiscoveringInputSource::new(
+        true,
+        false,
+        SymlinkTreatment::Skip,
+        vec![a_source.clone()],
+        empty_filter.clone(),
+    );
     test_input(permute(&files, &[0, 1, 2, 3]), disc);
     let filter: Box<dyn FileNameFilter + Send> = Box::new(ExtensionFilter::new(
         HashSet::from([OsString::from("a")]),
@@ -545,8 +1142,2200 @@ fn test_discovery_source() {
     let disc = DiscoveringInputSource::new(
         true,
         false,
+        SymlinkTreatment::Skip,
         vec![a_source.clone()],
         FileFilter(vec![filter].into_boxed_slice(), Box::new([])),
     );
     test_input(permute(&files, &[0, 2]), disc);
 }
+
+#[test]
+#[cfg(unix)]
+fn test_stdin_nul_separated() {
+    let mut prefix = CommonPrefix::new("stdin_nul_separated/");
+    let (_, plain) = prefix.create_file("plain", &[]);
+    let (_, newline) = prefix.create_file("new\nline", &[]);
+
+    let read = |input: &[u8], mut source: StdInSource| {
+        let (s, r) = flume::unbounded();
+        let mut sink = ChannelInputSink::new(s);
+        source
+            .consume_reader(input, &mut sink, &StopSignal::new(None, None))
+            .unwrap();
+        drop(sink);
+        r.iter().collect::<Vec<_>>()
+    };
+    let empty_filter = || FileFilter(Box::new([]), Box::new([]));
+
+    let input =
+        b"test_files/stdin_nul_separated/plain\0test_files/stdin_nul_separated/new\nline\0\0";
+    let paths = read(input, StdInSource::new(empty_filter()).with_nul_separator());
+    assert_eq!(paths, vec![plain.clone(), newline]);
+
+    let paths = read(
+        b"test_files/stdin_nul_separated/plain\n\n",
+        StdInSource::new(empty_filter()),
+    );
+    assert_eq!(paths, vec![plain]);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_path_map() {
+    use crate::set_consumer::MapToHost;
+    use crate::util::PathMap;
+
+    struct CollectPaths(Arc<std::sync::Mutex<Vec<PathBuf>>>);
+
+    impl FileSetConsumer for CollectPaths {
+        fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+            self.0.lock().unwrap().extend(set.paths());
+            Ok(())
+        }
+    }
+
+    let maps = vec![
+        PathMap {
+            host: PathBuf::from("/srv/data"),
+            container: PathBuf::from("/data"),
+        },
+        PathMap {
+            host: PathBuf::from("/host"),
+            container: PathBuf::from("test_files"),
+        },
+    ];
+    let files = ["/data/a", "/database/b", "/data", "test_files/c"]
+        .map(|path| HashedFile {
+            file_version_timestamp: None,
+            content_hash: 0,
+            file_path: (*LinkedPath::from_path_buf(Path::new(path))).clone(),
+            identity: None,
+            size: None,
+        })
+        .to_vec();
+    let collected = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut map_to_host = MapToHost::new(maps.clone(), Box::new(CollectPaths(collected.clone())));
+    map_to_host.consume_set(duplicate_set(0, files)).unwrap();
+    let expected = ["/srv/data/a", "/database/b", "/srv/data", "/host/c"].map(PathBuf::from);
+    assert_eq!(*collected.lock().unwrap(), expected);
+
+    // the paths from stdin are translated the other way around
+    let mut prefix = CommonPrefix::new("stdin_path_map/");
+    let (_, file) = prefix.create_file("file", &[]);
+    let (s, r) = flume::unbounded();
+    let mut sink = ChannelInputSink::new(s);
+    StdInSource::new(FileFilter(Box::new([]), Box::new([])))
+        .with_path_maps(maps)
+        .consume_reader(
+            b"/host/stdin_path_map/file\n".as_slice(),
+            &mut sink,
+            &StopSignal::new(None, None),
+        )
+        .unwrap();
+    drop(sink);
+    assert_eq!(r.iter().collect::<Vec<_>>(), vec![file]);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_discovery_symlinks() {
+    let mut prefix = CommonPrefix::new("discovery_symlinks/");
+    let file = prefix.create_file("file", b"abc");
+    let link_path = PathBuf::from("test_files/discovery_symlinks/link");
+    let _ = std::fs::remove_file(&link_path);
+    std::os::unix::fs::symlink("file", &link_path).unwrap();
+    let link = Arc::into_inner(LinkedPath::from_path_buf(&link_path)).unwrap();
+    let canonical = std::fs::canonicalize(file.1.to_push_buf()).unwrap();
+    let canonical = Arc::into_inner(LinkedPath::from_path_buf(&canonical)).unwrap();
+
+    let root = LinkedPath::from_path_buf("test_files/discovery_symlinks".as_ref());
+    let discover = |file_symlinks| {
+        let (s, r) = flume::unbounded();
+        DiscoveringInputSource::new(
+            false,
+            false,
+            file_symlinks,
+            vec![root.clone()],
+            FileFilter(Box::new([]), Box::new([])),
+        )
+        .consume_all(&mut ChannelInputSink::new(s), &StopSignal::new(None, None))
+        .unwrap();
+        r.iter().collect::<HashSet<_>>()
+    };
+    assert_eq!(
+        discover(SymlinkTreatment::Skip),
+        HashSet::from([file.1.clone()])
+    );
+    assert_eq!(
+        discover(SymlinkTreatment::Follow),
+        HashSet::from([file.1.clone(), canonical])
+    );
+    assert_eq!(
+        discover(SymlinkTreatment::AsFiles),
+        HashSet::from([file.1, link])
+    );
+}
+
+#[test]
+fn test_budgeted_sink() {
+    let paths = ["a", "b", "c"]
+        .map(|name| LinkedPath::new_child(&LinkedPath::root("budget"), OsString::from(name)));
+    let cost = MemoryBudget::file_cost(&paths[0]);
+    let budget = Arc::new(MemoryBudget::new(cost * 2));
+    let (s, r) = flume::unbounded();
+    let mut sink = BudgetedInputSink::new(budget.clone(), Box::new(ChannelInputSink::new(s)));
+    sink.put(paths[0].clone());
+    sink.put(paths[1].clone());
+    let last = paths[2].clone();
+    let putting = std::thread::spawn(move || sink.put(last));
+    // the path beyond the budget waits while the paths before it may still make room
+    sleep(Duration::from_millis(50));
+    assert_eq!(r.len(), 2);
+    for _ in r.try_iter() {
+        budget.finish_work();
+    }
+    putting.join().unwrap();
+    // nothing is left which could make room, the path is charged beyond the budget instead of skipped
+    assert_eq!(r.try_iter().collect::<Vec<_>>(), paths[2..]);
+    assert_eq!(budget.used(), cost * 3);
+    assert!(budget.exceeded());
+}
+
+#[test]
+fn test_tree_report() {
+    let mut prefix = CommonPrefix::new("tree_report_");
+    let file1 = prefix.create_file_auto(b"ab");
+    let file2 = prefix.create_file_auto(b"ab");
+
+    let mut target = Vec::new();
+    let mut tree = TreeReport::new(&mut target);
+    tree.consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    tree.finish().unwrap();
+
+    let result = String::from_utf8(target).unwrap();
+    let expected =
+        "test_files\n  2 copies of 2 B\n    tree_report_1 (original)\n    tree_report_2\n";
+    assert_eq!(result, expected);
+
+    let mut target = Vec::new();
+    let mut tree = TreeReport::new(&mut target).with_size_format(SizeFormat::Both);
+    tree.consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    tree.finish().unwrap();
+    let result = String::from_utf8(target).unwrap();
+    assert!(result.contains("  2 copies of 2 (2 B)\n"));
+}
+
+#[test]
+fn test_results_without_diagnostics() {
+    use crate::out_sink::ResultsOutput;
+    use crate::set_consumer::DryRun;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut prefix = CommonPrefix::new("results_without_diagnostics_");
+    let vanished = prefix.create_file_auto(b"ab");
+    let file1 = prefix.create_file_auto(b"ab");
+    let file2 = prefix.create_file_auto(b"ab");
+    let vanished_path = vanished.1.to_push_buf();
+    std::fs::remove_file(&vanished_path).unwrap();
+
+    let paths = [&file1, &file2].map(|file| file.1.to_push_buf().canonicalize().unwrap());
+    let record = format!("{},{}", paths[0].display(), paths[1].display());
+
+    // the original is gone, which is logged; the results hold nothing but the records of the set
+    let target = SharedBuf::default();
+    let output = ResultsOutput::sink(Box::new(target.clone()));
+    let consumers: [(Box<dyn FileSetConsumer>, Option<&str>); 5] = [
+        (Box::new(DryRun::for_output(&output)), None),
+        (
+            Box::new(MachineReadableEach::for_output(&output)),
+            Some(&record),
+        ),
+        (
+            Box::new(MachineReadableSet::for_output(&output)),
+            Some(&record),
+        ),
+        (Box::new(RmBatch::for_output(&output, 1024)), Some("")),
+        (Box::new(TreeReport::for_output(&output)), None),
+    ];
+    for (mut consumer, expected) in consumers {
+        let hashed = gather_hashed_files(&[&vanished, &file1, &file2]);
+        consumer.consume_set(duplicate_set(0, hashed)).unwrap();
+        consumer.finish().unwrap();
+        drop(consumer);
+        let written = String::from_utf8(std::mem::take(&mut *target.0.lock().unwrap())).unwrap();
+        if let Some(expected) = expected {
+            assert_eq!(written, expected);
+        }
+        // the log records start with their level, like [WARN](file_error): ...
+        let logged = written.lines().find(|line| line.starts_with('['));
+        assert_eq!(logged, None);
+    }
+}
+
+#[test]
+fn test_extension_report() {
+    use crate::set_consumer::ExtensionReport;
+
+    let mut prefix = CommonPrefix::new("extension_report/");
+    let photo1 = prefix.create_file("a.JPG", b"abc");
+    let photo2 = prefix.create_file("b.jpg", b"abc");
+    let text1 = prefix.create_file("c.txt", b"abcde");
+    let text2 = prefix.create_file("d.Txt", b"abcde");
+    let bare = prefix.create_file("e", b"abcde");
+
+    let mut target = Vec::new();
+    let mut report = ExtensionReport::new(&mut target);
+    // the original of each set is not counted, the extensions are counted regardless of their case
+    let photo_set = gather_hashed_files(&[&photo1, &photo2]);
+    let text_set = gather_hashed_files(&[&text1, &text2, &bare]);
+    report.consume_set(duplicate_set(0, photo_set)).unwrap();
+    report.consume_set(duplicate_set(1, text_set)).unwrap();
+    report.finish().unwrap();
+
+    let result = String::from_utf8(target).unwrap();
+    let expected = [
+        "extension    duplicates         size",
+        "(none)                1          5 B",
+        "txt                   1          5 B",
+        "jpg                   1          3 B",
+    ];
+    assert_eq!(result.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn test_size_format() {
+    assert_eq!(SizeFormat::Human.format(9933), "9.7 KiB");
+    assert_eq!(SizeFormat::Bytes.format(9933), "9933");
+    assert_eq!(SizeFormat::Both.format(9933), "9933 (9.7 KiB)");
+}
+
+#[test]
+fn test_sync_writer_sink() {
+    #[derive(Clone, Default)]
+    struct SharedBuf(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let target = SharedBuf::default();
+    let sink = SyncWriterSink::new(target.clone()).unwrap();
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            let mut sink = sink.clone();
+            s.spawn(move || {
+                for i in 0..50 {
+                    write!(sink, "{t}").unwrap();
+                    writeln!(sink, ",{i}").unwrap();
+                    sink.flush().unwrap();
+                }
+            });
+        }
+    });
+    drop(sink);
+
+    let written = String::from_utf8(target.0.lock().unwrap().clone()).unwrap();
+    let mut lines = written.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 200);
+    assert!(lines
+        .iter()
+        .all(|line| line.len() >= 3 && line.as_bytes()[1] == b','));
+    lines.sort_unstable();
+    lines.dedup();
+    assert_eq!(lines.len(), 200);
+}
+
+#[test]
+fn test_results_output() {
+    use crate::out_sink::ResultsOutput;
+
+    let mut prefix = CommonPrefix::new("results_output_");
+    let file1 = prefix.create_file_auto(b"ab");
+    let file2 = prefix.create_file_auto(b"ab");
+    let out_path = PathBuf::from("test_files/results_output_out");
+    let output = ResultsOutput::sink(Box::new(std::fs::File::create(&out_path).unwrap()));
+
+    // every consumer writes into the same sink through a writer of its own
+    for _ in 0..2 {
+        let mut tree = TreeReport::for_output(&output);
+        tree.consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+            .unwrap();
+        tree.finish().unwrap();
+    }
+    drop(output);
+    let tree =
+        "test_files\n  2 copies of 2 B\n    results_output_1 (original)\n    results_output_2\n";
+    assert_eq!(std::fs::read_to_string(&out_path).unwrap(), tree.repeat(2));
+
+    let mut discarded = ResultsOutput::Discard.writer();
+    assert_eq!(discarded.write(b"abc").unwrap(), 3);
+    discarded.flush().unwrap();
+    let _ = std::fs::remove_file(out_path);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_hard_link_accounting() {
+    use crate::set_consumer::EstimateReport;
+
+    let mut prefix = CommonPrefix::new("hard_link_accounting_");
+    let file1 = prefix.create_file_auto(b"abcd");
+    let file2 = prefix.create_file_auto(b"abcd");
+    let link_path = PathBuf::from("test_files/hard_link_accounting_link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(file1.1.to_push_buf(), &link_path).unwrap();
+    let link = (
+        std::fs::File::open(&link_path).unwrap(),
+        std::sync::Arc::into_inner(LinkedPath::from_path_buf(&link_path)).unwrap(),
+    );
+
+    let mut target = Vec::new();
+    let mut estimate = EstimateReport::new(4, &mut target);
+    estimate
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&file1, &link, &file2]),
+        ))
+        .unwrap();
+    estimate.finish().unwrap();
+    std::fs::remove_file(&link_path).unwrap();
+
+    let result = String::from_utf8(target).unwrap();
+    assert!(
+        result.starts_with("estimate: at most 4 bytes reclaimable from 2 duplicates in 1 set("),
+        "{result}"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_skip_small_sets() {
+    use crate::set_consumer::SkipSmallSets;
+
+    let mut prefix = CommonPrefix::new("skip_small_sets_");
+    let file1 = prefix.create_file_auto(b"abcd");
+    let file2 = prefix.create_file_auto(b"abcd");
+    let file3 = prefix.create_file_auto(b"abcd");
+    let link_path = PathBuf::from("test_files/skip_small_sets_link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(file1.1.to_push_buf(), &link_path).unwrap();
+    let link = (
+        std::fs::File::open(&link_path).unwrap(),
+        std::sync::Arc::into_inner(LinkedPath::from_path_buf(&link_path)).unwrap(),
+    );
+
+    let expected = ExpectingConsumeAction(HashSet::from([
+        (file2.1.to_push_buf(), Some(file1.1.to_push_buf())),
+        (file3.1.to_push_buf(), Some(file1.1.to_push_buf())),
+    ]));
+    let mut skip = SkipSmallSets::new(8, Box::new(UnconditionalAction::new(Box::new(expected))));
+    // the link frees no space
+    skip.consume_set(duplicate_set(
+        0,
+        gather_hashed_files(&[&file1, &link, &file2]),
+    ))
+    .unwrap();
+    skip.consume_set(duplicate_set(
+        0,
+        gather_hashed_files(&[&file1, &file2, &file3]),
+    ))
+    .unwrap();
+    std::fs::remove_file(&link_path).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_skip_hard_linked() {
+    use crate::set_consumer::SkipHardLinked;
+
+    let mut prefix = CommonPrefix::new("skip_hard_linked_");
+    let file1 = prefix.create_file_auto(b"abcd");
+    let file2 = prefix.create_file_auto(b"abcd");
+    let link_path = PathBuf::from("test_files/skip_hard_linked_link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(file1.1.to_push_buf(), &link_path).unwrap();
+    let link = (
+        std::fs::File::open(&link_path).unwrap(),
+        std::sync::Arc::into_inner(LinkedPath::from_path_buf(&link_path)).unwrap(),
+    );
+
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        file2.1.to_push_buf(),
+        Some(file1.1.to_push_buf()),
+    )]));
+    let mut skip = SkipHardLinked::new(Box::new(UnconditionalAction::new(Box::new(expected))));
+    // the link is left out, the other duplicate remains
+    skip.consume_set(duplicate_set(
+        0,
+        gather_hashed_files(&[&file1, &link, &file2]),
+    ))
+    .unwrap();
+    // nothing is left of a set of links to the same file
+    skip.consume_set(duplicate_set(1, gather_hashed_files(&[&link, &file1])))
+        .unwrap();
+    std::fs::remove_file(&link_path).unwrap();
+}
+
+#[test]
+fn test_replace_with_link() {
+    use crate::file_action::{replace_with_link, ReplaceWithHardLinkFileAction};
+
+    let mut prefix = CommonPrefix::new("replace_with_link/");
+    let original = prefix.create_file("original", b"abc").1.to_push_buf();
+    // a link left by an earlier run would be truncated along with the original
+    let _ = std::fs::remove_file("test_files/replace_with_link/duplicate");
+    let duplicate = prefix.create_file("duplicate", b"abc").1.to_push_buf();
+
+    // the duplicate stays if the link cannot be created
+    let failed = replace_with_link(&duplicate, |_| Err(std::io::Error::other("no links")));
+    assert!(failed.is_err());
+    assert_eq!(std::fs::read(&duplicate).unwrap(), b"abc");
+
+    assert!(ReplaceWithHardLinkFileAction::default()
+        .consume(&duplicate, Some(&original))
+        .is_ok());
+    std::fs::write(&original, b"abcd").unwrap();
+    assert_eq!(std::fs::read(&duplicate).unwrap(), b"abcd");
+    // replacing a link to the original again leaves nothing behind
+    assert!(ReplaceWithHardLinkFileAction::default()
+        .consume(&duplicate, Some(&original))
+        .is_ok());
+    let dir_entries = std::fs::read_dir("test_files/replace_with_link")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<HashSet<_>>();
+    assert_eq!(
+        dir_entries,
+        HashSet::from([OsString::from("original"), OsString::from("duplicate")])
+    );
+}
+
+#[test]
+fn test_unusable_original() {
+    use crate::file_action::ReplaceWithHardLinkFileAction;
+
+    let mut prefix = CommonPrefix::new("unusable_original/");
+    let file1 = prefix.create_file("file1", b"abc");
+    // a link left by an earlier run would be truncated along with the first file
+    let _ = std::fs::remove_file("test_files/unusable_original/file2");
+    let file2 = prefix.create_file("file2", b"abc");
+    let dir = PathBuf::from("test_files/unusable_original/dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    let replaced_original = HashedFile {
+        file_version_timestamp: None,
+        content_hash: 0,
+        file_path: (*LinkedPath::from_path_buf(&dir)).clone(),
+        identity: None,
+        size: None,
+    };
+
+    let mut set = vec![replaced_original];
+    set.append(&mut gather_hashed_files(&[&file1, &file2]));
+    let mut consumer = UnconditionalAction::new(Box::<ReplaceWithHardLinkFileAction>::default());
+    consumer.consume_set(duplicate_set(0, set)).unwrap();
+    // the next file became the original instead of the directory
+    assert!(dir.is_dir());
+    std::fs::write(file1.1.to_push_buf(), b"abcd").unwrap();
+    assert_eq!(std::fs::read(file2.1.to_push_buf()).unwrap(), b"abcd");
+}
+
+#[test]
+fn test_verify_original() {
+    let mut prefix = CommonPrefix::new("verify_original_");
+    let file1 = prefix.create_file("file1", b"abc");
+    let file2 = prefix.create_file("file2", b"abc");
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        file2.1.to_push_buf(),
+        Some(file1.1.to_push_buf()),
+    )]));
+    let mut consumer = UnconditionalAction::new(Box::new(expected))
+        .with_verify_original(Some(crate::HashAlgorithm::Xxh3));
+    consumer
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+
+    let set = gather_hashed_files(&[&file1, &file2]);
+    std::fs::write(file1.1.to_push_buf(), b"abd").unwrap();
+    let mut consumer = UnconditionalAction::new(Box::new(UnreachableFileConsumer))
+        .with_verify_original(Some(crate::HashAlgorithm::Xxh3));
+    consumer.consume_set(duplicate_set(0, set)).unwrap();
+}
+
+#[test]
+fn test_action_threads() {
+    use crate::file_action::DeleteFileAction;
+
+    let mut prefix = CommonPrefix::new("action_threads/");
+    let sets = ["a", "b", "c"].map(|content| {
+        ["a/", "b/", "c/"]
+            .map(|dir| prefix.create_file(&format!("{dir}{content}"), content.as_bytes()))
+    });
+    let mut consumer = UnconditionalAction::new(Box::<DeleteFileAction>::default())
+        .with_action_threads(std::num::NonZeroU32::new(2).unwrap());
+    for [original, dup1, dup2] in &sets {
+        consumer
+            .consume_set(duplicate_set(
+                0,
+                gather_hashed_files(&[original, dup1, dup2]),
+            ))
+            .unwrap();
+    }
+    consumer.finish().unwrap();
+    for [original, dup1, dup2] in &sets {
+        assert!(original.1.to_push_buf().exists());
+        assert!(!dup1.1.to_push_buf().exists());
+        assert!(!dup2.1.to_push_buf().exists());
+    }
+}
+
+#[test]
+fn test_dry_run_action() {
+    use crate::file_action::ReplaceWithHardLinkFileAction;
+
+    let mut prefix = CommonPrefix::new("dry_run_action_");
+    let files = [(); 2].map(|()| prefix.make_file_auto());
+    let mut target = Vec::new();
+    let mut dry_run =
+        DryRun::new_with(&mut target).with_action(&ReplaceWithHardLinkFileAction::default());
+    dry_run
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&files[0], &files[1]]),
+        ))
+        .unwrap();
+    let expected = format!(
+        "keeping {}, would replace with hardlink: {}\n",
+        files[0].1.to_push_buf().display(),
+        files[1].1.to_push_buf().display()
+    );
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+    // the action leaves the files untouched
+    assert!(files[1].1.to_push_buf().exists());
+}
+
+#[test]
+fn test_set_summary() {
+    let mut prefix = CommonPrefix::new("set_summary/");
+    let files = [(); 3].map(|()| prefix.create_file_auto(&[7; 1500]));
+    let mut summary = SetSummary::new(SizeFormat::Human);
+    assert_eq!(summary.line(), "0 duplicates in 0 sets, 0 B reclaimable");
+    summary.add(&duplicate_set(
+        0,
+        gather_hashed_files(&[&files[0], &files[1], &files[2]]),
+    ));
+    // a lone file is no set
+    summary.add(&duplicate_set(1, gather_hashed_files(&[&files[0]])));
+    assert_eq!(summary.line(), "2 duplicates in 1 set, 2.9 KiB reclaimable");
+    // a set without sizes leaves the reclaimable space unknown
+    let mut unsized_set = gather_hashed_files(&[&files[0], &files[1]]);
+    unsized_set[0].size = None;
+    summary.add(&duplicate_set(0, unsized_set));
+    assert_eq!(
+        summary.line(),
+        "3 duplicates in 2 sets, an unknown size reclaimable"
+    );
+
+    let mut summary = SetSummary::new(SizeFormat::Bytes);
+    summary.add(&duplicate_set(
+        0,
+        gather_hashed_files(&[&files[0], &files[1]]),
+    ));
+    assert_eq!(summary.line(), "1 duplicate in 1 set, 1500 reclaimable");
+}
+
+#[test]
+fn test_rm_batch() {
+    let mut prefix = CommonPrefix::new("rm_batch_");
+    let files = [(); 3].map(|()| prefix.make_file_auto());
+    // the paths are written as discovered
+    let [orig, dup1, dup2] = [&files[0], &files[1], &files[2]].map(|file| file.1.to_push_buf());
+
+    let mut target = Vec::new();
+    // every path exceeds the batch size, so each gets its own batch
+    let mut batches = RmBatch::new(1, &mut target);
+    batches
+        .consume_set(duplicate_set(
+            0,
+            gather_hashed_files(&[&files[0], &files[1], &files[2]]),
+        ))
+        .unwrap();
+    let result = String::from_utf8(target).unwrap();
+    let expected = format!(
+        "# batch 1\0# keep {0}\0{1}\0# batch 2\0# keep {0}\0{2}\0",
+        orig.display(),
+        dup1.display(),
+        dup2.display()
+    );
+    assert_eq!(result, expected);
+
+    // a symlink to the original is never written to be removed
+    #[cfg(unix)]
+    {
+        let link = PathBuf::from("test_files/rm_batch_link");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(orig.canonicalize().unwrap(), &link).unwrap();
+        let link_path = std::sync::Arc::into_inner(LinkedPath::from_path_buf(&link)).unwrap();
+        let link_file = (std::fs::File::open(&link).unwrap(), link_path);
+        let mut target = Vec::new();
+        RmBatch::new(1024, &mut target)
+            .consume_set(duplicate_set(
+                0,
+                gather_hashed_files(&[&files[0], &link_file, &files[1]]),
+            ))
+            .unwrap();
+        let expected = format!("# batch 1\0# keep {}\0{}\0", orig.display(), dup1.display());
+        assert_eq!(String::from_utf8(target).unwrap(), expected);
+        std::fs::remove_file(&link).unwrap();
+    }
+}
+
+#[test]
+fn test_diff_manifests() {
+    let manifest_a = PathBuf::from("test_files/diff_manifests_a");
+    let manifest_b = PathBuf::from("test_files/diff_manifests_b");
+    let manifest_c = PathBuf::from("test_files/diff_manifests_c");
+    let write_manifest = |path: &Path, algorithm: Option<&str>, files: &[(u128, &str)]| {
+        let mut manifest = Vec::new();
+        if let Some(algorithm) = algorithm {
+            write_manifest_header(&mut manifest, algorithm).unwrap();
+        }
+        for (hash, file) in files {
+            write_manifest_line(&mut manifest, *hash, file.as_ref()).unwrap();
+        }
+        std::fs::write(path, manifest).unwrap();
+    };
+    write_manifest(
+        &manifest_a,
+        Some(crate::HashAlgorithm::Xxh3.name()),
+        &[(1, "/a/x"), (2, "/a/y"), (1, "/a/z"), (3, "/a/w")],
+    );
+    // manifests which do not name their algorithm were hashed with the default
+    write_manifest(&manifest_b, None, &[(3, "/b/w"), (1, "/b/x"), (4, "/b/v")]);
+    write_manifest(&manifest_c, Some("other-128"), &[(3, "/c/w")]);
+
+    let xxh3 = crate::HashAlgorithm::Xxh3.name();
+    let mut out = Vec::new();
+    diff_manifests(&manifest_a, &manifest_b, xxh3, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "/a/w duplicated by /b/w\n/a/x, /a/z duplicated by /b/x\n"
+    );
+    assert!(diff_manifests(
+        &manifest_a,
+        "test_files/diff_manifests_missing".as_ref(),
+        xxh3,
+        &mut Vec::new()
+    )
+    .is_err());
+    // the hashes of another algorithm cannot be compared
+    assert!(diff_manifests(&manifest_a, &manifest_c, xxh3, &mut Vec::new()).is_err());
+    // a corrupt manifest would leave out its files
+    let mut corrupt = std::fs::read(&manifest_b).unwrap();
+    corrupt.extend_from_slice(b"not a hash\t/b/u\n");
+    std::fs::write(&manifest_c, corrupt).unwrap();
+    assert!(diff_manifests(&manifest_a, &manifest_c, xxh3, &mut Vec::new()).is_err());
+}
+
+#[test]
+fn test_failing_refiner_splits_sets() {
+    #[derive(Clone)]
+    struct NeverEqual;
+    impl FileEqualsChecker for NeverEqual {
+        fn check_equal(
+            &mut self,
+            _: &Path,
+            _: &Path,
+            _: &StopSignal,
+        ) -> Result<bool, CheckEqualsErrorOn> {
+            Ok(false)
+        }
+        fn hash_component(
+            &mut self,
+            _: &Path,
+            _: &mut dyn std::hash::Hasher,
+        ) -> Result<(), AlreadyReportedError> {
+            Ok(())
+        }
+        fn work_severity(&self) -> FileWorkload {
+            FileWorkload::Simple
+        }
+    }
+
+    let mut prefix = CommonPrefix::new("failing_refiner_");
+    let file1 = prefix.create_file_auto(b"abc");
+    let file2 = prefix.create_file_auto(b"abc");
+    let largest_set = |logic| {
+        let checkers: Vec<Box<dyn FileEqualsChecker + Send>> =
+            vec![Box::new(FileContentEquals::default()), Box::new(NeverEqual)];
+        let mut refiners = FileSetRefiners::new(checkers.into_boxed_slice(), logic);
+        let mut sets = Vec::new();
+        for (_, path) in [&file1, &file2] {
+            crate::place_into_file_set(
+                path.clone(),
+                &path.to_push_buf(),
+                &mut PathBuf::new(),
+                &mut refiners,
+                None,
+                worker_shared(&StopSignal::new(None, None)),
+                |_| &mut sets,
+            )
+            .unwrap();
+        }
+        sets.iter().map(|(_, set)| set.len()).max()
+    };
+    assert_eq!(largest_set(RefinerLogic::And), Some(1));
+    assert_eq!(largest_set(RefinerLogic::Or), Some(2));
+}
+
+#[test]
+fn test_equal_hash_distinct_sets() {
+    /// files are equal if their names start alike, no matter their content
+    #[derive(Clone)]
+    struct SameInitial;
+    impl FileEqualsChecker for SameInitial {
+        fn check_equal(
+            &mut self,
+            a: &Path,
+            b: &Path,
+            _: &StopSignal,
+        ) -> Result<bool, CheckEqualsErrorOn> {
+            let initial = |path: &Path| path.file_name().unwrap().as_encoded_bytes()[0];
+            Ok(initial(a) == initial(b))
+        }
+        fn hash_component(
+            &mut self,
+            _: &Path,
+            _: &mut dyn std::hash::Hasher,
+        ) -> Result<(), AlreadyReportedError> {
+            Ok(())
+        }
+        fn work_severity(&self) -> FileWorkload {
+            FileWorkload::Simple
+        }
+    }
+
+    let mut prefix = CommonPrefix::new("equal_hash_distinct_sets/");
+    let files = ["a1", "b1", "a2", "b2", "c1"].map(|name| prefix.create_file(name, b"abc").1);
+    let checkers: Vec<Box<dyn FileEqualsChecker + Send>> = vec![Box::new(SameInitial)];
+    let mut refiners = FileSetRefiners::new(checkers.into_boxed_slice(), RefinerLogic::And);
+    let mut sets = Vec::new();
+    for path in &files {
+        crate::place_into_file_set(
+            path.clone(),
+            &path.to_push_buf(),
+            &mut PathBuf::new(),
+            &mut refiners,
+            None,
+            worker_shared(&StopSignal::new(None, None)),
+            |_| &mut sets,
+        )
+        .unwrap();
+    }
+    // the files the first set rejects start sets of their own instead of being dropped
+    let placed = sets
+        .iter()
+        .map(|(_, set)| {
+            set.iter()
+                .map(|file| file.file_path.file_name().to_owned())
+                .collect()
+        })
+        .collect::<Vec<Vec<_>>>();
+    assert_eq!(placed, vec![vec!["a1", "a2"], vec!["b1", "b2"], vec!["c1"]]);
+}
+
+#[test]
+fn test_refine_file_sets() {
+    let mut prefix = CommonPrefix::new("refine_file_sets_");
+    let file1 = prefix.create_file_auto(b"abc");
+    let file2 = prefix.create_file_auto(b"abd");
+    let file3 = prefix.create_file_auto(b"abc");
+    let file4 = prefix.create_file_auto(b"abe");
+    let file5 = prefix.create_file_auto(b"abd");
+    let hashed = gather_hashed_files(&[&file1, &file2, &file3, &file4, &file5]);
+
+    let checkers: Vec<Box<dyn FileEqualsChecker + Send>> =
+        vec![Box::new(FileContentEquals::default())];
+    let mut refiners = FileSetRefiners::new(checkers.into_boxed_slice(), RefinerLogic::And);
+    let content_refiners = refiners.split_off(FileWorkload::FileContent);
+    assert!(refiners.is_empty());
+    assert!(!content_refiners.is_empty());
+
+    // the first set has more members than threads and is compared in parallel
+    let sets = vec![
+        (1, hashed.clone()),
+        (2, permute(&hashed, &[3])),
+        (3, permute(&hashed, &[2, 0])),
+    ];
+    let threads = std::num::NonZeroU32::new(2).unwrap();
+    let mut split_sets = crate::refine_file_sets(sets.into_iter(), &content_refiners, threads);
+    split_sets.sort_by_key(|(key, set)| (*key, set[0].file_path.to_push_buf()));
+    let expected = vec![
+        (1, permute(&hashed, &[0, 2])),
+        (1, permute(&hashed, &[1, 4])),
+        (3, permute(&hashed, &[2, 0])),
+    ];
+    assert_eq!(split_sets, expected);
+
+    // the sets a course set splits into are told apart by their files, the order of the files does not matter
+    let id = |key, files| DuplicateSet::from_key(key, files, SetProvenance::Content).id;
+    assert_ne!(
+        id(1, permute(&hashed, &[0, 2])),
+        id(1, permute(&hashed, &[1, 4]))
+    );
+    assert_eq!(
+        id(1, permute(&hashed, &[0, 2])),
+        id(1, permute(&hashed, &[2, 0]))
+    );
+    assert_ne!(
+        id(1, permute(&hashed, &[0, 2])),
+        id(3, permute(&hashed, &[0, 2]))
+    );
+}
+
+#[test]
+fn test_content_equals_probes() {
+    let mut prefix = CommonPrefix::new("content_equals_probes_");
+    let content = (0..20).collect::<Vec<u8>>();
+    let original = prefix.create_file_auto(&content);
+    let mut changed_at = |i: usize| {
+        let mut changed = content.clone();
+        changed[i] ^= 0xff;
+        prefix.create_file_auto(&changed).1.to_push_buf()
+    };
+    // the first, the last and the middle part with chunks of 4 bytes
+    let changed = [changed_at(1), changed_at(18), changed_at(9), changed_at(4)];
+    let same = prefix.create_file_auto(&content).1.to_push_buf();
+    let original = original.1.to_push_buf();
+
+    for buffer_size in [4, 64] {
+        let mut equals = FileContentEquals::new(buffer_size);
+        assert!(equals
+            .check_equal(&original, &same, &StopSignal::new(None, None))
+            .unwrap());
+        for changed in &changed {
+            assert!(!equals
+                .check_equal(&original, changed, &StopSignal::new(None, None))
+                .unwrap());
+        }
+        // the probes are placed by the recorded size
+        let (original, same) = ((original.as_path(), Some(20)), (same.as_path(), Some(20)));
+        let never = StopSignal::new(None, None);
+        assert!(equals.check_equal_sized(original, same, &never).unwrap());
+        for changed in &changed {
+            let changed = (changed.as_path(), Some(20));
+            assert!(!equals.check_equal_sized(original, changed, &never).unwrap());
+        }
+    }
+    // files of different recorded sizes are not opened
+    let missing = Path::new("test_files/content_equals_probes_missing");
+    let mut equals = FileContentEquals::default();
+    let differ = equals.check_equal_sized(
+        (missing, Some(1)),
+        (missing, Some(2)),
+        &StopSignal::new(None, None),
+    );
+    assert_eq!(differ, Ok(false));
+    // files which grew alike since their sizes were recorded are not compared by the stale size
+    let mut grown = content.clone();
+    grown.extend_from_slice(b"more");
+    let grown_a = prefix.create_file_auto(&grown).1.to_push_buf();
+    let grown_b = prefix.create_file_auto(&grown).1.to_push_buf();
+    let differ = equals.check_equal_sized(
+        (&grown_a, Some(20)),
+        (&grown_b, Some(20)),
+        &StopSignal::new(None, None),
+    );
+    assert_eq!(differ, Ok(false));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_report_hard_links() {
+    use crate::set_consumer::HardLinkReport;
+
+    let mut prefix = CommonPrefix::new("report_hard_links/");
+    let original = prefix.create_file("original", b"abc").1;
+    // the same content is not enough, only links to the same file are grouped
+    let copy = prefix.create_file("copy", b"abc").1;
+    let link_path = PathBuf::from("test_files/report_hard_links/link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(original.to_push_buf(), &link_path).unwrap();
+    let link = (*LinkedPath::from_path_buf(&link_path)).clone();
+
+    let mut groups = std::collections::HashMap::new();
+    let completed = [original.clone(), copy, link.clone()].map(|file| {
+        let path = file.to_push_buf();
+        crate::place_into_link_group(file, &path, |key| groups.entry(key).or_insert(Vec::new()))
+            .unwrap()
+    });
+    std::fs::remove_file(&link_path).unwrap();
+    assert_eq!(completed, [false, false, true]);
+    let linked = groups
+        .into_values()
+        .map(|mut group| group.remove(0).1)
+        .find(|group| group.len() > 1)
+        .unwrap();
+
+    let mut target = Vec::new();
+    let mut report = HardLinkReport::new(&mut target);
+    report.consume_set(duplicate_set(0, linked)).unwrap();
+    report.finish().unwrap();
+    drop(report);
+    let expected = format!(
+        "3 B file linked as {}, {}\n1 files with several links, saving 3 B\n",
+        original.to_push_buf().display(),
+        link.to_push_buf().display()
+    );
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+}
+
+#[test]
+fn test_copy_audit() {
+    use crate::error_handling::ErrorSummary;
+    use crate::set_consumer::CopyAudit;
+
+    let mut prefix = CommonPrefix::new("copy_audit/");
+    let original = prefix.create_file("original", b"abc");
+    let copy = prefix.create_file("copy", b"abc");
+    let lone = prefix.create_file("lone", b"def");
+    let linked = prefix.create_file("linked", b"ghi");
+    let link_path = PathBuf::from("test_files/copy_audit/link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(linked.1.to_push_buf(), &link_path).unwrap();
+    let link = (
+        std::fs::File::open(&link_path).unwrap(),
+        (*LinkedPath::from_path_buf(&link_path)).clone(),
+    );
+    let audit = |required: usize, errors: &Arc<ErrorSummary>| {
+        let sets = [
+            duplicate_set(0, gather_hashed_files(&[&original, &copy])),
+            duplicate_set(1, gather_hashed_files(&[&lone])),
+            duplicate_set(2, gather_hashed_files(&[&linked, &link])),
+        ];
+        let mut target = Vec::new();
+        let mut audit = CopyAudit::new(required, &mut target).with_errors(errors.clone());
+        let stop = StopSignal::new(None, None);
+        let mut summary = SetSummary::new(SizeFormat::Human);
+        let audited =
+            crate::consume_sets(sets.into_iter(), &mut [], &mut audit, &stop, &mut summary);
+        drop(audit);
+        (audited.is_ok(), String::from_utf8(target).unwrap())
+    };
+    let path = |file: &LinkedPath| file.to_push_buf().display().to_string();
+    let errors = Arc::new(ErrorSummary::new());
+    // the files without any duplicate reach the audit as well, a hard link is no copy
+    assert_eq!(
+        audit(1, &errors),
+        (
+            true,
+            format!(
+                "{}: 0 of 1 copies\n{}: 0 of 1 copies\n{}: 0 of 1 copies\n3 files with fewer than 1 copies\n",
+                path(&lone.1),
+                path(&linked.1),
+                path(&link.1)
+            )
+        )
+    );
+    assert_eq!(
+        audit(2, &errors).1,
+        format!(
+            "{}: 1 of 2 copies\n{}: 1 of 2 copies\n{}: 0 of 2 copies\n{}: 0 of 2 copies\n{}: 0 of 2 copies\n5 files with fewer than 2 copies\n",
+            path(&original.1),
+            path(&copy.1),
+            path(&lone.1),
+            path(&linked.1),
+            path(&link.1)
+        )
+    );
+    // a file which never reached the audit may lack its copies as well
+    errors.file_failed();
+    assert!(!audit(1, &errors).0);
+    std::fs::remove_file(&link_path).unwrap();
+}
+
+#[test]
+fn test_mirror_tree() {
+    use crate::set_consumer::MirrorTree;
+
+    let mut prefix = CommonPrefix::new("mirror_tree/");
+    let original = prefix.create_file("original", b"abc");
+    let copy = prefix.create_file("sub/copy", b"abc");
+    let lone = prefix.create_file("lone", b"def");
+    let target = PathBuf::from("test_files/mirror_tree_target");
+    let _ = std::fs::remove_dir_all(&target);
+
+    let sets = [
+        duplicate_set(0, gather_hashed_files(&[&original, &copy])),
+        duplicate_set(1, gather_hashed_files(&[&lone])),
+    ];
+    let mut output = Vec::new();
+    let mut mirror = MirrorTree::new(target.clone(), &mut output);
+    crate::consume_sets(
+        sets.into_iter(),
+        &mut [],
+        &mut mirror,
+        &StopSignal::new(None, None),
+        &mut SetSummary::new(SizeFormat::Human),
+    )
+    .unwrap();
+    drop(mirror);
+
+    let mirrored = |file: &CreateFileRet| target.join(file.1.to_push_buf());
+    let identity = |path: &Path| crate::os::link_identity(path).unwrap();
+    // the copy is mirrored as a link to the original, the scanned files stay apart
+    assert_eq!(
+        identity(&mirrored(&copy)),
+        identity(&original.1.to_push_buf())
+    );
+    assert_eq!(
+        identity(&mirrored(&original)),
+        identity(&original.1.to_push_buf())
+    );
+    assert_ne!(
+        identity(&copy.1.to_push_buf()),
+        identity(&original.1.to_push_buf())
+    );
+    assert_eq!(identity(&mirrored(&lone)), identity(&lone.1.to_push_buf()));
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!(
+            "mirrored 3 files with 2 distinct contents into {}\n",
+            target.display()
+        )
+    );
+    std::fs::remove_dir_all(&target).unwrap();
+}
+
+#[test]
+fn test_file_identity() {
+    let mut prefix = CommonPrefix::new("file_identity/");
+    let original = prefix.create_file("original", b"abc").1;
+    let copy = prefix.create_file("copy", b"abc").1;
+    let link_path = PathBuf::from("test_files/file_identity/link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(original.to_push_buf(), &link_path).unwrap();
+    let link = (*LinkedPath::from_path_buf(&link_path)).clone();
+
+    let stop = StopSignal::new(None, None);
+    let mut sets = Vec::new();
+    for file in [original.clone(), copy.clone(), link.clone()] {
+        crate::place_into_file_set(
+            file.clone(),
+            &file.to_push_buf(),
+            &mut PathBuf::new(),
+            &mut FileSetRefiners::new(Box::new([]), RefinerLogic::And),
+            None,
+            worker_shared(&stop),
+            |_| &mut sets,
+        )
+        .unwrap();
+    }
+    // the identities are taken when the files are placed, the link is recognized after it is gone
+    std::fs::remove_file(&link_path).unwrap();
+    let set = sets.remove(0).1;
+    assert_eq!(set[0].identity(), set[2].identity());
+    assert_ne!(set[0].identity(), set[1].identity());
+
+    let mut target = Vec::new();
+    DryRun::new_with(&mut target)
+        .consume_set(duplicate_set(0, set))
+        .unwrap();
+    let expected = format!(
+        "keeping {}, dry-deleting {}, {} (hard link of {})\n",
+        original.to_push_buf().display(),
+        copy.to_push_buf().display(),
+        link.to_push_buf().display(),
+        original.to_push_buf().display()
+    );
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+}
+
+#[test]
+fn test_find_copies() {
+    let mut prefix = CommonPrefix::new("find_copies/");
+    let target = prefix.create_file("target", b"abc").1.to_push_buf();
+    let copy = prefix.create_file("copy", b"abc").1.to_push_buf();
+    prefix.create_file("same_size", b"abd");
+    prefix.create_file("larger", b"abcd");
+    let dir = Path::new("test_files/find_copies").canonicalize().unwrap();
+
+    let mut target_out = Vec::new();
+    crate::find_copies::find_copies(
+        &target.canonicalize().unwrap(),
+        vec![LinkedPath::from_path_buf(&dir)],
+        &mut target_out,
+    )
+    .unwrap();
+    let expected = format!("{}\n", copy.canonicalize().unwrap().display());
+    assert_eq!(String::from_utf8(target_out).unwrap(), expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_snapshot_live_files() {
+    use crate::snapshot::Snapshots;
+
+    let mut prefix = CommonPrefix::new("snapshot/");
+    let live = prefix.create_file("live/a", b"abc").1.to_push_buf();
+    let snapshot = prefix.create_file("snap/a", b"abc");
+    let live_dir = live.parent().unwrap().canonicalize().unwrap();
+    let snapshot_dir = snapshot
+        .1
+        .to_push_buf()
+        .parent()
+        .unwrap()
+        .canonicalize()
+        .unwrap();
+
+    let create_cmd = format!("echo '{}'", snapshot_dir.display());
+    let snapshots = Snapshots::create(&create_cmd, None, std::slice::from_ref(&live_dir)).unwrap();
+    assert_eq!(
+        snapshots.snapshot_dir(&live_dir).unwrap().to_push_buf(),
+        snapshot_dir
+    );
+    let hashed = gather_hashed_files(&[&(
+        snapshot.0,
+        (*LinkedPath::from_path_buf(&snapshot_dir.join("a"))).clone(),
+    )]);
+    let live_files = snapshots.live_files(hashed);
+    assert_eq!(live_files[0].file_path.to_push_buf(), live_dir.join("a"));
+
+    assert!(Snapshots::create("exit 1", None, &[live_dir]).is_err());
+}
+
+#[test]
+fn test_discovery_traversal() {
+    use crate::input_source::Traversal;
+
+    let mut prefix = CommonPrefix::new("discovery_traversal/");
+    prefix.create_file("r1/x", b"x");
+    prefix.create_file("r1/s/y", b"y");
+    prefix.create_file("r2/z", b"z");
+    let roots = ["r1", "r2"].map(|root| {
+        LinkedPath::from_path_buf(
+            Path::new("test_files/discovery_traversal")
+                .join(root)
+                .as_ref(),
+        )
+    });
+    let discover = |traversal| {
+        let (s, r) = flume::unbounded();
+        DiscoveringInputSource::new(
+            true,
+            false,
+            SymlinkTreatment::Skip,
+            roots.to_vec(),
+            FileFilter(Box::new([]), Box::new([])),
+        )
+        .with_traversal(traversal)
+        .consume_all(&mut ChannelInputSink::new(s), &StopSignal::new(None, None))
+        .unwrap();
+        r.iter()
+            .map(|file| file.file_name().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(discover(Traversal::DepthFirst), ["x", "y", "z"]);
+    assert_eq!(discover(Traversal::BreadthFirst), ["x", "z", "y"]);
+}
+
+#[test]
+fn test_min_depth() {
+    let mut prefix = CommonPrefix::new("min_depth/");
+    prefix.create_file("x", b"x");
+    prefix.create_file("s/y", b"y");
+    prefix.create_file("s/t/z", b"z");
+    let discover = |min_depth, max_depth| {
+        let (s, r) = flume::unbounded();
+        DiscoveringInputSource::new(
+            true,
+            false,
+            SymlinkTreatment::Skip,
+            vec![LinkedPath::from_path_buf("test_files/min_depth".as_ref())],
+            FileFilter(Box::new([]), Box::new([])),
+        )
+        .with_min_depth(min_depth)
+        .with_max_depth(max_depth)
+        .consume_all(&mut ChannelInputSink::new(s), &StopSignal::new(None, None))
+        .unwrap();
+        r.iter()
+            .map(|file| file.file_name().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(discover(1, None), ["x", "y", "z"]);
+    assert_eq!(discover(2, None), ["y", "z"]);
+    assert_eq!(discover(3, None), ["z"]);
+    assert!(discover(4, None).is_empty());
+    assert_eq!(discover(0, Some(1)), ["x"]);
+    assert_eq!(discover(0, Some(2)), ["x", "y"]);
+    assert_eq!(discover(2, Some(2)), ["y"]);
+    assert_eq!(discover(0, Some(3)), ["x", "y", "z"]);
+}
+
+#[test]
+fn test_stream_size_classes() {
+    let mut prefix = CommonPrefix::new("stream_size_classes/");
+    let [a, b, c, d] = [
+        ("a", b"ab" as &[u8]),
+        ("b", b"ab"),
+        ("c", b"abc"),
+        ("d", b"abd"),
+    ]
+    .map(|(name, content)| prefix.create_file(name, content).1);
+    let single = prefix.create_file("single", b"abcd").1;
+    let budget = MemoryBudget::new(1 << 20);
+    for file in [&a, &b, &c, &d, &single] {
+        budget.charge(MemoryBudget::file_cost(file));
+        budget.start_work();
+    }
+    let kept_cost: usize = [&a, &b, &c, &d].map(MemoryBudget::file_cost).iter().sum();
+    let stop = StopSignal::new(None, None);
+    let progress = crate::util::ProgressLog::new(None, None);
+    let shared = crate::WorkerShared {
+        budget: Some(&budget),
+        progress: Some(&progress),
+        ..worker_shared(&stop)
+    };
+
+    // the sizes which only one file has are dropped and release their budget
+    let (classes, spilled) =
+        crate::size_classes([&single, &d, &a, &c, &b].map(Clone::clone), shared);
+    assert_eq!(classes, [vec![a.clone(), b.clone()], vec![d, c]]);
+    assert!(spilled.batches(usize::MAX).is_empty());
+    assert_eq!(budget.used(), kept_cost);
+    // only the files which will be hashed count into the progress
+    assert_eq!(progress.live_line(), "hashed 0/4 files, 0 B (0%)");
+
+    let (classes_send, classes_rev) = flume::unbounded();
+    for class in classes {
+        classes_send.send(class).unwrap();
+    }
+    drop(classes_send);
+    let sets = std::sync::Mutex::new(Vec::new());
+    let place = |_, (_, set): (u128, Vec<HashedFile>)| {
+        let set = set.into_iter().map(|file| file.file_path);
+        sets.lock().unwrap().push(set.collect::<Vec<_>>());
+        true
+    };
+    let refiners = FileSetRefiners::new(Box::new([]), RefinerLogic::And);
+    let shared = crate::WorkerShared {
+        progress: Some(&progress),
+        ..worker_shared(&stop)
+    };
+    crate::hash_size_classes(refiners, classes_rev, &place, shared);
+    assert!(progress
+        .live_line()
+        .starts_with("hashed 4/4 files, 10 B (100%"));
+    assert_eq!(sets.into_inner().unwrap(), [vec![a, b]]);
+}
+
+#[test]
+fn test_spilled_size_classes() {
+    let mut prefix = CommonPrefix::new("spilled_size_classes/");
+    let [a, b, single, other, another] = [
+        ("a", b"ab" as &[u8]),
+        ("b", b"ab"),
+        ("single", b"abc"),
+        ("other", b"abcd"),
+        ("another", b"abce"),
+    ]
+    .map(|(name, content)| prefix.create_file(name, content).1);
+    let files = [&a, &b, &single, &other, &another].map(Clone::clone);
+    let budget = MemoryBudget::new(1);
+    // as if discovered while nothing was in flight yet
+    for file in &files {
+        budget.charge(MemoryBudget::file_cost(file));
+    }
+    for _ in &files {
+        budget.start_work();
+    }
+    let stop = StopSignal::new(None, None);
+    let shared = crate::WorkerShared {
+        budget: Some(&budget),
+        ..worker_shared(&stop)
+    };
+
+    // every class is spilled as soon as the budget is exceeded and releases its files
+    let (classes, spilled) = crate::size_classes(files, shared);
+    assert!(classes.is_empty());
+    assert_eq!([2, 3, 4].map(|size| spilled.files(size)), [2, 1, 2]);
+    assert_eq!(budget.used(), 0);
+
+    let sets = std::sync::Mutex::new(Vec::new());
+    let place = |_, (_, set): (u128, Vec<HashedFile>)| {
+        let set = set.into_iter().map(|file| file.file_path);
+        sets.lock().unwrap().push(set.collect::<Vec<_>>());
+        true
+    };
+    let refiners = FileSetRefiners::new(Box::new([]), RefinerLogic::And);
+    let threads = std::num::NonZeroU32::new(2).unwrap();
+    crate::hash_classes(classes, spilled, &refiners, threads, shared, &place);
+    assert_eq!(sets.into_inner().unwrap(), [vec![a.clone(), b.clone()]]);
+    // the files of the placed set stay charged until the set is consumed
+    let set_cost: usize = [&a, &b].map(MemoryBudget::file_cost).iter().sum();
+    assert_eq!(budget.used(), set_cost);
+}
+
+#[test]
+fn test_lost_spilled_classes() {
+    let mut prefix = CommonPrefix::new("lost_spilled_classes/");
+    let files = [
+        ("a", b"ab" as &[u8]),
+        ("b", b"ab"),
+        ("c", b"cd"),
+        ("d", b"abc"),
+        ("e", b"abc"),
+    ]
+    .map(|(name, content)| prefix.create_file(name, content).1);
+    let budget = MemoryBudget::new(1);
+    for file in &files {
+        budget.charge(MemoryBudget::file_cost(file));
+    }
+    let stop = StopSignal::new(None, None);
+    let shared = crate::WorkerShared {
+        budget: Some(&budget),
+        ..worker_shared(&stop)
+    };
+    let (classes, spilled) = crate::size_classes(files, shared);
+    std::fs::remove_file(spilled.path()).unwrap();
+
+    // the files of every class which cannot be read back are counted as skipped
+    let refiners = FileSetRefiners::new(Box::new([]), RefinerLogic::And);
+    let threads = std::num::NonZeroU32::new(1).unwrap();
+    crate::hash_classes(classes, spilled, &refiners, threads, shared, &|_, _| true);
+    assert_eq!(budget.dropped(), 5);
+    assert_eq!(budget.used(), 0);
+}
+
+#[test]
+fn test_stopped_run() {
+    let mut prefix = CommonPrefix::new("stopped_run/");
+    let a_file = prefix.create_file("a", b"abc");
+    let b_file = prefix.create_file("b", b"abc");
+    let c_file = prefix.create_file("c", b"abc");
+    let (a, b) = (a_file.1.to_push_buf(), b_file.1.to_push_buf());
+    let stop = StopSignal::new(None, None);
+    let discover = |stop: &StopSignal| {
+        let (s, r) = flume::unbounded();
+        DiscoveringInputSource::new(
+            true,
+            false,
+            SymlinkTreatment::Skip,
+            vec![LinkedPath::from_path_buf("test_files/stopped_run".as_ref())],
+            FileFilter(Box::new([]), Box::new([])),
+        )
+        .consume_all(&mut ChannelInputSink::new(s), stop)
+        .unwrap();
+        r.iter().count()
+    };
+    let mut equals = FileContentEquals::default();
+    assert_eq!(discover(&stop), 3);
+    assert!(equals.check_equal(&a, &b, &stop).unwrap());
+    assert!(crate::hash_file(
+        &a,
+        crate::HashAlgorithm::Xxh3,
+        crate::HashScope::Full,
+        &stop
+    )
+    .is_ok());
+
+    stop.stop(crate::util::StopReason::Interrupted);
+    assert_eq!(discover(&stop), 0);
+    // a stopped comparison cannot tell whether the files are equal
+    assert_eq!(
+        equals.check_equal(&a, &b, &stop),
+        Err(CheckEqualsErrorOn::Stopped)
+    );
+    // a set whose files were not all compared is left out instead of split
+    let stop = Arc::new(stop);
+    let refiners = FileSetRefiners::new(
+        Box::new([Box::<FileContentEquals>::default()]),
+        RefinerLogic::And,
+    )
+    .with_stop(stop.clone());
+    let set = gather_hashed_files(&[&a_file, &b_file, &c_file]);
+    // a set of more files than threads is compared in parallel
+    for threads in [4, 2] {
+        let threads = std::num::NonZeroU32::new(threads).unwrap();
+        let sets = [(0, set.clone())].into_iter();
+        assert!(crate::refine_file_sets(sets, &refiners, threads).is_empty());
+    }
+    let hashed = crate::hash_file(
+        &a,
+        crate::HashAlgorithm::Xxh3,
+        crate::HashScope::Full,
+        &stop,
+    );
+    assert!(matches!(hashed, Err(crate::HashFileError::Stopped)));
+}
+
+#[test]
+fn test_hash_algorithm_sets() {
+    use crate::{HashAlgorithm, HashScope};
+
+    let mut prefix = CommonPrefix::new("hash_algorithm_sets/");
+    let a = prefix.create_file("a", b"abc").1.to_push_buf();
+    prefix.create_file("b", b"abc");
+    prefix.create_file("c", b"abd");
+    let never = StopSignal::new(None, None);
+    let Ok((hash, _, _)) = crate::hash_file(&a, HashAlgorithm::Sha256, HashScope::Full, &never)
+    else {
+        panic!("cannot hash {}", a.display());
+    };
+    assert_eq!(hash, 0xba78_16bf_8f01_cfea_4141_40de_5dae_2223);
+
+    for algorithm in [
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Crc32,
+    ] {
+        let config = crate::Config::new()
+            .dir("test_files/hash_algorithm_sets")
+            .unwrap()
+            .hash_algorithm(algorithm)
+            .compare_content(false);
+        let sets = crate::find_duplicates(config).collect::<Vec<_>>();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].files.len(), 2);
+        assert_eq!(
+            sets[0].files[0].content_hash,
+            crate::hash_content(&a, algorithm).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_sample_hash() {
+    use crate::HashScope;
+
+    let mut prefix = CommonPrefix::new("sample_hash/");
+    let content = (0..64).collect::<Vec<u8>>();
+    let mut changed_at = |name: &str, i: usize| {
+        let mut changed = content.clone();
+        changed[i] ^= 0xff;
+        prefix.create_file(name, &changed).1.to_push_buf()
+    };
+    let middle = changed_at("middle", 30);
+    let end = changed_at("end", 62);
+    let original = prefix.create_file("original", &content).1.to_push_buf();
+    let stop = StopSignal::new(None, None);
+    let hash = |path: &PathBuf, scope| {
+        let Ok((hash, _, hashed_bytes)) =
+            crate::hash_file(path, crate::HashAlgorithm::Xxh3, scope, &stop)
+        else {
+            panic!("cannot hash {}", path.display());
+        };
+        (hash, hashed_bytes)
+    };
+
+    // the first and the last 4 bytes
+    let sampled = HashScope::Sampled {
+        blocks: 2,
+        block_size: 4,
+    };
+    assert_eq!(hash(&original, sampled).1, 8);
+    assert_eq!(hash(&original, sampled), hash(&middle, sampled));
+    assert_ne!(hash(&original, sampled), hash(&end, sampled));
+    // the blocks cover the entire file
+    let covering = HashScope::Sampled {
+        blocks: 4,
+        block_size: 16,
+    };
+    assert_eq!(hash(&original, covering), hash(&original, HashScope::Full));
+    assert_eq!(crate::sample_offset(2, 5, 4, 64), 30);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_file_timeout() {
+    use crate::error_handling::ErrorSummary;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::time::{Duration, Instant};
+
+    let mut prefix = CommonPrefix::new("file_timeout/");
+    let (_, file) = prefix.create_file("file", b"abc");
+    // opening a fifo for reading blocks until there is a writer, like a read from a hung mount
+    let fifo = PathBuf::from("test_files/file_timeout/fifo");
+    let _ = std::fs::remove_file(&fifo);
+    let fifo_name = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_name.as_ptr(), 0o600) }, 0);
+
+    let stop = StopSignal::new(None, None);
+    let errors = ErrorSummary::new();
+    // a single thread, which is stuck on the fifo afterwards
+    let pool = crate::FileTimeoutPool::new(std::num::NonZeroU32::MIN);
+    let shared = crate::WorkerShared {
+        errors: &errors,
+        file_timeout: Some((Duration::from_millis(200), &pool)),
+        ..worker_shared(&stop)
+    };
+    let mut sets = Vec::new();
+    let started = Instant::now();
+    let mut place = |path: LinkedPath| {
+        crate::place_into_file_set(
+            path.clone(),
+            &path.to_push_buf(),
+            &mut PathBuf::new(),
+            &mut FileSetRefiners::new(Box::new([]), RefinerLogic::And),
+            None,
+            shared,
+            |_| &mut sets,
+        )
+    };
+    assert!(place(file.clone()).is_ok());
+    assert!(place((*LinkedPath::from_path_buf(&fifo)).clone()).is_err());
+    // the file waits for the stuck thread until it times out as well
+    assert!(place(file).is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(errors.timed_out(), 2);
+    assert_eq!(sets.len(), 1);
+
+    // lets the stuck thread finish
+    let _ = std::fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(&fifo);
+    std::fs::remove_file(&fifo).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_skip_unreadable() {
+    use crate::error_handling::ErrorSummary;
+    use crate::file_filters::ReadableFileFilter;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut prefix = CommonPrefix::new("skip_unreadable/");
+    let readable = prefix.create_file("readable", b"abc").1;
+    let unreadable = prefix.create_file("unreadable", b"abc").1;
+    let unreadable_path = unreadable.to_push_buf();
+    std::fs::set_permissions(&unreadable_path, std::fs::Permissions::from_mode(0o200)).unwrap();
+
+    let errors = Arc::new(ErrorSummary::new());
+    let mut filter = FileFilter(
+        Box::new([]),
+        Box::new([Box::new(ReadableFileFilter::new(errors.clone()))]),
+    );
+    assert!(filter.keep_file(&readable, &readable.to_push_buf()));
+    // root reads any file regardless of its permissions
+    if unsafe { libc::geteuid() } != 0 {
+        assert!(!filter.keep_file(&unreadable, &unreadable_path));
+        assert_eq!(errors.unreadable(), 1);
+    }
+    std::fs::set_permissions(&unreadable_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ownership_policy() {
+    use crate::file_action::{OwnershipGuard, OwnershipPolicy};
+
+    let mut prefix = CommonPrefix::new("ownership_policy/");
+    let original = prefix.create_file("original", b"abc").1.to_push_buf();
+    let same_owner = prefix.create_file("same_owner", b"abc").1.to_push_buf();
+    let other_owner = prefix.create_file("other_owner", b"abc").1.to_push_buf();
+
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        same_owner.clone(),
+        Some(original.clone()),
+    )]));
+    let mut skip = OwnershipGuard::new(OwnershipPolicy::Skip, Box::new(expected));
+    assert!(skip.consume(&same_owner, Some(&original)).is_ok());
+    drop(skip);
+
+    // only root can give the file to another user
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    std::os::unix::fs::chown(&other_owner, Some(65534), None).unwrap();
+    let mut skip = OwnershipGuard::new(OwnershipPolicy::Skip, Box::new(UnreachableFileConsumer));
+    assert!(skip.consume(&other_owner, Some(&original)).is_err());
+    let expected = ExpectingConsumeAction(HashSet::from([(
+        other_owner.clone(),
+        Some(original.clone()),
+    )]));
+    let mut warn = OwnershipGuard::new(OwnershipPolicy::Warn, Box::new(expected));
+    assert!(warn.consume(&other_owner, Some(&original)).is_ok());
+}
+
+#[test]
+fn test_device_ids() {
+    use crate::file_set_refiner::DeviceEquals;
+
+    let mut prefix = CommonPrefix::new("device_ids/");
+    let file1 = prefix.create_file("file1", b"abc");
+    let file2 = prefix.create_file("file2", b"abc");
+    let file1p = file1.1.to_push_buf().canonicalize().unwrap();
+    let file2p = file2.1.to_push_buf().canonicalize().unwrap();
+    let device = crate::os::file_device(&file1p).unwrap();
+    let never = StopSignal::new(None, None);
+    assert!(DeviceEquals.check_equal(&file1p, &file2p, &never).unwrap());
+
+    let mut target = Vec::new();
+    let mut mreadable = MachineReadableEach::new(&mut target).with_device_ids();
+    mreadable
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    let expected = format!(
+        "{},{device},{},{device}",
+        file1p.display(),
+        file2p.display()
+    );
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+
+    let mut target = Vec::new();
+    let mut mreadable = MachineReadableSet::new(&mut target).with_device_ids();
+    mreadable
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+}
+
+#[test]
+fn test_file_details() {
+    use crate::util::{format_timestamp, SizeFormat};
+    use std::time::{Duration, SystemTime};
+
+    let mut prefix = CommonPrefix::new("file_details/");
+    let file1 = prefix.create_file("file1", b"abc");
+    let file2 = prefix.create_file("file2", b"abc");
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    file1.0.set_modified(modified).unwrap();
+    file2.0.set_modified(modified).unwrap();
+    let file1p = file1.1.to_push_buf().canonicalize().unwrap();
+    let file2p = file2.1.to_push_buf().canonicalize().unwrap();
+    assert_eq!(format_timestamp(modified), "2001-09-09T01:46:40Z");
+
+    let mut target = Vec::new();
+    let mut mreadable = MachineReadableEach::new(&mut target).with_file_details(SizeFormat::Human);
+    mreadable
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    let expected = format!(
+        "{},3,2001-09-09T01:46:40Z,{},3,2001-09-09T01:46:40Z",
+        file1p.display(),
+        file2p.display()
+    );
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+
+    let mut target = Vec::new();
+    let mut mreadable = MachineReadableSet::new(&mut target).with_file_details(SizeFormat::Both);
+    mreadable
+        .consume_set(duplicate_set(0, gather_hashed_files(&[&file1, &file2])))
+        .unwrap();
+    let expected = format!(
+        "{},3,3 B,2001-09-09T01:46:40Z,{},3,3 B,2001-09-09T01:46:40Z",
+        file1p.display(),
+        file2p.display()
+    );
+    assert_eq!(String::from_utf8(target).unwrap(), expected);
+}
+
+#[test]
+fn test_compare_report() {
+    use crate::compare_report::{read_set_report, CompareReport};
+    use std::collections::BTreeSet;
+
+    let mut prefix = CommonPrefix::new("compare_report/");
+    let kept = prefix.create_file("kept1", b"abc");
+    let kept2 = prefix.create_file("kept2", b"abc");
+    let new = prefix.create_file("new1", b"abcd");
+    let new2 = prefix.create_file("new2", b"abcd");
+    let resolved = prefix.create_file("resolved", b"ab").1.to_push_buf();
+    let canonical = |file: &CreateFileRet| file.1.to_push_buf().canonicalize().unwrap();
+    let kept_hash = gather_hashed_files(&[&kept])[0].content_hash;
+    let new_hash = gather_hashed_files(&[&new])[0].content_hash;
+    let resolved = resolved.canonicalize().unwrap();
+    let previous = BTreeSet::from([
+        (kept_hash, vec![canonical(&kept), canonical(&kept2)]),
+        // the same content with another file is another set
+        (new_hash, vec![canonical(&new)]),
+        (
+            7,
+            vec![resolved.clone(), resolved.clone(), resolved.clone()],
+        ),
+    ]);
+
+    let mut target = Vec::new();
+    let mut report = CompareReport::new(previous, &mut target);
+    // the paths are compared sorted
+    report
+        .consume_set(duplicate_set(1, gather_hashed_files(&[&kept2, &kept])))
+        .unwrap();
+    report
+        .consume_set(duplicate_set(3, gather_hashed_files(&[&new, &new2])))
+        .unwrap();
+    report.finish().unwrap();
+    drop(report);
+
+    let written = serde_json::from_slice::<serde_json::Value>(&target).unwrap();
+    let set_paths = |sets: &serde_json::Value| {
+        sets.as_array()
+            .unwrap()
+            .iter()
+            .map(|set| set["paths"].as_array().unwrap().len())
+            .collect::<BTreeSet<_>>()
+    };
+    assert_eq!(set_paths(&written["new"]), BTreeSet::from([2]));
+    assert_eq!(set_paths(&written["resolved"]), BTreeSet::from([1, 3]));
+    assert_eq!(written["reclaimable"]["before"], 7);
+    assert_eq!(written["reclaimable"]["now"], 7);
+    // the report is read back by the next run
+    let current = read_set_report(target.as_slice()).unwrap();
+    assert_eq!(
+        current,
+        BTreeSet::from([
+            (kept_hash, vec![canonical(&kept), canonical(&kept2)]),
+            (new_hash, vec![canonical(&new), canonical(&new2)]),
+        ])
+    );
+}
+
+#[test]
+fn test_hash_cache() {
+    use crate::hash_cache::HashCache;
+
+    let mut prefix = CommonPrefix::new("hash_cache/");
+    let (_, file) = prefix.create_file("file", b"abc");
+    let cache_path = PathBuf::from("test_files/hash_cache/cache");
+    let _ = std::fs::remove_file(&cache_path);
+    let file_path = file.to_push_buf();
+    let metadata = std::fs::metadata(&file_path).unwrap();
+    let modified = metadata.modified().unwrap();
+
+    let cache = HashCache::open(&cache_path, crate::HashAlgorithm::Xxh3.name()).unwrap();
+    cache.insert(
+        &file_path,
+        metadata.len(),
+        modified,
+        crate::HashScope::Full,
+        42,
+    );
+    cache.save().unwrap();
+    let cache = HashCache::open(&cache_path, crate::HashAlgorithm::Xxh3.name()).unwrap();
+
+    let stop = StopSignal::new(None, None);
+    let shared = crate::WorkerShared {
+        cache: Some(&cache),
+        ..worker_shared(&stop)
+    };
+    let place = |path: LinkedPath| {
+        let mut sets = Vec::new();
+        crate::place_into_file_set(
+            path.clone(),
+            &path.to_push_buf(),
+            &mut PathBuf::new(),
+            &mut FileSetRefiners::new(Box::new([]), RefinerLogic::And),
+            None,
+            shared,
+            |_| &mut sets,
+        )
+        .unwrap();
+        sets[0].0
+    };
+    // the cached hash is taken as long as the file does not change
+    assert_eq!(place(file.clone()), 42);
+    assert_eq!(
+        cache.get(
+            &file_path,
+            metadata.len(),
+            modified,
+            crate::HashScope::Prefix(3)
+        ),
+        None
+    );
+    // the hashes of another algorithm are not reused
+    let other_cache = HashCache::open(&cache_path, "other-128").unwrap();
+    assert_eq!(
+        other_cache.get(&file_path, metadata.len(), modified, crate::HashScope::Full),
+        None
+    );
+
+    create_file(&"hash_cache/file", b"abcd");
+    let changed = place(file.clone());
+    assert_ne!(changed, 42);
+    let metadata = std::fs::metadata(&file_path).unwrap();
+    assert_eq!(
+        cache.get(
+            &file_path,
+            metadata.len(),
+            metadata.modified().unwrap(),
+            crate::HashScope::Full
+        ),
+        Some(changed)
+    );
+    std::fs::remove_file(&cache_path).unwrap();
+}
+
+#[test]
+fn test_progress_estimate() {
+    use crate::util::progress_estimate;
+    use crate::HashScope;
+    use std::time::Duration;
+
+    assert_eq!(HashScope::Full.hashed_bytes(1000), 1000);
+    assert_eq!(HashScope::Prefix(100).hashed_bytes(1000), 100);
+    assert_eq!(HashScope::Prefix(100).hashed_bytes(10), 10);
+    let sampled = HashScope::Sampled {
+        blocks: 4,
+        block_size: 100,
+    };
+    assert_eq!(sampled.hashed_bytes(1000), 400);
+    assert_eq!(sampled.hashed_bytes(300), 300);
+
+    assert_eq!(progress_estimate(0, 0, 10), None);
+    // one large file left dominates the estimate, not the count of files
+    assert_eq!(
+        progress_estimate(1000, 101_000, 1000),
+        Some((0, Some(Duration::from_secs(100))))
+    );
+    assert_eq!(progress_estimate(500, 1000, 0), Some((50, None)));
+    assert_eq!(
+        progress_estimate(1200, 1000, 10),
+        Some((100, Some(Duration::ZERO)))
+    );
+}
+
+#[test]
+fn test_live_progress_line() {
+    use crate::util::ProgressLog;
+
+    let progress = ProgressLog::new(None, None).with_live(true);
+    assert_eq!(progress.live_line(), "hashed 0/0 files, 0 B");
+    progress.file_discovered(1000);
+    progress.file_discovered(3000);
+    progress.file_hashed(1000);
+    assert!(progress
+        .live_line()
+        .starts_with("hashed 1/2 files, 1000 B (25%, "));
+    progress.file_cached(3000);
+    assert!(progress
+        .live_line()
+        .starts_with("hashed 2/2 files, 1000 B (100%, "));
+}
+
+#[test]
+fn test_status_line_writer() {
+    use crate::util::StatusLineWriter;
+    use std::io::Write;
+
+    let mut prefix = CommonPrefix::new("status_line_writer/");
+    let (file, path) = prefix.create_file_auto(b"");
+    let mut status = StatusLineWriter::new(file);
+    status.set_line(Some(String::from("line 1")));
+    status.write_all(b"record 1\n").unwrap();
+    // the line waits until the record is written in full
+    status.write_all(b"rec").unwrap();
+    status.set_line(Some(String::from("line 2")));
+    status.write_all(b"ord 2\n").unwrap();
+    status.set_line(None);
+    assert_eq!(
+        std::fs::read_to_string(path.to_push_buf()).unwrap(),
+        "\x1b[Kline 1\r\x1b[Krecord 1\n\x1b[Kline 1\r\x1b[Krecord 2\n\x1b[Kline 2\r\x1b[K"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_preserve_dir_mtime() {
+    use crate::file_action::{DeleteFileAction, DirMtimePreserver};
+    use std::time::{Duration, SystemTime};
+
+    let mut prefix = CommonPrefix::new("preserve_dir_mtime/");
+    let original = prefix.create_file("original", b"abc").1.to_push_buf();
+    let duplicate = prefix.create_file("duplicate", b"abc").1.to_push_buf();
+    let dir = duplicate.parent().unwrap().to_path_buf();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    std::fs::File::open(&dir)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    let mut action = DirMtimePreserver::new(Box::<DeleteFileAction>::default());
+    let mut clone = action.try_clone().unwrap();
+    assert!(action.consume(&duplicate, Some(&original)).is_ok());
+    assert!(!duplicate.exists());
+    assert_eq!(dir.metadata().unwrap().modified().unwrap(), modified);
+    // a failed action restores the time as well
+    assert!(clone.consume(&duplicate, Some(&original)).is_err());
+    assert_eq!(dir.metadata().unwrap().modified().unwrap(), modified);
+}
+
+#[test]
+fn test_reflink_action() {
+    use crate::file_action::ReplaceWithReflinkFileAction;
+    use std::time::{Duration, SystemTime};
+
+    let mut prefix = CommonPrefix::new("reflink_action/");
+    let original = prefix.create_file("original", b"abc").1.to_push_buf();
+    let duplicate = prefix.create_file("duplicate", b"abc").1.to_push_buf();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    std::fs::File::options()
+        .write(true)
+        .open(&duplicate)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    let mut action = ReplaceWithReflinkFileAction::default();
+    let replaced = action.consume(&duplicate, Some(&original));
+    assert_eq!(std::fs::read(&duplicate).unwrap(), b"abc");
+    // the clone is a file of its own with the metadata of the duplicate; a file system which cannot share
+    // the blocks leaves the duplicate as it was
+    assert_eq!(duplicate.metadata().unwrap().modified().unwrap(), modified);
+    assert_ne!(
+        crate::os::link_identity(&duplicate),
+        crate::os::link_identity(&original)
+    );
+    assert!(replaced.is_err() || crate::os::SUPPORTS_REFLINK);
+    let leftovers = std::fs::read_dir("test_files/reflink_action")
+        .unwrap()
+        .count();
+    assert_eq!(leftovers, 2);
+}
+
+#[test]
+fn test_prefix_buckets() {
+    let mut prefix = CommonPrefix::new("prefix_buckets/");
+    let a1 = prefix.create_file("a1", b"abcd1").1;
+    let a2 = prefix.create_file("a2", b"abcd2").1;
+    let a3 = prefix.create_file("a3", b"abcd1").1;
+    let alone = prefix.create_file("alone", b"xyz12").1;
+
+    let stop = StopSignal::new(None, None);
+    let shared = crate::WorkerShared {
+        hash_scope: crate::HashScope::PrefixBuckets(4),
+        ..worker_shared(&stop)
+    };
+    let mut buckets = std::collections::HashMap::<u128, Vec<(u128, Vec<HashedFile>)>>::new();
+    for file in [&a1, &alone, &a2, &a3] {
+        crate::place_into_file_set(
+            file.clone(),
+            &file.to_push_buf(),
+            &mut PathBuf::new(),
+            &mut FileSetRefiners::new(Box::new([]), RefinerLogic::And),
+            None,
+            shared,
+            |key| buckets.entry(key).or_default(),
+        )
+        .unwrap();
+    }
+    assert_eq!(buckets.len(), 2);
+    let full_hash = |file: &LinkedPath| {
+        crate::hash_content(&file.to_push_buf(), crate::HashAlgorithm::Xxh3).unwrap()
+    };
+    let abcd = buckets.values().find(|bucket| bucket.len() == 2).unwrap();
+    for (set_hash, set) in abcd {
+        for file in set {
+            assert_eq!(*set_hash, full_hash(&file.file_path));
+            assert_eq!(file.content_hash, *set_hash);
+        }
+    }
+    let pair = abcd.iter().find(|(_, set)| set.len() == 2).unwrap();
+    assert_eq!(
+        pair.1
+            .iter()
+            .map(|file| &file.file_path)
+            .collect::<Vec<_>>(),
+        vec![&a1, &a3]
+    );
+    // the file alone in its bucket keeps the hash of its prefix
+    let lone = buckets.values().find(|bucket| bucket.len() == 1).unwrap();
+    assert_ne!(lone[0].0, full_hash(&alone));
+}
+
+#[test]
+fn test_find_duplicates() {
+    use crate::set_order::NameAlphabeticSetOrder;
+    use crate::{find_duplicates, Config};
+
+    let mut prefix = CommonPrefix::new("find_duplicates/");
+    let b = prefix.create_file("b", b"abc").1.to_push_buf();
+    let a = prefix.create_file("a", b"abc").1.to_push_buf();
+    prefix.create_file("sub/c", b"abc");
+    prefix.create_file("other", b"xyz");
+
+    let config = Config::new()
+        .dir("test_files/find_duplicates")
+        .unwrap()
+        .order(Box::new(NameAlphabeticSetOrder::new(false)));
+    let sets = find_duplicates(config).collect::<Vec<_>>();
+    assert_eq!(sets.len(), 1);
+    let expected = [a, b].map(|file| file.canonicalize().unwrap());
+    assert_eq!(sets[0].paths(), expected);
+    assert_eq!(sets[0].provenance, SetProvenance::Content);
+    assert_eq!(
+        sets[0].original().unwrap().file_path().to_push_buf(),
+        expected[0]
+    );
+    assert_eq!(sets[0].total_size(), Some(6));
+}
+
+#[test]
+fn test_simulation() {
+    use crate::simulate::Simulation;
+
+    let manifest = PathBuf::from("test_files/simulation_manifest");
+    let mut content = Vec::new();
+    write_manifest_header(&mut content, crate::HashAlgorithm::Xxh3.name()).unwrap();
+    let files = [
+        (1, "/a/x.txt"),
+        (2, "/b/w.txt"),
+        (1, "/a/z.log"),
+        (3, "/c/u.txt"),
+        (1, "/a/y.txt"),
+        (2, "/b/v.log"),
+    ];
+    for (hash, file) in files {
+        write_manifest_line(&mut content, hash, file.as_ref()).unwrap();
+    }
+    std::fs::write(&manifest, content).unwrap();
+
+    let simulate = || {
+        let txt_only = ExtensionFilter::new(HashSet::from([OsString::from("txt")]), false, true);
+        let file_filter = FileFilter(Box::new([Box::new(txt_only)]), Box::new([]));
+        Simulation::new(manifest.clone(), file_filter)
+            .sets()
+            .unwrap()
+    };
+    let sets = simulate();
+    assert_eq!(sets.len(), 1);
+    assert_eq!(sets[0].provenance, SetProvenance::Manifest);
+    assert_eq!(sets[0].total_size(), None);
+    assert!(sets[0].files.iter().all(|file| file.content_hash == 1));
+    let mut names = sets[0].paths();
+    names.sort();
+    assert_eq!(
+        names,
+        [PathBuf::from("/a/x.txt"), PathBuf::from("/a/y.txt")]
+    );
+    assert_eq!(simulate()[0].id, sets[0].id);
+}
+
+#[test]
+fn test_apply_plan() {
+    use crate::plan::{apply_plan, PlanLine, PLAN_HASH_ALGORITHM, PLAN_HEADER};
+
+    let mut prefix = CommonPrefix::new("apply_plan_");
+    let original = prefix.create_file_auto(b"ab").1.to_push_buf();
+    let duplicate = prefix.create_file_auto(b"ab").1.to_push_buf();
+    let line = |action| PlanLine {
+        action,
+        hash: crate::hash_content(&original, PLAN_HASH_ALGORITHM).unwrap(),
+        file: duplicate.to_str().unwrap(),
+        original: original.to_str().unwrap(),
+    };
+    let plan_path = PathBuf::from("test_files/apply_plan");
+    let mut plan = PLAN_HEADER.as_bytes().to_vec();
+    line("check").write_to(&mut plan).unwrap();
+    std::fs::write(&plan_path, &plan).unwrap();
+    let apply = || {
+        let expected = HashSet::from([(duplicate.clone(), Some(original.clone()))]);
+        let action: Box<dyn FileConsumeAction + Send> = Box::new(ExpectingConsumeAction(expected));
+        apply_plan(&plan_path, &mut [("check", action)])
+    };
+    assert!(apply().is_ok());
+
+    // the other lines are still executed, but a mangled plan does not succeed
+    plan.extend_from_slice(b"check\tnot a hash\t/a\t/b\n");
+    line("unknown").write_to(&mut plan).unwrap();
+    std::fs::write(&plan_path, &plan).unwrap();
+    assert!(apply().is_err());
+    std::fs::remove_file(&plan_path).unwrap();
+    assert!(apply_plan(&plan_path, &mut []).is_err());
+}
+
+#[test]
+fn test_log_timestamps() {
+    use crate::logger::{DuplisLogger, LogTimestamps};
+    use log::Log;
+
+    let mut prefix = CommonPrefix::new("log_timestamps/");
+    let mut logged = |timestamps| {
+        let (file, path) = prefix.create_file_auto(b"");
+        let logger = DuplisLogger::new(Vec::new(), log::LevelFilter::Trace, Box::new(file))
+            .with_timestamps(timestamps);
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("target")
+                .args(format_args!("message"))
+                .build(),
+        );
+        logger.flush();
+        std::fs::read_to_string(path.to_push_buf()).unwrap()
+    };
+    assert_eq!(logged(None), "[WARN](target): message\n");
+    let elapsed = logged(Some(LogTimestamps::Elapsed));
+    assert!(elapsed.starts_with("[+0."));
+    assert!(elapsed.ends_with("s][WARN](target): message\n"));
+    let absolute = logged(Some(LogTimestamps::Absolute));
+    // like [2023-01-31T12:30:15Z]
+    assert_eq!(absolute.find("Z]"), Some(20));
+    assert!(absolute.ends_with("Z][WARN](target): message\n"));
+}
+
+#[test]
+fn test_log_rate_limit() {
+    use crate::logger::DuplisLogger;
+    use log::Log;
+
+    let mut prefix = CommonPrefix::new("log_rate_limit/");
+    let (file, path) = prefix.create_file_auto(b"");
+    let logger = DuplisLogger::new(Vec::new(), log::LevelFilter::Trace, Box::new(file));
+    let log = |level, target, message: &str| {
+        logger.log(
+            &log::Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{message}"))
+                .build(),
+        );
+    };
+    for _ in 0..3 {
+        log(log::Level::Warn, "same", "message");
+    }
+    log(log::Level::Warn, "same", "other message");
+    for i in 0..25 {
+        log(log::Level::Info, "many", &format!("file {i}"));
+    }
+    // errors are never suppressed
+    log(log::Level::Error, "many", "failed");
+    logger.flush();
+
+    let output = std::fs::read_to_string(path.to_push_buf()).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[..3],
+        [
+            "[WARN](same): message",
+            "[WARN](same): the last message was repeated 2 times",
+            "[WARN](same): other message",
+        ]
+    );
+    assert_eq!(lines[3], "[INFO](many): file 0");
+    assert_eq!(lines[22], "[INFO](many): file 19");
+    assert_eq!(
+        lines[23..],
+        [
+            "[ERROR](many): failed",
+            "[INFO](many): suppressed 5 more records, at most 20 are logged per second",
+        ]
+    );
+}
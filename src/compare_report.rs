@@ -0,0 +1,164 @@
+use crate::error_handling::AlreadyReportedError;
+use crate::out_sink::ResultsOutput;
+use crate::set_consumer::{set_file_size, FileSetConsumer};
+use crate::{out_err_map, DuplicateSet};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+/// a set by the hash of its content and its sorted canonical paths, a set which gained or lost a file is
+/// another set
+pub type SetKey = (u128, Vec<PathBuf>);
+
+/// compare the sets of this run with the sets of an earlier report and write a JSON report of the sets of
+/// this run, the sets which are new, the sets which were resolved since and how the reclaimable bytes
+/// changed; the report can be compared against by the next run
+pub struct CompareReport<W> {
+    previous: BTreeSet<SetKey>,
+    /// the size of a file of each set of this run
+    current: BTreeMap<SetKey, u64>,
+    write: W,
+}
+
+fn invalid_report(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// the sets of a report written by `--compare-report`
+pub fn read_set_report(read: impl Read) -> std::io::Result<BTreeSet<SetKey>> {
+    let report: Value = serde_json::from_reader(read)?;
+    let sets = report
+        .get("sets")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid_report("the report has no list of sets"))?;
+    sets.iter()
+        .enumerate()
+        .map(|(i, set)| {
+            let hash = set
+                .get("hash")
+                .and_then(Value::as_str)
+                .and_then(|hash| u128::from_str_radix(hash, 16).ok());
+            let paths = set
+                .get("paths")
+                .and_then(Value::as_array)
+                .and_then(|paths| {
+                    paths
+                        .iter()
+                        .map(|path| path.as_str().map(PathBuf::from))
+                        .collect::<Option<Vec<_>>>()
+                });
+            match (hash, paths) {
+                (Some(hash), Some(mut paths)) => {
+                    paths.sort();
+                    Ok((hash, paths))
+                }
+                _ => Err(invalid_report(&format!(
+                    "set {} lacks the hash or the paths",
+                    i + 1
+                ))),
+            }
+        })
+        .collect()
+}
+
+impl<W> CompareReport<W> {
+    pub fn new(previous: BTreeSet<SetKey>, write: W) -> Self {
+        Self {
+            previous,
+            current: BTreeMap::new(),
+            write,
+        }
+    }
+}
+
+impl CompareReport<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput, previous: BTreeSet<SetKey>) -> Self {
+        Self::new(previous, output.writer())
+    }
+}
+
+/// the bytes which linking or deleting the duplicates of the set would free
+fn reclaimable(size: u64, files: usize) -> u64 {
+    size * (files as u64).saturating_sub(1)
+}
+
+/// the set as it is written into the report, the size is left out if it is not known
+fn set_json((hash, paths): &SetKey, size: Option<u64>) -> Value {
+    let paths = paths
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>();
+    let mut set = json!({ "hash": format!("{hash:032x}"), "paths": paths });
+    if let Some(size) = size {
+        set["size"] = json!(size);
+    }
+    set
+}
+
+impl<W: std::io::Write> FileSetConsumer for CompareReport<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let Some(size) = set_file_size(&set, &mut PathBuf::new()) else { return Ok(()) };
+        let Some(hash) = set.original().map(|file| file.content_hash) else { return Ok(()) };
+        // the canonical paths stay the same when the next run is started from another directory
+        let mut paths = set
+            .paths()
+            .into_iter()
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .collect::<Vec<_>>();
+        paths.sort();
+        self.current.insert((hash, paths), size);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        let mut now = 0;
+        let mut sets = Vec::with_capacity(self.current.len());
+        let mut new_sets = Vec::new();
+        for (set, size) in &self.current {
+            now += reclaimable(*size, set.1.len());
+            sets.push(set_json(set, Some(*size)));
+            if !self.previous.contains(set) {
+                new_sets.push(set_json(set, Some(*size)));
+            }
+        }
+        let mut before = 0;
+        // the files of the earlier sets have the size of the first which still exists
+        let mut vanished = 0;
+        let mut resolved_sets = Vec::new();
+        for set in &self.previous {
+            match set.1.iter().find_map(|path| path.metadata().ok()) {
+                Some(metadata) => before += reclaimable(metadata.len(), set.1.len()),
+                None => vanished += 1,
+            }
+            if !self.current.contains_key(set) {
+                resolved_sets.push(set_json(set, None));
+            }
+        }
+        let report = json!({
+            "sets": sets,
+            "new": new_sets,
+            "resolved": resolved_sets,
+            "reclaimable": { "before": before, "now": now },
+            // the earlier sets without any remaining file are not counted into the reclaimable bytes before
+            "uncounted": vanished,
+        });
+        serde_json::to_writer_pretty(&mut self.write, &report).map_err(out_err_map!())?;
+        writeln!(self.write).map_err(out_err_map!())?;
+        self.write.flush().map_err(out_err_map!())
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_set_report() {
+    let report = r#"{"sets": [{"hash": "ab", "size": 3, "paths": ["/b", "/a"]}, {"hash": "cd", "paths": ["/c"]}], "new": []}"#;
+    let sets = read_set_report(report.as_bytes()).unwrap();
+    let expected = BTreeSet::from([
+        (0xab, vec![PathBuf::from("/a"), PathBuf::from("/b")]),
+        (0xcd, vec![PathBuf::from("/c")]),
+    ]);
+    assert_eq!(sets, expected);
+    assert!(read_set_report("00ab,/a,/b\n".as_bytes()).is_err());
+    assert!(read_set_report(r#"{"sets": [{"paths": ["/a"]}]}"#.as_bytes()).is_err());
+}
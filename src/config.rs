@@ -0,0 +1,209 @@
+use crate::duplicate_set::DuplicateSet;
+use crate::error_handling::ErrorSummary;
+use crate::file_filters::FileFilter;
+use crate::file_set_refiner::{
+    FileContentEquals, FileEqualsChecker, FileSetRefiners, RefinerLogic,
+};
+use crate::input_source::{DiscoveringInputSource, InputSource, SymlinkTreatment};
+use crate::set_order::{SetOrder, SymlinkSetOrder};
+use crate::util::{LinkedPath, StopSignal};
+use crate::{
+    assemble_input_sink, files_channel, find_file_sets, flatten_file_sets, order_files,
+    HashAlgorithm, HashScope, WorkerShared,
+};
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::Arc;
+
+/// what `find_duplicates` searches and how, built like
+/// `Config::new().dir("photos")?.recurse(true).order(Box::new(order))`; the command line is parsed into one
+/// as well
+pub struct Config {
+    dirs: Vec<Arc<LinkedPath>>,
+    recurse: bool,
+    file_filter: Option<FileFilter>,
+    input_sources: Vec<Box<dyn InputSource>>,
+    pub(crate) orders: Vec<Box<dyn SetOrder + Send>>,
+    pub(crate) num_threads: NonZeroU32,
+    pub(crate) hash_scope: HashScope,
+    pub(crate) hash_algorithm: HashAlgorithm,
+    compare_content: bool,
+    file_equals: Vec<Box<dyn FileEqualsChecker + Send>>,
+    refiner_logic: RefinerLogic,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dirs: Vec::new(),
+            recurse: false,
+            file_filter: None,
+            input_sources: Vec::new(),
+            orders: Vec::new(),
+            num_threads: NonZeroU32::MIN,
+            hash_scope: HashScope::Full,
+            hash_algorithm: HashAlgorithm::default(),
+            compare_content: true,
+            file_equals: Vec::new(),
+            refiner_logic: RefinerLogic::And,
+        }
+    }
+}
+
+impl Config {
+    /// search nothing on a single thread, compare the files byte by byte after hashing them
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// search the files in the directory
+    /// # Errors
+    /// if the directory cannot be canonicalized
+    pub fn dir(mut self, dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref().canonicalize()?;
+        self.dirs.push(LinkedPath::from_path_buf(&dir));
+        Ok(self)
+    }
+
+    /// search the subdirectories of the directories as well
+    #[must_use]
+    pub fn recurse(mut self, recurse: bool) -> Self {
+        self.recurse = recurse;
+        self
+    }
+
+    /// only search the files of the directories the filter keeps
+    #[must_use]
+    pub fn file_filter(mut self, file_filter: FileFilter) -> Self {
+        self.file_filter = Some(file_filter);
+        self
+    }
+
+    /// search the files of the source in addition to the directories
+    #[must_use]
+    pub fn input_source(mut self, source: Box<dyn InputSource>) -> Self {
+        self.input_sources.push(source);
+        self
+    }
+
+    /// order the files of each set, the orders are applied one after another so the last order decides
+    #[must_use]
+    pub fn order(mut self, order: Box<dyn SetOrder + Send>) -> Self {
+        self.orders.push(order);
+        self
+    }
+
+    #[must_use]
+    pub fn threads(mut self, num_threads: NonZeroU32) -> Self {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// the part of each file the files are grouped by
+    #[must_use]
+    pub fn hash_scope(mut self, hash_scope: HashScope) -> Self {
+        self.hash_scope = hash_scope;
+        self
+    }
+
+    /// the algorithm the content of the files is hashed with
+    #[must_use]
+    pub fn hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// compare the files of a set byte by byte instead of trusting the hash
+    #[must_use]
+    pub fn compare_content(mut self, compare_content: bool) -> Self {
+        self.compare_content = compare_content;
+        self
+    }
+
+    /// only keep the files in a set which the checker finds equal as well
+    #[must_use]
+    pub fn file_equals(mut self, file_equals: Box<dyn FileEqualsChecker + Send>) -> Self {
+        self.file_equals.push(file_equals);
+        self
+    }
+
+    /// whether the files of a set must be equal by all checkers or by any
+    #[must_use]
+    pub fn refiner_logic(mut self, refiner_logic: RefinerLogic) -> Self {
+        self.refiner_logic = refiner_logic;
+        self
+    }
+
+    /// the input sources, the directories before the other sources
+    pub(crate) fn take_input_sources(&mut self) -> Vec<Box<dyn InputSource>> {
+        let mut input_sources = std::mem::take(&mut self.input_sources);
+        if !self.dirs.is_empty() {
+            let dirs = std::mem::take(&mut self.dirs);
+            let file_filter = self
+                .file_filter
+                .take()
+                .unwrap_or(FileFilter(Box::new([]), Box::new([])));
+            let source = DiscoveringInputSource::new(
+                self.recurse,
+                false,
+                SymlinkTreatment::Skip,
+                dirs,
+                file_filter,
+            );
+            input_sources.insert(0, Box::new(source));
+        }
+        input_sources
+    }
+
+    /// the refiners of the checkers, the content is compared after all other checkers
+    pub(crate) fn take_set_refiners(&mut self) -> FileSetRefiners {
+        let mut file_equals = std::mem::take(&mut self.file_equals);
+        if self.compare_content {
+            file_equals.push(Box::<FileContentEquals>::default());
+        }
+        FileSetRefiners::new(file_equals.into_boxed_slice(), self.refiner_logic)
+    }
+}
+
+/// find the sets of files with the same content, the original of each set first according to the orders of
+/// the config; errors are logged and the affected files left out
+pub fn find_duplicates(mut config: Config) -> impl Iterator<Item = DuplicateSet> {
+    let input_sources = config.take_input_sources();
+    let set_refiners = config.take_set_refiners();
+    let Config {
+        mut orders,
+        num_threads,
+        hash_scope,
+        hash_algorithm,
+        ..
+    } = config;
+    orders.push(Box::<SymlinkSetOrder>::default());
+
+    let stop = Arc::new(StopSignal::new(None, None));
+    let errors = ErrorSummary::new();
+    let (files_send, files_rev) = files_channel(num_threads, None, false);
+    let input_sink = assemble_input_sink(files_send, None, &stop, true, None, false);
+    let shared = WorkerShared {
+        budget: None,
+        stop: &stop,
+        errors: &errors,
+        hash_scope,
+        hash_algorithm,
+        file_timeout: None,
+        cache: None,
+        manifest: None,
+        progress: None,
+        group_hard_links: false,
+    };
+    let target = find_file_sets(
+        input_sources,
+        input_sink,
+        files_rev,
+        set_refiners,
+        num_threads,
+        shared,
+    );
+    flatten_file_sets(target, None, false)
+        .filter_map(move |mut set| order_files(&mut orders, &mut set).then_some(set))
+}
@@ -0,0 +1,165 @@
+use std::hash::Hasher;
+use xxhash_rust::xxh3::Xxh3;
+
+/// the algorithms the content of the files can be hashed with
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum HashAlgorithm {
+    /// fast, but collisions can be crafted
+    #[default]
+    Xxh3,
+    Blake3,
+    Sha256,
+    /// only 32 bits, files with the same hash are likely to differ in a large scan
+    Crc32,
+}
+
+/// hashes the content of a file, the content is fed in through `Hasher::write`
+pub trait ContentHasher: Hasher {
+    /// the hash of everything written so far, longer hashes are cut to their first 128 bits
+    fn finish128(&self) -> u128;
+}
+
+impl HashAlgorithm {
+    /// the name of the algorithm as given to `--hash`
+    #[must_use]
+    pub fn cli_name(self) -> &'static str {
+        match self {
+            Self::Xxh3 => "xxh3",
+            Self::Blake3 => "blake3",
+            Self::Sha256 => "sha256",
+            Self::Crc32 => "crc32",
+        }
+    }
+
+    /// the name recorded in manifests and hash caches, so that the hashes of different algorithms are never
+    /// compared
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Xxh3 => "xxh3-128",
+            Self::Blake3 => "blake3",
+            Self::Sha256 => "sha256",
+            Self::Crc32 => "crc32",
+        }
+    }
+
+    #[must_use]
+    pub fn from_cli_name(name: &str) -> Option<Self> {
+        [Self::Xxh3, Self::Blake3, Self::Sha256, Self::Crc32]
+            .into_iter()
+            .find(|algorithm| algorithm.cli_name() == name)
+    }
+
+    pub(crate) fn hasher(self) -> Box<dyn ContentHasher + Send> {
+        match self {
+            Self::Xxh3 => Box::<Xxh3>::default(),
+            Self::Blake3 => Box::<Blake3Hasher>::default(),
+            Self::Sha256 => Box::<Sha256Hasher>::default(),
+            Self::Crc32 => Box::<Crc32Hasher>::default(),
+        }
+    }
+}
+
+impl ContentHasher for Xxh3 {
+    fn finish128(&self) -> u128 {
+        self.digest128()
+    }
+}
+
+#[derive(Default)]
+struct Blake3Hasher(blake3::Hasher);
+
+#[derive(Default)]
+struct Sha256Hasher(sha2::Sha256);
+
+#[derive(Default)]
+struct Crc32Hasher(crc32fast::Hasher);
+
+/// the first 128 bits of the digest, in the order of the bytes so that its hex form is a prefix of the
+/// usual hex form of the digest
+fn leading_bits(digest: &[u8]) -> u128 {
+    let mut leading = [0; 16];
+    leading.copy_from_slice(&digest[..16]);
+    u128::from_be_bytes(leading)
+}
+
+impl Hasher for Blake3Hasher {
+    fn finish(&self) -> u64 {
+        u64::try_from(self.finish128() >> 64).unwrap_or_default()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+impl ContentHasher for Blake3Hasher {
+    fn finish128(&self) -> u128 {
+        leading_bits(self.0.finalize().as_bytes())
+    }
+}
+
+impl Hasher for Sha256Hasher {
+    fn finish(&self) -> u64 {
+        u64::try_from(self.finish128() >> 64).unwrap_or_default()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        sha2::Digest::update(&mut self.0, bytes);
+    }
+}
+
+impl ContentHasher for Sha256Hasher {
+    fn finish128(&self) -> u128 {
+        leading_bits(&sha2::Digest::finalize(self.0.clone()))
+    }
+}
+
+impl Hasher for Crc32Hasher {
+    fn finish(&self) -> u64 {
+        u64::from(self.0.clone().finalize())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+impl ContentHasher for Crc32Hasher {
+    fn finish128(&self) -> u128 {
+        u128::from(self.0.clone().finalize())
+    }
+}
+
+#[test]
+fn test_hash_algorithms() {
+    let hash = |algorithm: HashAlgorithm, content: &[u8]| {
+        let mut hasher = algorithm.hasher();
+        hasher.write(content);
+        hasher.finish128()
+    };
+    assert_eq!(
+        hash(HashAlgorithm::Sha256, b"abc"),
+        0xba78_16bf_8f01_cfea_4141_40de_5dae_2223
+    );
+    assert_eq!(
+        hash(HashAlgorithm::Blake3, b"abc"),
+        0x6437_b3ac_3846_5133_ffb6_3b75_273a_8db5
+    );
+    assert_eq!(hash(HashAlgorithm::Crc32, b"abc"), 0x3524_41c2);
+    assert_eq!(
+        hash(HashAlgorithm::Xxh3, b"abc"),
+        xxhash_rust::xxh3::xxh3_128(b"abc")
+    );
+    for algorithm in [
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Crc32,
+    ] {
+        assert_eq!(
+            HashAlgorithm::from_cli_name(algorithm.cli_name()),
+            Some(algorithm)
+        );
+        assert_ne!(hash(algorithm, b"abc"), hash(algorithm, b"abd"));
+    }
+}
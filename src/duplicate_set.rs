@@ -0,0 +1,87 @@
+use crate::util::path_bytes;
+use crate::HashedFile;
+use std::path::PathBuf;
+
+/// how the files of a set were found to be duplicates
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SetProvenance {
+    /// the files have the same hash and the refiners consider them equal
+    Content,
+    /// the paths link to the same file, their content was not looked at
+    HardLinks,
+    /// the files have the same hash in a manifest, the files themselves were not looked at
+    Manifest,
+}
+
+/// files with the same content; the first is the original once the set is ordered
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateSet {
+    /// stays the same across runs as long as the content and the paths of the files do not change
+    pub id: u128,
+    pub files: Vec<HashedFile>,
+    pub provenance: SetProvenance,
+}
+
+impl DuplicateSet {
+    #[must_use]
+    pub fn new(id: u128, files: Vec<HashedFile>, provenance: SetProvenance) -> Self {
+        Self {
+            id,
+            files,
+            provenance,
+        }
+    }
+
+    /// the set with an id derived from the key of its course set and the sorted paths of its files, so that the
+    /// sets a course set splits into have distinct ids; the id stays the same across runs as long as the content
+    /// and the paths of the files do
+    #[must_use]
+    pub fn from_key(key: u128, files: Vec<HashedFile>, provenance: SetProvenance) -> Self {
+        let mut paths = files
+            .iter()
+            .map(|file| file.file_path.to_push_buf())
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        let mut id = xxhash_rust::xxh3::Xxh3::default();
+        std::hash::Hasher::write_u128(&mut id, key);
+        for path in &paths {
+            // no path contains a nul byte, so the paths cannot run into each other
+            id.update(&path_bytes(path));
+            id.update(&[0]);
+        }
+        Self::new(id.digest128(), files, provenance)
+    }
+
+    /// the file the others are duplicates of, none if the set is empty
+    #[must_use]
+    pub fn original(&self) -> Option<&HashedFile> {
+        self.files.first()
+    }
+
+    /// the files apart from the original
+    #[must_use]
+    pub fn duplicates(&self) -> &[HashedFile] {
+        self.files.get(1..).unwrap_or_default()
+    }
+
+    /// the size of each file of the set as recorded while hashing, none if it was not recorded
+    #[must_use]
+    pub fn file_size(&self) -> Option<u64> {
+        self.original().and_then(HashedFile::size)
+    }
+
+    /// the size of all files together, none if the size of a file was not recorded
+    #[must_use]
+    pub fn total_size(&self) -> Option<u64> {
+        self.files.iter().map(HashedFile::size).sum()
+    }
+
+    /// the full paths of the files, in the order of the set
+    #[must_use]
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .map(|file| file.file_path.to_push_buf())
+            .collect()
+    }
+}
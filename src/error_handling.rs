@@ -1,3 +1,6 @@
+use crate::util::count_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 macro_rules! declare_log_targets {
     ($($name: ident = $value: literal;)*) => {
         $(pub static $name: &str = $value;)*
@@ -16,32 +19,45 @@ declare_log_targets! {
     ACTION_SUCCESS_TARGET = "action_success";
     DISCOVERY_ERR_TARGET = "file_discovery_err";
     FILE_ERR_TARGET = "file_error";
+    FILE_VANISHED_TARGET = "file_vanished";
+    FILE_PERMISSION_TARGET = "file_permission_err";
+    FILE_CHANGED_TARGET = "file_changed";
+    FILE_METADATA_ERR_TARGET = "file_metadata_err";
     FILE_SET_ERR_TARGET = "file_set_err";
+    FILE_TIMEOUT_TARGET = "file_timeout";
+    FILE_OWNERSHIP_TARGET = "file_ownership";
+    REFLINK_COPIED_TARGET = "reflink_copied";
+    PROGRESS_TARGET = "progress";
+    SUMMARY_TARGET = "summary";
 }
 
 #[macro_export]
 macro_rules! report_file_missing {
     ($path: expr) => {
         log::trace!(
-            target: $crate::error_handling::FILE_ERR_TARGET,
+            target: $crate::error_handling::FILE_VANISHED_TARGET,
             "file {} disappeared while being processed",
             $path.display()
         )
     };
 }
 
+/// unexpected errors are reported to `$other_target`, `FILE_ERR_TARGET` if not given
 #[macro_export]
 macro_rules! handle_file_error {
     ($file_path: expr, $err: expr) => {
+        $crate::handle_file_error!($file_path, $err, $crate::error_handling::FILE_ERR_TARGET)
+    };
+    ($file_path: expr, $err: expr, $other_target: expr) => {
         match $err.kind() {
             std::io::ErrorKind::NotFound => $crate::report_file_missing!(&$file_path),
             std::io::ErrorKind::PermissionDenied => log::info!(
-                target: $crate::error_handling::FILE_ERR_TARGET,
+                target: $crate::error_handling::FILE_PERMISSION_TARGET,
                 "cannot access file {}(permission denied)",
                 $file_path.display()
             ),
             _ => log::warn!(
-                target: $crate::error_handling::FILE_ERR_TARGET,
+                target: $other_target,
                 "unexpected error while accessing file {}: {}",
                 $file_path.display(),
                 $err
@@ -63,9 +79,27 @@ macro_rules! handle_file_op {
     };
 }
 
+/// like `handle_file_op`, but for reading the metadata of a file
+#[macro_export]
+macro_rules! handle_metadata_op {
+    ($result: expr, $file_path: expr, $handle_action: expr) => {
+        match $result {
+            Ok(result) => result,
+            Err(err) => {
+                $crate::handle_file_error!(
+                    $file_path,
+                    err,
+                    $crate::error_handling::FILE_METADATA_ERR_TARGET
+                );
+                $handle_action
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! handle_file_modified {
-    ($file_path: expr) => { log::warn!(target: $crate::error_handling::FILE_ERR_TARGET, "file {} was modified while still being processed; The file will not be processed further", $file_path.display()) };
+    ($file_path: expr) => { log::warn!(target: $crate::error_handling::FILE_CHANGED_TARGET, "file {} was modified while still being processed; The file will not be processed further", $file_path.display()) };
 }
 
 /// in case the out-stream of the printing consumers fails
@@ -98,3 +132,67 @@ macro_rules! in_err_map {
 
 #[derive(Copy, Clone, Debug)]
 pub struct AlreadyReportedError;
+
+/// counts the skipped files which are summarized once the run is done, in addition to the message per file
+#[derive(Default)]
+pub struct ErrorSummary {
+    timed_out: AtomicU64,
+    unreadable: AtomicU64,
+    /// the files dropped while hashing, including those which timed out
+    failed: AtomicU64,
+}
+
+impl ErrorSummary {
+    pub const fn new() -> Self {
+        Self {
+            timed_out: AtomicU64::new(0),
+            unreadable: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn file_timed_out(&self) {
+        self.timed_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn timed_out(&self) -> u64 {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    pub fn file_unreadable(&self) {
+        self.unreadable.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn unreadable(&self) -> u64 {
+        self.unreadable.load(Ordering::Relaxed)
+    }
+
+    pub fn file_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// the files which never reached a set, because they were unreadable or failed while hashing
+    pub fn skipped(&self) -> u64 {
+        self.unreadable() + self.failed.load(Ordering::Relaxed)
+    }
+
+    /// logs how many files were skipped, if any
+    pub fn report(&self) {
+        let unreadable = self.unreadable();
+        if unreadable > 0 {
+            log::warn!(
+                target: FILE_PERMISSION_TARGET,
+                "skipped {} which the current user cannot read",
+                count_of(unreadable, "file")
+            );
+        }
+        let timed_out = self.timed_out();
+        if timed_out > 0 {
+            log::warn!(
+                target: FILE_TIMEOUT_TARGET,
+                "skipped {} because hashing them took longer than --file-timeout",
+                count_of(timed_out, "file")
+            );
+        }
+    }
+}
@@ -1,19 +1,31 @@
 use crate::error_handling::AlreadyReportedError;
-use crate::{handle_file_op, Recoverable};
+use crate::os::{copy_owner, reflink, remove_file_guarded, rename_guarded, shares_blocks};
+use crate::{handle_file_op, handle_metadata_op, Recoverable};
+use dashmap::DashMap;
 use std::borrow::Cow;
-use std::path::Path;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 pub trait FileConsumeAction {
     /// consumes the file pointed to by `path`
     /// The `original` may be used for multiple calls of consume, so this should be kept in mind
     /// the file pointed to by `original` can be assumed to exist
+    /// # Errors
+    /// if the action failed for this file or, if fatal, for all further files; the reason is reported
     fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult;
     /// return true if this requires an original file, for example because it links to the original file
     fn requires_original(&self) -> bool;
     /// short description of this consumer like 'delete' or 'replace with hardlink'
-    fn short_name(&self) -> Cow<str>;
+    fn short_name(&self) -> Cow<'_, str>;
     /// short description of not execution this action like 'keep'
-    fn short_opposite(&self) -> Cow<str>;
+    fn short_opposite(&self) -> Cow<'_, str>;
+    /// an independent action doing the same, so that the action can run on several threads; None if it cannot
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        None
+    }
 }
 
 pub type FileConsumeResult = Result<(), Recoverable<AlreadyReportedError, AlreadyReportedError>>;
@@ -36,6 +48,37 @@ pub struct ReplaceWithHardLinkFileAction {
     // make file only constructable with new method
     _p: (),
 }
+/// replace the file with a copy-on-write clone of the 'original' file, which stays a file of its own
+#[derive(Default)]
+pub struct ReplaceWithReflinkFileAction {
+    // make file only constructable with new method
+    _p: (),
+}
+
+/// what to do if a duplicate is replaced with a link to an original owned by another user
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OwnershipPolicy {
+    /// replace it anyway, but say so
+    Warn,
+    /// keep the duplicate
+    Skip,
+}
+
+/// check the owners of the duplicate and the original before the linking action runs; once the duplicate is
+/// a link, its content is owned by the owner of the original
+pub struct OwnershipGuard {
+    policy: OwnershipPolicy,
+    inner: Box<dyn FileConsumeAction + Send>,
+}
+
+/// restore the modification time of the directory of each consumed file, so that backup tools which find
+/// changed trees by the modification times of their directories do not scan the entire tree again
+pub struct DirMtimePreserver {
+    /// the modification time of each directory before the first action in it, shared by all clones so that
+    /// an action running on another thread is not taken for the original time
+    recorded: Arc<DashMap<PathBuf, SystemTime>>,
+    inner: Box<dyn FileConsumeAction + Send>,
+}
 
 /// report a successful file action
 #[macro_export]
@@ -43,6 +86,171 @@ macro_rules! report_file_action {
     ($text: literal, $($r: expr),*) => {log::info!(target: $crate::error_handling::ACTION_SUCCESS_TARGET, $text, $($r),*)};
 }
 
+/// check that the original can be read and is a regular file, so that replacing a duplicate with a link to it
+/// does not lose the content; the reason is reported if not
+pub fn original_is_usable(original: &Path) -> bool {
+    let file = handle_file_op!(std::fs::File::open(original), original, return false);
+    let metadata = handle_metadata_op!(file.metadata(), original, return false);
+    if !metadata.is_file() {
+        log::error!(
+            target: crate::error_handling::FILE_SET_ERR_TARGET,
+            "{} is not a regular file and cannot be the original of its set",
+            original.display()
+        );
+        return false;
+    }
+    true
+}
+
+/// create a link with `create_link` under a temporary name in the directory of `path` and rename it
+/// over `path`, so that `path` is never missing; the temporary link is removed if the rename fails
+pub fn replace_with_link(
+    path: &Path,
+    mut create_link: impl FnMut(&Path) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let temp_path = loop {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(
+            ".duplis-{}-{}",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+        match create_link(&temp_path) {
+            Ok(()) => break temp_path,
+            // a leftover of an earlier run
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    };
+    let renamed = rename_guarded(&temp_path, path);
+    // renaming does nothing if both already are links to the same file, which leaves the temporary link
+    if renamed.is_err() || temp_path.symlink_metadata().is_ok() {
+        let _ = remove_file_guarded(&temp_path);
+    }
+    renamed
+}
+
+impl OwnershipGuard {
+    pub fn new(policy: OwnershipPolicy, inner: Box<dyn FileConsumeAction + Send>) -> Self {
+        Self { policy, inner }
+    }
+}
+
+impl FileConsumeAction for OwnershipGuard {
+    fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult {
+        let original_path = original.expect("original required");
+        let owner = |path: &Path| {
+            let metadata = handle_metadata_op!(
+                path.metadata(),
+                path,
+                return Err(Recoverable::Recoverable(AlreadyReportedError))
+            );
+            Ok(crate::os::file_owner(&metadata))
+        };
+        let owners = (owner(path)?, owner(original_path)?);
+        if let (Some(duplicate_owner), Some(original_owner)) = owners {
+            if duplicate_owner == original_owner {
+                return self.inner.consume(path, original);
+            }
+            let consequence = match self.policy {
+                OwnershipPolicy::Warn => "replacing it anyway",
+                OwnershipPolicy::Skip => "keeping it",
+            };
+            log::warn!(
+                target: crate::error_handling::FILE_OWNERSHIP_TARGET,
+                "{} is owned by user {duplicate_owner}, but its original {} by user {original_owner}; {consequence}",
+                path.display(),
+                original_path.display()
+            );
+            if self.policy == OwnershipPolicy::Skip {
+                return Err(Recoverable::Recoverable(AlreadyReportedError));
+            }
+        }
+        self.inner.consume(path, original)
+    }
+
+    fn requires_original(&self) -> bool {
+        true
+    }
+
+    fn short_name(&self) -> Cow<'_, str> {
+        self.inner.short_name()
+    }
+
+    fn short_opposite(&self) -> Cow<'_, str> {
+        self.inner.short_opposite()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        let inner = self.inner.try_clone()?;
+        Some(Box::new(Self::new(self.policy, inner)))
+    }
+}
+
+impl DirMtimePreserver {
+    pub fn new(inner: Box<dyn FileConsumeAction + Send>) -> Self {
+        Self {
+            recorded: Arc::new(DashMap::new()),
+            inner,
+        }
+    }
+
+    fn restore(dir: &Path, modified: SystemTime) {
+        let restored = std::fs::File::open(dir).and_then(|dir| dir.set_modified(modified));
+        if let Err(err) = restored {
+            log::warn!(
+                target: crate::error_handling::FILE_METADATA_ERR_TARGET,
+                "cannot restore the modification time of directory {}: {err}",
+                dir.display()
+            );
+        }
+    }
+}
+
+impl FileConsumeAction for DirMtimePreserver {
+    fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        if let Some(dir) = dir.filter(|dir| !self.recorded.contains_key(*dir)) {
+            // without a modification time to restore the action still runs
+            if let Ok(modified) = dir.metadata().and_then(|metadata| metadata.modified()) {
+                self.recorded.entry(dir.to_path_buf()).or_insert(modified);
+            }
+        }
+        let result = self.inner.consume(path, original);
+        let recorded = dir.and_then(|dir| Some((dir, *self.recorded.get(dir)?)));
+        if let Some((dir, modified)) = recorded {
+            Self::restore(dir, modified);
+        }
+        result
+    }
+
+    fn requires_original(&self) -> bool {
+        self.inner.requires_original()
+    }
+
+    fn short_name(&self) -> Cow<'_, str> {
+        self.inner.short_name()
+    }
+
+    fn short_opposite(&self) -> Cow<'_, str> {
+        self.inner.short_opposite()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        let inner = self.inner.try_clone()?;
+        Some(Box::new(Self {
+            recorded: self.recorded.clone(),
+            inner,
+        }))
+    }
+}
+
 impl FileConsumeAction for DebugFileAction {
     fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult {
         dbg!(path, original);
@@ -53,19 +261,23 @@ impl FileConsumeAction for DebugFileAction {
         false
     }
 
-    fn short_name(&self) -> Cow<str> {
+    fn short_name(&self) -> Cow<'_, str> {
         Cow::Borrowed("debug print")
     }
 
-    fn short_opposite(&self) -> Cow<str> {
+    fn short_opposite(&self) -> Cow<'_, str> {
         Cow::Borrowed("ignore")
     }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        Some(Box::<DebugFileAction>::default())
+    }
 }
 
 impl FileConsumeAction for DeleteFileAction {
     fn consume(&mut self, path: &Path, _original: Option<&Path>) -> FileConsumeResult {
         handle_file_op!(
-            std::fs::remove_file(path),
+            remove_file_guarded(path),
             path,
             return Err(Recoverable::Recoverable(AlreadyReportedError))
         );
@@ -77,33 +289,28 @@ impl FileConsumeAction for DeleteFileAction {
         false
     }
 
-    fn short_name(&self) -> Cow<str> {
+    fn short_name(&self) -> Cow<'_, str> {
         Cow::Borrowed("delete")
     }
 
-    fn short_opposite(&self) -> Cow<str> {
+    fn short_opposite(&self) -> Cow<'_, str> {
         Cow::Borrowed("keep")
     }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        Some(Box::<DeleteFileAction>::default())
+    }
 }
 
 impl FileConsumeAction for ReplaceWithHardLinkFileAction {
     fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult {
         let original = original.expect("original required");
+        // the duplicate is left untouched if the link cannot be created
         handle_file_op!(
-            std::fs::remove_file(path),
+            replace_with_link(path, |temp_path| std::fs::hard_link(original, temp_path)),
             path,
             return Err(Recoverable::Recoverable(AlreadyReportedError))
         );
-        if let Err(err) = std::fs::hard_link(original, path) {
-            log::error!(
-                target: crate::error_handling::ACTION_FATAL_FAILURE_TARGET,
-                "FATAL ERROR: failed to create hard link to {} from {} due to error {err}",
-                path.display(),
-                original.display()
-            );
-            // Something is absolutely not right here, continuing means risk of data loss
-            return Err(Recoverable::Fatal(AlreadyReportedError));
-        }
         report_file_action!(
             "replaced {} with a hard link to {}",
             path.display(),
@@ -116,11 +323,74 @@ impl FileConsumeAction for ReplaceWithHardLinkFileAction {
         true
     }
 
-    fn short_name(&self) -> Cow<str> {
+    fn short_name(&self) -> Cow<'_, str> {
         Cow::Borrowed("replace with hardlink")
     }
 
-    fn short_opposite(&self) -> Cow<str> {
+    fn short_opposite(&self) -> Cow<'_, str> {
         Cow::Borrowed("keep")
     }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        Some(Box::<ReplaceWithHardLinkFileAction>::default())
+    }
+}
+
+/// clone the original under the temporary name with the owner, the permissions and the modification time of
+/// the duplicate; the clone is removed again if they cannot be set
+fn clone_for(original: &Path, duplicate: &Path, temp_path: &Path) -> std::io::Result<()> {
+    reflink(original, temp_path)?;
+    let with_metadata = duplicate.metadata().and_then(|metadata| {
+        let clone = std::fs::File::open(temp_path)?;
+        // before the permissions, changing the owner may clear the set-user-ID bit
+        copy_owner(&metadata, &clone)?;
+        clone.set_modified(metadata.modified()?)?;
+        clone.set_permissions(metadata.permissions())
+    });
+    if with_metadata.is_err() {
+        let _ = remove_file_guarded(temp_path);
+    }
+    with_metadata
+}
+
+impl FileConsumeAction for ReplaceWithReflinkFileAction {
+    fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult {
+        let original = original.expect("original required");
+        // the duplicate is left untouched if the clone cannot be created
+        handle_file_op!(
+            replace_with_link(path, |temp_path| clone_for(original, path, temp_path)),
+            path,
+            return Err(Recoverable::Recoverable(AlreadyReportedError))
+        );
+        report_file_action!(
+            "replaced {} with a reflink to {}",
+            path.display(),
+            original.display()
+        );
+        if shares_blocks(path) == Some(false) {
+            log::warn!(
+                target: crate::error_handling::REFLINK_COPIED_TARGET,
+                "the file system copied the content of {} instead of sharing it with {}; no space is saved",
+                path.display(),
+                original.display()
+            );
+        }
+        Ok(())
+    }
+
+    fn requires_original(&self) -> bool {
+        true
+    }
+
+    fn short_name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("replace with reflink")
+    }
+
+    fn short_opposite(&self) -> Cow<'_, str> {
+        Cow::Borrowed("keep")
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        Some(Box::<ReplaceWithReflinkFileAction>::default())
+    }
 }
@@ -1,10 +1,12 @@
+use crate::error_handling::ErrorSummary;
 use crate::util::LinkedPath;
-use crate::{dyn_clone_impl, handle_file_op};
+use crate::{dyn_clone_impl, handle_metadata_op};
 use std::collections::{HashMap, HashSet};
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fs::Metadata;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 pub struct FileFilter(
     pub Box<[Box<dyn FileNameFilter + Send>]>,
@@ -12,7 +14,8 @@ pub struct FileFilter(
 );
 
 impl FileFilter {
-    fn filter_name(&mut self, name: &LinkedPath, name_path: &Path) -> bool {
+    /// run the file through the name filters only
+    pub fn filter_name(&mut self, name: &LinkedPath, name_path: &Path) -> bool {
         for name_filter in &mut *self.0 {
             let result = name_filter
                 .filter_file_name(name, name_path)
@@ -50,7 +53,7 @@ impl FileFilter {
         if self.1.is_empty() {
             return true;
         }
-        let metadata = handle_file_op!(std::fs::metadata(name_path), name_path, return false);
+        let metadata = handle_metadata_op!(std::fs::metadata(name_path), name_path, return false);
         self.filter_metadata(name, name_path, &metadata)
     }
 
@@ -67,6 +70,13 @@ impl FileFilter {
         self.filter_metadata(name, name_path, metadata)
     }
 
+    /// return false if the directory below a scan root should not be searched
+    pub fn keep_dir(&mut self, dir_name: &OsStr) -> bool {
+        self.0
+            .iter_mut()
+            .all(|name_filter| name_filter.filter_dir_name(dir_name))
+    }
+
     pub fn keep_file_dir_entry(
         &mut self,
         name: &LinkedPath,
@@ -102,11 +112,24 @@ impl Clone for FileFilter {
 
 /// Filters files only based on the name
 pub trait FileNameFilter: FileNameFilterDynClone {
+    /// whether the file is kept
+    /// # Errors
+    /// if the filter cannot tell, the file is dropped
+    #[allow(clippy::result_unit_err)]
     fn filter_file_name(&mut self, name: &LinkedPath, name_path: &Path) -> Result<bool, ()>;
+
+    /// called during discovery for directories below the scan roots, returning false prunes the directory
+    fn filter_dir_name(&mut self, _dir_name: &OsStr) -> bool {
+        true
+    }
 }
 
 /// Filters files based on the name and metadata
 pub trait FileMetadataFilter: FileMetadataFilterDynClone {
+    /// whether the file is kept
+    /// # Errors
+    /// if the filter cannot tell, the file is dropped
+    #[allow(clippy::result_unit_err)]
     fn filter_file_metadata(
         &mut self,
         name: &LinkedPath,
@@ -118,6 +141,46 @@ pub trait FileMetadataFilter: FileMetadataFilterDynClone {
 dyn_clone_impl!(FileNameFilterDynClone, FileNameFilter);
 dyn_clone_impl!(FileMetadataFilterDynClone, FileMetadataFilter);
 
+/// curated lowercase extension whitelists for `--ext-group`
+pub static EXTENSION_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "images",
+        &[
+            "jpg", "jpeg", "jpe", "png", "gif", "bmp", "tif", "tiff", "webp", "heic", "heif",
+            "avif", "jxl", "svg", "ico", "raw", "dng", "cr2", "cr3", "nef", "arw", "orf", "rw2",
+            "raf", "srw", "psd", "xcf",
+        ],
+    ),
+    (
+        "video",
+        &[
+            "mp4", "m4v", "mkv", "mov", "avi", "wmv", "flv", "webm", "mpg", "mpeg", "m2ts", "mts",
+            "ts", "3gp", "ogv", "vob",
+        ],
+    ),
+    (
+        "audio",
+        &[
+            "mp3", "flac", "wav", "aac", "m4a", "ogg", "oga", "opus", "wma", "aiff", "aif", "ape",
+            "wv", "mid", "midi",
+        ],
+    ),
+    (
+        "documents",
+        &[
+            "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv", "ppt",
+            "pptx", "odp", "epub", "mobi", "djvu", "tex",
+        ],
+    ),
+    (
+        "archives",
+        &[
+            "zip", "tar", "gz", "tgz", "bz2", "tbz2", "xz", "txz", "zst", "7z", "rar", "iso",
+            "img", "dmg", "cab", "lz", "lzma", "z",
+        ],
+    ),
+];
+
 /// Only allow files with more than the given size
 #[derive(Clone)]
 pub struct MinSizeFileFilter(u64);
@@ -126,10 +189,23 @@ pub struct MinSizeFileFilter(u64);
 #[derive(Clone)]
 pub struct MaxSizeFileFilter(u64);
 
+/// Only allow files with exactly the given size
+#[derive(Clone)]
+pub struct ExactSizeFileFilter(u64);
+
+/// Only allow files last modified in the range, the start is inclusive and the end exclusive
+#[derive(Clone)]
+pub struct ModifiedTimeFileFilter {
+    start: Option<SystemTime>,
+    end: Option<SystemTime>,
+}
+
 /// Only allow files whose extensions are not in the set
 #[derive(Clone)]
 pub struct ExtensionFilter {
     extensions: Arc<HashSet<OsString>>,
+    /// matched regardless of their ASCII case, like the extensions of the groups
+    any_case: Arc<[OsString]>,
     no_ext_in_set: bool,
     /// if true then extensions is a white-list, otherwise, extensions is a blacklist
     positive: bool,
@@ -138,6 +214,10 @@ pub struct ExtensionFilter {
 #[derive(Clone)]
 pub struct PathFilter(Arc<PathFilterTree>);
 
+/// Only allow files the current user can read, the others are counted instead of reported one by one
+#[derive(Clone)]
+pub struct ReadableFileFilter(Arc<ErrorSummary>);
+
 #[derive(Debug)]
 struct PathFilterTree(HashMap<OsString, Option<PathFilterTree>>);
 
@@ -164,6 +244,25 @@ impl MaxSizeFileFilter {
     }
 }
 
+impl ModifiedTimeFileFilter {
+    pub fn new(start: Option<SystemTime>, end: Option<SystemTime>) -> Self {
+        Self { start, end }
+    }
+}
+
+impl FileMetadataFilter for ModifiedTimeFileFilter {
+    fn filter_file_metadata(
+        &mut self,
+        _: &LinkedPath,
+        _: &Path,
+        metadata: &Metadata,
+    ) -> Result<bool, ()> {
+        let modified = metadata.modified().map_err(|_| ())?;
+        Ok(self.start.iter().all(|start| modified >= *start)
+            && self.end.iter().all(|end| modified < *end))
+    }
+}
+
 impl FileMetadataFilter for MaxSizeFileFilter {
     fn filter_file_metadata(
         &mut self,
@@ -175,6 +274,23 @@ impl FileMetadataFilter for MaxSizeFileFilter {
     }
 }
 
+impl ExactSizeFileFilter {
+    pub fn new(size: u64) -> Self {
+        Self(size)
+    }
+}
+
+impl FileMetadataFilter for ExactSizeFileFilter {
+    fn filter_file_metadata(
+        &mut self,
+        _: &LinkedPath,
+        _: &Path,
+        metadata: &Metadata,
+    ) -> Result<bool, ()> {
+        Ok(metadata.len() == self.0)
+    }
+}
+
 impl ExtensionFilter {
     pub(crate) fn new(
         extensions: HashSet<OsString>,
@@ -183,17 +299,27 @@ impl ExtensionFilter {
     ) -> Self {
         Self {
             extensions: Arc::new(extensions),
+            any_case: Arc::new([]),
             no_ext_in_set: no_extension_in_set,
             positive,
         }
     }
+
+    pub(crate) fn with_any_case(mut self, extensions: Vec<OsString>) -> Self {
+        self.any_case = extensions.into();
+        self
+    }
+
+    fn contains(&self, ext: &OsStr) -> bool {
+        self.extensions.contains(ext) || self.any_case.iter().any(|e| e.eq_ignore_ascii_case(ext))
+    }
 }
 
 impl FileNameFilter for ExtensionFilter {
     fn filter_file_name(&mut self, _: &LinkedPath, name_path: &Path) -> Result<bool, ()> {
         Ok(name_path
             .extension()
-            .map_or(self.no_ext_in_set, |ext| self.extensions.contains(ext))
+            .map_or(self.no_ext_in_set, |ext| self.contains(ext))
             ^ !self.positive)
     }
 }
@@ -204,7 +330,7 @@ impl PathFilter {
         'path_loop: for path in paths {
             let mut current = &mut root;
             if let Some(parent) = path.parent() {
-                for seg in parent.iter() {
+                for seg in parent {
                     if current.0.contains_key(seg) {
                         let entry = current.0.get_mut(seg).unwrap().as_mut();
                         if let Some(next) = entry {
@@ -233,7 +359,7 @@ impl PathFilter {
 impl FileNameFilter for PathFilter {
     fn filter_file_name(&mut self, _: &LinkedPath, name_path: &Path) -> Result<bool, ()> {
         let mut current = self.0.as_ref();
-        for seg in name_path.iter() {
+        for seg in name_path {
             let Some(entry) = current.0.get(seg) else { return Ok(true); };
             match entry.as_ref() {
                 Some(next) => current = next,
@@ -243,3 +369,24 @@ impl FileNameFilter for PathFilter {
         Ok(true)
     }
 }
+
+impl ReadableFileFilter {
+    pub fn new(errors: Arc<ErrorSummary>) -> Self {
+        Self(errors)
+    }
+}
+
+impl FileMetadataFilter for ReadableFileFilter {
+    fn filter_file_metadata(
+        &mut self,
+        _: &LinkedPath,
+        name_path: &Path,
+        _: &Metadata,
+    ) -> Result<bool, ()> {
+        let readable = crate::os::is_readable(name_path);
+        if !readable {
+            self.0.file_unreadable();
+        }
+        Ok(readable)
+    }
+}
@@ -1,14 +1,70 @@
 use crate::error_handling::AlreadyReportedError;
-use crate::{dyn_clone_impl, handle_file_op};
-use std::io::Read;
+use crate::os::FileIdentity;
+use crate::util::StopSignal;
+use crate::{dyn_clone_impl, handle_file_op, handle_metadata_op};
+use dashmap::DashMap;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::Arc;
 
-pub struct FileSetRefiners(Box<[Box<dyn FileEqualsChecker + Send>]>);
+/// the comparisons remembered at most; the files of a bucket are compared close together, so forgetting all once
+/// the cache is full mostly forgets the buckets which are done
+const PAIR_CACHE_LIMIT: usize = 1 << 16;
+
+pub struct FileSetRefiners(
+    Box<[Box<dyn FileEqualsChecker + Send>]>,
+    RefinerLogic,
+    Arc<StopSignal>,
+    /// the results of the comparisons so far by the identities of the files, shared by the clones
+    Arc<DashMap<(FileIdentity, FileIdentity), bool>>,
+);
+
+/// a file to compare with what is already known about it
+#[derive(Copy, Clone, Debug)]
+pub struct CompareFile<'a> {
+    pub path: &'a Path,
+    /// the size recorded while hashing
+    pub size: Option<u64>,
+    pub identity: Option<FileIdentity>,
+}
+
+impl<'a> CompareFile<'a> {
+    pub fn new(path: &'a Path, size: Option<u64>, identity: Option<FileIdentity>) -> Self {
+        Self {
+            path,
+            size,
+            identity,
+        }
+    }
+}
+
+/// how the results of the refiners are combined
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RefinerLogic {
+    /// files are equal if all refiners consider them equal
+    And,
+    /// files are equal if any refiner considers them equal
+    Or,
+}
 
 impl FileSetRefiners {
-    pub fn new(mut checkers: Box<[Box<dyn FileEqualsChecker + Send>]>) -> Self {
-        checkers.sort_unstable_by_key(|fec| fec.work_severity());
-        Self(checkers)
+    pub fn new(
+        mut checkers: Box<[Box<dyn FileEqualsChecker + Send>]>,
+        logic: RefinerLogic,
+    ) -> Self {
+        checkers.sort_by_key(|fec| fec.work_severity());
+        Self(
+            checkers,
+            logic,
+            Arc::new(StopSignal::new(None, None)),
+            Arc::default(),
+        )
+    }
+
+    /// let the refiners give up on their comparisons once the signal has been stopped
+    pub fn with_stop(mut self, stop: Arc<StopSignal>) -> Self {
+        self.2 = stop;
+        self
     }
 
     pub fn hash_components(
@@ -16,24 +72,85 @@ impl FileSetRefiners {
         hasher: &mut dyn std::hash::Hasher,
         file: &Path,
     ) -> Result<(), AlreadyReportedError> {
-        for refiner in self.0.iter_mut() {
+        // files differing in one property may still be equal by another, so no property may split the sets
+        if self.1 == RefinerLogic::Or {
+            return Ok(());
+        }
+        for refiner in &mut self.0 {
             refiner.hash_component(file, hasher)?;
         }
         Ok(())
     }
 
-    pub fn check_equal(&mut self, a: &Path, b: &Path) -> Result<bool, CheckEqualsErrorOn> {
-        for refiner in self.0.iter_mut() {
-            refiner.check_equal(a, b)?;
+    /// move the refiners of at least the given workload into a set of their own, so that they can be
+    /// run separately; with [`RefinerLogic::Or`] a refiner cannot be run separately from the others
+    pub fn split_off(&mut self, from: FileWorkload) -> Self {
+        if self.1 == RefinerLogic::Or {
+            return Self(Box::new([]), self.1, self.2.clone(), Arc::default());
         }
-        Ok(true)
+        let (light, heavy): (Vec<_>, Vec<_>) = std::mem::take(&mut self.0)
+            .into_vec()
+            .into_iter()
+            .partition(|refiner| refiner.work_severity() < from);
+        self.0 = light.into_boxed_slice();
+        // the results so far were decided by all refiners
+        self.3 = Arc::default();
+        Self(
+            heavy.into_boxed_slice(),
+            self.1,
+            self.2.clone(),
+            Arc::default(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// stops at the first refiner deciding the result, the cheapest refiners are asked first; a pair of
+    /// files is only compared once, paths to the same file are equal without comparing them
+    pub fn check_equal(
+        &mut self,
+        a: CompareFile,
+        b: CompareFile,
+    ) -> Result<bool, CheckEqualsErrorOn> {
+        let Some(pair) = a.identity.zip(b.identity) else { return self.compare(a, b) };
+        if pair.0 == pair.1 {
+            return Ok(true);
+        }
+        let known = self.3.get(&pair).or_else(|| self.3.get(&(pair.1, pair.0)));
+        if let Some(known) = known.map(|known| *known) {
+            return Ok(known);
+        }
+        let is_equal = self.compare(a, b)?;
+        if self.3.len() >= PAIR_CACHE_LIMIT {
+            self.3.clear();
+        }
+        self.3.insert(pair, is_equal);
+        Ok(is_equal)
+    }
+
+    fn compare(&mut self, a: CompareFile, b: CompareFile) -> Result<bool, CheckEqualsErrorOn> {
+        let deciding = self.1 == RefinerLogic::Or;
+        for refiner in &mut self.0 {
+            if refiner.check_equal_sized((a.path, a.size), (b.path, b.size), &self.2)? == deciding {
+                return Ok(deciding);
+            }
+        }
+        // without any refiners there is nothing to tell the files apart
+        Ok(!deciding || self.0.is_empty())
     }
 }
 
 impl Clone for FileSetRefiners {
     fn clone(&self) -> Self {
         let cks = self.0.iter().map(|ck| ck.dyn_clone()).collect::<Vec<_>>();
-        Self(cks.into_boxed_slice())
+        Self(
+            cks.into_boxed_slice(),
+            self.1,
+            self.2.clone(),
+            self.3.clone(),
+        )
     }
 }
 
@@ -42,6 +159,8 @@ pub enum CheckEqualsErrorOn {
     First,
     Second,
     Both,
+    /// the comparison was given up as the run was stopped, neither file is at fault
+    Stopped,
 }
 
 impl CheckEqualsErrorOn {
@@ -50,6 +169,7 @@ impl CheckEqualsErrorOn {
             CheckEqualsErrorOn::First => (true, false),
             CheckEqualsErrorOn::Second => (false, true),
             CheckEqualsErrorOn::Both => (true, true),
+            CheckEqualsErrorOn::Stopped => (false, false),
         }
     }
     pub fn first_err() -> Self {
@@ -73,7 +193,23 @@ pub enum FileWorkload {
 
 /// checks whether to files are equal
 pub trait FileEqualsChecker: FileEqualsCheckDynClone {
-    fn check_equal(&mut self, a: &Path, b: &Path) -> Result<bool, CheckEqualsErrorOn>;
+    /// lengthy comparisons may give up with [`CheckEqualsErrorOn::Stopped`] once `stop` has been stopped
+    fn check_equal(
+        &mut self,
+        a: &Path,
+        b: &Path,
+        stop: &StopSignal,
+    ) -> Result<bool, CheckEqualsErrorOn>;
+    /// like `check_equal`, with the sizes of the files where they are already known so that they need
+    /// not be read again
+    fn check_equal_sized(
+        &mut self,
+        a: (&Path, Option<u64>),
+        b: (&Path, Option<u64>),
+        stop: &StopSignal,
+    ) -> Result<bool, CheckEqualsErrorOn> {
+        self.check_equal(a.0, b.0, stop)
+    }
     /// hash the property were checking for(like the permissions), may be a noop if property cannot be hashed.
     fn hash_component(
         &mut self,
@@ -85,28 +221,100 @@ pub trait FileEqualsChecker: FileEqualsCheckDynClone {
 
 dyn_clone_impl!(FileEqualsCheckDynClone, FileEqualsChecker);
 
+/// compares the files chunk by chunk, each instance owns a buffer of the chunk size for either file
 #[derive(Clone)]
 pub struct FileContentEquals {
-    buf: Box<([u8; 64], [u8; 64])>,
+    buf: (Box<[u8]>, Box<[u8]>),
 }
 
 impl Default for FileContentEquals {
     fn default() -> Self {
+        Self::new(Self::DEFAULT_BUFFER_SIZE)
+    }
+}
+
+impl FileContentEquals {
+    pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+    pub fn new(buffer_size: usize) -> Self {
+        let buffer_size = buffer_size.max(1);
         Self {
-            buf: Box::new(([0; 64], [0; 64])),
+            buf: (
+                vec![0; buffer_size].into_boxed_slice(),
+                vec![0; buffer_size].into_boxed_slice(),
+            ),
         }
     }
 }
 
-impl FileContentEquals {
-    pub fn new() -> Self {
-        Self::default()
+/// compare at most `limit` bytes from the current positions of the files
+fn compare_streams(
+    (a, a_path): (&mut std::fs::File, &Path),
+    (b, b_path): (&mut std::fs::File, &Path),
+    limit: u64,
+    (buf_a, buf_b): (&mut [u8], &mut [u8]),
+    stop: &StopSignal,
+) -> Result<bool, CheckEqualsErrorOn> {
+    let (mut a, mut b) = (a.take(limit), b.take(limit));
+    loop {
+        if stop.reason().is_some() {
+            return Err(CheckEqualsErrorOn::Stopped);
+        }
+        let l = handle_file_op!(
+            read_chunk(&mut a, buf_a),
+            a_path,
+            return Err(CheckEqualsErrorOn::First)
+        );
+        if l == 0 {
+            return Ok(true);
+        }
+        let l2 = handle_file_op!(
+            read_chunk(&mut b, buf_b),
+            b_path,
+            return Err(CheckEqualsErrorOn::Second)
+        );
+        if (l != l2) || (buf_a[..l] != buf_b[..l]) {
+            return Ok(false);
+        }
     }
 }
 
+/// read until the buffer is full or the file ends, returns the number of bytes read
+fn read_chunk(file: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
 impl FileEqualsChecker for FileContentEquals {
-    fn check_equal(&mut self, a_path: &Path, b_path: &Path) -> Result<bool, CheckEqualsErrorOn> {
-        let (buf_a, buf_b) = &mut *self.buf;
+    fn check_equal(
+        &mut self,
+        a_path: &Path,
+        b_path: &Path,
+        stop: &StopSignal,
+    ) -> Result<bool, CheckEqualsErrorOn> {
+        self.check_equal_sized((a_path, None), (b_path, None), stop)
+    }
+
+    fn check_equal_sized(
+        &mut self,
+        (a_path, a_size): (&Path, Option<u64>),
+        (b_path, b_size): (&Path, Option<u64>),
+        stop: &StopSignal,
+    ) -> Result<bool, CheckEqualsErrorOn> {
+        if let (Some(a_size), Some(b_size)) = (a_size, b_size) {
+            if a_size != b_size {
+                return Ok(false);
+            }
+        }
+        let (buf_a, buf_b) = &mut self.buf;
 
         let mut a = handle_file_op!(
             std::fs::File::open(a_path),
@@ -119,29 +327,45 @@ impl FileEqualsChecker for FileContentEquals {
             return Err(CheckEqualsErrorOn::Second)
         );
 
+        // the files may have changed since their sizes were recorded, the open handles decide
         let metadata_a =
-            handle_file_op!(a.metadata(), a_path, return Err(CheckEqualsErrorOn::First));
+            handle_metadata_op!(a.metadata(), a_path, return Err(CheckEqualsErrorOn::First));
         let metadata_b =
-            handle_file_op!(b.metadata(), b_path, return Err(CheckEqualsErrorOn::Second));
-
-        if metadata_a.len() != metadata_b.len() {
+            handle_metadata_op!(b.metadata(), b_path, return Err(CheckEqualsErrorOn::Second));
+        let len = metadata_a.len();
+        let mut recorded = [a_size, b_size].into_iter().flatten();
+        if metadata_b.len() != len || recorded.any(|size| size != len) {
             return Ok(false);
         }
-
-        loop {
-            let l = handle_file_op!(a.read(buf_a), a_path, return Err(CheckEqualsErrorOn::First));
-            if l == 0 {
-                return Ok(true);
-            }
-            let l2 = handle_file_op!(
-                b.read(buf_b),
+        let block = buf_a.len() as u64;
+        if len <= 2 * block {
+            return compare_streams(
+                (&mut a, a_path),
+                (&mut b, b_path),
+                u64::MAX,
+                (buf_a, buf_b),
+                stop,
+            );
+        }
+        // most same sized files which differ do so near the beginning or the end, probe both before the middle
+        for offset in [0, len - block, block] {
+            handle_file_op!(
+                a.seek(SeekFrom::Start(offset)),
+                a_path,
+                return Err(CheckEqualsErrorOn::First)
+            );
+            handle_file_op!(
+                b.seek(SeekFrom::Start(offset)),
                 b_path,
                 return Err(CheckEqualsErrorOn::Second)
             );
-            if (l != l2) || (buf_a[..l] != buf_b[..l]) {
+            let limit = if offset == block { len - 2 * block } else { block };
+            let bufs = (&mut **buf_a, &mut **buf_b);
+            if !compare_streams((&mut a, a_path), (&mut b, b_path), limit, bufs, stop)? {
                 return Ok(false);
             }
         }
+        Ok(true)
     }
 
     fn hash_component(
@@ -156,3 +380,169 @@ impl FileEqualsChecker for FileContentEquals {
         FileWorkload::FileContent
     }
 }
+
+/// files on different devices are different, as they cannot be linked to each other
+#[derive(Clone, Default)]
+pub struct DeviceEquals;
+
+impl FileEqualsChecker for DeviceEquals {
+    fn check_equal(
+        &mut self,
+        a: &Path,
+        b: &Path,
+        _stop: &StopSignal,
+    ) -> Result<bool, CheckEqualsErrorOn> {
+        let device_a = handle_metadata_op!(
+            crate::os::file_device(a),
+            a,
+            return Err(CheckEqualsErrorOn::First)
+        );
+        let device_b = handle_metadata_op!(
+            crate::os::file_device(b),
+            b,
+            return Err(CheckEqualsErrorOn::Second)
+        );
+        Ok(device_a == device_b)
+    }
+
+    fn hash_component(
+        &mut self,
+        f: &Path,
+        hasher: &mut dyn std::hash::Hasher,
+    ) -> Result<(), AlreadyReportedError> {
+        let device = handle_metadata_op!(
+            crate::os::file_device(f),
+            f,
+            return Err(AlreadyReportedError)
+        );
+        hasher.write_u64(device);
+        Ok(())
+    }
+
+    fn work_severity(&self) -> FileWorkload {
+        FileWorkload::FileMetadata
+    }
+}
+
+#[test]
+fn test_refiner_logic() {
+    #[derive(Clone)]
+    struct Constant(Result<bool, CheckEqualsErrorOn>);
+    impl FileEqualsChecker for Constant {
+        fn check_equal(
+            &mut self,
+            _: &Path,
+            _: &Path,
+            _: &StopSignal,
+        ) -> Result<bool, CheckEqualsErrorOn> {
+            self.0
+        }
+        fn hash_component(
+            &mut self,
+            _: &Path,
+            _: &mut dyn std::hash::Hasher,
+        ) -> Result<(), AlreadyReportedError> {
+            Ok(())
+        }
+        fn work_severity(&self) -> FileWorkload {
+            FileWorkload::Simple
+        }
+    }
+
+    let check = |logic, results: &[Result<bool, CheckEqualsErrorOn>]| {
+        let checkers = results
+            .iter()
+            .map(|result| Box::new(Constant(*result)) as Box<dyn FileEqualsChecker + Send>)
+            .collect();
+        FileSetRefiners::new(checkers, logic).check_equal(
+            CompareFile::new("a".as_ref(), None, None),
+            CompareFile::new("b".as_ref(), None, None),
+        )
+    };
+    let err = Err(CheckEqualsErrorOn::First);
+    assert_eq!(check(RefinerLogic::And, &[]), Ok(true));
+    assert_eq!(check(RefinerLogic::And, &[Ok(true), Ok(true)]), Ok(true));
+    assert_eq!(check(RefinerLogic::And, &[Ok(true), Ok(false)]), Ok(false));
+    assert_eq!(check(RefinerLogic::And, &[Ok(false), err]), Ok(false));
+    assert_eq!(check(RefinerLogic::And, &[Ok(true), err]), err);
+    assert_eq!(check(RefinerLogic::Or, &[]), Ok(true));
+    assert_eq!(check(RefinerLogic::Or, &[Ok(false), Ok(false)]), Ok(false));
+    assert_eq!(check(RefinerLogic::Or, &[Ok(false), Ok(true)]), Ok(true));
+    assert_eq!(check(RefinerLogic::Or, &[Ok(true), err]), Ok(true));
+    assert_eq!(check(RefinerLogic::Or, &[Ok(false), err]), err);
+}
+
+#[test]
+fn test_refiner_cache() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone)]
+    struct Counting(Arc<AtomicUsize>);
+    impl FileEqualsChecker for Counting {
+        fn check_equal(
+            &mut self,
+            _: &Path,
+            _: &Path,
+            _: &StopSignal,
+        ) -> Result<bool, CheckEqualsErrorOn> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(false)
+        }
+        fn hash_component(
+            &mut self,
+            _: &Path,
+            _: &mut dyn std::hash::Hasher,
+        ) -> Result<(), AlreadyReportedError> {
+            Ok(())
+        }
+        fn work_severity(&self) -> FileWorkload {
+            FileWorkload::Simple
+        }
+    }
+
+    let compared = Arc::new(AtomicUsize::new(0));
+    let checkers: Box<[Box<dyn FileEqualsChecker + Send>]> =
+        Box::new([Box::new(Counting(compared.clone()))]);
+    let mut refiners = FileSetRefiners::new(checkers, RefinerLogic::And);
+    let file = |name: &'static str, index| {
+        let identity = FileIdentity { device: 1, index };
+        CompareFile::new(name.as_ref(), None, Some(identity))
+    };
+    assert_eq!(refiners.check_equal(file("a", 1), file("b", 2)), Ok(false));
+    // the pair was compared before, either way round and by any clone
+    assert_eq!(refiners.check_equal(file("b", 2), file("a", 1)), Ok(false));
+    assert_eq!(
+        refiners.clone().check_equal(file("a", 1), file("b", 2)),
+        Ok(false)
+    );
+    assert_eq!(compared.load(Ordering::Relaxed), 1);
+    // hard links are the same file
+    assert_eq!(refiners.check_equal(file("a", 1), file("c", 1)), Ok(true));
+    // without identities there is nothing to remember
+    let unknown = |name: &'static str| CompareFile::new(name.as_ref(), None, None);
+    assert_eq!(refiners.check_equal(unknown("a"), unknown("b")), Ok(false));
+    assert_eq!(refiners.check_equal(unknown("a"), unknown("b")), Ok(false));
+    assert_eq!(compared.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_read_chunk() {
+    /// returns at most one byte per read like a slow pipe
+    struct Trickle<'a>(&'a [u8]);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else { return Ok(0) };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    let mut buf = [0; 4];
+    let mut source = Trickle(b"abcdef");
+    assert_eq!(read_chunk(&mut source, &mut buf).unwrap(), 4);
+    assert_eq!(&buf, b"abcd");
+    assert_eq!(read_chunk(&mut source, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"ef");
+    assert_eq!(read_chunk(&mut source, &mut buf).unwrap(), 0);
+}
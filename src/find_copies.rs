@@ -0,0 +1,46 @@
+use crate::error_handling::AlreadyReportedError;
+use crate::file_filters::{ExactSizeFileFilter, FileFilter};
+use crate::input_source::{
+    ChannelInputSink, DiscoveringInputSource, InputSource, SymlinkTreatment,
+};
+use crate::util::{LinkedPath, StopSignal};
+use crate::{content_matches, handle_metadata_op, hash_content, out_err_map, HashAlgorithm};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// write the files below the directories which have the content of the target, one per line;
+/// the target is hashed once and only the files of its size are hashed to compare against it
+pub fn find_copies(
+    target: &Path,
+    dirs: Vec<Arc<LinkedPath>>,
+    write: &mut impl std::io::Write,
+) -> Result<(), AlreadyReportedError> {
+    let metadata = handle_metadata_op!(target.metadata(), target, return Err(AlreadyReportedError));
+    let target_hash = hash_content(target, HashAlgorithm::default())?;
+
+    let size_filter = FileFilter(
+        Box::new([]),
+        Box::new([Box::new(ExactSizeFileFilter::new(metadata.len()))]),
+    );
+    let mut source =
+        DiscoveringInputSource::new(true, false, SymlinkTreatment::Skip, dirs, size_filter);
+    let (send, candidates) = flume::unbounded();
+    // the search is short enough to not need a way to stop it early
+    source.consume_all(
+        &mut ChannelInputSink::new(send),
+        &StopSignal::new(None, None),
+    )?;
+
+    let mut path_buf = PathBuf::new();
+    for candidate in candidates.drain() {
+        candidate.write_full_to_buf(&mut path_buf);
+        if path_buf == target {
+            continue;
+        }
+        // an unreadable candidate is reported and left out
+        if content_matches(&path_buf, target_hash, HashAlgorithm::default()).unwrap_or(false) {
+            writeln!(write, "{}", path_buf.display()).map_err(out_err_map!())?;
+        }
+    }
+    write.flush().map_err(out_err_map!())
+}
@@ -0,0 +1,190 @@
+use crate::plan::plan_can_represent;
+use crate::HashScope;
+use dashmap::DashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// written at the top of every cache, lines starting with '#' are ignored when reading
+static CACHE_HEADER: &str =
+    "# duplis hash cache\n# hash\tsize\tmodification time\thashed part\tfile\n";
+/// the header line naming the hash algorithm of the cache
+static ALGORITHM_PREFIX: &str = "# hash algorithm: ";
+
+/// the hashes of earlier runs by path and hashed part, a hash is reused as long as the size and the
+/// modification time of the file are unchanged
+pub struct HashCache {
+    path: PathBuf,
+    /// the algorithm of the hashes, written into the cache
+    algorithm: String,
+    entries: DashMap<(PathBuf, String), CacheEntry>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CacheEntry {
+    hash: u128,
+    size: u64,
+    /// the modification time as nanoseconds since the unix epoch
+    modified: u128,
+}
+
+impl HashCache {
+    /// read the cache, a missing file is an empty cache which is created once the cache is saved; a cache of
+    /// another hash algorithm is dropped so that every file is hashed again
+    pub fn open(path: &Path, algorithm: &str) -> std::io::Result<Self> {
+        let entries = DashMap::new();
+        match std::fs::File::open(path) {
+            Ok(file) => {
+                let mut found_algorithm = None;
+                for line in std::io::BufReader::new(file).lines() {
+                    let line = line?;
+                    if let Some(found) = line.strip_prefix(ALGORITHM_PREFIX) {
+                        found_algorithm = Some(found.trim_end().to_owned());
+                    }
+                    if line.starts_with('#') {
+                        continue;
+                    }
+                    let found_algorithm = found_algorithm
+                        .get_or_insert_with(|| crate::UNRECORDED_HASH_ALGORITHM.to_owned());
+                    if found_algorithm != algorithm {
+                        log::warn!(
+                            target: crate::error_handling::CONFIG_ERR_TARGET,
+                            "hash cache {} was hashed with {found_algorithm} instead of {algorithm}; hashing all files again",
+                            path.display()
+                        );
+                        break;
+                    }
+                    // a malformed line only costs hashing the file again
+                    if let Some((key, entry)) = parse_line(line.trim_end_matches('\r')) {
+                        entries.insert(key, entry);
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            algorithm: algorithm.to_owned(),
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the cached hash of the file if it has not changed since
+    pub fn get(
+        &self,
+        file: &Path,
+        size: u64,
+        modified: SystemTime,
+        scope: HashScope,
+    ) -> Option<u128> {
+        let entry = self.entries.get(&(file.to_path_buf(), scope_tag(scope)))?;
+        let unchanged = entry.size == size && Some(entry.modified) == nanos_since_epoch(modified);
+        unchanged.then_some(entry.hash)
+    }
+
+    /// remember the hash of the file, files which cannot be written into the cache are left out
+    pub fn insert(
+        &self,
+        file: &Path,
+        size: u64,
+        modified: SystemTime,
+        scope: HashScope,
+        hash: u128,
+    ) {
+        let Some(modified) = nanos_since_epoch(modified) else {
+            return;
+        };
+        if !file.to_str().is_some_and(plan_can_represent) {
+            return;
+        }
+        let entry = CacheEntry {
+            hash,
+            size,
+            modified,
+        };
+        self.entries
+            .insert((file.to_path_buf(), scope_tag(scope)), entry);
+    }
+
+    /// replace the file of the cache with the current entries; the entries of files which no longer
+    /// exist are dropped
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut temp_name = self.path.clone().into_os_string();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        let mut write = std::io::BufWriter::new(std::fs::File::create(&temp_path)?);
+        write.write_all(CACHE_HEADER.as_bytes())?;
+        writeln!(write, "{ALGORITHM_PREFIX}{}", self.algorithm)?;
+        for entry in &self.entries {
+            let ((file, scope), entry) = entry.pair();
+            let Some(file_str) = file.to_str() else {
+                continue;
+            };
+            if !file.exists() {
+                continue;
+            }
+            writeln!(
+                write,
+                "{:032x}\t{}\t{}\t{}\t{file_str}",
+                entry.hash, entry.size, entry.modified, scope
+            )?;
+        }
+        write
+            .into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?
+            .sync_all()?;
+        std::fs::rename(&temp_path, &self.path)
+    }
+}
+
+fn parse_line(line: &str) -> Option<((PathBuf, String), CacheEntry)> {
+    let mut fields = line.splitn(5, '\t');
+    let hash = u128::from_str_radix(fields.next()?, 16).ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let modified = fields.next()?.parse().ok()?;
+    let scope = fields.next()?.to_owned();
+    let file = fields.next().filter(|file| !file.is_empty())?;
+    let entry = CacheEntry {
+        hash,
+        size,
+        modified,
+    };
+    Some(((PathBuf::from(file), scope), entry))
+}
+
+fn nanos_since_epoch(time: SystemTime) -> Option<u128> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since| since.as_nanos())
+}
+
+/// the part of the file the hash covers, a hash of another part cannot be reused
+fn scope_tag(scope: HashScope) -> String {
+    match scope {
+        HashScope::Full => String::from("full"),
+        HashScope::Prefix(limit) | HashScope::PrefixBuckets(limit) => format!("prefix {limit}"),
+        HashScope::Sampled { blocks, block_size } => format!("sampled {blocks}x{block_size}"),
+    }
+}
+
+#[test]
+fn test_cache_line() {
+    let (key, entry) = parse_line("ab\t3\t1000\tprefix 4\t/a/b c").unwrap();
+    assert_eq!(key, (PathBuf::from("/a/b c"), String::from("prefix 4")));
+    assert_eq!(
+        entry,
+        CacheEntry {
+            hash: 0xab,
+            size: 3,
+            modified: 1000,
+        }
+    );
+    assert!(parse_line("ab\t3\t1000\tfull\t").is_none());
+    assert!(parse_line("zz\t3\t1000\tfull\t/a").is_none());
+    assert!(parse_line("ab\tx\t1000\tfull\t/a").is_none());
+}
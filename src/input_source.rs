@@ -1,15 +1,29 @@
 use crate::dyn_clone_impl;
 use crate::error_handling::AlreadyReportedError;
 use crate::file_filters::FileFilter;
-use crate::util::{push_to_path, LinkedPath};
+use crate::os::file_identity;
+use crate::util::{
+    path_from_bytes, push_to_path, LinkedPath, MemoryBudget, PathMap, ProgressLog, StopSignal,
+};
+use crate::HashScope;
 use dashmap::DashSet;
+use std::collections::VecDeque;
 use std::io::BufRead;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct ChannelInputSink(flume::Sender<LinkedPath>);
+/// Drops the files which were put before by their canonical paths, which are the same for a file reached through
+/// a subst drive or a junction; the hard links to a file have paths of their own and are kept
 pub struct DedupingInputSink(Arc<DashSet<LinkedPath>>, Box<dyn InputSink + Send>);
+/// Charges the files to the memory budget; once it is exhausted, discovery waits for the queued files to be
+/// sorted into their size classes, which are spilled to disk to make room
+pub struct BudgetedInputSink(Arc<MemoryBudget>, Box<dyn InputSink + Send>);
+/// Counts the bytes which will be hashed of the files handed on for the estimate of the progress log
+pub struct ProgressInputSink(Arc<ProgressLog>, HashScope, Box<dyn InputSink + Send>);
+/// Drops all files once discovery should stop early
+pub struct StoppingInputSink(Arc<StopSignal>, Box<dyn InputSink + Send>);
 
 /// A sink for all files discovered during discovery phase
 pub trait InputSink: InputSinkDynClone {
@@ -21,7 +35,14 @@ dyn_clone_impl!(InputSinkDynClone, InputSink);
 
 /// An source for files during file discovery phase
 pub trait InputSource {
-    fn consume_all(&mut self, sink: &mut dyn InputSink) -> Result<(), AlreadyReportedError>;
+    /// ends early once `stop` has been stopped
+    /// # Errors
+    /// if the source could not hand on its files, the reason is reported
+    fn consume_all(
+        &mut self,
+        sink: &mut dyn InputSink,
+        stop: &StopSignal,
+    ) -> Result<(), AlreadyReportedError>;
 }
 
 impl ChannelInputSink {
@@ -38,7 +59,7 @@ impl InputSink for ChannelInputSink {
                 "path sink closed! dropping path {}",
                 path.0.to_push_buf().display()
             );
-        };
+        }
     }
 }
 
@@ -63,18 +84,121 @@ impl Clone for DedupingInputSink {
     }
 }
 
+impl BudgetedInputSink {
+    pub fn new(budget: Arc<MemoryBudget>, inherit: Box<dyn InputSink + Send>) -> Self {
+        Self(budget, inherit)
+    }
+}
+
+impl InputSink for BudgetedInputSink {
+    fn put(&mut self, path: LinkedPath) {
+        self.0.charge(MemoryBudget::file_cost(&path));
+        // the file is in flight until it is sorted into its size class
+        self.0.start_work();
+        self.1.put(path);
+    }
+}
+
+impl Clone for BudgetedInputSink {
+    fn clone(&self) -> Self {
+        Self(self.0.clone(), self.1.dyn_clone())
+    }
+}
+
+impl ProgressInputSink {
+    pub fn new(
+        progress: Arc<ProgressLog>,
+        hash_scope: HashScope,
+        inherit: Box<dyn InputSink + Send>,
+    ) -> Self {
+        Self(progress, hash_scope, inherit)
+    }
+}
+
+impl InputSink for ProgressInputSink {
+    fn put(&mut self, path: LinkedPath) {
+        // a file which cannot be read is reported once it is hashed
+        if let Ok(metadata) = path.to_push_buf().metadata() {
+            self.0.file_discovered(self.1.hashed_bytes(metadata.len()));
+        }
+        self.2.put(path);
+    }
+}
+
+impl Clone for ProgressInputSink {
+    fn clone(&self) -> Self {
+        Self(self.0.clone(), self.1, self.2.dyn_clone())
+    }
+}
+
+impl StoppingInputSink {
+    pub fn new(stop: Arc<StopSignal>, inherit: Box<dyn InputSink + Send>) -> Self {
+        Self(stop, inherit)
+    }
+}
+
+impl InputSink for StoppingInputSink {
+    fn put(&mut self, path: LinkedPath) {
+        if self.0.should_stop() {
+            self.0.report_discovery_stopped();
+        } else {
+            self.1.put(path);
+        }
+    }
+}
+
+impl Clone for StoppingInputSink {
+    fn clone(&self) -> Self {
+        Self(self.0.clone(), self.1.dyn_clone())
+    }
+}
+
+/// What discovery does with symlinks to files
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SymlinkTreatment {
+    /// ignore the symlink
+    Skip,
+    /// emit the canonical path of the target instead
+    Follow,
+    /// emit the symlink itself, it is compared by the content of its target
+    AsFiles,
+}
+
+/// The order in which discovery visits the directories
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Traversal {
+    /// descend into the first subdirectory before visiting its siblings, keeping the reads of a tree together
+    DepthFirst,
+    /// visit all directories of a level before the next level, covering all trees early
+    BreadthFirst,
+}
+
 /// Discover file by walking a directory
 pub struct DiscoveringInputSource {
     /// walk the directories recursively
     recurse: bool,
+    /// follow symlinks to directories
     follow_symlink: bool,
+    file_symlinks: SymlinkTreatment,
     file_filters: FileFilter,
-    sources: Vec<Arc<LinkedPath>>,
+    traversal: Traversal,
+    /// files less deep than this are skipped, the files directly in a root have a depth of 1
+    min_depth: u32,
+    /// files deeper than this are skipped and the directories they are in are not visited
+    max_depth: Option<u32>,
+    /// the directories still to visit, in the order of visiting for breadth first
+    /// and in the reverse order for depth first; each with the device of its root
+    /// if symlinks are only followed on that device and its depth below the root
+    sources: VecDeque<(Arc<LinkedPath>, Option<u64>, u32)>,
     path_acc: PathBuf,
 }
 
 pub struct StdInSource {
     file_filters: FileFilter,
+    /// the byte the paths are separated by
+    separator: u8,
+    /// the paths are given as on the host and read as in the container
+    path_maps: Vec<PathMap>,
 }
 
 macro_rules! handle_access_dir {
@@ -150,34 +274,119 @@ impl DiscoveringInputSource {
     pub fn new(
         recurse: bool,
         follow_symlink: bool,
+        file_symlinks: SymlinkTreatment,
         sources: Vec<Arc<LinkedPath>>,
         file_filters: FileFilter,
     ) -> Self {
         Self {
             recurse,
             follow_symlink,
+            file_symlinks,
             file_filters,
-            sources,
+            traversal: Traversal::DepthFirst,
+            min_depth: 0,
+            max_depth: None,
+            sources: sources
+                .into_iter()
+                .rev()
+                .map(|dir| (dir, None, 0))
+                .collect(),
             path_acc: PathBuf::new(),
         }
     }
-    fn handle_symlink(&mut self, entry: &std::fs::DirEntry, sink: &mut dyn InputSink) {
+
+    /// only follow symlinks whose target is on the same device as the root they were found under
+    pub fn with_same_device_symlinks(mut self, same_device: bool) -> Self {
+        for (dir, root_device, _) in &mut self.sources {
+            *root_device = same_device
+                .then(|| std::fs::metadata(dir.to_push_buf()).ok())
+                .flatten()
+                .and_then(|metadata| file_identity(&metadata))
+                .map(|identity| identity.device);
+        }
+        self
+    }
+
+    pub fn with_traversal(mut self, traversal: Traversal) -> Self {
+        if traversal != self.traversal {
+            self.sources.make_contiguous().reverse();
+        }
+        self.traversal = traversal;
+        self
+    }
+
+    /// skip the files above the depth, like find's -mindepth
+    pub fn with_min_depth(mut self, min_depth: u32) -> Self {
+        self.min_depth = min_depth;
+        self
+    }
+
+    /// skip the files below the depth, like find's -maxdepth
+    pub fn with_max_depth(mut self, max_depth: Option<u32>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// whether the files at the depth are discovered
+    fn within_depth(&self, depth: u32) -> bool {
+        depth >= self.min_depth && self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    /// whether the files in a directory at the depth may be discovered
+    fn descend_into(&self, depth: u32) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth < max_depth)
+    }
+
+    fn handle_symlink(
+        &mut self,
+        entry: &std::fs::DirEntry,
+        dir_path: &Arc<LinkedPath>,
+        (root_device, depth): (Option<u64>, u32),
+        sink: &mut dyn InputSink,
+    ) {
         let entry_name = entry.file_name();
+        // decided before the path borrows self
+        let (within_depth, descend) = (self.within_depth(depth + 1), self.descend_into(depth + 1));
         let pop_token = push_to_path(&mut self.path_acc, &entry_name);
         let metadata = handle_follow_symlink!(std::fs::metadata(&pop_token.0), pop_token.0, return);
-        // canonicalize so that all emitted paths are absolute
-        let actual_path = handle_canonicalize!(pop_token.0, return);
-        let actual_lpath = LinkedPath::from_path_buf(&actual_path);
-        if metadata.is_file() {
-            let actual_lpath = Arc::into_inner(actual_lpath).unwrap();
+        let other_device = root_device.is_some_and(|root_device| {
+            file_identity(&metadata).is_some_and(|identity| identity.device != root_device)
+        });
+        if other_device {
+            return;
+        }
+        if metadata.is_file() && within_depth {
+            let (file_lpath, file_path) = match self.file_symlinks {
+                SymlinkTreatment::Skip => return,
+                SymlinkTreatment::Follow => {
+                    // canonicalize so that all emitted paths are absolute
+                    let actual_path = handle_canonicalize!(pop_token.0, return);
+                    let actual_lpath = LinkedPath::from_path_buf(&actual_path);
+                    (Arc::into_inner(actual_lpath).unwrap(), actual_path)
+                }
+                SymlinkTreatment::AsFiles => (
+                    LinkedPath::new_child(dir_path, entry_name),
+                    pop_token.0.clone(),
+                ),
+            };
             let keep_file = self
                 .file_filters
-                .keep_file_md(&actual_lpath, &actual_path, &metadata);
+                .keep_file_md(&file_lpath, &file_path, &metadata);
             if keep_file {
-                sink.put(actual_lpath);
+                sink.put(file_lpath);
             }
-        } else if metadata.is_dir() && self.recurse {
-            self.sources.push(actual_lpath);
+        } else if metadata.is_dir()
+            && self.recurse
+            && self.follow_symlink
+            && descend
+            && self.file_filters.keep_dir(&entry_name)
+        {
+            let actual_path = handle_canonicalize!(pop_token.0, return);
+            self.sources.push_back((
+                LinkedPath::from_path_buf(&actual_path),
+                root_device,
+                depth + 1,
+            ));
         }
     }
 
@@ -186,11 +395,15 @@ impl DiscoveringInputSource {
         &mut self,
         entry: &std::fs::DirEntry,
         dir_path: &Arc<LinkedPath>,
+        (root_device, depth): (Option<u64>, u32),
         sink: &mut dyn InputSink,
     ) {
         let file_type =
             handle_get_file_type!(entry.file_type(), self.path_acc, entry.file_name(), return);
         if file_type.is_file() {
+            if !self.within_depth(depth + 1) {
+                return;
+            }
             let file_name = entry.file_name();
             let pop_token = push_to_path(&mut self.path_acc, &file_name);
             let file_name = LinkedPath::new_child(dir_path, file_name);
@@ -200,28 +413,63 @@ impl DiscoveringInputSource {
             if keep_file {
                 sink.put(file_name);
             }
-        } else if file_type.is_dir() && self.recurse {
-            let dir_path = LinkedPath::new_child(dir_path, entry.file_name());
-            self.sources.push(Arc::new(dir_path));
-        } else if file_type.is_symlink() && self.follow_symlink {
-            self.handle_symlink(entry, sink);
+        } else if file_type.is_dir() && self.recurse && self.descend_into(depth + 1) {
+            let dir_name = entry.file_name();
+            if self.file_filters.keep_dir(&dir_name) {
+                let dir_path = LinkedPath::new_child(dir_path, dir_name);
+                self.sources
+                    .push_back((Arc::new(dir_path), root_device, depth + 1));
+            }
+        } else if file_type.is_symlink()
+            && (self.follow_symlink || self.file_symlinks != SymlinkTreatment::Skip)
+        {
+            self.handle_symlink(entry, dir_path, (root_device, depth), sink);
         }
     }
-    fn consume_one(&mut self, dir: &Arc<LinkedPath>, sink: &mut dyn InputSink) {
+    fn consume_one(
+        &mut self,
+        dir: &Arc<LinkedPath>,
+        root: (Option<u64>, u32),
+        sink: &mut dyn InputSink,
+        stop: &StopSignal,
+    ) {
         dir.write_full_to_buf(&mut self.path_acc);
         let current_dir =
             handle_access_dir!(std::fs::read_dir(&self.path_acc), self.path_acc, return);
         for entry in current_dir {
+            // the sink already reads the clock for every file
+            if stop.reason().is_some() {
+                stop.report_discovery_stopped();
+                break;
+            }
             let entry = handle_access_dir!(entry, self.path_acc, break);
-            self.consume_entry(&entry, dir, sink);
+            self.consume_entry(&entry, dir, root, sink);
         }
     }
 }
 
 impl InputSource for DiscoveringInputSource {
-    fn consume_all(&mut self, sink: &mut dyn InputSink) -> Result<(), AlreadyReportedError> {
-        while let Some(source) = self.sources.pop() {
-            self.consume_one(&source, sink);
+    fn consume_all(
+        &mut self,
+        sink: &mut dyn InputSink,
+        stop: &StopSignal,
+    ) -> Result<(), AlreadyReportedError> {
+        loop {
+            if stop.should_stop() {
+                stop.report_discovery_stopped();
+                break;
+            }
+            let source = match self.traversal {
+                Traversal::DepthFirst => self.sources.pop_back(),
+                Traversal::BreadthFirst => self.sources.pop_front(),
+            };
+            let Some((source, root_device, depth)) = source else { break };
+            let visited = self.sources.len();
+            self.consume_one(&source, (root_device, depth), sink, stop);
+            if self.traversal == Traversal::DepthFirst {
+                // the first subdirectory has to be visited next
+                self.sources.make_contiguous()[visited..].reverse();
+            }
         }
         Ok(())
     }
@@ -230,29 +478,71 @@ impl InputSource for DiscoveringInputSource {
 /// Read a list of \n-separated paths from stdin
 impl StdInSource {
     pub fn new(file_filters: FileFilter) -> Self {
-        Self { file_filters }
+        Self {
+            file_filters,
+            separator: b'\n',
+            path_maps: Vec::new(),
+        }
     }
-}
 
-impl InputSource for StdInSource {
-    fn consume_all(&mut self, sink: &mut dyn InputSink) -> Result<(), AlreadyReportedError> {
-        let source = std::io::stdin().lock();
-        for line in source.lines() {
-            let line = line.map_err(|err| {
+    /// separate the paths by \0 instead, as written by `find -print0`, so that paths may contain newlines
+    #[must_use]
+    pub fn with_nul_separator(mut self) -> Self {
+        self.separator = b'\0';
+        self
+    }
+
+    /// translate the paths below the directories of the host to where they are mounted in the container
+    #[must_use]
+    pub fn with_path_maps(mut self, path_maps: Vec<PathMap>) -> Self {
+        self.path_maps = path_maps;
+        self
+    }
+
+    pub(crate) fn consume_reader(
+        &mut self,
+        source: impl BufRead,
+        sink: &mut dyn InputSink,
+        stop: &StopSignal,
+    ) -> Result<(), AlreadyReportedError> {
+        for record in source.split(self.separator) {
+            if stop.reason().is_some() {
+                stop.report_discovery_stopped();
+                break;
+            }
+            let record = record.map_err(|err| {
                 log::error!(
                     target: crate::error_handling::DISCOVERY_ERR_TARGET,
                     "failed to read files from stdin: {err}"
                 );
                 AlreadyReportedError
             })?;
-            if line.is_empty() {
+            if record.is_empty() {
                 continue;
             }
-            let path = Arc::into_inner(LinkedPath::from_path_buf(line.as_ref())).unwrap();
-            if self.file_filters.keep_file(&path, line.as_ref()) {
+            let mut file_path = path_from_bytes(record);
+            if let Some(mapped) = self
+                .path_maps
+                .iter()
+                .find_map(|map| map.to_container(&file_path))
+            {
+                file_path = mapped;
+            }
+            let path = Arc::into_inner(LinkedPath::from_path_buf(&file_path)).unwrap();
+            if self.file_filters.keep_file(&path, &file_path) {
                 sink.put(path);
             }
         }
         Ok(())
     }
 }
+
+impl InputSource for StdInSource {
+    fn consume_all(
+        &mut self,
+        sink: &mut dyn InputSink,
+        stop: &StopSignal,
+    ) -> Result<(), AlreadyReportedError> {
+        self.consume_reader(std::io::stdin().lock(), sink, stop)
+    }
+}
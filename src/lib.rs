@@ -0,0 +1,1651 @@
+#![warn(clippy::pedantic)]
+#![allow(clippy::redundant_else, clippy::module_name_repetitions)]
+
+extern crate core;
+
+mod allow_dupes;
+mod answers;
+#[cfg(test)]
+mod common_tests;
+mod compare_report;
+mod config;
+mod content_hash;
+mod duplicate_set;
+mod error_handling;
+mod file_action;
+mod file_filters;
+mod file_set_refiner;
+mod find_copies;
+mod hash_cache;
+mod input_source;
+mod logger;
+mod manifest;
+mod os;
+mod out_sink;
+mod parse_cli;
+mod plan;
+mod report_header;
+mod seen_db;
+mod set_consumer;
+mod set_order;
+mod simulate;
+mod snapshot;
+mod spill;
+mod util;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{stderr, Stderr};
+use std::num::NonZeroU32;
+use std::ops::DerefMut;
+
+use dashmap::DashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error_handling::ErrorSummary;
+use crate::file_set_refiner::{CheckEqualsErrorOn, CompareFile, FileSetRefiners, FileWorkload};
+use crate::hash_cache::HashCache;
+use crate::input_source::{
+    BudgetedInputSink, ChannelInputSink, DedupingInputSink, ProgressInputSink, StoppingInputSink,
+};
+use crate::logger::LogTimestamps;
+use crate::out_sink::ResultsOutput;
+use log::LevelFilter;
+
+use crate::parse_cli::{
+    ApplyPlan, DiffManifests, DismissSet, ExecutionPlan, FindCopies, Invocation,
+};
+use crate::set_consumer::{DryRun, FileSetConsumer, SetSummary, SyncWriterSink};
+use crate::set_order::SymlinkSetOrder;
+use crate::simulate::Simulation;
+use crate::snapshot::Snapshots;
+use crate::spill::SpilledClasses;
+use crate::util::{count_of, MemoryBudget, ProgressLog, StatusLineWriter, StopReason, StopSignal};
+
+pub use crate::config::{find_duplicates, Config};
+pub use crate::content_hash::HashAlgorithm;
+pub use crate::duplicate_set::{DuplicateSet, SetProvenance};
+pub use crate::error_handling::AlreadyReportedError;
+pub use crate::file_action::{DebugFileAction, FileConsumeAction, FileConsumeResult};
+pub use crate::file_filters::{FileFilter, FileMetadataFilter, FileNameFilter};
+pub use crate::input_source::{InputSink, InputSource};
+pub use crate::os::FileIdentity;
+pub use crate::set_order::SetOrder;
+pub use crate::util::LinkedPath;
+
+pub enum Recoverable<R, F> {
+    Recoverable(R),
+    Fatal(F),
+}
+
+enum HashFileError {
+    IO(std::io::Error),
+    FileChanged,
+    /// the run was stopped while the file was hashed
+    Stopped,
+    /// hashing the file took longer than `--file-timeout`
+    TimedOut,
+}
+
+impl From<std::io::Error> for HashFileError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+/// the part of each file which is hashed into the digest the files are grouped by
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HashScope {
+    Full,
+    /// the size and this many leading bytes
+    Prefix(u64),
+    /// the size and this many leading bytes, the entire content of the files which share them is hashed
+    /// once a second file shares them
+    PrefixBuckets(u64),
+    /// the size and this many evenly spaced blocks of the size, the first at the start and the last at the end
+    Sampled {
+        blocks: u64,
+        block_size: u64,
+    },
+}
+
+impl HashScope {
+    /// the number of bytes hashed of a file of this length
+    pub(crate) fn hashed_bytes(self, len: u64) -> u64 {
+        match self {
+            HashScope::Full => len,
+            HashScope::Prefix(limit) | HashScope::PrefixBuckets(limit) => len.min(limit),
+            HashScope::Sampled { blocks, block_size } => blocks.saturating_mul(block_size).min(len),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HashedFile {
+    file_version_timestamp: Option<SystemTime>,
+    /// the hash the content had when the file was placed into its set
+    content_hash: u128,
+    file_path: LinkedPath,
+    /// the file behind the path when it was placed into its set, None if it could not be told
+    identity: Option<FileIdentity>,
+    /// the size of the file when it was hashed, None if the file was not looked at
+    size: Option<u64>,
+}
+
+impl HashedFile {
+    /// the path the file was found under
+    #[must_use]
+    pub fn file_path(&self) -> &LinkedPath {
+        &self.file_path
+    }
+
+    /// the modification time of the file when it was hashed
+    #[must_use]
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.file_version_timestamp
+    }
+
+    /// the file behind the path when it was hashed, hard links to one file share it
+    #[must_use]
+    pub fn identity(&self) -> Option<FileIdentity> {
+        self.identity
+    }
+
+    /// the size of the file when it was hashed, None if the file was not looked at
+    #[must_use]
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+}
+
+/// the state of a file when it was hashed
+#[derive(Clone, Copy)]
+struct FileStamp {
+    /// unavailable on some platforms
+    modified: Option<SystemTime>,
+    size: u64,
+    /// the identity of the file which was hashed, None where the metadata does not give it
+    identity: Option<FileIdentity>,
+}
+
+pub type BoxErr = Box<dyn std::error::Error>;
+
+/// the exit code if the run was cut short by `--timeout`, same as coreutils' timeout
+const TIMEOUT_EXIT_CODE: i32 = 124;
+/// the exit code of shells for programs ended by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+/// the exit code if the consumer could not complete its result, like an audit which missed files
+const FAILED_EXIT_CODE: i32 = 1;
+
+/// the discovered files waiting to be hashed per hashing thread, unless `--queue-depth` is given
+const QUEUE_DEPTH_PER_THREAD: usize = 64;
+/// how often a worker waiting on a file with `--file-timeout` checks whether the run was stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// run the command line program with the arguments of the process
+/// # Panics
+/// if another logger was installed already
+pub fn run() {
+    match parse_cli::parse() {
+        Invocation::Scan(mut execution_plan) => match execution_plan.simulation.take() {
+            Some(simulation) => simulate(simulation, *execution_plan),
+            None => scan(*execution_plan),
+        },
+        Invocation::Apply(ApplyPlan { plan, mut actions }) => {
+            logger::DuplisLogger::init(Vec::new(), LevelFilter::Trace, Box::new(stderr())).unwrap();
+            exit_on_failure(plan::apply_plan(&plan, &mut actions));
+        }
+        Invocation::Diff(DiffManifests { a, b, algorithm }) => {
+            logger::DuplisLogger::init(Vec::new(), LevelFilter::Trace, Box::new(stderr())).unwrap();
+            let diffed =
+                manifest::diff_manifests(&a, &b, algorithm.name(), &mut os::console_output());
+            exit_on_failure(diffed);
+        }
+        Invocation::FindCopies(FindCopies { target, dirs }) => {
+            logger::DuplisLogger::init(Vec::new(), LevelFilter::Trace, Box::new(stderr())).unwrap();
+            let found = find_copies::find_copies(&target, dirs, &mut os::console_output());
+            exit_on_failure(found);
+        }
+        Invocation::Dismiss(DismissSet { seen_db, files }) => {
+            logger::DuplisLogger::init(Vec::new(), LevelFilter::Trace, Box::new(stderr())).unwrap();
+            let dismissed =
+                seen_db::SeenDb::open(&seen_db).and_then(|mut seen| seen.dismiss(&files));
+            if let Err(err) = dismissed {
+                log::error!(
+                    target: error_handling::CONFIG_ERR_TARGET,
+                    "cannot record the set in {}: {err}",
+                    seen_db.display()
+                );
+            }
+        }
+    }
+    // the log is buffered
+    log::logger().flush();
+}
+
+/// end the program with [`FAILED_EXIT_CODE`] if the command failed, the reason is reported already
+fn exit_on_failure(result: Result<(), AlreadyReportedError>) {
+    if result.is_err() {
+        log::logger().flush();
+        std::process::exit(FAILED_EXIT_CODE);
+    }
+}
+
+fn init_logging(
+    ignore_log_set: Vec<String>,
+    log_timestamps: Option<LogTimestamps>,
+    config_warnings: Vec<String>,
+    write: Box<dyn std::io::Write + Send>,
+) {
+    logger::DuplisLogger::new(ignore_log_set, LevelFilter::Trace, write)
+        .with_timestamps(log_timestamps)
+        .install()
+        .unwrap();
+    for warning in config_warnings {
+        log::warn!(target: crate::error_handling::CONFIG_ERR_TARGET, "{warning}");
+    }
+}
+
+/// hand the sets of the manifest to the orders and the action of the plan, the rest of the plan is ignored
+fn simulate(simulation: Simulation, mut execution_plan: ExecutionPlan) {
+    let stop = run_stop_signal(execution_plan.timeout, execution_plan.stop_after);
+    init_logging(
+        execution_plan.ignore_log_set,
+        execution_plan.log_timestamps,
+        execution_plan.config_warnings,
+        Box::new(stderr()),
+    );
+    let mut consumed = Ok(());
+    let mut summary = SetSummary::new(execution_plan.size_format);
+    if let Ok(sets) = simulation.sets() {
+        consumed = consume_sets(
+            sets.into_iter(),
+            &mut execution_plan.search.orders,
+            execution_plan.action.as_mut(),
+            &stop,
+            &mut summary,
+        );
+    }
+    summary.report();
+    execution_plan.errors.report();
+    if consumed.is_err() {
+        drop(execution_plan.action);
+        log::logger().flush();
+        std::process::exit(FAILED_EXIT_CODE);
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn scan(execution_plan: ExecutionPlan) {
+    // the data required to run the program
+    let ExecutionPlan {
+        mut search,
+        action: mut file_set_action,
+        content_threads,
+        ignore_log_set,
+        log_timestamps,
+        dedup_files,
+        memory_budget,
+        timeout,
+        file_timeout,
+        stop_after,
+        manifest,
+        progress,
+        group_hard_links,
+        queue_depth,
+        snapshots,
+        stream,
+        config_warnings,
+        errors,
+        hash_cache,
+        size_format,
+        simulation: _,
+        output,
+    } = execution_plan;
+    let stop = run_stop_signal(timeout, stop_after);
+
+    // the live progress line is kept below the log records
+    let status_stderr = StatusLineWriter::new(stderr());
+    init_logging(
+        ignore_log_set,
+        log_timestamps,
+        config_warnings,
+        Box::new(status_stderr.clone()),
+    );
+
+    let input_sources = search.take_input_sources();
+    let mut set_refiners = search.take_set_refiners().with_stop(stop.clone());
+    let Config {
+        orders: mut order_set,
+        num_threads,
+        hash_scope,
+        hash_algorithm,
+        ..
+    } = search;
+    // the hashing threads only run the cheap refiners, the content is compared afterwards
+    let content_refiners = content_threads
+        .map(|threads| (set_refiners.split_off(FileWorkload::FileContent), threads))
+        .filter(|(refiners, _)| !refiners.is_empty());
+    order_set.push(Box::<SymlinkSetOrder>::default());
+    // within a memory budget, the size classes which do not fit wait on disk until they are hashed
+    let by_class = stream || memory_budget.is_some();
+    let (files_send, files_rev) = files_channel(num_threads, queue_depth, by_class);
+    let timeout_pool = file_timeout.map(|_| FileTimeoutPool::new(num_threads));
+
+    let input_sink = assemble_input_sink(
+        files_send,
+        memory_budget.as_ref(),
+        &stop,
+        dedup_files,
+        progress
+            .as_ref()
+            .map(|progress| (progress, hash_scope, &status_stderr)),
+        by_class,
+    );
+    let shared = WorkerShared {
+        budget: memory_budget.as_deref(),
+        stop: &stop,
+        errors: &errors,
+        hash_scope,
+        hash_algorithm,
+        file_timeout: file_timeout.zip(timeout_pool.as_ref()),
+        cache: hash_cache.as_ref(),
+        manifest: manifest.as_ref(),
+        progress: progress.as_deref(),
+        group_hard_links,
+    };
+
+    let mut consumed = Ok(());
+    let mut summary = SetSummary::new(size_format);
+    if stream {
+        stream_file_sets(
+            input_sources,
+            input_sink,
+            files_rev,
+            &set_refiners,
+            num_threads,
+            shared,
+            |sets| {
+                let sets = sets.map(|set| live_files(snapshots.as_ref(), set));
+                consumed = consume_sets(
+                    sets,
+                    &mut order_set,
+                    file_set_action.as_mut(),
+                    &stop,
+                    &mut summary,
+                );
+            },
+        );
+        if let Some(progress) = &progress {
+            progress.finish();
+        }
+    } else {
+        let target = if by_class {
+            find_file_sets_by_class(
+                input_sources,
+                input_sink,
+                files_rev,
+                &set_refiners,
+                num_threads,
+                shared,
+            )
+        } else {
+            find_file_sets(
+                input_sources,
+                input_sink,
+                files_rev,
+                set_refiners,
+                num_threads,
+                shared,
+            )
+        };
+        if let Some(progress) = &progress {
+            progress.finish();
+        }
+        // waits for the manifest to be written completely
+        drop(manifest);
+        if let Some(dry_run) = dry_run_if_cut_short(&stop, file_set_action.as_ref(), &output) {
+            file_set_action = dry_run;
+        }
+        let sets = flatten_file_sets(target, content_refiners, group_hard_links)
+            .map(|set| live_files(snapshots.as_ref(), set));
+        consumed = consume_sets(
+            sets,
+            &mut order_set,
+            file_set_action.as_mut(),
+            &stop,
+            &mut summary,
+        );
+    }
+    summary.report();
+    errors.report();
+    let dropped = memory_budget.as_deref().map_or(0, MemoryBudget::dropped);
+    if dropped > 0 {
+        log::error!(
+            target: crate::error_handling::DISCOVERY_ERR_TARGET,
+            "skipped {} which did not fit into --max-memory and could not be kept on disk",
+            count_of(u64::try_from(dropped).unwrap_or(u64::MAX), "file")
+        );
+    }
+    save_hash_cache(hash_cache.as_ref());
+    // the sets may lack the skipped files
+    let exit_code = cut_short_exit_code(&stop)
+        .or(consumed.err().map(|_| FAILED_EXIT_CODE))
+        .or((dropped > 0).then_some(FAILED_EXIT_CODE));
+    if let Some(exit_code) = exit_code {
+        drop(file_set_action);
+        drop(snapshots);
+        log::logger().flush();
+        std::process::exit(exit_code);
+    }
+}
+
+/// a run which did not get to see all files must not act destructively on the incomplete sets
+fn dry_run_if_cut_short(
+    stop: &StopSignal,
+    action: &dyn FileSetConsumer,
+    output: &ResultsOutput,
+) -> Option<Box<dyn FileSetConsumer>> {
+    let cut_short = stop.reason().filter(|r| r.leaves_sets_incomplete());
+    let reason = match cut_short.filter(|_| action.is_destructive())? {
+        StopReason::Interrupted => "was interrupted",
+        _ => "timed out",
+    };
+    log::warn!(
+        target: crate::error_handling::CONFIG_ERR_TARGET,
+        "the run {reason}; only reporting the sets found so far instead of acting on them"
+    );
+    Some(Box::new(DryRun::for_output(output)))
+}
+
+/// the stop of the run, stopped by the timeout, enough sets or an interrupt
+fn run_stop_signal(timeout: Option<Duration>, stop_after: Option<u64>) -> Arc<StopSignal> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let stop = Arc::new(StopSignal::new(deadline, stop_after));
+    stop.stop_on_interrupt();
+    stop
+}
+
+fn save_hash_cache(cache: Option<&HashCache>) {
+    let Some(cache) = cache else { return };
+    if let Err(err) = cache.save() {
+        log::error!(
+            target: crate::error_handling::CONFIG_ERR_TARGET,
+            "cannot save the hash cache {}: {err}",
+            cache.path().display()
+        );
+    }
+}
+
+/// the exit code if the run did not get to see all files
+fn cut_short_exit_code(stop: &StopSignal) -> Option<i32> {
+    if stop.is_interrupted() {
+        Some(INTERRUPTED_EXIT_CODE)
+    } else if stop.reason() == Some(StopReason::Timeout) {
+        Some(TIMEOUT_EXIT_CODE)
+    } else {
+        None
+    }
+}
+
+fn files_channel(
+    num_threads: NonZeroU32,
+    queue_depth: Option<usize>,
+    by_class: bool,
+) -> (flume::Sender<LinkedPath>, flume::Receiver<LinkedPath>) {
+    // if don't thread we want essentially a list, if we thread, there is no harm in keeping then backlog in check;
+    // the files sorted into their size classes are sorted while they are discovered
+    if num_threads.get() > 1 || by_class {
+        flume::bounded(queue_depth.unwrap_or(QUEUE_DEPTH_PER_THREAD * num_threads.get() as usize))
+    } else {
+        flume::unbounded()
+    }
+}
+
+/// the sets of the course sets; `group_hard_links` if the files were grouped by the file they link to
+fn flatten_file_sets(
+    target: DashMap<u128, Vec<(u128, Vec<HashedFile>)>>,
+    content_refiners: Option<(FileSetRefiners, NonZeroU32)>,
+    group_hard_links: bool,
+) -> Box<dyn Iterator<Item = DuplicateSet>> {
+    let provenance = placed_provenance(group_hard_links);
+    let sets = target
+        .into_iter()
+        // the key of the course set is the hash of the content and the refined properties
+        .flat_map(|(key, sets)| sets.into_iter().map(move |(_, set)| (key, set)));
+    let to_set = move |(key, set)| DuplicateSet::from_key(key, set, provenance);
+    match content_refiners {
+        Some((refiners, threads)) => Box::new(
+            refine_file_sets(sets, &refiners, threads)
+                .into_iter()
+                .map(to_set),
+        ),
+        None => Box::new(sets.map(to_set)),
+    }
+}
+
+fn live_files(snapshots: Option<&Snapshots>, mut set: DuplicateSet) -> DuplicateSet {
+    if let Some(snapshots) = snapshots {
+        set.files = snapshots.live_files(set.files);
+    }
+    set
+}
+
+/// order the sets and hand them to the action, stops after `stop_after` sets, when the action aborts or
+/// the user interrupts; finishes the action unless it aborted. The handed on sets are counted into the summary
+fn consume_sets(
+    sets: impl Iterator<Item = DuplicateSet>,
+    order_set: &mut [Box<dyn SetOrder + Send>],
+    file_set_action: &mut dyn FileSetConsumer,
+    stop: &StopSignal,
+    summary: &mut SetSummary,
+) -> Result<(), AlreadyReportedError> {
+    let stop_after = stop.stop_after_sets();
+    // an interrupt during hashing only cuts the hashing short, the sets found so far are still consumed
+    let interrupted_before = stop.is_interrupted();
+    let lone_files = file_set_action.consumes_lone_files();
+    let mut consumed_sets = 0;
+    for mut set in sets {
+        if !interrupted_before && stop.is_interrupted() {
+            break;
+        }
+        let lone_file = lone_files && set.files.len() == 1;
+        if !order_files(order_set, &mut set) && !lone_file {
+            continue;
+        }
+
+        if stop_after.is_some_and(|max| consumed_sets >= max) {
+            break;
+        }
+        consumed_sets += 1;
+        summary.add(&set);
+        if let Err(AlreadyReportedError) = file_set_action.consume_set(set) {
+            // the consumer gave up on its own, which is no failure of its result
+            return Ok(());
+        }
+    }
+    file_set_action.finish()
+}
+
+/// order the files of a set so that the original comes first; false if less than two files are left
+fn order_files(order_set: &mut [Box<dyn SetOrder + Send>], set: &mut DuplicateSet) -> bool {
+    if set.files.len() <= 1 {
+        return false;
+    }
+    for order in order_set.iter_mut() {
+        if let Err(AlreadyReportedError {}) = order.order(&mut set.files) {
+            break;
+        }
+    }
+    set.files.len() > 1
+}
+
+fn assemble_input_sink(
+    files_send: flume::Sender<LinkedPath>,
+    memory_budget: Option<&Arc<MemoryBudget>>,
+    stop: &Arc<StopSignal>,
+    dedup_files: bool,
+    progress: Option<(&Arc<ProgressLog>, HashScope, &StatusLineWriter<Stderr>)>,
+    by_class: bool,
+) -> Box<dyn InputSink + Send> {
+    let mut input_sink: Box<dyn InputSink + Send> = Box::new(ChannelInputSink::new(files_send));
+    if let Some((progress, hash_scope, stderr)) = progress {
+        // the live line shows the files from their discovery on
+        progress.start_live(stderr);
+        // the size classes count their files by the metadata they read anyway
+        if !by_class {
+            input_sink = Box::new(ProgressInputSink::new(
+                progress.clone(),
+                hash_scope,
+                input_sink,
+            ));
+        }
+    }
+    if let Some(budget) = memory_budget {
+        input_sink = Box::new(BudgetedInputSink::new(budget.clone(), input_sink));
+    }
+    input_sink = Box::new(StoppingInputSink::new(stop.clone(), input_sink));
+    if dedup_files {
+        input_sink = Box::new(DedupingInputSink::new(input_sink));
+    }
+    input_sink
+}
+
+/// state shared between the hashing workers
+#[derive(Copy, Clone)]
+struct WorkerShared<'a> {
+    budget: Option<&'a MemoryBudget>,
+    stop: &'a StopSignal,
+    errors: &'a ErrorSummary,
+    hash_scope: HashScope,
+    hash_algorithm: HashAlgorithm,
+    /// skip a file if hashing it on the pool takes longer
+    file_timeout: Option<(Duration, &'a FileTimeoutPool)>,
+    /// the hashes of unchanged files are taken from it instead of hashing the files again
+    cache: Option<&'a HashCache>,
+    /// every hashed file is written into the manifest
+    manifest: Option<&'a SyncWriterSink>,
+    progress: Option<&'a ProgressLog>,
+    /// place the files by the file they link to instead of by their content
+    group_hard_links: bool,
+}
+
+/// how the files of the placed sets are found to be duplicates
+fn placed_provenance(group_hard_links: bool) -> SetProvenance {
+    if group_hard_links {
+        SetProvenance::HardLinks
+    } else {
+        SetProvenance::Content
+    }
+}
+
+fn find_file_sets(
+    input_sources: Vec<Box<dyn InputSource>>,
+    mut input_sink: Box<dyn InputSink + Send>,
+    files_rev: flume::Receiver<LinkedPath>,
+    set_refiners: FileSetRefiners,
+    num_threads: NonZeroU32,
+    shared: WorkerShared,
+) -> DashMap<u128, Vec<(u128, Vec<HashedFile>)>> {
+    let target: DashMap<u128, Vec<(u128, Vec<HashedFile>)>> = DashMap::new();
+
+    std::thread::scope(|s| {
+        if num_threads.get() > 1 {
+            // spawn n - 1 threads, if is for clarity
+            for t in 1..num_threads.get() {
+                let set_refiners = set_refiners.clone();
+                let files_rev = files_rev.clone();
+                let target = &target;
+                let thread = std::thread::Builder::new()
+                    .name(format!("file_hash_worker_{t}"))
+                    .spawn_scoped(s, move || {
+                        place_files_to_set(set_refiners, files_rev, target, shared);
+                    });
+                if let Err(err) = thread {
+                    log::error!(target: crate::error_handling::CONFIG_ERR_TARGET, "threading not supported on this platform; please do not use the threading option({err})");
+                    return;
+                }
+            }
+        }
+        for mut source in input_sources {
+            let _ = source.consume_all(input_sink.as_mut(), shared.stop);
+        }
+
+        drop(input_sink);
+
+        if num_threads.get() == 1 {
+            place_files_to_set(set_refiners, files_rev, &target, shared);
+        }
+    });
+    target
+}
+
+/// sorts the files into their size classes once they are discovered and hashes the classes one after another,
+/// so that the classes beyond the memory budget can wait on disk
+fn find_file_sets_by_class(
+    input_sources: Vec<Box<dyn InputSource>>,
+    input_sink: Box<dyn InputSink + Send>,
+    files_rev: flume::Receiver<LinkedPath>,
+    set_refiners: &FileSetRefiners,
+    num_threads: NonZeroU32,
+    shared: WorkerShared,
+) -> DashMap<u128, Vec<(u128, Vec<HashedFile>)>> {
+    let (classes, spilled) = classify_files(input_sources, input_sink, files_rev, shared);
+    let target: DashMap<u128, Vec<(u128, Vec<HashedFile>)>> = DashMap::new();
+    let place = |key, set| {
+        target.entry(key).or_default().push(set);
+        true
+    };
+    hash_classes(classes, spilled, set_refiners, num_threads, shared, &place);
+    target
+}
+
+/// hashes the files one size class after another and hands the sets of every class to `consume` as soon as
+/// the class is complete, while the other classes are still being hashed
+fn stream_file_sets(
+    input_sources: Vec<Box<dyn InputSource>>,
+    input_sink: Box<dyn InputSink + Send>,
+    files_rev: flume::Receiver<LinkedPath>,
+    set_refiners: &FileSetRefiners,
+    num_threads: NonZeroU32,
+    shared: WorkerShared,
+    consume: impl FnOnce(&mut dyn Iterator<Item = DuplicateSet>),
+) {
+    let (classes, spilled) = classify_files(input_sources, input_sink, files_rev, shared);
+    let (sets_send, sets_rev) = flume::unbounded();
+    std::thread::scope(|s| {
+        let place = move |key, (_, set)| {
+            let set = DuplicateSet::from_key(key, set, placed_provenance(shared.group_hard_links));
+            sets_send.send(set).is_ok()
+        };
+        let set_refiners = set_refiners.clone();
+        let hashing = std::thread::Builder::new()
+            .name(String::from("size_class_hashing"))
+            .spawn_scoped(s, move || {
+                hash_classes(classes, spilled, &set_refiners, num_threads, shared, &place);
+            });
+        if let Err(err) = hashing {
+            log::error!(target: crate::error_handling::CONFIG_ERR_TARGET, "threading not supported on this platform; please do not use the threading option({err})");
+        }
+        // the consumed sets give their memory back to the budget
+        consume(
+            &mut sets_rev
+                .iter()
+                .inspect(|set| release_files(shared.budget, &set.files)),
+        );
+        // the workers stop once nobody takes their sets anymore
+        drop(sets_rev);
+    });
+}
+
+/// discovers the files and sorts them into their size classes on the way
+fn classify_files(
+    input_sources: Vec<Box<dyn InputSource>>,
+    mut input_sink: Box<dyn InputSink + Send>,
+    files_rev: flume::Receiver<LinkedPath>,
+    shared: WorkerShared,
+) -> (Vec<Vec<LinkedPath>>, SpilledClasses) {
+    // a size class can only be complete once every file has been discovered
+    std::thread::scope(|s| {
+        // the channel is bounded, the files are sorted into their classes while they are discovered
+        let classify = std::thread::Builder::new()
+            .name(String::from("size_classes"))
+            .spawn_scoped(s, move || size_classes(files_rev, shared));
+        match &classify {
+            // nobody would take the discovered files
+            Err(err) => {
+                log::error!(target: crate::error_handling::CONFIG_ERR_TARGET, "threading not supported on this platform; please do not use the threading option({err})");
+            }
+            Ok(_) => {
+                for mut source in input_sources {
+                    let _ = source.consume_all(input_sink.as_mut(), shared.stop);
+                }
+            }
+        }
+        drop(input_sink);
+        classify
+            .ok()
+            .and_then(|classify| classify.join().ok())
+            .unwrap_or_default()
+    })
+}
+
+/// groups the files by their size, smallest first, and drops the files whose size no other file has; the
+/// files which will be hashed are counted for the progress as soon as another file has their size. Once the
+/// memory budget is exceeded, the classes are spilled to disk, followed by the later files of their size
+fn size_classes(
+    files: impl IntoIterator<Item = LinkedPath>,
+    shared: WorkerShared,
+) -> (Vec<Vec<LinkedPath>>, SpilledClasses) {
+    let release = |file: &LinkedPath| {
+        if let Some(budget) = shared.budget {
+            budget.release(MemoryBudget::file_cost(file));
+        }
+    };
+    // the file is sorted in, discovery may go on
+    let finish_work = || {
+        if let Some(budget) = shared.budget {
+            budget.finish_work();
+        }
+    };
+    let spill = |spilled: &mut SpilledClasses, size, file: &LinkedPath| {
+        let spilled_file = spilled.spill(size, file);
+        release(file);
+        // the file is neither kept nor on disk
+        if let (false, Some(budget)) = (spilled_file, shared.budget) {
+            budget.files_dropped(1);
+        }
+        spilled_file
+    };
+    let mut path_buf = PathBuf::new();
+    let mut classes: BTreeMap<u64, Vec<LinkedPath>> = BTreeMap::new();
+    let mut spilled = SpilledClasses::new();
+    for file in files {
+        file.write_full_to_buf(&mut path_buf);
+        let metadata = handle_metadata_op!(std::fs::metadata(&path_buf), &path_buf, {
+            release(&file);
+            finish_work();
+            continue;
+        });
+        let size = metadata.len();
+        let class_len = if spilled.files(size) > 0 {
+            spill(&mut spilled, size, &file).then(|| spilled.files(size))
+        } else {
+            let class = classes.entry(size).or_default();
+            class.push(file);
+            Some(class.len())
+        };
+        if let (Some(progress), Some(class_len)) = (shared.progress, class_len) {
+            let bytes = shared.hash_scope.hashed_bytes(size);
+            // the first file of the class is counted along with the second
+            let counted = match class_len {
+                1 => 0,
+                2 => 2,
+                _ => 1,
+            };
+            for _ in 0..counted {
+                progress.file_discovered(bytes);
+            }
+        }
+        // the classes stay in memory if they cannot be written
+        if shared.budget.is_some_and(MemoryBudget::exceeded) && !spilled.failed() {
+            for (size, class) in std::mem::take(&mut classes) {
+                for file in class {
+                    spill(&mut spilled, size, &file);
+                }
+            }
+        }
+        finish_work();
+    }
+    let classes = classes
+        .into_values()
+        .filter(|class| {
+            if let [single] = class.as_slice() {
+                release(single);
+            }
+            class.len() > 1
+        })
+        .collect();
+    (classes, spilled)
+}
+
+/// hashes the size classes on the workers and hands the sets of every class to `place`; the spilled classes
+/// are read back once the memory budget has room for them
+fn hash_classes(
+    classes: Vec<Vec<LinkedPath>>,
+    spilled: SpilledClasses,
+    set_refiners: &FileSetRefiners,
+    num_threads: NonZeroU32,
+    shared: WorkerShared,
+    place: &(dyn Fn(u128, (u128, Vec<HashedFile>)) -> bool + Sync),
+) {
+    std::thread::scope(|s| {
+        // a class is only handed over, or read back from disk, once a worker is free to take it
+        let (classes_send, classes_rev) = flume::bounded(0);
+        for t in 0..num_threads.get() {
+            let set_refiners = set_refiners.clone();
+            let classes_rev = classes_rev.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("file_hash_worker_{t}"))
+                .spawn_scoped(s, move || {
+                    hash_size_classes(set_refiners, classes_rev, place, shared);
+                });
+            if let Err(err) = thread {
+                log::error!(target: crate::error_handling::CONFIG_ERR_TARGET, "threading not supported on this platform; please do not use the threading option({err})");
+                break;
+            }
+        }
+        drop(classes_rev);
+        load_classes(classes, spilled, &classes_send, shared);
+    });
+}
+
+/// hands the classes to the workers and reads the spilled classes back in batches which fit into the
+/// memory budget
+fn load_classes(
+    classes: Vec<Vec<LinkedPath>>,
+    mut spilled: SpilledClasses,
+    classes_send: &flume::Sender<Vec<LinkedPath>>,
+    shared: WorkerShared,
+) {
+    for class in classes {
+        if classes_send.send(class).is_err() {
+            return;
+        }
+    }
+    let limit = shared.budget.map_or(usize::MAX, MemoryBudget::limit);
+    let batches = spilled.batches(limit);
+    for (i, (sizes, cost)) in batches.iter().enumerate() {
+        if let Some(budget) = shared.budget {
+            budget.charge(*cost);
+        }
+        let batch = match spilled.read_classes(sizes) {
+            Ok(batch) => batch,
+            Err(err) => {
+                log::error!(
+                    target: crate::error_handling::DISCOVERY_ERR_TARGET,
+                    "cannot read back the size classes spilled to {}: {err}; their files are skipped",
+                    spilled.path().display()
+                );
+                if let Some(budget) = shared.budget {
+                    budget.release(*cost);
+                    let sizes = batches[i..].iter().flat_map(|(sizes, _)| sizes);
+                    budget.files_dropped(sizes.map(|size| spilled.files(*size)).sum());
+                }
+                return;
+            }
+        };
+        for class in batch {
+            if classes_send.send(class).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// gives the memory of the files back to the budget
+fn release_files(budget: Option<&MemoryBudget>, files: &[HashedFile]) {
+    if let Some(budget) = budget {
+        let cost = files
+            .iter()
+            .map(|file| MemoryBudget::file_cost(&file.file_path));
+        budget.release(cost.sum());
+    }
+}
+
+/// hashes every size class on its own and hands its sets to `place` once all of its files are placed
+fn hash_size_classes(
+    mut set_refiners: FileSetRefiners,
+    classes: flume::Receiver<Vec<LinkedPath>>,
+    place: &(dyn Fn(u128, (u128, Vec<HashedFile>)) -> bool + Sync),
+    shared: WorkerShared,
+) {
+    let mut path_buf = PathBuf::new();
+    let mut path_buf_tmp = PathBuf::new();
+    let mut manifest = shared.manifest.cloned();
+
+    for class in classes {
+        // the loader waits for the class before it reads more classes back from disk
+        if let Some(budget) = shared.budget {
+            budget.start_work();
+        }
+        let mut target: HashMap<u128, Vec<(u128, Vec<HashedFile>)>> = HashMap::new();
+        for file_path in class {
+            if shared.stop.should_stop() {
+                break;
+            }
+            place_file(
+                file_path,
+                (&mut path_buf, &mut path_buf_tmp),
+                &mut set_refiners,
+                manifest.as_mut(),
+                shared,
+                |hash| target.entry(hash).or_default(),
+            );
+        }
+        // the class may lack files which are part of its sets
+        let reason = shared.stop.reason();
+        let placed = !reason.is_some_and(StopReason::leaves_sets_incomplete)
+            && target.into_iter().all(|(key, sets)| {
+                sets.into_iter().all(|set| {
+                    if set.1.len() > 1 {
+                        place(key, set)
+                    } else {
+                        release_files(shared.budget, &set.1);
+                        true
+                    }
+                })
+            });
+        if let Some(budget) = shared.budget {
+            budget.finish_work();
+        }
+        if !placed {
+            return;
+        }
+    }
+}
+
+fn place_files_to_set(
+    mut set_refiners: FileSetRefiners,
+    files: flume::Receiver<LinkedPath>,
+    target: &DashMap<u128, Vec<(u128, Vec<HashedFile>)>>,
+    shared: WorkerShared,
+) {
+    let mut path_buf = PathBuf::new();
+    let mut path_buf_tmp = PathBuf::new();
+    let mut manifest = shared.manifest.cloned();
+
+    for file_path in files {
+        // keep draining the channel after stopping so that discovery never blocks on it
+        if shared.stop.should_stop() {
+            continue;
+        }
+        place_file(
+            file_path,
+            (&mut path_buf, &mut path_buf_tmp),
+            &mut set_refiners,
+            manifest.as_mut(),
+            shared,
+            |hash| target.entry(hash).or_default(),
+        );
+    }
+}
+
+/// places the file into its set and keeps the stop signal and the memory budget up to date
+fn place_file<R, F>(
+    file_path: LinkedPath,
+    (path_buf, path_buf_tmp): (&mut PathBuf, &mut PathBuf),
+    set_refiners: &mut FileSetRefiners,
+    manifest: Option<&mut SyncWriterSink>,
+    shared: WorkerShared,
+    find_set: F,
+) where
+    R: DerefMut<Target = Vec<(u128, Vec<HashedFile>)>>,
+    F: FnOnce(u128) -> R,
+{
+    file_path.write_full_to_buf(path_buf);
+    let cost = MemoryBudget::file_cost(&file_path);
+    let placed = if shared.group_hard_links {
+        place_into_link_group(file_path, path_buf, find_set)
+    } else {
+        place_into_file_set(
+            file_path,
+            path_buf,
+            path_buf_tmp,
+            set_refiners,
+            manifest,
+            shared,
+            find_set,
+        )
+    };
+    match placed {
+        Ok(true) => shared.stop.set_confirmed(),
+        Ok(false) => {}
+        Err(AlreadyReportedError) => {
+            if let Some(budget) = shared.budget {
+                budget.release(cost);
+            }
+            // the files left behind by a stopped run are not failures of their own
+            if shared.stop.reason().is_none() {
+                shared.errors.file_failed();
+            }
+        }
+    }
+}
+
+/// returns true if the file completed a new duplicate set
+fn place_into_file_set<R, F>(
+    file_path: LinkedPath,
+    file: &Path,
+    tmp_buf: &mut PathBuf,
+    refiners: &mut FileSetRefiners,
+    manifest: Option<&mut SyncWriterSink>,
+    shared: WorkerShared,
+    find_set: F,
+) -> Result<bool, AlreadyReportedError>
+where
+    R: DerefMut<Target = Vec<(u128, Vec<HashedFile>)>>,
+    F: FnOnce(u128) -> R,
+{
+    let (file_hash, stamp) = hash_for_set(file, shared)?;
+    if let Some(manifest) = manifest {
+        manifest::write_manifest_line(manifest, file_hash, file)?;
+    }
+
+    let mut course_set = find_set(course_set_key(file_hash, refiners, file)?);
+    let course_set = &mut *course_set;
+
+    let (file_hash, stamp) = match shared.hash_scope {
+        // a file alone in its bucket is never hashed entirely
+        HashScope::PrefixBuckets(_) if !course_set.is_empty() => {
+            let full = WorkerShared {
+                hash_scope: HashScope::Full,
+                ..shared
+            };
+            full_hash_lone_file(course_set, full);
+            if course_set.is_empty() {
+                (file_hash, stamp)
+            } else {
+                hash_for_set(file, full)?
+            }
+        }
+        _ => (file_hash, stamp),
+    };
+
+    // the platforms whose metadata lacks the identity look it up by the path
+    let identity = stamp.identity.or_else(|| os::link_identity(file));
+    let hashed = HashedFile {
+        file_version_timestamp: stamp.modified,
+        content_hash: file_hash,
+        file_path,
+        identity,
+        size: Some(stamp.size),
+    };
+    // every set with this hash is probed, the refiners may tell the files of equal hash apart
+    for (_, set) in course_set
+        .iter_mut()
+        .filter(|(shash, _)| *shash == file_hash)
+    {
+        let compared = CompareFile::new(file, Some(stamp.size), identity);
+        // a file the run stopped comparing is left out rather than taken for different from the set
+        let fits = fits_into_file_set(set, compared, tmp_buf, refiners);
+        if fits.map_err(|_| AlreadyReportedError)? {
+            set.push(hashed);
+            return Ok(set.len() == 2);
+        }
+    }
+    // no file in the course set has this content or the refiners tell the file apart from all files of
+    // its hash, so it starts a set of its own; the sets whose files all failed are dropped
+    course_set.retain(|(_, set)| !set.is_empty());
+    course_set.push((file_hash, vec![hashed]));
+    Ok(false)
+}
+
+/// hash the entire content of the only file of a bucket, which was only hashed by its prefix; the file is
+/// dropped if it cannot be hashed
+fn full_hash_lone_file(course_set: &mut Vec<(u128, Vec<HashedFile>)>, full: WorkerShared) {
+    let [(set_hash, set)] = course_set.as_mut_slice() else {
+        return;
+    };
+    let [lone] = set.as_mut_slice() else { return };
+    match hash_for_set(&lone.file_path.to_push_buf(), full) {
+        Ok((hash, stamp)) => {
+            *set_hash = hash;
+            lone.content_hash = hash;
+            lone.file_version_timestamp = stamp.modified;
+            lone.size = Some(stamp.size);
+        }
+        Err(AlreadyReportedError) => course_set.clear(),
+    }
+}
+
+/// the manifests and hash caches which do not record their algorithm were hashed with this one
+pub(crate) static UNRECORDED_HASH_ALGORITHM: &str = "xxh3-128";
+
+/// the key of the course set of the file, the hash of its content combined with the properties the
+/// refiners split the sets by
+fn course_set_key(
+    file_hash: u128,
+    refiners: &mut FileSetRefiners,
+    file: &Path,
+) -> Result<u128, AlreadyReportedError> {
+    let mut key = xxhash_rust::xxh3::Xxh3::default();
+    std::hash::Hasher::write_u128(&mut key, file_hash);
+    refiners.hash_components(&mut key, file)?;
+    Ok(key.digest128())
+}
+
+/// the hash of the scoped part of the file and its modification time and size, taken from the cache if
+/// the file did not change since it was cached; errors are reported
+fn hash_for_set(
+    file: &Path,
+    shared: WorkerShared,
+) -> Result<(u128, FileStamp), AlreadyReportedError> {
+    let cached = shared.cache.and_then(|cache| {
+        let metadata = file.metadata().ok()?;
+        let identity = os::file_identity(&metadata);
+        Some((cache, metadata.len(), metadata.modified().ok()?, identity))
+    });
+    if let Some((cache, size, modified, identity)) = cached {
+        if let Some(hash) = cache.get(file, size, modified, shared.hash_scope) {
+            if let Some(progress) = shared.progress {
+                progress.file_cached(shared.hash_scope.hashed_bytes(size));
+            }
+            let stamp = FileStamp {
+                modified: Some(modified),
+                size,
+                identity,
+            };
+            return Ok((hash, stamp));
+        }
+    }
+    let (algorithm, scope) = (shared.hash_algorithm, shared.hash_scope);
+    let hash = match shared.file_timeout {
+        Some((timeout, pool)) => {
+            pool.hash_file_within(file, algorithm, scope, shared.stop, timeout)
+        }
+        None => hash_file(file, algorithm, scope, shared.stop),
+    };
+    let (file_hash, stamp, hashed_bytes) = match hash {
+        Ok(value) => value,
+        Err(HashFileError::FileChanged) => {
+            handle_file_modified!(file);
+            return Err(AlreadyReportedError);
+        }
+        Err(HashFileError::IO(err)) => {
+            handle_file_error!(file, err);
+            return Err(AlreadyReportedError);
+        }
+        Err(HashFileError::TimedOut) => {
+            log::warn!(
+                target: crate::error_handling::FILE_TIMEOUT_TARGET,
+                "hashing {} took longer than {:?}; skipping the file",
+                file.display(),
+                shared.file_timeout.map_or(Duration::ZERO, |(timeout, _)| timeout)
+            );
+            shared.errors.file_timed_out();
+            return Err(AlreadyReportedError);
+        }
+        Err(HashFileError::Stopped) => return Err(AlreadyReportedError),
+    };
+    if let Some(progress) = shared.progress {
+        progress.file_hashed(hashed_bytes);
+    }
+    // the file did not change between the stat and the hash
+    if let Some((cache, size, modified, _)) =
+        cached.filter(|(_, _, modified, _)| stamp.modified == Some(*modified))
+    {
+        cache.insert(file, size, modified, shared.hash_scope, file_hash);
+    }
+    Ok((file_hash, stamp))
+}
+
+/// places the file into the set of the links to the same file; returns true if the file completed a new set
+fn place_into_link_group<R, F>(
+    file_path: LinkedPath,
+    file: &Path,
+    find_set: F,
+) -> Result<bool, AlreadyReportedError>
+where
+    R: DerefMut<Target = Vec<(u128, Vec<HashedFile>)>>,
+    F: FnOnce(u128) -> R,
+{
+    let metadata = handle_metadata_op!(
+        std::fs::symlink_metadata(file),
+        file,
+        return Err(AlreadyReportedError)
+    );
+    let Some(identity) = os::file_identity(&metadata) else {
+        return Ok(false);
+    };
+    let mut link_group = find_set(u128::from(identity));
+    if link_group.is_empty() {
+        link_group.push((0, Vec::new()));
+    }
+    let links = &mut link_group[0].1;
+    links.push(HashedFile {
+        file_version_timestamp: metadata.modified().ok(),
+        content_hash: 0,
+        file_path,
+        identity: Some(identity),
+        size: Some(metadata.len()),
+    });
+    Ok(links.len() == 2)
+}
+
+/// whether the file belongs to the set; the files of the set which fail are dropped, Err is
+/// [`CheckEqualsErrorOn::Second`] if the file itself fails and [`CheckEqualsErrorOn::Stopped`] if the run stopped
+/// before it could be told
+fn fits_into_file_set(
+    file_set: &mut Vec<HashedFile>,
+    file: CompareFile,
+    tmp_buf: &mut PathBuf,
+    refiners: &mut FileSetRefiners,
+) -> Result<bool, CheckEqualsErrorOn> {
+    loop {
+        let Some(check_against) = file_set.first() else { return Ok(false); };
+        check_against.file_path.write_full_to_buf(tmp_buf);
+
+        let check_against = CompareFile::new(tmp_buf, check_against.size, check_against.identity);
+        let equals_result = refiners.check_equal(check_against, file);
+
+        match equals_result {
+            Ok(is_eq) => return Ok(is_eq),
+            Err(CheckEqualsErrorOn::Stopped) => return Err(CheckEqualsErrorOn::Stopped),
+            Err(err) => {
+                let (first_faulty, second_faulty) = err.is_faulty();
+                if first_faulty {
+                    file_set.remove(0);
+                }
+                if second_faulty {
+                    return Err(CheckEqualsErrorOn::Second);
+                }
+            }
+        }
+    }
+}
+
+/// split the sets by the refiners which were left out while hashing, on a pool of their own; the sets are given
+/// and returned with the key of their course set
+fn refine_file_sets(
+    sets: impl Iterator<Item = (u128, Vec<HashedFile>)>,
+    refiners: &FileSetRefiners,
+    num_threads: NonZeroU32,
+) -> Vec<(u128, Vec<HashedFile>)> {
+    // the files of sets with more members than threads are compared in parallel instead
+    let (large, small): (Vec<_>, Vec<_>) = sets
+        .filter(|(_, set)| set.len() > 1)
+        .partition(|(_, set)| num_threads.get() > 1 && set.len() > num_threads.get() as usize);
+    let mut refined = on_compare_pool(
+        small.into_iter(),
+        refiners,
+        num_threads,
+        |refiners, (key, set)| {
+            let (mut path_buf, mut path_buf_tmp) = (PathBuf::new(), PathBuf::new());
+            refine_file_set(set, refiners, &mut path_buf, &mut path_buf_tmp)
+                .into_iter()
+                .map(|refined| (key, refined))
+                .collect::<Vec<_>>()
+        },
+    )
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    for (key, set) in large {
+        let mut remaining = set;
+        while remaining.len() > 1 {
+            let (equal, rest) = split_off_equal(remaining, refiners, num_threads);
+            if equal.len() > 1 {
+                refined.push((key, equal));
+            }
+            remaining = rest;
+        }
+    }
+    refined
+}
+
+/// compare all files of the set to its first file in parallel, returns the files equal to the first file
+/// including it and the remaining files; if the first file fails, it is dropped and all others remain, if the run
+/// stopped before all files were compared neither are returned
+fn split_off_equal(
+    mut set: Vec<HashedFile>,
+    refiners: &FileSetRefiners,
+    num_threads: NonZeroU32,
+) -> (Vec<HashedFile>, Vec<HashedFile>) {
+    let first = set.remove(0);
+    let first_path = first.file_path.to_push_buf();
+    let mut compared = on_compare_pool(
+        set.into_iter().enumerate(),
+        refiners,
+        num_threads,
+        |refiners, (i, file)| {
+            let file_path = file.file_path.to_push_buf();
+            let is_equal = refiners.check_equal(
+                CompareFile::new(&first_path, first.size, first.identity),
+                CompareFile::new(&file_path, file.size, file.identity),
+            );
+            (i, file, is_equal)
+        },
+    );
+    compared.sort_unstable_by_key(|(i, _, _)| *i);
+    // the set is incomplete, neither the equal files nor the rest are known
+    let stopped = compared
+        .iter()
+        .any(|(_, _, is_equal)| *is_equal == Err(CheckEqualsErrorOn::Stopped));
+    if stopped {
+        return (Vec::new(), Vec::new());
+    }
+    let first_faulty = compared
+        .iter()
+        .any(|(_, _, is_equal)| is_equal.is_err_and(|err| err.is_faulty().0));
+
+    let (mut equal, mut rest) = (vec![first], Vec::new());
+    for (_, file, is_equal) in compared {
+        match is_equal {
+            Ok(true) if !first_faulty => equal.push(file),
+            Err(err) if err.is_faulty().1 => {}
+            _ => rest.push(file),
+        }
+    }
+    if first_faulty {
+        equal.clear();
+    }
+    (equal, rest)
+}
+
+/// run the work for every item on this many threads, each thread with refiners of its own
+fn on_compare_pool<T: Send, R: Send>(
+    items: impl Iterator<Item = T>,
+    refiners: &FileSetRefiners,
+    num_threads: NonZeroU32,
+    work: impl Fn(&mut FileSetRefiners, T) -> R + Sync,
+) -> Vec<R> {
+    let (items_send, items_rev) = flume::unbounded();
+    for item in items {
+        let _ = items_send.send(item);
+    }
+    drop(items_send);
+    let (results_send, results_rev) = flume::unbounded();
+
+    let run = |mut refiners: FileSetRefiners,
+               items_rev: flume::Receiver<T>,
+               results_send: flume::Sender<R>| {
+        for item in items_rev {
+            let _ = results_send.send(work(&mut refiners, item));
+        }
+    };
+
+    std::thread::scope(|s| {
+        for t in 0..num_threads.get() {
+            let (refiners, items_rev, results_send) =
+                (refiners.clone(), items_rev.clone(), results_send.clone());
+            let run = &run;
+            let thread = std::thread::Builder::new()
+                .name(format!("file_compare_worker_{t}"))
+                .spawn_scoped(s, move || run(refiners, items_rev, results_send));
+            if let Err(err) = thread {
+                log::error!(target: crate::error_handling::CONFIG_ERR_TARGET, "threading not supported on this platform; please do not use the content threads option({err})");
+                break;
+            }
+        }
+    });
+    // items only remain if the threads could not be spawned
+    run(refiners.clone(), items_rev, results_send);
+    results_rev.into_iter().collect()
+}
+
+/// returns the subsets of at least two files which the refiners consider equal, none if the run stopped before
+/// all files were compared
+fn refine_file_set(
+    set: Vec<HashedFile>,
+    refiners: &mut FileSetRefiners,
+    path_buf: &mut PathBuf,
+    tmp_buf: &mut PathBuf,
+) -> Vec<Vec<HashedFile>> {
+    let mut refined: Vec<Vec<HashedFile>> = Vec::new();
+    'files: for file in set {
+        file.file_path.write_full_to_buf(path_buf);
+        for subset in &mut refined {
+            let compared = CompareFile::new(path_buf, file.size, file.identity);
+            match fits_into_file_set(subset, compared, tmp_buf, refiners) {
+                Ok(true) => {
+                    subset.push(file);
+                    continue 'files;
+                }
+                Ok(false) => {}
+                // the set is incomplete, it is not split into what was told apart so far
+                Err(CheckEqualsErrorOn::Stopped) => return Vec::new(),
+                Err(_) => continue 'files,
+            }
+        }
+        refined.push(vec![file]);
+    }
+    refined.retain(|subset| subset.len() > 1);
+    refined
+}
+
+/// hash the entire content of the file, like the content hash the sets are built from; errors are reported
+fn hash_content(path: &Path, algorithm: HashAlgorithm) -> Result<u128, AlreadyReportedError> {
+    // only the scan itself is stopped early
+    let never = StopSignal::new(None, None);
+    match hash_file(path, algorithm, HashScope::Full, &never) {
+        Ok((hash, _, _)) => Ok(hash),
+        Err(HashFileError::FileChanged) => {
+            handle_file_modified!(path);
+            Err(AlreadyReportedError)
+        }
+        Err(HashFileError::IO(err)) => {
+            handle_file_error!(path, err);
+            Err(AlreadyReportedError)
+        }
+        Err(HashFileError::Stopped | HashFileError::TimedOut) => Err(AlreadyReportedError),
+    }
+}
+
+/// whether the entire content of the file still has the hash, errors are reported
+fn content_matches(
+    path: &Path,
+    expected: u128,
+    algorithm: HashAlgorithm,
+) -> Result<bool, AlreadyReportedError> {
+    hash_content(path, algorithm).map(|hash| hash == expected)
+}
+
+/// hash the part of the content of the file given by the scope
+/// returns the hash, the modification time and the amount of hashed bytes
+fn hash_file(
+    path: impl AsRef<Path>,
+    algorithm: HashAlgorithm,
+    scope: HashScope,
+    stop: &StopSignal,
+) -> Result<(u128, FileStamp, u64), HashFileError> {
+    let mut hash = algorithm.hasher();
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(false)
+        .open(path.as_ref())?;
+    let metadata = file.metadata()?;
+    let before_mod_time = metadata.modified().ok(); // might be unavailable on the platform
+    let mut buf = Box::new([0; 512]);
+    let len = metadata.len();
+    let hashed_bytes = match scope {
+        HashScope::Prefix(limit) | HashScope::PrefixBuckets(limit) => {
+            hash.write_u64(len);
+            let limited = std::io::Read::take(&mut file, limit);
+            hash_source(&mut buf, &mut hash, limited, stop)?
+        }
+        // the blocks would cover the entire file anyway
+        HashScope::Sampled { blocks, block_size }
+            if u128::from(blocks) * u128::from(block_size) < u128::from(len) =>
+        {
+            hash.write_u64(len);
+            let mut hashed_bytes = 0;
+            for block in 0..blocks {
+                let offset = sample_offset(block, blocks, block_size, len);
+                std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(offset))?;
+                let limited = std::io::Read::take(&mut file, block_size);
+                hashed_bytes += hash_source(&mut buf, &mut hash, limited, stop)?;
+            }
+            hashed_bytes
+        }
+        HashScope::Full | HashScope::Sampled { .. } => {
+            hash_source(&mut buf, &mut hash, &mut file, stop)?
+        }
+    };
+    let metadata = file.metadata()?;
+    let after_mod_time = metadata.modified().ok();
+
+    if before_mod_time == after_mod_time {
+        let stamp = FileStamp {
+            modified: before_mod_time,
+            size: len,
+            identity: os::file_identity(&metadata),
+        };
+        Ok((hash.finish128(), stamp, hashed_bytes))
+    } else {
+        Err(HashFileError::FileChanged)
+    }
+}
+
+/// a file hashed on the threads of `--file-timeout`
+struct TimeoutHashJob {
+    path: PathBuf,
+    algorithm: HashAlgorithm,
+    scope: HashScope,
+    /// told to stop once the waiting worker gave up on the file
+    abandoned: Arc<StopSignal>,
+    hashed: flume::Sender<Result<(u128, FileStamp, u64), HashFileError>>,
+}
+
+/// the threads which hash the files for the workers if `--file-timeout` is given, so that a read which
+/// hangs(e.g. on a network mount) only holds up the worker until the timeout; a thread stuck on such a read
+/// is left behind until the read returns, the files waiting for a free thread meanwhile time out as well
+struct FileTimeoutPool {
+    /// none if no thread could be started, the files are hashed by the workers themselves then
+    jobs: Option<flume::Sender<TimeoutHashJob>>,
+}
+
+impl FileTimeoutPool {
+    fn new(threads: NonZeroU32) -> Self {
+        let (jobs, jobs_rev) = flume::unbounded::<TimeoutHashJob>();
+        let spawned = (0..threads.get())
+            .filter(|_| {
+                let jobs_rev = jobs_rev.clone();
+                std::thread::Builder::new()
+                    .spawn(move || {
+                        for job in jobs_rev {
+                            // the worker gave up on the file while it waited for a thread
+                            if job.abandoned.reason().is_some() {
+                                continue;
+                            }
+                            let hashed =
+                                hash_file(&job.path, job.algorithm, job.scope, &job.abandoned);
+                            let _ = job.hashed.send(hashed);
+                        }
+                    })
+                    .is_ok()
+            })
+            .count();
+        Self {
+            jobs: Some(jobs).filter(|_| spawned > 0),
+        }
+    }
+
+    /// hash the file on a thread of the pool, but give up once the timeout is exceeded or the run stopped
+    fn hash_file_within(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        scope: HashScope,
+        stop: &StopSignal,
+        timeout: Duration,
+    ) -> Result<(u128, FileStamp, u64), HashFileError> {
+        let deadline = Instant::now() + timeout;
+        let Some(jobs) = &self.jobs else {
+            return hash_file(path, algorithm, scope, stop);
+        };
+        // the thread cannot borrow the stop of the run, it is told to stop by its own
+        let abandoned = Arc::new(StopSignal::new(None, None));
+        let (hashed_send, hashed_rev) = flume::bounded(1);
+        let job = TimeoutHashJob {
+            path: path.to_path_buf(),
+            algorithm,
+            scope,
+            abandoned: abandoned.clone(),
+            hashed: hashed_send,
+        };
+        if jobs.send(job).is_err() {
+            return hash_file(path, algorithm, scope, stop);
+        }
+        loop {
+            match hashed_rev.recv_deadline(deadline.min(Instant::now() + STOP_POLL_INTERVAL)) {
+                Ok(hashed) => return hashed,
+                Err(flume::RecvTimeoutError::Disconnected) => {
+                    return Err(HashFileError::IO(std::io::Error::other(
+                        "the hashing thread panicked",
+                    )));
+                }
+                Err(flume::RecvTimeoutError::Timeout) => {}
+            }
+            if Instant::now() >= deadline {
+                abandoned.stop(StopReason::Timeout);
+                return Err(HashFileError::TimedOut);
+            }
+            if stop.reason().is_some() {
+                abandoned.stop(StopReason::Interrupted);
+                return Err(HashFileError::Stopped);
+            }
+        }
+    }
+}
+
+/// the start of the sampled block, the blocks are spread evenly from the start to the end of the file
+fn sample_offset(block: u64, blocks: u64, block_size: u64, len: u64) -> u64 {
+    if blocks == 1 {
+        return 0;
+    }
+    let span = len - block_size;
+    let offset = u128::from(block) * u128::from(span) / u128::from(blocks - 1);
+    u64::try_from(offset).unwrap_or(span)
+}
+
+/// returns the amount of bytes read
+fn hash_source<H: std::hash::Hasher>(
+    buf: &mut Box<[u8; 512]>,
+    hash: &mut H,
+    mut file: impl std::io::Read,
+    stop: &StopSignal,
+) -> Result<u64, HashFileError> {
+    let mut total = 0;
+    while let Some(bytes_read) = Some(file.read(buf.as_mut_slice())?).filter(|amount| *amount != 0)
+    {
+        // large files would hold up the stop for long
+        if stop.reason().is_some() {
+            return Err(HashFileError::Stopped);
+        }
+        hash.write(&buf[..bytes_read]);
+        total += bytes_read as u64;
+    }
+    Ok(total)
+}
@@ -1,4 +1,14 @@
-use log::{LevelFilter, Metadata, Record};
+use crate::util::format_timestamp;
+use log::{Level, LevelFilter, Metadata, Record};
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant, SystemTime};
+
+/// the buffered records are written at least this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// at most this many records of a target are logged per window, errors are always logged
+const RATE_LIMIT: u32 = 20;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
 
 /// This logger needs to:
 /// - filter by target
@@ -11,7 +21,44 @@ use log::{LevelFilter, Metadata, Record};
 pub struct DuplisLogger {
     disallowed_targets: Vec<String>,
     log_level_filter: LevelFilter,
-    write: std::sync::Mutex<Box<dyn std::io::Write + Send>>,
+    /// the records are prefixed with the time they were logged at if present
+    timestamps: Option<LogTimestamps>,
+    /// the elapsed time is counted from the creation of the logger
+    start: Instant,
+    state: std::sync::Mutex<LogState>,
+}
+
+struct LogState {
+    write: BufWriter<Box<dyn Write + Send>>,
+    /// the last record written, identical records following it are only counted
+    last: Option<LastRecord>,
+    /// the records logged and suppressed per target in its current window
+    windows: HashMap<String, RateWindow>,
+    last_flush: Instant,
+}
+
+struct LastRecord {
+    level: Level,
+    target: String,
+    message: String,
+    repeated: u64,
+}
+
+struct RateWindow {
+    start: Instant,
+    logged: u32,
+    suppressed: u64,
+    /// the level of the last suppressed record
+    level: Level,
+}
+
+/// the time a record is prefixed with
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogTimestamps {
+    /// the point in time in UTC, like `2023-01-31T12:30:15Z`
+    Absolute,
+    /// the time since the start of the run, like `+12.345s`
+    Elapsed,
 }
 
 impl DuplisLogger {
@@ -24,18 +71,171 @@ impl DuplisLogger {
         DuplisLogger {
             disallowed_targets: ignore_targets,
             log_level_filter,
-            write: std::sync::Mutex::new(write),
+            timestamps: None,
+            start: Instant::now(),
+            state: std::sync::Mutex::new(LogState {
+                write: BufWriter::new(write),
+                last: None,
+                windows: HashMap::new(),
+                last_flush: Instant::now(),
+            }),
         }
     }
 
+    pub fn with_timestamps(mut self, timestamps: Option<LogTimestamps>) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
     pub fn init(
         ignore_targets: Vec<String>,
         log_level_filter: LevelFilter,
         write: Box<dyn std::io::Write + Send>,
     ) -> Result<(), log::SetLoggerError> {
-        log::set_max_level(log_level_filter);
-        let logger = Self::new(ignore_targets, log_level_filter, write);
-        log::set_boxed_logger(Box::new(logger))
+        Self::new(ignore_targets, log_level_filter, write).install()
+    }
+
+    /// make this the logger of the program; the buffered records are written periodically and when the
+    /// logger is flushed
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.log_level_filter);
+        log::set_boxed_logger(Box::new(self))?;
+        // the records are still written on every flush and every FLUSH_INTERVAL while logging otherwise
+        let _ = std::thread::Builder::new()
+            .name(String::from("log_flusher"))
+            .spawn(|| loop {
+                std::thread::sleep(FLUSH_INTERVAL);
+                log::logger().flush();
+            });
+        Ok(())
+    }
+
+    fn write_prefix(
+        &self,
+        write: &mut impl Write,
+        level: Level,
+        target: &str,
+    ) -> std::io::Result<()> {
+        match self.timestamps {
+            Some(LogTimestamps::Absolute) => {
+                write!(write, "[{}]", format_timestamp(SystemTime::now()))?;
+            }
+            Some(LogTimestamps::Elapsed) => {
+                let elapsed = self.start.elapsed();
+                write!(
+                    write,
+                    "[+{}.{:03}s]",
+                    elapsed.as_secs(),
+                    elapsed.subsec_millis()
+                )?;
+            }
+            None => {}
+        }
+        write!(write, "[{level}]({target}): ")
+    }
+
+    /// note how often the last record was repeated
+    fn write_repeated(&self, state: &mut LogState) -> std::io::Result<()> {
+        let Some(last) = state.last.as_mut().filter(|last| last.repeated > 0) else {
+            return Ok(());
+        };
+        let repeated = std::mem::take(&mut last.repeated);
+        self.write_prefix(&mut state.write, last.level, &last.target)?;
+        writeln!(
+            state.write,
+            "the last message was repeated {repeated} times"
+        )
+    }
+
+    /// note how many records of the target were suppressed in its window so far
+    fn write_suppressed(
+        &self,
+        write: &mut impl Write,
+        target: &str,
+        window: &mut RateWindow,
+    ) -> std::io::Result<()> {
+        if window.suppressed == 0 {
+            return Ok(());
+        }
+        let suppressed = std::mem::take(&mut window.suppressed);
+        self.write_prefix(write, window.level, target)?;
+        writeln!(
+            write,
+            "suppressed {suppressed} more records, at most {RATE_LIMIT} are logged per second"
+        )
+    }
+
+    /// whether the record may be written; counts it towards the limit of its target
+    fn within_rate_limit(
+        &self,
+        state: &mut LogState,
+        level: Level,
+        target: &str,
+    ) -> std::io::Result<bool> {
+        if level == Level::Error {
+            return Ok(true);
+        }
+        let now = Instant::now();
+        let window = match state.windows.get_mut(target) {
+            Some(window) => window,
+            None => state
+                .windows
+                .entry(target.to_owned())
+                .or_insert(RateWindow {
+                    start: now,
+                    logged: 0,
+                    suppressed: 0,
+                    level,
+                }),
+        };
+        if now.duration_since(window.start) >= RATE_LIMIT_WINDOW {
+            self.write_suppressed(&mut state.write, target, window)?;
+            window.start = now;
+            window.logged = 0;
+        }
+        if window.logged >= RATE_LIMIT {
+            window.suppressed += 1;
+            window.level = level;
+            return Ok(false);
+        }
+        window.logged += 1;
+        Ok(true)
+    }
+
+    fn write_record(&self, state: &mut LogState, record: &Record) -> std::io::Result<()> {
+        let message = record.args().to_string();
+        if let Some(last) = state.last.as_mut().filter(|last| {
+            last.level == record.level()
+                && last.target == record.target()
+                && last.message == message
+        }) {
+            last.repeated += 1;
+            return Ok(());
+        }
+        self.write_repeated(state)?;
+        if !self.within_rate_limit(state, record.level(), record.target())? {
+            return Ok(());
+        }
+        self.write_prefix(&mut state.write, record.level(), record.target())?;
+        writeln!(state.write, "{message}")?;
+        state.last = Some(LastRecord {
+            level: record.level(),
+            target: record.target().to_owned(),
+            message,
+            repeated: 0,
+        });
+        Ok(())
+    }
+
+    /// write the notes about the repeated and suppressed records and everything buffered
+    fn flush_state(&self, state: &mut LogState) -> std::io::Result<()> {
+        self.write_repeated(state)?;
+        let LogState { write, windows, .. } = state;
+        for (target, window) in windows.iter_mut() {
+            self.write_suppressed(write, target, window)?;
+        }
+        state.last_flush = Instant::now();
+        state.write.flush()
     }
 }
 
@@ -59,19 +259,19 @@ impl log::Log for DuplisLogger {
             return;
         }
 
-        let Ok(mut write) = self.write.lock() else { return };
-        let _ = writeln!(
-            write,
-            "[{}]({}): {}",
-            record.level(),
-            record.target(),
-            record.args()
-        );
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let _ = self.write_record(&mut state, record);
+        if record.level() == Level::Error || state.last_flush.elapsed() >= FLUSH_INTERVAL {
+            let _ = self.flush_state(&mut state);
+        }
     }
 
     fn flush(&self) {
-        use std::io::Write;
-        let Ok(mut write) = self.write.lock() else { return };
-        let _ = write.flush();
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let _ = self.flush_state(&mut state);
     }
 }
@@ -0,0 +1,177 @@
+use crate::error_handling::AlreadyReportedError;
+use crate::out_err_map;
+use crate::plan::plan_can_represent;
+use crate::util::count_of;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// written at the top of every manifest, lines starting with '#' are ignored when reading
+static MANIFEST_HEADER: &str =
+    "# duplis manifest: compare two manifests with `duplis diff <A> <B>`\n# content hash\tfile\n";
+/// the header line naming the hash algorithm of the manifest
+static ALGORITHM_PREFIX: &str = "# hash algorithm: ";
+
+/// one hashed file of a scan, written as a tab separated line
+pub struct ManifestLine<'a> {
+    /// the hash of the entire content of the file
+    pub hash: u128,
+    pub file: &'a str,
+}
+
+impl<'a> ManifestLine<'a> {
+    pub fn write_to(&self, write: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(write, "{:032x}\t{}", self.hash, self.file)
+    }
+
+    fn parse(line: &'a str) -> Option<Self> {
+        let (hash, file) = line.split_once('\t')?;
+        let hash = u128::from_str_radix(hash, 16).ok()?;
+        if file.is_empty() || !plan_can_represent(file) {
+            return None;
+        }
+        Some(Self { hash, file })
+    }
+}
+
+/// write the header of a manifest whose hashes are computed with the algorithm
+pub fn write_manifest_header(
+    write: &mut impl std::io::Write,
+    algorithm: &str,
+) -> std::io::Result<()> {
+    write.write_all(MANIFEST_HEADER.as_bytes())?;
+    writeln!(write, "{ALGORITHM_PREFIX}{algorithm}")
+}
+
+/// append the file to the manifest in one piece, files which cannot be represented are left out
+pub fn write_manifest_line(
+    write: &mut impl std::io::Write,
+    hash: u128,
+    file: &Path,
+) -> Result<(), AlreadyReportedError> {
+    let Some(file_str) = file.to_str().filter(|path| plan_can_represent(path)) else {
+        log::warn!(
+            target: crate::error_handling::FORMAT_ERR_TARGET,
+            "path {} is not valid unicode or contains a tab or newline and cannot be written into a manifest",
+            file.display()
+        );
+        return Ok(());
+    };
+    ManifestLine {
+        hash,
+        file: file_str,
+    }
+    .write_to(write)
+    .and_then(|()| write.flush())
+    .map_err(out_err_map!())
+}
+
+/// the files of the manifest by their content hash; fails if the manifest was hashed with another algorithm
+/// since its hashes cannot be compared to those of the algorithm, and if any of its lines is malformed
+pub fn read_manifest(
+    manifest: &Path,
+    algorithm: &str,
+) -> Result<HashMap<u128, Vec<String>>, AlreadyReportedError> {
+    let check_algorithm = |found: &str| {
+        if found == algorithm {
+            return Ok(());
+        }
+        log::error!(
+            target: crate::error_handling::CONFIG_ERR_TARGET,
+            "manifest {} was hashed with {found} instead of {algorithm}; its hashes cannot be compared",
+            manifest.display()
+        );
+        Err(AlreadyReportedError)
+    };
+    let read_err = |err: std::io::Error| {
+        log::error!(
+            target: crate::error_handling::CONFIG_ERR_TARGET,
+            "cannot read manifest {}: {err}",
+            manifest.display()
+        );
+        AlreadyReportedError
+    };
+    let manifest_file = std::fs::File::open(manifest).map_err(read_err)?;
+    let mut files: HashMap<u128, Vec<String>> = HashMap::new();
+    let mut algorithm_checked = false;
+    let mut malformed = 0;
+    for (line_number, line) in std::io::BufReader::new(manifest_file).lines().enumerate() {
+        let line = line.map_err(read_err)?;
+        if let Some(found) = line.strip_prefix(ALGORITHM_PREFIX) {
+            check_algorithm(found.trim_end())?;
+            algorithm_checked = true;
+            continue;
+        }
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !algorithm_checked {
+            check_algorithm(crate::UNRECORDED_HASH_ALGORITHM)?;
+            algorithm_checked = true;
+        }
+        let Some(entry) = ManifestLine::parse(line.trim_end_matches('\r')) else {
+            log::error!(target: crate::error_handling::FORMAT_ERR_TARGET, "line {} of manifest {} is malformed", line_number + 1, manifest.display());
+            malformed += 1;
+            continue;
+        };
+        files
+            .entry(entry.hash)
+            .or_default()
+            .push(entry.file.to_owned());
+    }
+    if malformed > 0 {
+        log::error!(
+            target: crate::error_handling::FORMAT_ERR_TARGET,
+            "manifest {} is corrupt: {}",
+            manifest.display(),
+            count_of(malformed, "malformed line")
+        );
+        return Err(AlreadyReportedError);
+    }
+    Ok(files)
+}
+
+/// write every content found in both manifests, one line each, as the files of `a` followed by the files of `b`
+pub fn diff_manifests(
+    a: &Path,
+    b: &Path,
+    algorithm: &str,
+    write: &mut impl std::io::Write,
+) -> Result<(), AlreadyReportedError> {
+    let a_files = read_manifest(a, algorithm)?;
+    let mut b_files = read_manifest(b, algorithm)?;
+    let mut shared = a_files
+        .into_iter()
+        .filter_map(|(hash, a_files)| Some((a_files, b_files.remove(&hash)?)))
+        .map(|(mut a_files, mut b_files)| {
+            a_files.sort_unstable();
+            b_files.sort_unstable();
+            (a_files, b_files)
+        })
+        .collect::<Vec<_>>();
+    shared.sort_unstable();
+    for (a_files, b_files) in shared {
+        writeln!(
+            write,
+            "{} duplicated by {}",
+            a_files.join(", "),
+            b_files.join(", ")
+        )
+        .map_err(out_err_map!())?;
+    }
+    write.flush().map_err(out_err_map!())
+}
+
+#[test]
+fn test_manifest_line() {
+    let mut written = Vec::new();
+    write_manifest_line(&mut written, 0xab, Path::new("/a/b c")).unwrap();
+    write_manifest_line(&mut written, 0xab, Path::new("/a/b\tc")).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert_eq!(written, "000000000000000000000000000000ab\t/a/b c\n");
+    let parsed = ManifestLine::parse(written.trim_end()).unwrap();
+    assert_eq!((parsed.hash, parsed.file), (0xab, "/a/b c"));
+    assert!(ManifestLine::parse("zz\t/a").is_none());
+    assert!(ManifestLine::parse("ab\t").is_none());
+    assert!(ManifestLine::parse("ab").is_none());
+}
@@ -1,4 +1,10 @@
 use crate::set_order::SetOrder;
+use crate::{
+    handle_file_op, report_file_action, AlreadyReportedError, FileConsumeAction, FileConsumeResult,
+    Recoverable,
+};
+use std::borrow::Cow;
+use std::path::Path;
 
 #[cfg(unix)]
 mod unix_specific;
@@ -7,13 +13,94 @@ mod windows_specific;
 
 use crate::file_filters::FileMetadataFilter;
 use crate::file_set_refiner::FileEqualsChecker;
+use crate::parse_cli::DirScope;
+#[cfg(unix)]
+use unix_specific::trash_file as trash_file_impl;
 #[cfg(unix)]
 use unix_specific::{
     get_file_consume_action_simple as gfcas, get_file_equals_arg_simple as gfeas,
     get_file_name_filters as gfnf, get_set_order_options as gsoo,
 };
 #[cfg(windows)]
-use windows_specific::{complex_cmd_config as ccc, complex_parse_file_metadata_filter as cpfmf};
+use windows_specific::trash_file as trash_file_impl;
+#[cfg(windows)]
+use windows_specific::{
+    complex_cmd_config as ccc, complex_parse_file_metadata_filter as cpfmf,
+    get_set_order_options as gsoow,
+};
+#[cfg(windows)]
+pub use windows_specific::{vss_cleanup_command, vss_create_command};
+
+/// identifies a file independent of the paths it is reached by, all hard links to a file share it; the
+/// device and the inode on unix, the volume serial number and the file index on windows
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FileIdentity {
+    pub device: u64,
+    pub index: u64,
+}
+
+impl From<FileIdentity> for u128 {
+    fn from(identity: FileIdentity) -> Self {
+        (u128::from(identity.device) << 64) | u128::from(identity.index)
+    }
+}
+
+/// move the file into the trash of the platform, so that it can be restored
+pub struct TrashFileAction;
+
+impl FileConsumeAction for TrashFileAction {
+    fn consume(&mut self, path: &Path, _original: Option<&Path>) -> FileConsumeResult {
+        handle_file_op!(
+            trash_file(path),
+            path,
+            return Err(Recoverable::Recoverable(AlreadyReportedError))
+        );
+        report_file_action!("moved file {} to the trash", path.display());
+        Ok(())
+    }
+
+    fn requires_original(&self) -> bool {
+        false
+    }
+
+    fn short_name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("move to trash")
+    }
+
+    fn short_opposite(&self) -> Cow<'_, str> {
+        Cow::Borrowed("keep")
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        Some(Box::new(TrashFileAction))
+    }
+}
+
+/// whether `trash_file` can move files into a trash on this platform at all
+pub const SUPPORTS_TRASH: bool = cfg!(any(unix, windows));
+
+#[allow(unused_variables)]
+fn trash_file(path: &Path) -> std::io::Result<()> {
+    #[cfg(any(unix, windows))]
+    return trash_file_impl(path);
+    #[cfg(not(any(unix, windows)))]
+    return Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "there is no trash on this platform",
+    ));
+}
+
+/// the kind of storage a scanned directory resides on
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StorageKind {
+    /// spinning disk, parallel reads mostly cause seeking
+    Rotational,
+    /// flash storage, with the depth of its request queue if known
+    SolidState {
+        queue_depth: Option<u32>,
+    },
+    Unknown,
+}
 
 pub struct SetOrderOption {
     pub name: &'static str,
@@ -66,7 +153,14 @@ fn make_no_hidden<T>(
     )
 }
 
-delegating_impl!(get_set_order_options, Vec<SetOrderOption>, gsoo, Vec::new());
+pub fn get_set_order_options() -> Vec<SetOrderOption> {
+    #[cfg(unix)]
+    return gsoo();
+    #[cfg(windows)]
+    return gsoow();
+    #[cfg(not(any(unix, windows)))]
+    return Vec::new();
+}
 delegating_impl!(
     get_file_consumer_simple,
     Vec<SimpleFileConsumeActionArg>,
@@ -87,17 +181,204 @@ delegating_impl!(
 );
 
 pub fn complex_cmd_config(command: clap::Command) -> clap::Command {
-    #[cfg(any(windows))]
+    #[cfg(windows)]
     return ccc(command);
-    #[cfg(not(any(windows)))]
+    #[cfg(not(windows))]
     return command;
 }
 #[allow(unused_variables)]
 pub fn complex_parse_file_metadata_filters(
-    matches: &clap::ArgMatches,
+    matches: &DirScope,
 ) -> Vec<Box<dyn FileMetadataFilter + Send>> {
-    #[cfg(any(windows))]
+    #[cfg(windows)]
     return cpfmf(matches);
-    #[cfg(not(any(windows)))]
+    #[cfg(not(windows))]
     return Vec::new();
 }
+
+/// whether the directories are scanned in volume shadow copies
+#[allow(unused_variables)]
+pub fn use_vss(matches: &clap::ArgMatches) -> bool {
+    #[cfg(windows)]
+    return windows_specific::use_vss(matches);
+    #[cfg(not(windows))]
+    return false;
+}
+
+/// identifies the file behind the metadata, None where the metadata does not tell
+#[allow(unused_variables, clippy::unnecessary_wraps)]
+pub fn file_identity(metadata: &std::fs::Metadata) -> Option<FileIdentity> {
+    #[cfg(unix)]
+    return Some(unix_specific::file_identity(metadata));
+    #[cfg(not(unix))]
+    return None;
+}
+
+/// the device or volume the file lies on
+pub fn file_device(path: &Path) -> std::io::Result<u64> {
+    #[cfg(unix)]
+    return path
+        .metadata()
+        .map(|metadata| unix_specific::file_identity(&metadata).device);
+    #[cfg(windows)]
+    return windows_specific::path_identity(path, true)
+        .map(|identity| identity.device)
+        .ok_or_else(std::io::Error::last_os_error);
+    #[cfg(not(any(unix, windows)))]
+    return Ok(0);
+}
+
+/// the user who owns the file, None if the platform has no owners like this
+#[allow(unused_variables, clippy::unnecessary_wraps)]
+pub fn file_owner(metadata: &std::fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    return Some(unix_specific::file_owner(metadata));
+    #[cfg(not(unix))]
+    return None;
+}
+
+/// whether `reflink` can clone files on this platform at all
+pub const SUPPORTS_REFLINK: bool = cfg!(any(target_os = "linux", target_os = "macos"));
+
+/// create `link` as a copy-on-write clone of `original`, which shares the blocks of the original until either
+/// is changed; fails if the file system cannot share them
+#[allow(unused_variables)]
+pub fn reflink(original: &Path, link: &Path) -> std::io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    return unix_specific::reflink(original, link);
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    return Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reflinks are not supported on this platform",
+    ));
+}
+
+/// whether the file shares its blocks with another file, like a reflink which the file system did not turn
+/// into a full copy; None if the platform or the file system cannot tell
+#[allow(unused_variables)]
+pub fn shares_blocks(path: &Path) -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    return unix_specific::shares_blocks(path);
+    #[cfg(not(target_os = "linux"))]
+    return None;
+}
+
+/// give the file the owner and the group of the file the metadata belongs to, where the platform has them
+#[allow(unused_variables)]
+pub fn copy_owner(metadata: &std::fs::Metadata, file: &std::fs::File) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return unix_specific::copy_owner(metadata, file);
+    #[cfg(not(unix))]
+    return Ok(());
+}
+
+/// identifies the file at the path without following a symlink at its end, None if it cannot be accessed
+#[allow(unused_variables)]
+pub fn link_identity(path: &Path) -> Option<FileIdentity> {
+    #[cfg(unix)]
+    return path
+        .symlink_metadata()
+        .ok()
+        .map(|metadata| unix_specific::file_identity(&metadata));
+    #[cfg(windows)]
+    return windows_specific::path_identity(path, false);
+    #[cfg(not(any(unix, windows)))]
+    return None;
+}
+
+/// remove the file relative to its parent directory, which is opened without following symlinks where the
+/// platform allows it, so that a concurrently renamed or replaced ancestor cannot redirect the removal
+pub fn remove_file_guarded(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return unix_specific::remove_file_guarded(path);
+    #[cfg(not(unix))]
+    return std::fs::remove_file(path);
+}
+
+/// rename the file over `to` in the same directory, guarded like [`remove_file_guarded`]
+pub fn rename_guarded(from: &Path, to: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return unix_specific::rename_guarded(from, to);
+    #[cfg(not(unix))]
+    return std::fs::rename(from, to);
+}
+
+/// whether the current user may read the file, without reading any of it
+pub fn is_readable(path: &Path) -> bool {
+    #[cfg(unix)]
+    return unix_specific::is_readable(path);
+    #[cfg(not(unix))]
+    return std::fs::File::open(path).is_ok();
+}
+
+/// the directory the user's configuration lies in, None if it is not known
+pub fn user_config_dir() -> Option<std::path::PathBuf> {
+    #[cfg(windows)]
+    return std::env::var_os("APPDATA").map(std::path::PathBuf::from);
+    #[cfg(not(windows))]
+    return std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(std::path::PathBuf::from)
+        .or_else(|| Some(std::path::PathBuf::from(std::env::var_os("HOME")?).join(".config")));
+}
+
+/// let Ctrl+C call [`crate::util::interrupt`] instead of ending the program
+pub fn install_interrupt_handler() {
+    #[cfg(unix)]
+    unix_specific::install_interrupt_handler();
+    #[cfg(windows)]
+    windows_specific::install_interrupt_handler();
+}
+
+/// the standard output; on a windows console it is written as wide characters, so that the paths render
+/// correctly regardless of the code page of the console
+pub struct ConsoleOutput {
+    #[cfg(windows)]
+    console: Option<windows_specific::WideConsole>,
+    stdout: std::io::Stdout,
+}
+
+pub fn console_output() -> ConsoleOutput {
+    ConsoleOutput {
+        #[cfg(windows)]
+        console: windows_specific::WideConsole::stdout(),
+        stdout: std::io::stdout(),
+    }
+}
+
+impl std::io::Write for ConsoleOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        #[cfg(windows)]
+        if let Some(console) = &mut self.console {
+            return console.write(buf);
+        }
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        #[cfg(windows)]
+        if let Some(console) = &mut self.console {
+            return console.flush();
+        }
+        self.stdout.flush()
+    }
+}
+
+/// the open file descriptor as a file, which closes it once dropped
+pub fn open_fd(fd: i32) -> std::io::Result<std::fs::File> {
+    #[cfg(unix)]
+    return unix_specific::open_fd(fd);
+    #[cfg(not(unix))]
+    return Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("file descriptor {fd} cannot be written to on this platform"),
+    ));
+}
+
+#[allow(unused_variables)]
+pub fn probe_storage_kind(path: &Path) -> StorageKind {
+    #[cfg(target_os = "linux")]
+    return unix_specific::probe_storage_kind(path);
+    #[cfg(not(target_os = "linux"))]
+    return StorageKind::Unknown;
+}
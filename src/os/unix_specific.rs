@@ -1,17 +1,20 @@
 use crate::error_handling::AlreadyReportedError;
-use crate::file_action::{FileConsumeAction, FileConsumeResult};
+use crate::file_action::{replace_with_link, FileConsumeAction, FileConsumeResult};
 use crate::file_filters::FileNameFilter;
 use crate::file_set_refiner::{CheckEqualsErrorOn, FileEqualsChecker, FileWorkload};
 use crate::os::{
-    make_no_hidden, FileNameFilterArg, SetOrderOption, SimpleFileConsumeActionArg,
-    SimpleFileEqualCheckerArg,
+    make_no_hidden, FileIdentity, FileNameFilterArg, SetOrderOption, SimpleFileConsumeActionArg,
+    SimpleFileEqualCheckerArg, StorageKind,
 };
-use crate::util::LinkedPath;
-use crate::{handle_file_op, report_file_action, Recoverable};
+use crate::util::{LinkedPath, StopSignal};
+use crate::{handle_file_op, handle_metadata_op, report_file_action, Recoverable};
 use std::borrow::Cow;
+use std::ffi::{CString, OsStr, OsString};
 use std::hash::Hasher;
-use std::os::unix::fs::{MetadataExt, PermissionsExt};
-use std::path::Path;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
 
 pub fn get_set_order_options() -> Vec<SetOrderOption> {
     vec![]
@@ -54,26 +57,437 @@ pub fn get_file_name_filters() -> Vec<FileNameFilterArg> {
     vec![hidden]
 }
 
+pub fn file_identity(metadata: &std::fs::Metadata) -> FileIdentity {
+    FileIdentity {
+        device: metadata.dev(),
+        index: metadata.ino(),
+    }
+}
+
+pub fn file_owner(metadata: &std::fs::Metadata) -> u32 {
+    metadata.uid()
+}
+
+/// look up the block device of the path in sysfs
+#[cfg(target_os = "linux")]
+pub fn probe_storage_kind(path: &Path) -> StorageKind {
+    let Ok(metadata) = path.metadata() else { return StorageKind::Unknown };
+    let dev = metadata.dev();
+    // decoded like glibc's major() and minor()
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let dev_dir = std::path::PathBuf::from(format!("/sys/dev/block/{major}:{minor}"));
+    // partitions do not have a queue of their own, the queue belongs to the parent device
+    let queue_dir = [dev_dir.join("queue"), dev_dir.join("../queue")]
+        .into_iter()
+        .find(|dir| dir.is_dir());
+    let Some(queue_dir) = queue_dir else { return StorageKind::Unknown };
+    let read_num = |name: &str| {
+        std::fs::read_to_string(queue_dir.join(name))
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+    };
+    match read_num("rotational") {
+        Some(1) => StorageKind::Rotational,
+        Some(_) => StorageKind::SolidState {
+            queue_depth: read_num("nr_requests"),
+        },
+        None => StorageKind::Unknown,
+    }
+}
+
+/// open the directory without following any symlink on the way, the path is walked as given one directory
+/// at a time from the root or the current directory
+fn open_dir_nofollow(dir: &Path) -> std::io::Result<OwnedFd> {
+    let start = if dir.is_absolute() { "/" } else { "." };
+    let mut dir_fd: OwnedFd = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+        .open(start)?
+        .into();
+    for component in dir.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            Component::ParentDir => OsStr::new(".."),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => continue,
+        };
+        let name = CString::new(name.as_bytes())?;
+        // SAFETY: the name is nul terminated and the directory fd is valid as it is owned
+        let fd = unsafe {
+            libc::openat(
+                dir_fd.as_raw_fd(),
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: the fd was just opened and is owned by nothing else
+        dir_fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    }
+    Ok(dir_fd)
+}
+
+/// the opened parent directory and the name of the file in it
+fn open_parent_nofollow(path: &Path) -> std::io::Result<(OwnedFd, CString)> {
+    let name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok((open_dir_nofollow(parent)?, CString::new(name.as_bytes())?))
+}
+
+pub fn remove_file_guarded(path: &Path) -> std::io::Result<()> {
+    let (dir, name) = open_parent_nofollow(path)?;
+    // SAFETY: the name is nul terminated and the directory fd is valid as it is owned
+    if unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), 0) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn rename_guarded(from: &Path, to: &Path) -> std::io::Result<()> {
+    let (dir, to_name) = open_parent_nofollow(to)?;
+    let from_name = from
+        .file_name()
+        .filter(|_| from.parent() == to.parent())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "can only rename within the same directory",
+            )
+        })?;
+    let from_name = CString::new(from_name.as_bytes())?;
+    // SAFETY: the names are nul terminated and the directory fd is valid as it is owned
+    let renamed = unsafe {
+        libc::renameat(
+            dir.as_raw_fd(),
+            from_name.as_ptr(),
+            dir.as_raw_fd(),
+            to_name.as_ptr(),
+        )
+    };
+    if renamed < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// clone the original with FICLONE, which fails instead of copying if the file system cannot share the blocks
+#[cfg(target_os = "linux")]
+pub fn reflink(original: &Path, link: &Path) -> std::io::Result<()> {
+    let source = std::fs::File::open(original)?;
+    let target = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(link)?;
+    // SAFETY: both files stay open while the call runs
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } < 0 {
+        let err = std::io::Error::last_os_error();
+        drop(target);
+        let _ = std::fs::remove_file(link);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// whether any block of the file is shared with another file according to FIEMAP, None if the file system
+/// cannot tell or the file has no blocks
+#[cfg(target_os = "linux")]
+pub fn shares_blocks(path: &Path) -> Option<bool> {
+    #[repr(C)]
+    #[derive(Copy, Clone, Default)]
+    struct FiemapExtent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+    #[repr(C)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [FiemapExtent; 32],
+    }
+    // _IOWR('f', 11, struct fiemap)
+    const FS_IOC_FIEMAP: u32 = 0xC020_660B;
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+
+    let file = std::fs::File::open(path).ok()?;
+    let mut map = Fiemap {
+        start: 0,
+        length: u64::MAX,
+        flags: FIEMAP_FLAG_SYNC,
+        mapped_extents: 0,
+        extent_count: 32,
+        reserved: 0,
+        extents: [FiemapExtent::default(); 32],
+    };
+    // SAFETY: the map has room for the extent count it names and the file stays open while the call runs
+    // the request is an int on musl, where it wraps like in C
+    #[allow(clippy::cast_lossless, clippy::cast_possible_wrap)]
+    let result = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            FS_IOC_FIEMAP as libc::Ioctl,
+            std::ptr::addr_of_mut!(map),
+        )
+    };
+    if result < 0 || map.mapped_extents == 0 {
+        return None;
+    }
+    let extents = map.extents.get(..map.mapped_extents as usize)?;
+    Some(
+        extents
+            .iter()
+            .any(|extent| extent.flags & FIEMAP_EXTENT_SHARED != 0),
+    )
+}
+
+/// give the file the owner and the group from the metadata
+pub fn copy_owner(metadata: &std::fs::Metadata, file: &std::fs::File) -> std::io::Result<()> {
+    std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+/// clone the original with clonefile, which fails if the file system cannot share the blocks
+#[cfg(target_os = "macos")]
+pub fn reflink(original: &Path, link: &Path) -> std::io::Result<()> {
+    let original = CString::new(original.as_os_str().as_bytes())?;
+    let link = CString::new(link.as_os_str().as_bytes())?;
+    // SAFETY: the paths are nul terminated
+    if unsafe { libc::clonefile(original.as_ptr(), link.as_ptr(), 0) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn open_fd(fd: i32) -> std::io::Result<std::fs::File> {
+    // SAFETY: F_GETFD only reads the flags of the descriptor
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor is open and handed over by the caller for writing the results
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+/// the path with its parent canonicalized, a symlink at its end is not followed
+fn absolute_link_path(path: &Path) -> std::io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(parent.canonicalize()?.join(name))
+}
+
+/// the first of `name`, `name.2`, `name.3`, ... for which `claim` does not find the name taken
+fn claim_free_name(
+    name: &OsStr,
+    mut claim: impl FnMut(&OsStr) -> std::io::Result<()>,
+) -> std::io::Result<OsString> {
+    for number in 1u32.. {
+        let mut candidate = name.to_os_string();
+        if number > 1 {
+            candidate.push(format!(".{number}"));
+        }
+        match claim(&candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "the trash has no free name for the file",
+    ))
+}
+
+/// move the file into the trash as the XDG trash specification describes: into the home trash if the file lies
+/// on its device and into `.Trash-$uid` at the top of the mount of the file otherwise
+#[cfg(not(target_os = "macos"))]
+pub fn trash_file(path: &Path) -> std::io::Result<()> {
+    trash_file_into(path, home_trash())
+}
+
+/// `trash_file` with the home trash and the device it lies on given
+#[cfg(not(target_os = "macos"))]
+fn trash_file_into(path: &Path, home_trash: Option<(PathBuf, u64)>) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let path = absolute_link_path(path)?;
+    let device = path.symlink_metadata()?.dev();
+    let trash = match home_trash.filter(|(_, home_device)| *home_device == device) {
+        Some((home_trash, _)) => home_trash,
+        None => top_dir(&path, device)?.join(format!(".Trash-{}", current_uid())),
+    };
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    for dir in [&files, &info] {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    let name = path.file_name().expect("the absolute path has a file name");
+    let mut info_path = PathBuf::new();
+    let trash_name = claim_free_name(name, |candidate| {
+        let mut info_name = candidate.to_os_string();
+        info_name.push(".trashinfo");
+        info_path = info.join(info_name);
+        let mut info_file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)?;
+        write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(path.as_os_str().as_bytes()),
+            local_timestamp()
+        )
+    })?;
+    std::fs::rename(&path, files.join(trash_name)).inspect_err(|_| {
+        let _ = std::fs::remove_file(&info_path);
+    })
+}
+
+/// the home trash and the device it lies on, None if there is no home
+#[cfg(not(target_os = "macos"))]
+fn home_trash() -> Option<(PathBuf, u64)> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/share")))?;
+    let trash = data_home.join("Trash");
+    // the trash is created on the device of the closest directory which exists
+    let device = trash.ancestors().find_map(|dir| dir.metadata().ok())?.dev();
+    Some((trash, device))
+}
+
+/// the highest directory above the file which still lies on its device
+#[cfg(not(target_os = "macos"))]
+fn top_dir(path: &Path, device: u64) -> std::io::Result<&Path> {
+    let mut top = path.parent().unwrap_or(path);
+    while let Some(parent) = top.parent() {
+        if parent.metadata()?.dev() != device {
+            break;
+        }
+        top = parent;
+    }
+    Ok(top)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn current_uid() -> u32 {
+    // SAFETY: getuid cannot fail
+    unsafe { libc::getuid() }
+}
+
+/// the bytes of the path with everything but unreserved characters and slashes percent encoded, like a uri
+#[cfg(not(target_os = "macos"))]
+fn percent_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                char::from(*byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// the current local time as `YYYY-MM-DDThh:mm:ss`
+#[cfg(not(target_os = "macos"))]
+fn local_timestamp() -> String {
+    // SAFETY: time accepts a null pointer and localtime_r only writes into the given struct
+    let local = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut local = std::mem::zeroed::<libc::tm>();
+        libc::localtime_r(std::ptr::addr_of!(now), std::ptr::addr_of_mut!(local));
+        local
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        local.tm_year + 1900,
+        local.tm_mon + 1,
+        local.tm_mday,
+        local.tm_hour,
+        local.tm_min,
+        local.tm_sec
+    )
+}
+
+/// move the file into the trash of the user, which only takes files from the volume of the home directory
+#[cfg(target_os = "macos")]
+pub fn trash_file(path: &Path) -> std::io::Result<()> {
+    let path = absolute_link_path(path)?;
+    let home = std::env::var_os("HOME").ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "there is no home directory")
+    })?;
+    let trash = PathBuf::from(home).join(".Trash");
+    if trash.metadata()?.dev() != path.symlink_metadata()?.dev() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the file is not on the volume of the trash",
+        ));
+    }
+    let name = path.file_name().expect("the absolute path has a file name");
+    claim_free_name(name, |candidate| {
+        let target = trash.join(candidate);
+        if target.symlink_metadata().is_ok() {
+            return Err(std::io::ErrorKind::AlreadyExists.into());
+        }
+        std::fs::rename(&path, target)
+    })
+    .map(drop)
+}
+
+pub fn is_readable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else { return false };
+    // SAFETY: the path is nul terminated
+    unsafe { libc::access(path.as_ptr(), libc::R_OK) == 0 }
+}
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if crate::util::interrupt() {
+        // SAFETY: _exit is async signal safe, unlike the regular exit
+        unsafe { libc::_exit(crate::INTERRUPTED_EXIT_CODE) }
+    }
+}
+
+pub fn install_interrupt_handler() {
+    // SAFETY: the handler only touches atomics and calls _exit
+    unsafe {
+        let handler: extern "C" fn(libc::c_int) = on_interrupt;
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
 struct ReplaceWithSymlinkFileAction;
 
 impl FileConsumeAction for ReplaceWithSymlinkFileAction {
     fn consume(&mut self, path: &Path, original: Option<&Path>) -> FileConsumeResult {
         let original = original.expect("original required");
+        // the duplicate is left untouched if the link cannot be created
         handle_file_op!(
-            std::fs::remove_file(path),
+            replace_with_link(path, |temp_path| std::os::unix::fs::symlink(
+                original, temp_path
+            )),
             path,
             return Err(Recoverable::Recoverable(AlreadyReportedError))
         );
-        if let Err(err) = std::os::unix::fs::symlink(original, path) {
-            log::error!(
-                target: crate::error_handling::ACTION_FATAL_FAILURE_TARGET,
-                "FATAL ERROR: failed to create sym link to {} from {} due to error {err}",
-                path.display(),
-                original.display()
-            );
-            // Something is absolutely not right here, continuing means risk of data loss
-            return Err(Recoverable::Fatal(AlreadyReportedError));
-        }
         report_file_action!(
             "replaced {} with symlink to {}",
             path.display(),
@@ -86,23 +500,32 @@ impl FileConsumeAction for ReplaceWithSymlinkFileAction {
         true
     }
 
-    fn short_name(&self) -> Cow<str> {
+    fn short_name(&self) -> Cow<'_, str> {
         Cow::Borrowed("replace with symlink")
     }
 
-    fn short_opposite(&self) -> Cow<str> {
+    fn short_opposite(&self) -> Cow<'_, str> {
         Cow::Borrowed("keep")
     }
+
+    fn try_clone(&self) -> Option<Box<dyn FileConsumeAction + Send>> {
+        Some(Box::new(ReplaceWithSymlinkFileAction))
+    }
 }
 
 #[derive(Clone, Default)]
 struct PermissionEqualChecker;
 
 impl FileEqualsChecker for PermissionEqualChecker {
-    fn check_equal(&mut self, a: &Path, b: &Path) -> Result<bool, CheckEqualsErrorOn> {
+    fn check_equal(
+        &mut self,
+        a: &Path,
+        b: &Path,
+        _stop: &StopSignal,
+    ) -> Result<bool, CheckEqualsErrorOn> {
         let metadata_a =
-            handle_file_op!(a.metadata(), a, return Err(CheckEqualsErrorOn::first_err()));
-        let metadata_b = handle_file_op!(
+            handle_metadata_op!(a.metadata(), a, return Err(CheckEqualsErrorOn::first_err()));
+        let metadata_b = handle_metadata_op!(
             b.metadata(),
             b,
             return Err(CheckEqualsErrorOn::second_err())
@@ -117,7 +540,7 @@ impl FileEqualsChecker for PermissionEqualChecker {
         a: &Path,
         hasher: &mut dyn Hasher,
     ) -> Result<(), AlreadyReportedError> {
-        let metadata = handle_file_op!(a.metadata(), a, return Err(AlreadyReportedError));
+        let metadata = handle_metadata_op!(a.metadata(), a, return Err(AlreadyReportedError));
         let perms = metadata.mode() & 0b111_111_111;
         hasher.write_u32(perms);
         Ok(())
@@ -148,7 +571,8 @@ fn test_permission_equal_checker() {
     let mut equals_checker = PermissionEqualChecker;
     let path1 = file1.1.to_push_buf();
     let path2 = file2.1.to_push_buf();
-    assert!(!equals_checker.check_equal(&path1, &path2).unwrap());
+    let never = StopSignal::new(None, None);
+    assert!(!equals_checker.check_equal(&path1, &path2, &never).unwrap());
     let builder = std::collections::hash_map::RandomState::default();
     let mut hash1 = builder.build_hasher();
     equals_checker.hash_component(&path1, &mut hash1).unwrap();
@@ -164,8 +588,9 @@ fn test_permission_equal_checker() {
 
     let path1 = file1.1.to_push_buf();
     let path2 = file2.1.to_push_buf();
+    let never = StopSignal::new(None, None);
 
-    assert!(equals_checker.check_equal(&path1, &path2).unwrap());
+    assert!(equals_checker.check_equal(&path1, &path2, &never).unwrap());
 
     let builder = std::collections::hash_map::RandomState::default();
     let mut hash1 = builder.build_hasher();
@@ -177,14 +602,138 @@ fn test_permission_equal_checker() {
     assert_eq!(hash1, hash2);
 }
 
+/// Only checks the file name itself, hidden directories are pruned during discovery so that
+/// explicitly passed hidden roots are still searched
 #[derive(Clone)]
 struct HiddenFileFilter;
 
+fn is_hidden(name: &OsStr) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().starts_with(b".")
+}
+
 impl FileNameFilter for HiddenFileFilter {
-    fn filter_file_name(&mut self, _name: &LinkedPath, name_path: &Path) -> Result<bool, ()> {
-        use std::os::unix::ffi::OsStrExt;
-        Ok(name_path
-            .iter()
-            .all(|name| !name.as_bytes().starts_with(&[b'.'])))
+    fn filter_file_name(&mut self, name: &LinkedPath, _name_path: &Path) -> Result<bool, ()> {
+        Ok(!is_hidden(name.file_name()))
+    }
+
+    fn filter_dir_name(&mut self, dir_name: &OsStr) -> bool {
+        !is_hidden(dir_name)
     }
 }
+
+#[test]
+fn test_hidden_file_filter() {
+    use crate::common_tests::CommonPrefix;
+    use crate::file_filters::FileFilter;
+    use crate::input_source::{
+        ChannelInputSink, DiscoveringInputSource, InputSource, SymlinkTreatment,
+    };
+
+    let mut prefix = CommonPrefix::new("unix_hidden_filter/.root/");
+    let visible = prefix.create_file("a", &[]);
+    let _hidden = prefix.create_file(".b", &[]);
+    let nested = prefix.create_file("sub/c", &[]);
+    let _pruned = prefix.create_file(".sub/d", &[]);
+
+    let root = LinkedPath::from_path_buf("test_files/unix_hidden_filter/.root".as_ref());
+    let filter: Box<dyn FileNameFilter + Send> = Box::new(HiddenFileFilter);
+    let mut source = DiscoveringInputSource::new(
+        true,
+        false,
+        SymlinkTreatment::Skip,
+        vec![root],
+        FileFilter(vec![filter].into_boxed_slice(), Box::new([])),
+    );
+    let (s, r) = flume::unbounded();
+    source
+        .consume_all(&mut ChannelInputSink::new(s), &StopSignal::new(None, None))
+        .unwrap();
+    let found = r.iter().collect::<std::collections::HashSet<_>>();
+    assert_eq!(found, [visible.1, nested.1].into_iter().collect());
+}
+
+#[test]
+fn test_guarded_file_ops() {
+    use crate::common_tests::CommonPrefix;
+
+    let mut prefix = CommonPrefix::new("unix_guarded_ops/");
+    let a = prefix.create_file("a", b"a").1.to_push_buf();
+    let b = prefix.create_file("b", b"b").1.to_push_buf();
+    let other = prefix.create_file("sub/other", b"other").1.to_push_buf();
+
+    rename_guarded(&a, &b).unwrap();
+    assert!(!a.exists());
+    assert_eq!(std::fs::read(&b).unwrap(), b"a");
+    let across = rename_guarded(&b, &other).unwrap_err();
+    assert_eq!(across.kind(), std::io::ErrorKind::InvalidInput);
+
+    // only the link is removed, not the file it points to
+    let link = std::path::PathBuf::from("test_files/unix_guarded_ops/link");
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink("b", &link).unwrap();
+    remove_file_guarded(&link).unwrap();
+    assert!(link.symlink_metadata().is_err());
+    assert_eq!(std::fs::read(&b).unwrap(), b"a");
+
+    // a symlink in place of a directory on the way is not followed
+    let dir_link = std::path::PathBuf::from("test_files/unix_guarded_ops/dir_link");
+    let _ = std::fs::remove_file(&dir_link);
+    std::os::unix::fs::symlink("sub", &dir_link).unwrap();
+    assert!(remove_file_guarded(&dir_link.join("other")).is_err());
+    assert!(other.exists());
+    std::fs::remove_file(&dir_link).unwrap();
+
+    remove_file_guarded(&b).unwrap();
+    assert!(!b.exists());
+    assert!(remove_file_guarded(&b).is_err());
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn test_trash_file() {
+    use crate::common_tests::CommonPrefix;
+
+    let mut prefix = CommonPrefix::new("unix_trash_file/");
+    std::fs::create_dir_all("test_files/unix_trash_file/data").unwrap();
+    let data_home = Path::new("test_files/unix_trash_file/data")
+        .canonicalize()
+        .unwrap();
+    let trash = data_home.join("Trash");
+    let _ = std::fs::remove_dir_all(&trash);
+    let device = data_home.metadata().unwrap().dev();
+
+    for content in [b"first", b"other"] {
+        let file = prefix.create_file("a file", content).1.to_push_buf();
+        trash_file_into(&file, Some((trash.clone(), device))).unwrap();
+        assert!(!file.exists());
+    }
+    assert_eq!(std::fs::read(trash.join("files/a file")).unwrap(), b"first");
+    assert_eq!(
+        std::fs::read(trash.join("files/a file.2")).unwrap(),
+        b"other"
+    );
+    let info = std::fs::read_to_string(trash.join("info/a file.2.trashinfo")).unwrap();
+    let expected_path = format!("Path={}/a%20file\n", data_home.parent().unwrap().display());
+    assert!(info.starts_with("[Trash Info]\n"));
+    assert!(info.contains(&expected_path));
+    assert!(info.contains("DeletionDate="));
+    std::fs::remove_dir_all(&trash).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_shares_blocks() {
+    use crate::common_tests::CommonPrefix;
+
+    let mut prefix = CommonPrefix::new("unix_shares_blocks/");
+    let alone = prefix.create_file("alone", &[1; 8192]).1.to_push_buf();
+    // a file written on its own shares nothing, whether or not the file system can tell
+    assert_ne!(shares_blocks(&alone), Some(true));
+    assert_eq!(shares_blocks(Path::new("test_files/unix_shares_blocks/missing")), None);
+
+    let copy = prefix.create_file("copy", &[1; 8192]).1.to_push_buf();
+    let file = std::fs::File::open(&copy).unwrap();
+    copy_owner(&alone.metadata().unwrap(), &file).unwrap();
+    assert_eq!(file.metadata().unwrap().gid(), alone.metadata().unwrap().gid());
+}
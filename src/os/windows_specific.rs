@@ -1,44 +1,158 @@
+use crate::error_handling::AlreadyReportedError;
 use crate::file_filters::FileMetadataFilter;
-use crate::parse_cli::UNumberParser;
+use crate::os::{FileIdentity, SetOrderOption};
+use crate::parse_cli::{DirScope, UNumberParser};
+use crate::set_order::{MetadataSetOrder, SetOrder};
 use crate::util::LinkedPath;
-use clap::{arg, value_parser, ArgAction};
+use crate::HashedFile;
+use clap::{arg, ArgAction};
 use std::fs::Metadata;
+use std::os::windows::fs::MetadataExt;
 use std::path::Path;
+use std::process::Command;
+
+const FILE_ATTRIBUTE_HIDDEN: u32 = 0x0000_0002;
+const FILE_ATTRIBUTE_SYSTEM: u32 = 0x0000_0004;
+const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+pub fn get_set_order_options() -> Vec<SetOrderOption> {
+    let visible = SetOrderOption {
+        name: "visible",
+        help: String::from(
+            "Order the files which are neither hidden nor system files before those that are",
+        ),
+        implementation: Box::<VisibleSetOrder>::default(),
+    };
+    vec![visible]
+}
 
 pub fn complex_cmd_config(command: clap::Command) -> clap::Command {
     command
         .arg(
-            arg!(file_attr_filter: --fattrfilter <MASK> "only process files who do not match mask")
+            arg!(attr_mask: --"attr-mask" <MASK> "only process files which have none of the file attributes in the mask")
                 .action(ArgAction::Append)
                 .value_parser(UNumberParser::u32())
                 .value_delimiter(','),
         )
         .arg(
-            arg!(no_hidden: --fattrfilter "only process non hidden files")
-                .action(ArgAction::SetTrue),
+            arg!(no_hidden: --"no-hidden" "only process files which are not hidden")
+                .action(ArgAction::SetTrue)
+                .overrides_with("hidden"),
+        )
+        .arg(
+            arg!(hidden: --hidden "also process hidden files(default)")
+                .action(ArgAction::SetTrue)
+                .overrides_with("no_hidden"),
+        )
+        .arg(
+            arg!(no_system: --"no-system" "only process files which are not system files(default)")
+                .action(ArgAction::SetTrue)
+                .overrides_with("system"),
+        )
+        .arg(
+            arg!(system: --system "also process system files")
+                .action(ArgAction::SetTrue)
+                .overrides_with("no_system"),
+        )
+        .arg(
+            arg!(use_vss: --"use-vss" "Scan volume shadow copies of the directories, the sets are acted on in the live directories; requires administrator rights")
+                .action(ArgAction::SetTrue)
+                .requires("discovering_source")
+                .conflicts_with_all(["snapshotcmd", "discoverstdin", "samplehash"]),
         )
+        // like with --snapshot-cmd, the hash of the samples cannot tell whether the live files changed
+        .mut_arg("samplehash", |arg| arg.conflicts_with("use_vss"))
 }
 
-fn parse_file_attr_filter(matches: &clap::ArgMatches) -> Box<dyn FileMetadataFilter + Send> {
-    let mut filter = 0x00000004;
+pub fn use_vss(matches: &clap::ArgMatches) -> bool {
+    matches.get_flag("use_vss")
+}
 
-    if let Some(masks) = matches.get_many::<u32>("file_attr_filter") {
-        filter |= masks.fold(0, |a, b| (a | b));
-    }
+/// quote the text as a powershell string literal
+fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn powershell(script: &str) -> Command {
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+    command
+}
+
+/// creates a shadow copy of the volume of the directory and prints where the directory is found in it
+pub fn vss_create_command(path: &Path) -> Result<Command, String> {
+    // shadow copies are created for the volumes of drive letters, not for network shares
+    let dir = path
+        .to_str()
+        .map(|dir| dir.strip_prefix(r"\\?\").unwrap_or(dir));
+    let Some(dir) = dir.filter(|dir| !dir.starts_with(r"\\")) else {
+        return Err(format!("cannot create a shadow copy of {}", path.display()));
+    };
+    let script = format!(
+        "$dir = {}; $volume = [System.IO.Path]::GetPathRoot($dir); \
+        $result = (Get-WmiObject -List Win32_ShadowCopy).Create($volume, 'ClientAccessible'); \
+        if ($result.ReturnValue -ne 0) {{ exit 1 }}; \
+        $shadow = Get-WmiObject Win32_ShadowCopy | Where-Object {{ $_.ID -eq $result.ShadowID }}; \
+        $shadow.DeviceObject + '\\' + $dir.Substring($volume.Length)",
+        powershell_quote(dir)
+    );
+    Ok(powershell(&script))
+}
+
+/// deletes the shadow copy the snapshot directory is in
+pub fn vss_cleanup_command(_live: &Path, snapshot: &Path) -> Command {
+    let script = format!(
+        "$snapshot = {}; Get-WmiObject Win32_ShadowCopy \
+        | Where-Object {{ $snapshot.StartsWith($_.DeviceObject + '\\') }} \
+        | ForEach-Object {{ $_.Delete() }}",
+        powershell_quote(&snapshot.to_string_lossy())
+    );
+    powershell(&script)
+}
+
+/// the file attributes which exclude a file, the last of a flag and its negation wins
+fn file_attr_mask(matches: &DirScope) -> u32 {
+    let mut mask = matches
+        .get_many::<u32>("attr_mask")
+        .map_or(0, |masks| masks.fold(0, |a, b| a | b));
 
     if matches.get_flag("no_hidden") {
-        filter |= 0x00000002;
+        mask |= FILE_ATTRIBUTE_HIDDEN;
+    }
+    if !matches.get_flag("system") {
+        mask |= FILE_ATTRIBUTE_SYSTEM;
     }
+    mask
+}
 
-    Box::new(FileAttributeFilter { mask: filter })
+fn parse_file_attr_filter(matches: &DirScope) -> Box<dyn FileMetadataFilter + Send> {
+    Box::new(FileAttributeFilter {
+        mask: file_attr_mask(matches),
+    })
 }
 
 pub fn complex_parse_file_metadata_filter(
-    matches: &clap::ArgMatches,
+    matches: &DirScope,
 ) -> Vec<Box<dyn FileMetadataFilter + Send>> {
     vec![parse_file_attr_filter(matches)]
 }
 
+/// sort files which are neither hidden nor system files first
+#[derive(Default, Clone)]
+struct VisibleSetOrder(MetadataSetOrder<bool>);
+
+impl SetOrder for VisibleSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        self.0.order(files, |md| {
+            Ok(md.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0)
+        })
+    }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
+}
+
 #[derive(Clone)]
 struct FileAttributeFilter {
     mask: u32,
@@ -51,9 +165,338 @@ impl FileMetadataFilter for FileAttributeFilter {
         _name_path: &Path,
         metadata: &Metadata,
     ) -> Result<bool, ()> {
-        use std::os::windows::fs::MetadataExt;
-
         let attrs = metadata.file_attributes();
         Ok((attrs & self.mask) == 0)
     }
 }
+
+/// (DWORD)-11
+const STD_OUTPUT_HANDLE: u32 = 0xFFFF_FFF5;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetStdHandle(std_handle: u32) -> isize;
+    fn GetConsoleMode(console: isize, mode: *mut u32) -> i32;
+    fn WriteConsoleW(
+        console: isize,
+        buffer: *const u16,
+        chars_to_write: u32,
+        chars_written: *mut u32,
+        reserved: *mut std::ffi::c_void,
+    ) -> i32;
+    fn GetFileInformationByHandle(file: isize, information: *mut ByHandleFileInformation) -> i32;
+    fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+}
+
+#[link(name = "shell32")]
+extern "system" {
+    fn SHFileOperationW(operation: *mut ShFileOpStructW) -> i32;
+    fn SHQueryRecycleBinW(root_path: *const u16, info: *mut ShQueryRbInfo) -> i32;
+}
+
+/// `SHFILEOPSTRUCTW`, which shellapi.h packs to 1 byte on 32 bit windows
+#[cfg_attr(target_pointer_width = "32", repr(C, packed(1)))]
+#[cfg_attr(not(target_pointer_width = "32"), repr(C))]
+struct ShFileOpStructW {
+    window: isize,
+    function: u32,
+    from: *const u16,
+    to: *const u16,
+    flags: u16,
+    any_operations_aborted: i32,
+    name_mappings: *mut std::ffi::c_void,
+    progress_title: *const u16,
+}
+
+/// `SHQUERYRBINFO`, which shellapi.h packs to 1 byte on 32 bit windows
+#[cfg_attr(target_pointer_width = "32", repr(C, packed(1)))]
+#[cfg_attr(not(target_pointer_width = "32"), repr(C))]
+struct ShQueryRbInfo {
+    size: u32,
+    bytes: i64,
+    items: i64,
+}
+
+const FO_DELETE: u32 = 0x0003;
+const FOF_SILENT: u16 = 0x0004;
+const FOF_NOCONFIRMATION: u16 = 0x0010;
+const FOF_ALLOWUNDO: u16 = 0x0040;
+const FOF_NOERRORUI: u16 = 0x0400;
+const FOF_WANTNUKEWARNING: u16 = 0x4000;
+
+fn wide_path(path: &Path) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+
+    path.as_os_str().encode_wide().chain([0]).collect()
+}
+
+/// move the file into the recycle bin without showing progress; fails instead of deleting the file for good
+/// if its drive has no recycle bin, and asks before if the file is too large for the recycle bin
+pub fn trash_file(path: &Path) -> std::io::Result<()> {
+    // the recycle bin only restores files deleted by their absolute path
+    let path = std::path::absolute(path)?;
+    let root = path.ancestors().last().unwrap_or(&path);
+    let mut recycle_bin = ShQueryRbInfo {
+        size: u32::try_from(std::mem::size_of::<ShQueryRbInfo>()).expect("the info is small"),
+        bytes: 0,
+        items: 0,
+    };
+    let root_path = wide_path(root);
+    // SAFETY: the root is nul terminated and the info is sized as the call expects
+    let queried =
+        unsafe { SHQueryRecycleBinW(root_path.as_ptr(), std::ptr::addr_of_mut!(recycle_bin)) };
+    if queried != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("the drive {} has no recycle bin", root.display()),
+        ));
+    }
+    // a list of paths, ended by an empty path
+    let mut from = wide_path(&path);
+    from.push(0);
+    let mut operation = ShFileOpStructW {
+        window: 0,
+        function: FO_DELETE,
+        from: from.as_ptr(),
+        to: std::ptr::null(),
+        // the nuke warning takes precedence over no confirmation, so a file is never deleted for good unasked
+        flags: FOF_ALLOWUNDO
+            | FOF_WANTNUKEWARNING
+            | FOF_NOCONFIRMATION
+            | FOF_SILENT
+            | FOF_NOERRORUI,
+        any_operations_aborted: 0,
+        name_mappings: std::ptr::null_mut(),
+        progress_title: std::ptr::null(),
+    };
+    // SAFETY: the operation and the path list it points to stay alive while the call runs
+    let result = unsafe { SHFileOperationW(std::ptr::addr_of_mut!(operation)) };
+    let aborted = operation.any_operations_aborted;
+    if result != 0 || aborted != 0 {
+        return Err(std::io::Error::other(format!(
+            "cannot move the file to the recycle bin(error {result:#x})"
+        )));
+    }
+    Ok(())
+}
+
+/// the control events up to this one are `CTRL_C_EVENT` and `CTRL_BREAK_EVENT`
+const CTRL_BREAK_EVENT: u32 = 1;
+
+extern "system" fn on_console_ctrl(ctrl_type: u32) -> i32 {
+    // closing the console and the second interrupt are left to the default handler, which ends the program
+    i32::from(ctrl_type <= CTRL_BREAK_EVENT && !crate::util::interrupt())
+}
+
+pub fn install_interrupt_handler() {
+    // SAFETY: the handler is a plain function which only touches atomics
+    unsafe {
+        SetConsoleCtrlHandler(Some(on_console_ctrl), 1);
+    }
+}
+
+/// `BY_HANDLE_FILE_INFORMATION`, the times are `FILETIME`s
+#[repr(C)]
+#[derive(Default)]
+struct ByHandleFileInformation {
+    file_attributes: u32,
+    creation_time: [u32; 2],
+    last_access_time: [u32; 2],
+    last_write_time: [u32; 2],
+    volume_serial_number: u32,
+    file_size_high: u32,
+    file_size_low: u32,
+    number_of_links: u32,
+    file_index_high: u32,
+    file_index_low: u32,
+}
+
+/// the volume serial number and the file index, which all paths to a file share, even through
+/// subst drives and junctions; a symlink at the end of the path is only followed if told so; None if the
+/// file cannot be opened
+pub fn path_identity(path: &Path, follow_symlink: bool) -> Option<FileIdentity> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+
+    let flags = if follow_symlink {
+        0
+    } else {
+        FILE_FLAG_OPEN_REPARSE_POINT
+    };
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(flags)
+        .open(path)
+        .ok()?;
+    let mut information = ByHandleFileInformation::default();
+    // SAFETY: the handle stays open while the call runs, information is a valid pointer
+    let ok = unsafe {
+        GetFileInformationByHandle(
+            file.as_raw_handle() as isize,
+            std::ptr::addr_of_mut!(information),
+        )
+    };
+    (ok != 0).then(|| FileIdentity {
+        device: u64::from(information.volume_serial_number),
+        index: u64::from(information.file_index_high) << 32 | u64::from(information.file_index_low),
+    })
+}
+
+/// writes utf-8 to the console as utf-16, so that the text does not depend on the code page of the console
+pub struct WideConsole {
+    handle: isize,
+    /// the start of a character which was split between writes
+    pending: Vec<u8>,
+}
+
+impl WideConsole {
+    /// None if the standard output is not a console, for example because it is redirected
+    pub fn stdout() -> Option<Self> {
+        let mut mode = 0;
+        // SAFETY: the handle is only checked for being a console, mode is a valid pointer
+        let is_console = unsafe {
+            let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+            (handle != 0
+                && handle != -1
+                && GetConsoleMode(handle, std::ptr::addr_of_mut!(mode)) != 0)
+                .then_some(handle)
+        };
+        is_console.map(|handle| Self {
+            handle,
+            pending: Vec::new(),
+        })
+    }
+
+    fn write_wide(&self, mut wide: &[u16]) -> std::io::Result<()> {
+        while !wide.is_empty() {
+            let chars = u32::try_from(wide.len()).unwrap_or(u32::MAX);
+            let mut written = 0;
+            // SAFETY: the buffer holds at least chars characters and written is a valid pointer
+            let ok = unsafe {
+                WriteConsoleW(
+                    self.handle,
+                    wide.as_ptr(),
+                    chars,
+                    std::ptr::addr_of_mut!(written),
+                    std::ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            wide = &wide[written as usize..];
+        }
+        Ok(())
+    }
+}
+
+impl std::io::Write for WideConsole {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let wide = utf16_lossy(&mut self.pending);
+        self.write_wide(&wide)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// convert the complete characters of the bytes, invalid bytes become the replacement character and an
+/// incomplete character at the end is left in the bytes
+fn utf16_lossy(bytes: &mut Vec<u8>) -> Vec<u16> {
+    let mut wide = Vec::with_capacity(bytes.len());
+    let mut rest = bytes.as_slice();
+    let incomplete = loop {
+        let err = match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                wide.extend(valid.encode_utf16());
+                break 0;
+            }
+            Err(err) => err,
+        };
+        let (valid, invalid) = rest.split_at(err.valid_up_to());
+        wide.extend(
+            std::str::from_utf8(valid)
+                .unwrap_or_default()
+                .encode_utf16(),
+        );
+        let Some(invalid_len) = err.error_len() else {
+            break invalid.len();
+        };
+        // the replacement character is a single utf-16 unit
+        wide.push(0xFFFD);
+        rest = &invalid[invalid_len..];
+    };
+    bytes.drain(..bytes.len() - incomplete);
+    wide
+}
+
+#[test]
+fn test_utf16_lossy() {
+    let mut bytes = "aé".as_bytes().to_vec();
+    bytes.push(0xE2);
+    assert_eq!(
+        utf16_lossy(&mut bytes),
+        "aé".encode_utf16().collect::<Vec<_>>()
+    );
+    assert_eq!(bytes, [0xE2]);
+    bytes.extend_from_slice(&[0x82, 0xAC, 0xFF, b'b']);
+    assert_eq!(
+        utf16_lossy(&mut bytes),
+        "€\u{FFFD}b".encode_utf16().collect::<Vec<_>>()
+    );
+    assert!(bytes.is_empty());
+}
+
+#[test]
+fn test_file_attr_mask() {
+    let mask = |args: &[&str]| {
+        let command = complex_cmd_config(clap::Command::new("test").no_binary_name(true));
+        let matches = command.try_get_matches_from(args).unwrap();
+        file_attr_mask(&DirScope::whole(&matches))
+    };
+    assert_eq!(mask(&[]), FILE_ATTRIBUTE_SYSTEM);
+    assert_eq!(mask(&["--system"]), 0);
+    assert_eq!(mask(&["--system", "--no-system"]), FILE_ATTRIBUTE_SYSTEM);
+    assert_eq!(
+        mask(&["--no-hidden"]),
+        FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM
+    );
+    assert_eq!(mask(&["--no-hidden", "--hidden"]), FILE_ATTRIBUTE_SYSTEM);
+    assert_eq!(
+        mask(&["--hidden", "--no-hidden", "--system"]),
+        FILE_ATTRIBUTE_HIDDEN
+    );
+    assert_eq!(
+        mask(&[
+            "--attr-mask",
+            "0x100,0x1",
+            "--attr-mask",
+            "0b10000",
+            "--system"
+        ]),
+        0x111
+    );
+}
+
+#[test]
+fn test_vss_create_command() {
+    assert_eq!(powershell_quote(r"C:\it's"), r"'C:\it''s'");
+    assert!(vss_create_command(Path::new(r"\\?\C:\photos")).is_ok());
+    assert!(vss_create_command(Path::new(r"\\?\UNC\server\share")).is_err());
+}
+
+#[test]
+fn test_path_identity() {
+    let manifest = path_identity(Path::new("Cargo.toml"), true).unwrap();
+    let absolute = Path::new("Cargo.toml").canonicalize().unwrap();
+    assert_eq!(path_identity(&absolute, true), Some(manifest));
+    assert_eq!(path_identity(&absolute, false), Some(manifest));
+    assert_ne!(
+        path_identity(Path::new("src/main.rs"), true),
+        Some(manifest)
+    );
+    assert_eq!(path_identity(Path::new("does_not_exist"), true), None);
+}
@@ -0,0 +1,115 @@
+use std::io::{LineWriter, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// where `--out` sends the results instead of stdout
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OutSink {
+    /// `file:<PATH>`, the file is replaced
+    File(PathBuf),
+    /// `fd:<N>`, an open file descriptor inherited from the caller
+    Fd(i32),
+    /// `tcp:<HOST>:<PORT>`, a collector listening on the address
+    Tcp(String),
+}
+
+impl OutSink {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (scheme, target) = text.split_once(':')?;
+        if target.is_empty() {
+            return None;
+        }
+        match scheme {
+            "file" => Some(Self::File(PathBuf::from(target))),
+            "fd" => target.parse().ok().filter(|fd| *fd >= 0).map(Self::Fd),
+            "tcp" => target
+                .rsplit_once(':')
+                .filter(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+                .map(|_| Self::Tcp(target.to_owned())),
+            _ => None,
+        }
+    }
+
+    /// open the destination; like stdout, the writer flushes every line
+    pub fn open(&self) -> std::io::Result<Box<dyn Write + Send>> {
+        Ok(match self {
+            Self::File(path) => Box::new(LineWriter::new(std::fs::File::create(path)?)),
+            Self::Fd(fd) => Box::new(LineWriter::new(crate::os::open_fd(*fd)?)),
+            Self::Tcp(address) => Box::new(LineWriter::new(TcpStream::connect(address)?)),
+        })
+    }
+}
+
+/// where the consumers write their results: stdout, the sink of `--out` or nowhere with `--quiet-results`;
+/// every consumer is handed a writer of its own
+#[derive(Clone)]
+pub enum ResultsOutput {
+    Console,
+    Discard,
+    Sink(Arc<Mutex<Box<dyn Write + Send>>>),
+}
+
+impl ResultsOutput {
+    pub fn sink(sink: Box<dyn Write + Send>) -> Self {
+        Self::Sink(Arc::new(Mutex::new(sink)))
+    }
+
+    pub fn writer(&self) -> Box<dyn Write + Send> {
+        match self {
+            Self::Console => Box::new(crate::os::console_output()),
+            Self::Discard => Box::new(std::io::sink()),
+            Self::Sink(sink) => Box::new(SharedSink(sink.clone())),
+        }
+    }
+}
+
+/// a writer to the sink shared by all consumers
+struct SharedSink(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl SharedSink {
+    /// a writer which panicked mid-write still takes the following results
+    fn lock(&self) -> MutexGuard<'_, Box<dyn Write + Send>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+impl std::fmt::Display for OutSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Fd(fd) => write!(f, "fd:{fd}"),
+            Self::Tcp(address) => write!(f, "tcp:{address}"),
+        }
+    }
+}
+
+#[test]
+fn test_parse_out_sink() {
+    assert_eq!(
+        OutSink::parse("file:out/report.txt"),
+        Some(OutSink::File(PathBuf::from("out/report.txt")))
+    );
+    assert_eq!(OutSink::parse("fd:3"), Some(OutSink::Fd(3)));
+    assert_eq!(OutSink::Fd(3).to_string(), "fd:3");
+    assert_eq!(
+        OutSink::parse("tcp:[::1]:9000"),
+        Some(OutSink::Tcp(String::from("[::1]:9000")))
+    );
+    assert_eq!(OutSink::parse("fd:-1"), None);
+    assert_eq!(OutSink::parse("tcp:localhost"), None);
+    assert_eq!(OutSink::parse("tcp::9000"), None);
+    assert_eq!(OutSink::parse("file:"), None);
+    assert_eq!(OutSink::parse("report.txt"), None);
+    assert_eq!(OutSink::parse("udp:host:9000"), None);
+}
@@ -0,0 +1,293 @@
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::ArgAction;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// read from the duplis directory of the user's config directory if no other config file is given
+pub const DEFAULT_CONFIG_FILE: &str = "duplis.toml";
+
+/// the options a config file may give: discovery, filters, orders, hashing, threads and logging; the options
+/// which act on files, run commands or write files can only be given on the command line
+const CONFIG_OPTIONS: [&str; 43] = [
+    "recurse",
+    "followsymlink",
+    "treatsymlinks",
+    "traversal",
+    "mindepth",
+    "maxdepth",
+    "numthreads",
+    "contentthreads",
+    "queuedepth",
+    "actionthreads",
+    "maxmemory",
+    "timeout",
+    "filetimeout",
+    "setorder",
+    "keep",
+    "stalenessweights",
+    "minfsize",
+    "maxfsize",
+    "fsizerange",
+    "modified",
+    "nonzerof",
+    "skipunreadable",
+    "extbl",
+    "extwl",
+    "extgroup",
+    "pathbl",
+    "samplehash",
+    "hashalgorithm",
+    "prefixhash",
+    "comparebuffer",
+    "refinerlogic",
+    "ignorehardlinked",
+    "logtargets",
+    "quietlogs",
+    "logtimestamps",
+    "sizeformat",
+    "progress",
+    "progressfiles",
+    "progressinterval",
+    "attr_mask",
+    "no_hidden",
+    "no_system",
+    "system",
+];
+
+/// the options of a config file, each by the id of its argument and the values it gives
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigFile {
+    options: Vec<(String, Vec<String>)>,
+}
+
+/// the command with the options of the config file as its defaults; the config file is given by --config or
+/// lies in the user's config directory
+pub fn with_config_defaults(command: clap::Command, args: &[OsString]) -> clap::Command {
+    // the command line is checked in full once the defaults are set
+    let Ok(cli) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(args)
+    else {
+        return command;
+    };
+    // the flags lack their defaults if the command line has errors
+    if cli.subcommand().is_some() || cli.get_one::<bool>("noconfig") == Some(&true) {
+        return command;
+    }
+    let path = match cli.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None => match default_config_file() {
+            Some(path) if path.is_file() => path,
+            _ => return command,
+        },
+    };
+    let mut command = command;
+    let config = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        let message = format!("failed to read the config file {}: {err}", path.display());
+        command.error(ErrorKind::Io, message).exit()
+    });
+    match ConfigFile::parse(&command, &config) {
+        Ok(config) => config.apply(command, &cli),
+        Err(err) => {
+            let message = format!("invalid config file {}: {err}", path.display());
+            command.error(ErrorKind::InvalidValue, message).exit()
+        }
+    }
+}
+
+/// `duplis/duplis.toml` in the user's config directory, None if there is none
+pub fn default_config_file() -> Option<PathBuf> {
+    crate::os::user_config_dir().map(|dir| dir.join("duplis").join(DEFAULT_CONFIG_FILE))
+}
+
+impl ConfigFile {
+    /// the config is given in TOML by the long names of the options, like `threads = 4` or
+    /// `orderby = ["modtime", "alphabetic"]`; flags are given by `true` or `false`
+    pub fn parse(command: &clap::Command, config: &str) -> Result<Self, String> {
+        let config = config
+            .parse::<toml::Table>()
+            .map_err(|err| err.to_string())?;
+        let mut options = Vec::with_capacity(config.len());
+        for (key, value) in &config {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key))
+                .ok_or_else(|| format!("unknown option '{key}'"))?;
+            let id = arg.get_id().as_str();
+            if !CONFIG_OPTIONS.contains(&id) {
+                return Err(format!("'{key}' can only be given on the command line"));
+            }
+            let values = config_values(key, arg, value)?;
+            options.push((id.to_owned(), values));
+        }
+        let config = Self { options };
+        // the defaults are not checked for conflicts by the command line parser
+        for (id, _) in &config.options {
+            if let Some(other) = conflicting(command, id, |other| config.gives(other)) {
+                return Err(format!(
+                    "'{}' conflicts with '{}'",
+                    long_name(command, id),
+                    long_name(command, other)
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// the command with the options of the config as its defaults, the options the command line gives or
+    /// conflicts with are left out, so that the command line wins
+    pub fn apply(self, command: clap::Command, cli: &clap::ArgMatches) -> clap::Command {
+        let on_cli = |id: &str| cli.value_source(id) == Some(ValueSource::CommandLine);
+        let kept = self
+            .options
+            .iter()
+            .filter(|(id, _)| !on_cli(id) && conflicting(&command, id, on_cli).is_none())
+            .map(|(id, values)| (id.clone(), values.clone()))
+            .collect::<Vec<_>>();
+        kept.into_iter().fold(command, |command, (id, values)| {
+            command.mut_arg(id, |arg| arg.default_values(values))
+        })
+    }
+
+    fn gives(&self, id: &str) -> bool {
+        self.options.iter().any(|(given, _)| given == id)
+    }
+}
+
+/// an option conflicting with the option among those which `given` accepts
+fn conflicting<'c>(
+    command: &'c clap::Command,
+    id: &str,
+    given: impl Fn(&str) -> bool,
+) -> Option<&'c str> {
+    let arg = command.get_arguments().find(|arg| arg.get_id() == id)?;
+    let conflicts_with = |a: &clap::Arg, b: &clap::Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|conflict| conflict.get_id() == b.get_id())
+    };
+    command
+        .get_arguments()
+        .filter(|other| other.get_id() != id && given(other.get_id().as_str()))
+        .find(|other| conflicts_with(arg, other) || conflicts_with(other, arg))
+        .map(|other| other.get_id().as_str())
+}
+
+fn long_name<'c>(command: &'c clap::Command, id: &'c str) -> &'c str {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .and_then(clap::Arg::get_long)
+        .unwrap_or(id)
+}
+
+fn config_values(key: &str, arg: &clap::Arg, value: &toml::Value) -> Result<Vec<String>, String> {
+    match (arg.get_action(), value) {
+        (ArgAction::SetTrue | ArgAction::SetFalse, toml::Value::Boolean(value)) => {
+            Ok(vec![value.to_string()])
+        }
+        (ArgAction::SetTrue | ArgAction::SetFalse, _) => {
+            Err(format!("'{key}' must be true or false"))
+        }
+        (ArgAction::Append, toml::Value::Array(values)) => values
+            .iter()
+            .map(|value| config_value(key, value))
+            .collect(),
+        (_, value) => config_value(key, value).map(|value| vec![value]),
+    }
+}
+
+fn config_value(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Datetime(value) => Ok(value.to_string()),
+        toml::Value::Boolean(_) => Err(format!("'{key}' needs a value")),
+        toml::Value::Array(_) => Err(format!("'{key}' takes a single value")),
+        toml::Value::Table(_) => Err(format!("'{key}' must be a value, not a table")),
+    }
+}
+
+#[test]
+fn test_config_file() {
+    let command = super::assemble_command_info();
+    let parse = |config: &str| ConfigFile::parse(&command, config);
+    let config =
+        "threads = 4\norderby = [\"rmodtime\", \"alphabetic\"]\nnonzero = true\nrecurse = false\n";
+    let to_vec = |values: &[&str]| values.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        parse(config).unwrap(),
+        ConfigFile {
+            options: vec![
+                (String::from("nonzerof"), to_vec(&["true"])),
+                (
+                    String::from("setorder"),
+                    to_vec(&["rmodtime", "alphabetic"])
+                ),
+                (String::from("recurse"), to_vec(&["false"])),
+                (String::from("numthreads"), to_vec(&["4"])),
+            ]
+        }
+    );
+
+    // the command line overrides the options it gives and the options conflicting with them
+    let matches = |config: &str, args: &[&str]| {
+        let cli = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(args)
+            .unwrap();
+        parse(config)
+            .unwrap()
+            .apply(command.clone(), &cli)
+            .try_get_matches_from(args)
+            .unwrap()
+    };
+    let defaults = matches(config, &["duplis", "."]);
+    assert_eq!(defaults.get_one::<u32>("numthreads"), Some(&4));
+    assert!(defaults.get_flag("nonzerof"));
+    assert_eq!(
+        defaults
+            .get_many::<String>("setorder")
+            .unwrap()
+            .collect::<Vec<_>>(),
+        ["rmodtime", "alphabetic"]
+    );
+    let overridden = matches(config, &["duplis", "-t=2", "-o", "modtime", "."]);
+    assert_eq!(overridden.get_one::<u32>("numthreads"), Some(&2));
+    assert_eq!(
+        overridden
+            .get_many::<String>("setorder")
+            .unwrap()
+            .collect::<Vec<_>>(),
+        ["modtime"]
+    );
+    let sized = matches("minsize = \"1KiB\"", &["duplis", "--size", "..2KiB", "."]);
+    assert_eq!(sized.value_source("minfsize"), None);
+
+    // options which act on files, run commands or write files are not taken from a config
+    for option in [
+        "delete = true",
+        "immediate = true",
+        "snapshot-cmd = \"rm -rf ~\"",
+        "out = \"file:x\"",
+        "mirror-to = \"x\"",
+        "dir = \"a\"",
+        "config = \"other.toml\"",
+    ] {
+        assert!(parse(option)
+            .unwrap_err()
+            .contains("can only be given on the command line"));
+    }
+    // the config must be consistent in itself
+    assert!(parse("minsize = \"1KiB\"\nsize = \"..2KiB\"").is_err());
+    assert!(parse("no-such-option = true").is_err());
+    assert!(parse("nonzero = 1").is_err());
+    assert!(parse("threads = [1, 2]").is_err());
+    assert!(parse("[threads]\ncount = 4").is_err());
+    assert!(parse("threads = ").is_err());
+}
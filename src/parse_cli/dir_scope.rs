@@ -0,0 +1,82 @@
+use clap::parser::ValueSource;
+use std::any::Any;
+
+/// the arguments which apply to the directory of a `--dir`: those given before the first `--dir` and
+/// those given after this `--dir` up to the next one
+#[derive(Clone, Copy)]
+pub struct DirScope<'a> {
+    matches: &'a clap::ArgMatches,
+    /// the index of the first `--dir`, the arguments before it apply to every directory
+    first_dir: usize,
+    /// the indices of the arguments given after the `--dir`
+    start: usize,
+    end: usize,
+}
+
+impl<'a> DirScope<'a> {
+    /// all arguments, as if there was no `--dir`
+    pub fn whole(matches: &'a clap::ArgMatches) -> Self {
+        Self::global(matches, usize::MAX)
+    }
+
+    /// the arguments before the first `--dir`, whose value has the index `first_dir`
+    pub fn global(matches: &'a clap::ArgMatches, first_dir: usize) -> Self {
+        Self {
+            matches,
+            first_dir,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// the arguments after the `--dir` whose value has the index `dir` up to the value of the next one at
+    /// `next_dir`
+    pub fn of_dir(&self, dir: usize, next_dir: usize) -> Self {
+        Self {
+            start: dir,
+            end: next_dir,
+            ..*self
+        }
+    }
+
+    fn contains(&self, id: &str, index: usize) -> bool {
+        // the defaults and the values of the config file apply to every directory
+        self.matches.value_source(id) != Some(ValueSource::CommandLine)
+            || index < self.first_dir
+            || (self.start..self.end).contains(&index)
+    }
+
+    /// the values of the argument given in the scope, None if there are none
+    pub fn get_many<T: Any + Clone + Send + Sync + 'static>(
+        &self,
+        id: &str,
+    ) -> Option<impl Iterator<Item = &'a T> + 'a> {
+        let scope = *self;
+        let id = id.to_owned();
+        let indices = self.matches.indices_of(&id)?;
+        let mut values = self
+            .matches
+            .get_many::<T>(&id)?
+            .zip(indices)
+            .filter(move |(_, index)| scope.contains(&id, *index))
+            .map(|(value, _)| value)
+            .peekable();
+        values.peek()?;
+        Some(values)
+    }
+
+    /// the last value of the argument given in the scope
+    pub fn get_one<T: Any + Clone + Send + Sync + 'static>(&self, id: &str) -> Option<&'a T> {
+        self.get_many(id)?.last()
+    }
+
+    pub fn get_flag(&self, id: &str) -> bool {
+        let flag = self.matches.get_flag(id);
+        // a flag given for another directory keeps its default here, which is the opposite
+        let elsewhere = self
+            .matches
+            .indices_of(id)
+            .is_some_and(|mut indices| !indices.any(|index| self.contains(id, index)));
+        flag != elsewhere
+    }
+}
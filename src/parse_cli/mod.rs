@@ -1,49 +1,173 @@
+mod config_file;
+mod dir_scope;
+mod parse_dir;
+mod parse_duration;
 mod parse_file_size;
+mod parse_keep_rule;
+#[cfg(windows)]
 mod parse_number;
+mod parse_out_sink;
+mod parse_path_map;
+mod parse_staleness_weights;
+mod parse_time_range;
 
+pub use dir_scope::DirScope;
+#[cfg(windows)]
 pub use parse_number::UNumberParser;
 
-use crate::error_handling::get_all_log_targets;
+use crate::error_handling::{get_all_log_targets, ErrorSummary};
 use clap::builder::{OsStr, PossibleValue, PossibleValuesParser, TypedValueParser, ValueParser};
+use clap::parser::ValueSource;
 use clap::{arg, value_parser, ArgAction, ArgGroup, ValueHint};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::ffi::OsString;
+use std::io::Write;
 use std::num::{NonZeroU32, NonZeroUsize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use crate::file_action::{DeleteFileAction, FileConsumeAction, ReplaceWithHardLinkFileAction};
+use crate::allow_dupes::AllowedDupes;
+use crate::answers::Answers;
+use crate::compare_report::{read_set_report, CompareReport};
+use crate::file_action::{
+    DeleteFileAction, DirMtimePreserver, FileConsumeAction, OwnershipGuard, OwnershipPolicy,
+    ReplaceWithHardLinkFileAction, ReplaceWithReflinkFileAction,
+};
 use crate::file_filters::{
     ExtensionFilter, FileFilter, FileMetadataFilter, FileNameFilter, MaxSizeFileFilter,
-    MinSizeFileFilter, PathFilter,
+    MinSizeFileFilter, ModifiedTimeFileFilter, PathFilter, ReadableFileFilter, EXTENSION_GROUPS,
+};
+use crate::file_set_refiner::{
+    DeviceEquals, FileContentEquals, FileEqualsChecker, FileWorkload, RefinerLogic,
 };
-use crate::file_set_refiner::{FileContentEquals, FileEqualsChecker};
-use crate::input_source::{DiscoveringInputSource, InputSource, StdInSource};
+use crate::hash_cache::HashCache;
+use crate::input_source::{
+    DiscoveringInputSource, InputSource, StdInSource, SymlinkTreatment, Traversal,
+};
+use crate::logger::LogTimestamps;
+use crate::manifest::write_manifest_header;
+use crate::out_sink::{OutSink, ResultsOutput};
+use crate::report_header::ReportHeader;
+use crate::seen_db::SeenDb;
 
 use crate::os::{
-    complex_cmd_config, complex_parse_file_metadata_filters, FileNameFilterArg, SetOrderOption,
-    SimpleFileConsumeActionArg, SimpleFileEqualCheckerArg,
+    complex_cmd_config, complex_parse_file_metadata_filters, probe_storage_kind, FileNameFilterArg,
+    SetOrderOption, SimpleFileConsumeActionArg, SimpleFileEqualCheckerArg, StorageKind,
+};
+use crate::parse_cli::parse_dir::{DirOptions, DirOptionsValueParser};
+use crate::parse_cli::parse_duration::DurationValueParser;
+use crate::parse_cli::parse_file_size::{
+    FileSize, FileSizeRange, FileSizeRangeValueParser, FileSizeValueParser, SampleHash,
+    SampleHashValueParser,
 };
-use crate::parse_cli::parse_file_size::{FileSize, FileSizeValueParser};
+use crate::parse_cli::parse_keep_rule::{KeepRule, KeepRuleValueParser};
+use crate::parse_cli::parse_out_sink::OutSinkValueParser;
+use crate::parse_cli::parse_path_map::PathMapValueParser;
+use crate::parse_cli::parse_staleness_weights::StalenessWeightsValueParser;
+use crate::parse_cli::parse_time_range::{TimeRange, TimeRangeValueParser};
 use crate::set_consumer::{
-    DryRun, FileSetConsumer, InteractiveEachChoice, MachineReadableEach, MachineReadableSet,
-    UnconditionalAction,
+    CopyAudit, DryRun, EstimateReport, ExtensionReport, FileSetConsumer, FilterAllowedDupes,
+    HardLinkReport, InteractiveEachChoice, InteractiveSetChoice, MachineReadableEach,
+    MachineReadableSet, MapToHost, MirrorTree, PlanWriter, RmBatch, SkipHardLinked, SkipSmallSets,
+    SuppressSeen, SyncWriterSink, TreeReport, UnconditionalAction, WithReportHeader,
 };
 use crate::set_order::{
-    CreateTimeSetOrder, ModTimeSetOrder, NameAlphabeticSetOrder, NoopSetOrder, SetOrder,
+    CleanNameSetOrder, CreateTimeSetOrder, ModTimeSetOrder, NameAlphabeticSetOrder, NoopSetOrder,
+    PathDepthSetOrder, PathLengthSetOrder, SetOrder, SizeSetOrder, StableSetOrder,
+    StalenessSetOrder, StalenessWeights,
 };
-use crate::util::LinkedPath;
+use crate::simulate::Simulation;
+use crate::snapshot::Snapshots;
+use crate::util::{LinkedPath, MemoryBudget, PathMap, ProgressLog, SizeFormat};
+use crate::{Config, HashAlgorithm, HashScope};
+
+/// what the program was invoked to do
+pub enum Invocation {
+    /// search for duplicates
+    Scan(Box<ExecutionPlan>),
+    /// execute a plan written by `--plan`
+    Apply(ApplyPlan),
+    /// compare two manifests written by `--manifest`
+    Diff(DiffManifests),
+    /// record a set in a database given to `--seen-db`
+    Dismiss(DismissSet),
+    /// search the copies of a single file
+    FindCopies(FindCopies),
+}
+
+pub struct ApplyPlan {
+    pub plan: PathBuf,
+    /// the actions a plan may contain, by the long name of their flag
+    pub actions: Vec<(&'static str, Box<dyn FileConsumeAction + Send>)>,
+}
+
+pub struct DiffManifests {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub algorithm: HashAlgorithm,
+}
+
+pub struct DismissSet {
+    pub seen_db: PathBuf,
+    pub files: Vec<PathBuf>,
+}
+
+pub struct FindCopies {
+    pub target: PathBuf,
+    /// the directories searched recursively
+    pub dirs: Vec<Arc<LinkedPath>>,
+}
 
 pub struct ExecutionPlan {
-    pub file_equals: Vec<Box<dyn FileEqualsChecker + Send>>,
-    pub order_set: Vec<Box<dyn SetOrder + Send>>,
+    /// the input sources, the checkers and the orders of the sets, the threads and the hashing
+    pub search: Config,
     pub action: Box<dyn FileSetConsumer>,
-    pub num_threads: NonZeroU32,
+    /// compare the contents after hashing on a pool of this size
+    pub content_threads: Option<NonZeroU32>,
     pub ignore_log_set: Vec<String>,
-    pub input_sources: Vec<Box<dyn InputSource>>,
+    /// the log records are prefixed with the time if present
+    pub log_timestamps: Option<LogTimestamps>,
     pub dedup_files: bool,
+    pub memory_budget: Option<Arc<MemoryBudget>>,
+    pub timeout: Option<Duration>,
+    /// skip a file if hashing it takes longer
+    pub file_timeout: Option<Duration>,
+    pub stop_after: Option<u64>,
+    /// receives the content hash of every hashed file
+    pub manifest: Option<SyncWriterSink>,
+    pub progress: Option<Arc<ProgressLog>>,
+    /// group the files by the file they link to instead of by their content
+    pub group_hard_links: bool,
+    /// the discovered files waiting to be hashed, scaled with the threads if not given
+    pub queue_depth: Option<usize>,
+    /// the directories are scanned in these snapshots of them, removed once dropped
+    pub snapshots: Option<Snapshots>,
+    /// hash the files by size class and consume the sets of each class once it is complete
+    pub stream: bool,
+    /// logged once the logger is set up
+    pub config_warnings: Vec<String>,
+    /// counts the skipped files for the summary at the end of the run
+    pub errors: Arc<ErrorSummary>,
+    /// the hashes of earlier runs, updated at the end of the run
+    pub hash_cache: Option<HashCache>,
+    /// the files of a manifest are used instead of the input sources
+    pub simulation: Option<Simulation>,
+    /// the sizes of the summary of the sets are written in this format
+    pub size_format: SizeFormat,
+    /// the consumers write the results to it
+    pub output: ResultsOutput,
 }
 
+/// the maximal bytes of paths in one batch of `--wout=rm-batch`, well below the usual argument limits
+const RM_BATCH_BYTES: usize = 128 * 1024;
+
+/// every content comparison allocates two buffers of this size at most
+const MAX_COMPARE_BUFFER: u64 = 1024 * 1024 * 1024;
+
+/// the amount of leading bytes files are compared by in estimate mode
+const ESTIMATE_HASH_PREFIX: u64 = 4096;
+
 static ACTION_MODE_GROUP: &str = "action_mode";
 static ACTION_MODE_ACTION_GROUP: &str = "file_action_action";
 static FILE_ACTION_GROUP: &str = "file_action";
@@ -67,20 +191,94 @@ fn assemble_command_info() -> clap::Command {
             .group(INPUT_SOURCE_GROUP)
             .group(DISCOVERING_SOURCE_GROUP)
         )
+        .arg(arg!(dir: --dir <"DIR[:recursive][:follow-symlinks]"> "A directory which should be searched for duplicates; the filters given after it only apply to it")
+            .long_help("A directory which should be searched for duplicates, optionally followed by ':recursive' to search it recursively and ':follow-symlinks' to follow the symlinks in it as if by -s\nThe filters given after it up to the next --dir only apply to the files in it, in addition to the filters given before the first --dir")
+            .value_hint(ValueHint::DirPath)
+            .value_parser(DirOptionsValueParser)
+            .action(ArgAction::Append)
+            .group(INPUT_SOURCE_GROUP)
+            .group(DISCOVERING_SOURCE_GROUP)
+        )
         .arg(arg!(recurse: -r --recurse "search all listed directories recursively(requires dirs to be given via cli)")
             .action(ArgAction::SetTrue)
             .group(DISCOVERY_CONFIG_GROUP)
         )
-        .arg(arg!(followsymlink: -s --symlink "follow symlinks to files and directories during discovery(requires dirs to be given  via cli)")
-            .action(ArgAction::SetTrue)
+        .arg(arg!(followsymlink: -s --symlink [MODE] "follow symlinks to files and directories during discovery(requires dirs to be given  via cli)")
+            .value_parser([
+                PossibleValue::new("all").help("follow all symlinks(default)"),
+                PossibleValue::new("same-device").help("only follow symlinks to files and directories on the device of the directory given via cli"),
+            ])
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("all")
+            .required(false)
+            .group(DISCOVERY_CONFIG_GROUP)
+        )
+        .arg(arg!(treatsymlinks: --"treat-symlinks" <MODE> "what to do with symlinks to files found during discovery, defaults to follow with -s and skip otherwise(requires dirs to be given via cli)")
+            .value_parser([
+                PossibleValue::new("skip").help("ignore symlinks to files"),
+                PossibleValue::new("follow").help("search the file the symlink points to instead"),
+                PossibleValue::new("as-files").help("search the symlink itself, it is compared by the content of the file it points to"),
+            ])
             .required(false)
             .group(DISCOVERY_CONFIG_GROUP)
         )
+        .arg(arg!(traversal: --traversal <ORDER> "the order in which the directories are searched(requires dirs to be given via cli)")
+            .value_parser([
+                PossibleValue::new("dfs").help("depth first, the subdirectories of a directory are searched before its siblings; keeps the reads of a tree together(default)"),
+                PossibleValue::new("bfs").help("breadth first, the directories closer to the roots are searched first; covers all trees early"),
+            ])
+            .required(false)
+            .group(DISCOVERY_CONFIG_GROUP)
+        )
+        .arg(arg!(mindepth: --"min-depth" <N> "skip the files less than N directories below the roots, the files directly in a root have a depth of 1(requires dirs to be given via cli)")
+            .value_parser(value_parser!(u32))
+            // the spelling of find
+            .visible_alias("mindepth")
+            .required(false)
+            .group(DISCOVERY_CONFIG_GROUP)
+        )
+        .arg(arg!(maxdepth: --"max-depth" <N> "skip the files more than N directories below the roots and do not search the directories they are in, the files directly in a root have a depth of 1(requires dirs to be given via cli)")
+            .value_parser(value_parser!(u32).range(1..))
+            .visible_alias("maxdepth")
+            .required(false)
+            .group(DISCOVERY_CONFIG_GROUP)
+        )
+        .arg(arg!(simulateon: --"simulate-on" <MANIFEST> "Simulate a dry run on the files recorded in a manifest written by --manifest without accessing the files; the files are ordered alphabetically unless told otherwise")
+            .long_help("Simulate a dry run on the files recorded in a manifest written by --manifest without accessing the files, for trying out name filters, orders and actions quickly\nThe files are ordered alphabetically unless told otherwise; the filters and orders which need the metadata of the files cannot be simulated")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .group(INPUT_SOURCE_GROUP)
+            .conflicts_with_all([
+                DISCOVERING_SOURCE_GROUP, "discoverstdin", "discoverstdin0", "uncond", "iact", "iactset", "plan",
+                "machine_readable", "reporthardlinks", "comparereport", "estimate", "stream", "manifest",
+                "hashcache", "minsetsavings",
+            ])
+        )
         .arg(arg!(discoverstdin: --readin "reads the files which should be tested for duplication from stdin")
             .action(ArgAction::SetTrue)
             .group(USES_STDIN_GROUP)
             .group(INPUT_SOURCE_GROUP)
         )
+        .arg(arg!(discoverstdin0: --readin0 "like --readin, but the paths are separated by \\0 as written by find -print0")
+            .action(ArgAction::SetTrue)
+            .group(USES_STDIN_GROUP)
+            .group(INPUT_SOURCE_GROUP)
+            .conflicts_with("discoverstdin")
+        )
+        .arg(arg!(snapshotcmd: --"snapshot-cmd" <CMD> "Scan snapshots of the directories; the command is run by the shell for each directory and prints where the snapshot of it is mounted")
+            .long_help("Scan snapshots of the directories; the command is run by the shell with the directory as its argument and has to print the directory the snapshot of it is mounted at\nThe files are discovered and hashed in the snapshots, the sets are reported and acted on with the paths of the live files and the originals are verified against their hash before acting")
+            .action(ArgAction::Set)
+            .value_hint(ValueHint::CommandString)
+            .requires(DISCOVERING_SOURCE_GROUP)
+            .conflicts_with_all(["discoverstdin", "discoverstdin0"])
+        )
+        .arg(arg!(snapshotcleanupcmd: --"snapshot-cleanup-cmd" <CMD> "Run by the shell with the directory and its snapshot as arguments once the scan is done")
+            .action(ArgAction::Set)
+            .value_hint(ValueHint::CommandString)
+            .requires("snapshotcmd")
+        )
         .arg(arg!(uncond: -u --immediate "Execute the specified action without asking")
             .action(ArgAction::SetTrue)
             .group(ACTION_MODE_GROUP)
@@ -92,21 +290,195 @@ fn assemble_command_info() -> clap::Command {
             .group(ACTION_MODE_ACTION_GROUP)
             .group(USES_STDIN_GROUP)
         )
+        .arg(arg!(iactset: --"interactive-set" "List the files of each set and execute the specified action on the files not chosen to be kept")
+            .long_help("List the files of each set and execute the specified action on the files not chosen to be kept\nThe files to keep are given by their numbers like 1,3, or by all or none; the first kept file is the original")
+            .action(ArgAction::SetTrue)
+            .group(ACTION_MODE_GROUP)
+            .group(ACTION_MODE_ACTION_GROUP)
+            .group(USES_STDIN_GROUP)
+        )
+        .arg(arg!(interactivedefault: --"interactive-default" <ANSWER> "The answer of -i to an empty line")
+            .value_parser([PossibleValue::new("yes"), PossibleValue::new("no")])
+            .action(ArgAction::Set)
+            .requires("iact")
+        )
+        .arg(arg!(answers: --answers <FILE> "Replay the answers recorded in the file before asking in -i; the new answers are appended to it")
+            .long_help("Replay the answers recorded in the file before asking in -i; the new answers are appended to it\nEvery answer is recorded with the file it was given for and replayed whenever that file is asked about; the answers about files which no longer exist are left out")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .requires("iact")
+        )
+        .arg(arg!(dryrun: --"dry-run" "Only report what the specified action would do")
+            .action(ArgAction::SetTrue)
+            .group(ACTION_MODE_GROUP)
+            .group(ACTION_MODE_ACTION_GROUP)
+        )
+        .arg(arg!(plan: --plan <FILE> "Write the specified action for each duplicate into an editable plan, which can be executed later with 'duplis apply'")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .group(ACTION_MODE_GROUP)
+            .group(ACTION_MODE_ACTION_GROUP)
+        )
+        .arg(arg!(queuedepth: --"queue-depth" <N_FILES> "The number of discovered files which may wait to be hashed before discovery pauses; by default 64 per thread")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(u32).range(1..))
+            .requires("numthreads")
+        )
+        .arg(arg!(actionthreads: --"action-threads" <NUM_THREADS> "Execute the action of -u on this many threads; the actions on the files of one directory still run one after another")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(u32).range(1..))
+            .requires("uncond")
+        )
+        .arg(arg!(verifyoriginal: --"verify-original" "Hash the original again before acting on its set and skip the set if the original changed since it was hashed")
+            .action(ArgAction::SetTrue)
+            .requires(ACTION_MODE_ACTION_GROUP)
+            .conflicts_with("plan")
+        )
+        .arg(arg!(ownershippolicy: --"ownership-policy" <POLICY> "What to do before replacing a duplicate with a link to an original owned by another user")
+            .value_parser([
+                PossibleValue::new("ignore").help("replace it without checking the owners(default)"),
+                PossibleValue::new("warn").help("replace it, but warn that its content is now owned by the owner of the original"),
+                PossibleValue::new("skip").help("warn and keep the duplicate"),
+            ])
+            .action(ArgAction::Set)
+            .requires(ACTION_MODE_ACTION_GROUP)
+            .conflicts_with("plan")
+        )
+        .arg(arg!(preservedirmtime: --"preserve-dir-mtime" "Restore the modification time of the directories of the deleted or replaced files afterwards, so that backup tools do not scan the directories again")
+            .action(ArgAction::SetTrue)
+            .requires(ACTION_MODE_ACTION_GROUP)
+            .conflicts_with("plan")
+        )
         .arg(arg!(machine_readable: --wout <STRUCTURE> "Write all duplicates pairwise to stdout")
             .value_parser([
                 PossibleValue::new("pairwise").help("print duplicates in format $original,$duplicate\\n"),
-                PossibleValue::new("setwise").help("print entire duplicate sets, with set members separated by comma and sets separated by \\n")
+                PossibleValue::new("setwise").help("print entire duplicate sets, with set members separated by comma and sets separated by \\n"),
+                PossibleValue::new("tree").help("print the duplicate sets with their sizes as a tree grouped by their common directories"),
+                PossibleValue::new("extensions").help("print the number and size of the duplicates per extension"),
+                PossibleValue::new("rm-batch").help("print the duplicates \\0 terminated in batches for 'grep -zv ^# | xargs -0 rm --', records starting with # are comments naming the batch and the original of each set"),
             ])
             .require_equals(true)
             .num_args(0..=1)
             .action(ArgAction::Set)
             .default_missing_value(OsStr::from("pairwise"))
             .group(ACTION_MODE_GROUP)
+        )
+        .arg(arg!(sizeformat: --"size-format" <FORMAT> "How the reports and the summary of the sets write the sizes of files")
+            .value_parser([
+                PossibleValue::new("human").help("with binary prefixes, like 9.7 KiB(default)"),
+                PossibleValue::new("bytes").help("the exact bytes, like 9933, so that the reports can be sorted and compared by tools"),
+                PossibleValue::new("both").help("the exact bytes followed by the size with binary prefixes, like 9933 (9.7 KiB)"),
+            ])
+            .action(ArgAction::Set)
+        )
+        .arg(arg!(reportheader: --"report-header" "Start the output of --wout with a record of the version, roots, orderings, arguments and start of the scan; its lines start with '#'")
+            .action(ArgAction::SetTrue)
+            .requires("machine_readable")
+        )
+        .arg(arg!(setids: --"set-ids" "Start every record of --wout=pairwise and --wout=setwise with the id of its set")
+            .long_help("Start every record of --wout=pairwise and --wout=setwise with the id of its set\nThe id is the hash of the content, of the properties the files were compared by and of the sorted paths of the files, so that each set has its own id and keeps it across runs as long as its content and its files do")
+            .action(ArgAction::SetTrue)
+            .requires("machine_readable")
+        )
+        .arg(arg!(deviceids: --"device-ids" "Follow every path in --wout=pairwise and --wout=setwise by the id of the device it lies on")
+            .action(ArgAction::SetTrue)
+            .requires("machine_readable")
+        )
+        .arg(arg!(filedetails: --"file-details" "Follow every path in --wout=pairwise and --wout=setwise by the size of its file in bytes and its modification time in RFC 3339 UTC")
+            .long_help("Follow every path in --wout=pairwise and --wout=setwise by the size of its file in bytes and its modification time in RFC 3339 UTC, like 9933,2023-01-31T12:30:15Z\nWith --size-format=both the bytes are followed by the size with binary prefixes; the fields are empty if they cannot be read")
+            .action(ArgAction::SetTrue)
+            .requires("machine_readable")
+        )
+        .arg(arg!(reporthardlinks: --"report-hardlinks" "Report the groups of files which are hard links to the same file, without comparing any content")
+            .action(ArgAction::SetTrue)
+            .group(ACTION_MODE_GROUP)
+        )
+        .arg(arg!(estimate: --estimate "quickly estimate the reclaimable space by comparing only the size and beginning of files")
+            .action(ArgAction::SetTrue)
+            .group(ACTION_MODE_GROUP)
+        )
+        .arg(arg!(requirecopies: --"require-copies" <N> "Report the files with fewer than N copies across the directories instead of the duplicates, like to verify that a backup holds a copy of every file")
+            .value_parser(value_parser!(u32).range(1..))
+            .action(ArgAction::Set)
+            .group(ACTION_MODE_GROUP)
+            // every file has to reach the report, including those without any copy
+            .conflicts_with_all(["stream", "contentthreads", "stopafter", "timeout", "maxmemory", "filetimeout", "minsetsavings", "seendb", "allowdupes", "ignorehardlinked", "simulateon"])
+        )
+        .arg(arg!(mirrorto: --"mirror-to" <DIR> "Build a mirror of the files below the directory by their full paths, the files of each set are hard links to its original; the scanned files are left untouched")
+            .value_hint(ValueHint::DirPath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .group(ACTION_MODE_GROUP)
+            // every file has to reach the mirror, including those without any duplicate
+            .conflicts_with_all(["stream", "contentthreads", "stopafter", "timeout", "maxmemory", "filetimeout", "minsetsavings", "seendb", "allowdupes", "ignorehardlinked", "simulateon"])
+        )
+        .arg(arg!(comparereport: --"compare-report" <OLD_REPORT> "Compare the sets with the sets of an earlier JSON report written by --compare-report and print a JSON report of the sets, the new sets, the resolved sets and the reclaimable bytes; every set is new if OLD_REPORT does not exist yet")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .group(ACTION_MODE_GROUP)
+        )
+        .arg(arg!(samplehash: --"sample-hash" <SAMPLES> "Group the files by the hash of their size and N evenly spaced blocks of SIZE(like 16x64KiB) instead of their entire content; the files of a group are still compared in full unless told otherwise")
+            .action(ArgAction::Set)
+            .value_parser(ValueParser::from(SampleHashValueParser))
+            .ignore_case(true)
+            // the hash of the samples cannot tell whether the original changed
+            .conflicts_with_all(["estimate", "manifest", "verifyoriginal", "snapshotcmd"])
+        )
+        .arg(arg!(hashalgorithm: --hash <ALGORITHM> "The algorithm the content of the files is hashed with")
+            .value_parser(hash_algorithm_parser())
+            .default_value(HashAlgorithm::default().cli_name())
+            .ignore_case(true)
+        )
+        .arg(arg!(prefixhash: --"prefix-hash" <SIZE> "Group the files by the hash of their size and first SIZE bytes(like 64KiB) and hash the entire content only of the files which share a group")
+            .action(ArgAction::Set)
+            .value_parser(ValueParser::from(FileSizeValueParser))
+            .ignore_case(true)
+            // the manifest lists the hash of the entire content
+            .conflicts_with_all(["estimate", "samplehash", "manifest"])
+        )
+        .arg(arg!(manifest: --manifest <FILE> "Write the content hash of every searched file into a manifest, which can be compared to other manifests with 'duplis diff'")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .conflicts_with("estimate")
+        )
+        .arg(arg!(seendb: --"seen-db" <FILE> "Do not report the sets recorded in the file; with -i a set can be recorded by answering i(gnore) or x")
+            .long_help("Do not report the sets recorded in the file; with -i a set can be recorded by answering i(gnore) or x\nA set recorded with i(gnore) is only suppressed as long as it consists of exactly the recorded files; sets can also be recorded like this with 'duplis dismiss'\nA set recorded with x is suppressed by its id, whatever files it consists of, as long as the content of the files does not change")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+        )
+        .arg(arg!(hashcache: --cache <FILE> "Reuse the hashes of the files whose size and modification time did not change since an earlier run with the cache; the cache is updated at the end of the run")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+        )
+        .arg(arg!(minsetsavings: --"min-set-savings" <SIZE> "Skip the sets whose duplicates take up less space than the size; hard links to other files of the set take up no space")
+            .action(ArgAction::Set)
+            .value_parser(ValueParser::from(FileSizeValueParser))
+            .ignore_case(true)
+        )
+        .arg(arg!(ignorehardlinked: --"ignore-hardlinked" "Leave out the files which are hard links to an earlier file of their set, they take up no space of their own and need no action")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["keephardlinked", "reporthardlinks"])
+        )
+        .arg(arg!(keephardlinked: --"keep-hardlinked" "Report and act on the files which are hard links to an earlier file of their set like on any other duplicate(default)")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(arg!(allowdupes: --"allow-dupes" <FILE> "Do not report or act on the duplicates allowed by the rules in the file")
+            .long_help("Do not report or act on the duplicates allowed by the rules in the file\nEach line is either a glob, whose matching files may duplicate each other, or two tab separated paths which may duplicate each other; lines starting with '#' are ignored\nA glob without a separator is matched against the file name; '*' does not match separators, '**' does")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
         );
     command = apply_all_args(command, get_file_consume_action_args().into_iter());
 
     command = command
         .arg(arg!(numthreads: -t --threads <NUM_THREADS> "Use multi-threading(optionally provide the number of threads)")
+            .long_help("Use multi-threading(optionally provide the number of threads)\nWithout a number, the number of hashing threads and of threads comparing file contents are picked based on the storage the directories reside on")
             .action(ArgAction::Set)
             .required(false)
             .require_equals(true)
@@ -114,6 +486,48 @@ fn assemble_command_info() -> clap::Command {
             .value_parser(value_parser!(u32))
             .default_missing_value(OsString::from("0"))
         )
+        .arg(arg!(maxmemory: --"max-memory" <SIZE> "approximate ceiling on the memory held for the discovered files; once it is reached, discovery waits and the size classes are spilled to disk, to be hashed one after another")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(ValueParser::from(FileSizeValueParser))
+            .ignore_case(true)
+        )
+        .arg(arg!(timeout: --timeout <DURATION> "stop discovery and hashing after the duration(like 90s, 30m or 2h) and only report the sets found so far")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(ValueParser::from(DurationValueParser))
+        )
+        .arg(arg!(filetimeout: --"file-timeout" <DURATION> "skip a file if hashing it takes longer than the duration(like 30s or 5m), e.g. because it lies on a hung network mount")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(ValueParser::from(DurationValueParser))
+        )
+        .arg(arg!(contentthreads: --"content-threads" <NUM_THREADS> "Compare the contents of files on this many threads of their own once hashing is done, so that the hashing threads only run the cheap comparisons")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(value_parser!(u32).range(1..))
+            .conflicts_with("stopafter")
+        )
+        .arg(arg!(stream: --stream "Hash the files one size class after another and act on the sets of each class as soon as it is done, instead of once all files are hashed")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("contentthreads")
+        )
+        .arg(arg!(progress: --progress "show the discovered and hashed files, the hashed bytes, the time left and the memory used of --max-memory in a line on stderr while hashing, if stderr is a terminal")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(arg!(progressfiles: --"progress-files" <N_FILES> "log the number of hashed files and bytes under the progress log target every time this many files have been hashed")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(u64).range(1..))
+        )
+        .arg(arg!(progressinterval: --"progress-interval" <DURATION> "log the number of hashed files and bytes under the progress log target after this duration(like 30s or 5m) has passed since the last record")
+            .action(ArgAction::Set)
+            .value_parser(ValueParser::from(DurationValueParser))
+        )
+        .arg(arg!(stopafter: --"stop-after" <N_SETS> "stop once this many duplicate sets have been found")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(value_parser!(u64).range(1..))
+        )
         .arg(arg!(setorder: -o --orderby <ORDERINGS>)
             .action(ArgAction::Append)
             .value_delimiter(',')
@@ -122,6 +536,16 @@ fn assemble_command_info() -> clap::Command {
             .long_help("Set the order in which the elements of equal file sets are ordered\nThe smallest is considered the original\nMay contain multiple orderings in decreasing importance\nSome orderings may be prefixed with r to reverse(example rmodtime)")
             .required(false)
         )
+        .arg(arg!(keep: --keep <RULE> "Keep the files matching the rule as the original, before any ordering: path_prefix:DIR, shortest_path, newest or oldest")
+            .long_help("Keep the files matching the rule as the original, before any ordering\npath_prefix:DIR keeps the files below DIR, shortest_path the file with the shortest full path, newest the most and oldest the least recently modified file\nMay be given several times in decreasing importance; the orderings decide between the files the rules cannot tell apart")
+            .value_parser(ValueParser::from(KeepRuleValueParser))
+            .action(ArgAction::Append)
+        )
+        .arg(arg!(stalenessweights: --"staleness-weights" <"MTIME:ATIME"> "How much the modification and the access time count in the staleness ordering, 1:1 by default")
+            .long_help("How much the modification and the access time count in the staleness ordering, 1:1 by default\nThe file with the largest weighted sum of its timestamps is the most recently modified and used; give the access time no weight like 1:0 on file systems mounted with noatime")
+            .value_parser(ValueParser::from(StalenessWeightsValueParser))
+            .action(ArgAction::Set)
+        )
         .arg(arg!(minfsize: --minsize <SIZE> "Only consider files with >= $minsize bytes")
             .action(ArgAction::Set)
             .required(false)
@@ -134,10 +558,27 @@ fn assemble_command_info() -> clap::Command {
             .value_parser(ValueParser::from(FileSizeValueParser))
             .ignore_case(true)
         )
+        .arg(arg!(fsizerange: --size <RANGE> "Only consider files with MIN <= size < MAX bytes, given as MIN..MAX, MIN.. or ..MAX")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(ValueParser::from(FileSizeRangeValueParser))
+            .ignore_case(true)
+            .conflicts_with_all(["minfsize", "maxfsize"])
+        )
+        .arg(arg!(modified: --modified <RANGE> "Only consider files last modified in START..END, START.. or ..END")
+            .long_help("Only consider files last modified in START..END, START.. or ..END\nThe bounds are either dates in UTC(2023-01-31 or 2023-01-31T12:30) or durations before now(like 30d), so ..30d selects files modified more than 30 days ago and 30d.. files modified in the last 30 days")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(ValueParser::from(TimeRangeValueParser))
+        )
         .arg(arg!(nonzerof: -Z --nonzero "Only consider non-zero sized files")
             .action(ArgAction::SetTrue)
             .required(false)
         )
+        .arg(arg!(skipunreadable: --"skip-unreadable" "Skip the files the current user cannot read during discovery and only report how many there were")
+            .action(ArgAction::SetTrue)
+            .required(false)
+        )
         .arg(arg!(extbl: --extbl <EXTENSIONS>)
             .help("files with these extensions are not processed(~ means no extension)")
             .long_help("files with these extensions are not processed(~ means no extension), extensions must be given without preceding dot(\"txt\" not \".txt\")")
@@ -156,6 +597,13 @@ fn assemble_command_info() -> clap::Command {
             .required(false)
             .group(EXT_LIST_GROUP)
         )
+        .arg(arg!(extgroup: --"ext-group" <GROUPS> "ONLY files with the extensions of these groups are processed(may be combined with --extwl)")
+            .value_delimiter(',')
+            .value_parser(PossibleValuesParser::new(EXTENSION_GROUPS.iter().map(|(name, _)| *name)))
+            .action(ArgAction::Append)
+            .required(false)
+            .conflicts_with("extbl")
+        )
         .arg(arg!(pathbl: --pathbl <PATHS> "files with these paths as prefix will not be processed(symlinks are resolved)")
             .value_hint(ValueHint::AnyPath)
             .value_delimiter(',')
@@ -173,8 +621,24 @@ fn assemble_command_info() -> clap::Command {
             .required(false)
         );
     command = apply_all_args(command, get_file_name_filters().into_iter());
-    command = apply_all_args(command, get_file_equals_args().into_iter());
+    command = apply_all_args(
+        command,
+        get_file_equals_args(FileContentEquals::DEFAULT_BUFFER_SIZE).into_iter(),
+    );
     command = command
+        .arg(arg!(comparebuffer: --"compare-buffer" <SIZE> "the size of the chunks in which the contents of files are compared")
+            .action(ArgAction::Set)
+            .value_parser(ValueParser::from(FileSizeValueParser))
+            .ignore_case(true)
+            .default_value("64KiB")
+        )
+        .arg(arg!(refinerlogic: --"refiner-logic" <LOGIC> "how the comparisons of files with the same hash are combined")
+            .value_parser([
+                PossibleValue::new("and").help("files are duplicates if all comparisons consider them equal"),
+                PossibleValue::new("or").help("files are duplicates if any comparison considers them equal"),
+            ])
+            .default_value("and")
+        )
         .arg(arg!(logtargets: --loginfo <INFO> "update the log targets(+$TARGET turns on, ~$TARGET turns off)")
             .action(ArgAction::Append)
             .value_delimiter(',')
@@ -183,6 +647,45 @@ fn assemble_command_info() -> clap::Command {
             .ignore_case(true)
             .group(SET_LOG_TARGET_GROUP)
         )
+        .arg(arg!(quietlogs: --"quiet-logs" "do not log anything; the log is written to stderr otherwise")
+            .action(ArgAction::SetTrue)
+            .group(SET_LOG_TARGET_GROUP)
+        )
+        .arg(arg!(logtimestamps: --"log-timestamps" [KIND] "prefix every log record with the time it was logged at")
+            .value_parser([
+                PossibleValue::new("absolute").help("the date and time in UTC(default)"),
+                PossibleValue::new("elapsed").help("the time since the start of the run"),
+            ])
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("absolute")
+            .conflicts_with("quietlogs")
+        )
+        .arg(arg!(out: --out <SINK> "write the results to file:<PATH>, fd:<N> or tcp:<HOST>:<PORT> instead of stdout")
+            .value_parser(ValueParser::from(OutSinkValueParser))
+            .conflicts_with_all(["quietresults", "iact", "iactset"])
+        )
+        .arg(arg!(pathmap: --"path-map" <MAP> "Report the files below CONTAINER_PREFIX below HOST_PREFIX instead and read the paths from stdin the other way around, given as HOST_PREFIX=CONTAINER_PREFIX")
+            .long_help("Report the files below CONTAINER_PREFIX below HOST_PREFIX instead and read the paths from stdin the other way around, given as HOST_PREFIX=CONTAINER_PREFIX like /srv/data=/data\nFor running duplis in a container against a bind mount, so that the report can be used on the host; may be given several times, the first prefix a path is below is replaced\nThe files are only looked at in the container, so it cannot be combined with acting on the files")
+            .value_parser(ValueParser::from(PathMapValueParser))
+            .action(ArgAction::Append)
+            // these look at the files by the reported paths
+            .conflicts_with_all(["uncond", "iact", "iactset", "dryrun", "plan", "mirrorto", "reporthardlinks"])
+        )
+        .arg(arg!(quietresults: --"quiet-results" "do not write the results to stdout, for example when only the actions or the manifest are of interest")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["iact", "iactset"])
+        )
+        .arg(arg!(config: --config <FILE> "read the defaults of the options from the TOML file instead of duplis/duplis.toml in the user's config directory; the options given on the command line win")
+            .long_help("read the defaults of the options from the TOML file instead of duplis/duplis.toml in the user's config directory; the options given on the command line win\nThe options are given by their long names, like threads = 4, orderby = [\"modtime\", \"alphabetic\"] or nonzero = true; an option is left out if the command line gives it or an option it conflicts with\nOnly the options for discovery, filters, orders, hashing, threads and logging may be given; the actions, commands and the files written can only be given on the command line")
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .action(ArgAction::Set)
+        )
+        .arg(arg!(noconfig: --"no-config" "do not read duplis/duplis.toml in the user's config directory")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("config")
+        )
         .arg(arg!(setlogtargets: --setloginfo <INFO> "set the log targets to be logged")
             .action(ArgAction::Append)
             .required(false)
@@ -195,9 +698,63 @@ fn assemble_command_info() -> clap::Command {
             .group(SET_LOG_TARGET_GROUP)
         )
         .group(ArgGroup::new(INPUT_SOURCE_GROUP).required(true).multiple(true))
+        .group(ArgGroup::new(DISCOVERING_SOURCE_GROUP).multiple(true))
         .group(ArgGroup::new(ACTION_MODE_ACTION_GROUP).requires(FILE_ACTION_GROUP))
-        .group(ArgGroup::new(FILE_ACTION_GROUP).requires(ACTION_MODE_ACTION_GROUP))
-        .group(ArgGroup::new(DISCOVERY_CONFIG_GROUP).requires(DISCOVERING_SOURCE_GROUP).multiple(true));
+        .group(ArgGroup::new(FILE_ACTION_GROUP)
+            .args(get_file_consume_action_args().into_iter().map(|arg| arg.name))
+            .requires(ACTION_MODE_ACTION_GROUP)
+        )
+        .group(ArgGroup::new(DISCOVERY_CONFIG_GROUP).requires(DISCOVERING_SOURCE_GROUP).multiple(true))
+        .subcommand(clap::Command::new("apply")
+            .about("execute the actions remaining in a plan written by --plan; files whose content changed since are skipped")
+            .arg(arg!(planfile: <PLAN> "the plan to execute")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(value_parser!(PathBuf))
+            )
+        )
+        .subcommand(clap::Command::new("diff")
+            .about("report the files of manifest A whose content is also found in manifest B, without accessing the files")
+            .arg(arg!(manifesta: <MANIFEST_A> "the manifest of the first scan")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(value_parser!(PathBuf))
+            )
+            .arg(arg!(manifestb: <MANIFEST_B> "the manifest of the second scan")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(value_parser!(PathBuf))
+            )
+            .arg(arg!(hashalgorithm: --hash <ALGORITHM> "the algorithm both manifests were hashed with")
+                .value_parser(hash_algorithm_parser())
+                .default_value(HashAlgorithm::default().cli_name())
+                .ignore_case(true)
+            )
+        )
+        .subcommand(clap::Command::new("dismiss")
+            .about("record the files as a set which --seen-db shall no longer report")
+            .arg(arg!(seendb: <SEEN_DB> "the database of dismissed sets")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(value_parser!(PathBuf))
+            )
+            .arg(arg!(files: <FILES> "the files of the set")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(CanonicalPathValueParser)
+                .num_args(2..)
+            )
+        )
+        .subcommand(clap::Command::new("find-copies")
+            .about("report the files below the directories with the same content as the file, only files of its size are compared")
+            .arg(arg!(target: <FILE> "the file to find the copies of")
+                .value_hint(ValueHint::FilePath)
+                .value_parser(CanonicalPathValueParser)
+            )
+            .arg(arg!(dirs: [DIRS] "the directories to search recursively, the current directory if none are given")
+                .value_hint(ValueHint::DirPath)
+                .value_parser(CanonicalPathValueParser)
+                .num_args(0..)
+                .default_value(".")
+            )
+        )
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true);
 
     complex_cmd_config(command)
 }
@@ -265,7 +822,8 @@ impl clap::builder::TypedValueParser for PathListFileParser {
             clap::Error::raw(
                 clap::error::ErrorKind::Io,
                 format!(
-                    "failed to open path file({arg_text}) {value:?}: {}{err}{}\n",
+                    "failed to open path file({arg_text}) {}: {}{err}{}\n",
+                    value.display(),
                     err_style.render(),
                     err_style.render_reset()
                 ),
@@ -290,7 +848,7 @@ impl clap::builder::TypedValueParser for PathListFileParser {
                     format!("(for '{}{arg}{}')", literal.render(), literal.render_reset())
                 });
                 let err_style = cmd.get_styles().get_error();
-                clap::Error::raw(clap::error::ErrorKind::Io, format!("failed to canonicalize path {} from file {:?}({arg_text}) {value:?}: {}{err}{}\n", path.display(), value, err_style.render(), err_style.render_reset()))
+                clap::Error::raw(clap::error::ErrorKind::Io, format!("failed to canonicalize path {} from file {}({arg_text}): {}{err}{}\n", path.display(), value.display(), err_style.render(), err_style.render_reset()))
                     .with_cmd(cmd)
             })?;
         }
@@ -324,7 +882,7 @@ impl TypedValueParser for CanonicalPathValueParser {
             clap::Error::raw(
                 clap::error::ErrorKind::Io,
                 format!(
-                    "failed to canonicalize path {} ({arg_text}) {value:?}: {}{err}{}\n",
+                    "failed to canonicalize path {} ({arg_text}): {}{err}{}\n",
                     value.display(),
                     err_style.render(),
                     err_style.render_reset()
@@ -335,43 +893,104 @@ impl TypedValueParser for CanonicalPathValueParser {
     }
 }
 
+/// the directories given by position and by --dir
+fn directories(matches: &clap::ArgMatches) -> impl Iterator<Item = &PathBuf> {
+    let dirs = matches.get_many::<PathBuf>("dirs").into_iter().flatten();
+    let dir_options = matches.get_many::<DirOptions>("dir").into_iter().flatten();
+    dirs.chain(dir_options.map(|dir| &dir.path))
+}
+
+/// the filters for all sources and the filters for each --dir, which apply the arguments given after it
+fn parse_scoped_file_filters(
+    matches: &clap::ArgMatches,
+    errors: &Arc<ErrorSummary>,
+) -> Result<(FileFilter, Vec<(DirOptions, FileFilter)>), clap::Error> {
+    let dirs = matches
+        .get_many::<DirOptions>("dir")
+        .map_or_else(Vec::new, Iterator::collect::<Vec<_>>);
+    let indices = matches
+        .indices_of("dir")
+        .map_or_else(Vec::new, Iterator::collect::<Vec<_>>);
+    if dirs.len() != indices.len() {
+        return Err(clap::Error::raw(
+            clap::error::ErrorKind::ArgumentConflict,
+            "the filters cannot be assigned to the directories given by --dir\n",
+        ));
+    }
+    let global = DirScope::global(matches, indices.first().copied().unwrap_or(usize::MAX));
+    let scoped_filters = dirs
+        .into_iter()
+        .zip(&indices)
+        .zip(indices.iter().skip(1).copied().chain([usize::MAX]))
+        .map(|((dir, &index), next)| {
+            let scope = global.of_dir(index, next);
+            (dir.clone(), parse_file_filter(&scope, errors))
+        })
+        .collect();
+    Ok((parse_file_filter(&global, errors), scoped_filters))
+}
+
 fn parse_directories(matches: &clap::ArgMatches) -> Vec<Arc<LinkedPath>> {
     matches
         .get_many::<std::path::PathBuf>("dirs")
-        .map(|paths| {
-            paths
-                .map(PathBuf::as_path)
-                .map(LinkedPath::from_path_buf)
-                .collect::<Vec<_>>()
-        })
-        .unwrap_or(Vec::new())
+        .into_iter()
+        .flatten()
+        .map(PathBuf::as_path)
+        .map(LinkedPath::from_path_buf)
+        .collect()
 }
 
 fn parse_set_order(matches: &clap::ArgMatches) -> Vec<Box<dyn SetOrder + Send>> {
+    let weights = matches
+        .get_one::<StalenessWeights>("stalenessweights")
+        .copied()
+        .unwrap_or_default();
     let mut order = matches
         .get_many::<String>("setorder")
         .map_or(Vec::new(), |options| {
             let variants = get_set_order_options();
             options
-                .map(|sname| {
-                    variants
-                        .iter()
-                        .find(|(name, _, _)| name == sname)
-                        .unwrap()
-                        .2
-                        .dyn_clone()
+                .map(|sname| -> Box<dyn SetOrder + Send> {
+                    match sname.as_str() {
+                        "staleness" => Box::new(StalenessSetOrder::new(false, weights)),
+                        "rstaleness" => Box::new(StalenessSetOrder::new(true, weights)),
+                        _ => variants
+                            .iter()
+                            .find(|(name, _, _)| name == sname)
+                            .unwrap()
+                            .2
+                            .dyn_clone(),
+                    }
                 })
                 .collect::<Vec<_>>()
         });
-    if order.is_empty() {
+    // a simulation has no modification times
+    if order.is_empty() && matches.contains_id("simulateon") {
+        order.push(Box::new(NameAlphabeticSetOrder::new(false)));
+    } else if order.is_empty() {
         order.push(Box::new(ModTimeSetOrder::new(false)));
     }
     order.reverse();
+    // the rules come before the orderings, the first rule is applied last so that it decides
+    let rules = matches.get_many::<KeepRule>("keep").into_iter().flatten();
+    order.extend(rules.rev().map(KeepRule::set_order));
     order
 }
 
+fn parse_log_timestamps(matches: &clap::ArgMatches) -> Option<LogTimestamps> {
+    match matches.get_one::<String>("logtimestamps")?.as_str() {
+        "elapsed" => Some(LogTimestamps::Elapsed),
+        _ => Some(LogTimestamps::Absolute),
+    }
+}
+
 fn parse_ignore_log_targets(matches: &clap::ArgMatches) -> Vec<String> {
-    if let Some(targets) = matches.get_many::<String>("setlogtargets") {
+    if matches.get_flag("quietlogs") {
+        get_all_log_targets()
+            .into_iter()
+            .map(std::borrow::ToOwned::to_owned)
+            .collect()
+    } else if let Some(targets) = matches.get_many::<String>("setlogtargets") {
         let all_targets = get_all_log_targets();
         let targets = targets
             .map(|it| it.to_ascii_lowercase())
@@ -401,7 +1020,7 @@ fn parse_ignore_log_targets(matches: &clap::ArgMatches) -> Vec<String> {
     }
 }
 
-fn parse_path_blacklist(matches: &clap::ArgMatches) -> Option<Box<dyn FileNameFilter + Send>> {
+fn parse_path_blacklist(matches: &DirScope) -> Option<Box<dyn FileNameFilter + Send>> {
     let mut blacklisted = Vec::new();
     if let Some(bl) = matches.get_many::<PathBuf>("pathbl") {
         blacklisted.extend(bl);
@@ -417,7 +1036,7 @@ fn parse_path_blacklist(matches: &clap::ArgMatches) -> Option<Box<dyn FileNameFi
     }
 }
 
-fn parse_file_filter(matches: &clap::ArgMatches) -> FileFilter {
+fn parse_file_filter(matches: &DirScope, errors: &Arc<ErrorSummary>) -> FileFilter {
     fn gather_exts<'a>(exts: impl Iterator<Item = &'a OsString>) -> (HashSet<OsString>, bool) {
         let mut exts_col = HashSet::with_capacity(exts.size_hint().0);
         let mut no_ext = false;
@@ -440,6 +1059,21 @@ fn parse_file_filter(matches: &clap::ArgMatches) -> FileFilter {
     if let Some(filter) = matches.get_one::<FileSize>("minfsize") {
         metadata_filter.push(Box::new(MinSizeFileFilter::new(filter.0.saturating_sub(1))));
     }
+    if let Some(range) = matches.get_one::<FileSizeRange>("fsizerange") {
+        if let Some(max) = range.max {
+            metadata_filter.push(Box::new(MaxSizeFileFilter::new(max)));
+        }
+        if let Some(min) = range.min.filter(|min| *min > 0) {
+            metadata_filter.push(Box::new(MinSizeFileFilter::new(min.saturating_sub(1))));
+        }
+    }
+
+    if let Some(range) = matches.get_one::<TimeRange>("modified") {
+        metadata_filter.push(Box::new(ModifiedTimeFileFilter::new(
+            range.start,
+            range.end,
+        )));
+    }
 
     let additional = get_file_name_filters()
         .into_iter()
@@ -457,42 +1091,738 @@ fn parse_file_filter(matches: &clap::ArgMatches) -> FileFilter {
         let filter = ExtensionFilter::new(exts, no_ext, false);
         filename_filter.push(Box::new(filter));
     }
-    if let Some(exts) = matches.get_many::<OsString>("extwl") {
-        let (exts, no_ext) = gather_exts(exts);
-        let filter = ExtensionFilter::new(exts, no_ext, true);
+    let group_exts = matches
+        .get_many::<String>("extgroup")
+        .into_iter()
+        .flatten()
+        .flat_map(|group| {
+            EXTENSION_GROUPS
+                .iter()
+                .filter(move |(name, _)| name == group)
+                .flat_map(|(_, exts)| exts.iter())
+        })
+        .map(OsString::from)
+        .collect::<Vec<_>>();
+    let extwl = matches.get_many::<OsString>("extwl");
+    if extwl.is_some() || !group_exts.is_empty() {
+        let (exts, no_ext) = gather_exts(extwl.into_iter().flatten());
+        // the extensions of the groups also match in upper or mixed case, like IMG_0001.JPG
+        let filter = ExtensionFilter::new(exts, no_ext, true).with_any_case(group_exts);
         filename_filter.push(Box::new(filter));
     }
     if let Some(filter) = parse_path_blacklist(matches) {
         filename_filter.push(filter);
     }
+    // the access check is the most expensive, the other filters go first
+    if matches.get_flag("skipunreadable") {
+        metadata_filter.push(Box::new(ReadableFileFilter::new(errors.clone())));
+    }
     FileFilter(
         filename_filter.into_boxed_slice(),
         metadata_filter.into_boxed_slice(),
     )
 }
 
-fn parse_input_source(matches: &clap::ArgMatches) -> Vec<Box<dyn InputSource>> {
+fn parse_input_source(
+    matches: &clap::ArgMatches,
+    snapshots: Option<&Snapshots>,
+    command: &clap::Command,
+    errors: &Arc<ErrorSummary>,
+) -> (Vec<Box<dyn InputSource>>, Vec<String>) {
     let mut input_source: Vec<Box<dyn InputSource>> = Vec::new();
 
     let recurse = matches.get_flag("recurse");
-    let follow_symlinks = matches.get_flag("followsymlink");
-    let read_from_stdin = matches.get_flag("discoverstdin");
+    let follow_symlinks = matches.contains_id("followsymlink");
+    let same_device = matches
+        .get_one::<String>("followsymlink")
+        .is_some_and(|mode| mode == "same-device");
+    let file_symlinks = parse_symlink_treatment(matches);
+    let read_from_stdin = matches.get_flag("discoverstdin") || matches.get_flag("discoverstdin0");
+    let traversal = match matches.get_one::<String>("traversal").map(String::as_str) {
+        Some("bfs") => Traversal::BreadthFirst,
+        Some("dfs") | None => Traversal::DepthFirst,
+        Some(order) => panic!("invalid traversal {order}"),
+    };
+    let min_depth = matches.get_one::<u32>("mindepth").copied().unwrap_or(0);
+    let max_depth = matches.get_one::<u32>("maxdepth").copied();
 
-    let dirs = parse_directories(matches);
+    // the directory is discovered in its snapshot if there is one
+    let root = |dir: &PathBuf| {
+        snapshots
+            .and_then(|snapshots| snapshots.snapshot_dir(dir))
+            .unwrap_or_else(|| LinkedPath::from_path_buf(dir))
+    };
+    let dirs = matches.get_many::<PathBuf>("dirs").into_iter().flatten();
+    let (dirs, warnings) = collapse_overlapping_roots(dirs, recurse);
+    let dirs = dirs.into_iter().map(root).collect::<Vec<_>>();
 
-    let file_filter = parse_file_filter(matches);
+    let (file_filter, scoped_filters) = parse_scoped_file_filters(matches, errors)
+        .unwrap_or_else(|err| err.with_cmd(command).exit());
 
     if !dirs.is_empty() {
-        let source =
-            DiscoveringInputSource::new(recurse, follow_symlinks, dirs, file_filter.clone());
+        let source = DiscoveringInputSource::new(
+            recurse,
+            follow_symlinks,
+            file_symlinks,
+            dirs,
+            file_filter.clone(),
+        )
+        .with_traversal(traversal)
+        .with_min_depth(min_depth)
+        .with_max_depth(max_depth)
+        .with_same_device_symlinks(same_device);
+        input_source.push(Box::new(source));
+    }
+    for (dir, filter) in scoped_filters {
+        // following symlinks for the directory follows those to files unless told otherwise
+        let file_symlinks = if dir.follow_symlinks && !matches.contains_id("treatsymlinks") {
+            SymlinkTreatment::Follow
+        } else {
+            file_symlinks
+        };
+        let source = DiscoveringInputSource::new(
+            recurse || dir.recursive,
+            follow_symlinks || dir.follow_symlinks,
+            file_symlinks,
+            vec![root(&dir.path)],
+            filter,
+        )
+        .with_traversal(traversal)
+        .with_min_depth(min_depth)
+        .with_max_depth(max_depth)
+        .with_same_device_symlinks(same_device);
         input_source.push(Box::new(source));
     }
 
     if read_from_stdin {
-        input_source.push(Box::new(StdInSource::new(file_filter)));
+        let source = StdInSource::new(file_filter)
+            .with_path_maps(parse_path_maps(matches).unwrap_or_default());
+        if matches.get_flag("discoverstdin0") {
+            input_source.push(Box::new(source.with_nul_separator()));
+        } else {
+            input_source.push(Box::new(source));
+        }
+    }
+
+    (input_source, warnings)
+}
+
+/// drops the roots which are given twice or, when recursing, lie within another root, so that no directory
+/// is discovered twice; returns the remaining roots in order and a warning for each dropped root
+fn collapse_overlapping_roots<'a>(
+    dirs: impl IntoIterator<Item = &'a PathBuf>,
+    recurse: bool,
+) -> (Vec<&'a PathBuf>, Vec<String>) {
+    let covers = |root: &Path, dir: &Path| root == dir || (recurse && dir.starts_with(root));
+    let mut roots: Vec<&PathBuf> = Vec::new();
+    let mut warnings = Vec::new();
+    let mut warn = |dir: &Path, root: &Path| {
+        warnings.push(if dir == root {
+            format!("{} is given twice; discovering it once", dir.display())
+        } else {
+            format!(
+                "{} lies within {}; discovering it once as part of the latter",
+                dir.display(),
+                root.display()
+            )
+        });
+    };
+    for dir in dirs {
+        if let Some(root) = roots.iter().find(|root| covers(root, dir)) {
+            warn(dir, root);
+            continue;
+        }
+        // the new root takes the place of the first root it contains
+        let position = roots.iter().position(|root| covers(dir, root));
+        roots.retain(|root| {
+            let within = covers(dir, root);
+            if within {
+                warn(root, dir);
+            }
+            !within
+        });
+        roots.insert(position.unwrap_or(roots.len()), dir);
+    }
+    (roots, warnings)
+}
+
+fn parse_file_equals(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    estimate: bool,
+) -> Vec<Box<dyn FileEqualsChecker + Send>> {
+    let compare_buffer = match matches.get_one::<FileSize>("comparebuffer") {
+        Some(FileSize(size @ 1..=MAX_COMPARE_BUFFER)) => {
+            usize::try_from(*size).unwrap_or(usize::MAX)
+        }
+        Some(_) => command
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                "the compare buffer must be between 1B and 1GiB",
+            )
+            .exit(),
+        None => FileContentEquals::DEFAULT_BUFFER_SIZE,
+    };
+    get_file_equals_args(compare_buffer)
+        .into_iter()
+        .filter(|arg| matches.get_flag(arg.name))
+        .map(|arg| arg.action)
+        // an estimate must not read whole files
+        .filter(|action| !estimate || action.work_severity() < FileWorkload::FileContent)
+        .collect()
+}
+
+/// the files are hashed in snapshots, which may be outdated when acting on the live files
+fn scans_snapshots(matches: &clap::ArgMatches) -> bool {
+    matches.contains_id("snapshotcmd") || crate::os::use_vss(matches)
+}
+
+fn parse_snapshots(command: &mut clap::Command, matches: &clap::ArgMatches) -> Option<Snapshots> {
+    if !scans_snapshots(matches) {
+        return None;
+    }
+    let dirs = directories(matches).cloned().collect::<Vec<_>>();
+    #[cfg(windows)]
+    let snapshots = if crate::os::use_vss(matches) {
+        Snapshots::create_vss(&dirs)
+    } else {
+        Snapshots::create(
+            matches.get_one::<String>("snapshotcmd").unwrap(),
+            matches.get_one::<String>("snapshotcleanupcmd").cloned(),
+            &dirs,
+        )
+    };
+    #[cfg(not(windows))]
+    let snapshots = Snapshots::create(
+        matches.get_one::<String>("snapshotcmd").unwrap(),
+        matches.get_one::<String>("snapshotcleanupcmd").cloned(),
+        &dirs,
+    );
+    match snapshots {
+        Ok(snapshots) => Some(snapshots),
+        Err(err) => command.error(clap::error::ErrorKind::Io, err).exit(),
+    }
+}
+
+fn parse_hash_scope(matches: &clap::ArgMatches, estimate: bool) -> HashScope {
+    if estimate {
+        return HashScope::Prefix(ESTIMATE_HASH_PREFIX);
+    }
+    if let Some(prefix) = matches.get_one::<FileSize>("prefixhash") {
+        return HashScope::PrefixBuckets(prefix.0);
+    }
+    match matches.get_one::<SampleHash>("samplehash") {
+        Some(sample) => HashScope::Sampled {
+            blocks: sample.blocks,
+            block_size: sample.block_size,
+        },
+        None => HashScope::Full,
+    }
+}
+
+fn parse_manifest(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+) -> Option<SyncWriterSink> {
+    let manifest = matches.get_one::<PathBuf>("manifest")?;
+    let sink = std::fs::File::create(manifest).and_then(|file| {
+        let mut sink = SyncWriterSink::new(std::io::BufWriter::new(file))?;
+        write_manifest_header(&mut sink, parse_hash_algorithm(matches).name())?;
+        sink.flush()?;
+        Ok(sink)
+    });
+    match sink {
+        Ok(sink) => Some(sink),
+        Err(err) => command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot create manifest {}: {err}", manifest.display()),
+            )
+            .exit(),
+    }
+}
+
+fn parse_machine_readable(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    output: &ResultsOutput,
+    kind: &str,
+) -> Box<dyn FileSetConsumer> {
+    let set_ids = matches.get_flag("setids");
+    let device_ids = matches.get_flag("deviceids");
+    let file_details = matches
+        .get_flag("filedetails")
+        .then(|| parse_size_format(matches));
+    for (given, name) in [
+        (set_ids, "--set-ids"),
+        (device_ids, "--device-ids"),
+        (file_details.is_some(), "--file-details"),
+    ] {
+        if given && !matches!(kind, "pairwise" | "setwise") {
+            command
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    format!("{name} cannot be used with --wout={kind}"),
+                )
+                .exit();
+        }
+    }
+    let consumer: Box<dyn FileSetConsumer> = match kind {
+        "pairwise" => {
+            let mut each = MachineReadableEach::for_output(output);
+            if set_ids {
+                each = each.with_set_ids();
+            }
+            if device_ids {
+                each = each.with_device_ids();
+            }
+            if let Some(size_format) = file_details {
+                each = each.with_file_details(size_format);
+            }
+            Box::new(each)
+        }
+        "setwise" => {
+            let mut set = MachineReadableSet::for_output(output);
+            if set_ids {
+                set = set.with_set_ids();
+            }
+            if device_ids {
+                set = set.with_device_ids();
+            }
+            if let Some(size_format) = file_details {
+                set = set.with_file_details(size_format);
+            }
+            Box::new(set)
+        }
+        "tree" => {
+            Box::new(TreeReport::for_output(output).with_size_format(parse_size_format(matches)))
+        }
+        "extensions" => Box::new(
+            ExtensionReport::for_output(output).with_size_format(parse_size_format(matches)),
+        ),
+        "rm-batch" => Box::new(RmBatch::for_output(output, RM_BATCH_BYTES)),
+        _ => panic!("invalid maschine-reable-out config {kind}"),
+    };
+    if !matches.get_flag("reportheader") {
+        return consumer;
+    }
+    let header = ReportHeader {
+        started: SystemTime::now(),
+        roots: directories(matches).cloned().collect(),
+        from_stdin: matches.get_flag("discoverstdin") || matches.get_flag("discoverstdin0"),
+        orderings: matches.get_many::<String>("setorder").map_or_else(
+            || vec![String::from("modtime")],
+            |orderings| orderings.cloned().collect(),
+        ),
+        arguments: std::env::args_os().skip(1).collect(),
+    };
+    // the records of rm-batch are \0 terminated
+    let record_end = if kind == "rm-batch" { b'\0' } else { b'\n' };
+    Box::new(WithReportHeader::for_output(
+        output, header, record_end, consumer,
+    ))
+}
+
+/// the long name and the implementation of the chosen file action
+fn parse_file_action(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+) -> (
+    Option<&'static str>,
+    Option<Box<dyn FileConsumeAction + Send>>,
+) {
+    get_file_consume_action_args()
+        .into_iter()
+        .find(|arg| matches.get_flag(arg.name))
+        .map(|arg| {
+            let supported = match arg.name {
+                "reflink" => crate::os::SUPPORTS_REFLINK,
+                "trash" => crate::os::SUPPORTS_TRASH,
+                _ => true,
+            };
+            if !supported {
+                command
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        format!("--{} is not supported on this platform", arg.long),
+                    )
+                    .exit()
+            }
+            let action = parse_ownership_policy(command, matches, arg.action);
+            (arg.long, parse_preserve_dir_mtime(command, matches, action))
+        })
+        .unzip()
+}
+
+/// guard the linking actions by the owners of the files if requested
+fn parse_ownership_policy(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    file_action: Box<dyn FileConsumeAction + Send>,
+) -> Box<dyn FileConsumeAction + Send> {
+    let policy = match matches
+        .get_one::<String>("ownershippolicy")
+        .map(String::as_str)
+    {
+        Some("warn") => OwnershipPolicy::Warn,
+        Some("skip") => OwnershipPolicy::Skip,
+        Some("ignore") | None => return file_action,
+        Some(policy) => panic!("invalid ownership policy {policy}"),
+    };
+    if cfg!(not(unix)) {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--ownership-policy is not supported on this platform",
+            )
+            .exit()
+    }
+    // only a link leaves the content of the duplicate with the owner of the original
+    if file_action.requires_original() {
+        Box::new(OwnershipGuard::new(policy, file_action))
+    } else {
+        file_action
+    }
+}
+
+fn parse_preserve_dir_mtime(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    file_action: Box<dyn FileConsumeAction + Send>,
+) -> Box<dyn FileConsumeAction + Send> {
+    if !matches.get_flag("preservedirmtime") {
+        return file_action;
+    }
+    // directories cannot be opened to set their times without extra flags on other platforms
+    if cfg!(not(unix)) {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--preserve-dir-mtime is not supported on this platform",
+            )
+            .exit()
+    }
+    Box::new(DirMtimePreserver::new(file_action))
+}
+
+fn parse_plan_writer(
+    command: &mut clap::Command,
+    plan: &Path,
+    action_name: &'static str,
+) -> Box<dyn FileSetConsumer> {
+    let writer = std::fs::File::create(plan)
+        .and_then(|file| PlanWriter::new(action_name, std::io::BufWriter::new(file)));
+    match writer {
+        Ok(writer) => Box::new(writer),
+        Err(err) => command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot create plan {}: {err}", plan.display()),
+            )
+            .exit(),
+    }
+}
+
+/// the consumer of the modes which only report the sets without acting on them
+fn parse_report(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    output: &ResultsOutput,
+    estimate: bool,
+    errors: &Arc<ErrorSummary>,
+) -> Box<dyn FileSetConsumer> {
+    if let Some(copies) = matches.get_one::<u32>("requirecopies") {
+        Box::new(CopyAudit::for_output(output, *copies as usize).with_errors(errors.clone()))
+    } else if let Some(target) = matches.get_one::<PathBuf>("mirrorto") {
+        parse_mirror_tree(command, output, target)
+    } else if matches.get_flag("reporthardlinks") {
+        parse_hard_link_report(command, output, parse_size_format(matches))
+    } else if let Some(report) = matches.get_one::<PathBuf>("comparereport") {
+        parse_compare_report(command, output, report)
+    } else if let Some(kind) = matches.get_one::<String>("machine_readable") {
+        parse_machine_readable(command, matches, output, kind)
+    } else if estimate {
+        Box::new(EstimateReport::for_output(output, ESTIMATE_HASH_PREFIX))
+    } else {
+        Box::new(DryRun::for_output(output).simulated(matches.contains_id("simulateon")))
+    }
+}
+
+fn parse_compare_report(
+    command: &mut clap::Command,
+    output: &ResultsOutput,
+    report: &Path,
+) -> Box<dyn FileSetConsumer> {
+    let previous =
+        std::fs::File::open(report).and_then(|file| read_set_report(std::io::BufReader::new(file)));
+    match previous {
+        Ok(previous) => Box::new(CompareReport::for_output(output, previous)),
+        // the first run of a recurring job has nothing to compare against
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Box::new(CompareReport::for_output(output, BTreeSet::new()))
+        }
+        Err(err) => command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot read report {}: {err}", report.display()),
+            )
+            .exit(),
+    }
+}
+
+fn parse_mirror_tree(
+    command: &mut clap::Command,
+    output: &ResultsOutput,
+    target: &Path,
+) -> Box<dyn FileSetConsumer> {
+    if let Err(err) = std::fs::create_dir_all(target) {
+        command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot create the mirror {}: {err}", target.display()),
+            )
+            .exit()
+    }
+    Box::new(MirrorTree::for_output(output, target.to_path_buf()))
+}
+
+fn parse_hard_link_report(
+    command: &mut clap::Command,
+    output: &ResultsOutput,
+    size_format: SizeFormat,
+) -> Box<dyn FileSetConsumer> {
+    if cfg!(not(unix)) {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--report-hardlinks is not supported on this platform",
+            )
+            .exit()
+    }
+    Box::new(HardLinkReport::for_output(output).with_size_format(size_format))
+}
+
+fn parse_size_format(matches: &clap::ArgMatches) -> SizeFormat {
+    match matches.get_one::<String>("sizeformat").map(String::as_str) {
+        Some("bytes") => SizeFormat::Bytes,
+        Some("both") => SizeFormat::Both,
+        Some("human") | None => SizeFormat::Human,
+        Some(format) => panic!("invalid size format {format}"),
+    }
+}
+
+/// the algorithm the originals are hashed again with before acting on their sets, if they are
+fn verify_original(matches: &clap::ArgMatches) -> Option<HashAlgorithm> {
+    (matches.get_flag("verifyoriginal") || scans_snapshots(matches))
+        .then(|| parse_hash_algorithm(matches))
+}
+
+fn parse_unconditional_action(
+    matches: &clap::ArgMatches,
+    file_action: Box<dyn FileConsumeAction + Send>,
+) -> UnconditionalAction {
+    let action_threads = matches
+        .get_one::<u32>("actionthreads")
+        .and_then(|threads| NonZeroU32::new(*threads))
+        .unwrap_or(NonZeroU32::MIN);
+    UnconditionalAction::new(file_action)
+        .with_verify_original(verify_original(matches))
+        .with_action_threads(action_threads)
+}
+
+fn parse_interactive_action(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    file_action: Box<dyn FileConsumeAction + Send>,
+    seen_db: Option<SeenDb>,
+) -> Box<dyn FileSetConsumer> {
+    let mut interactive = InteractiveEachChoice::for_console(file_action)
+        .with_verify_original(verify_original(matches))
+        .with_default_answer(
+            matches
+                .get_one::<String>("interactivedefault")
+                .map(|answer| answer == "yes"),
+        );
+    if let Some(seen_db) = seen_db {
+        interactive = interactive.with_seen_db(seen_db);
+    }
+    if let Some(answers) = matches.get_one::<PathBuf>("answers") {
+        match Answers::open(answers) {
+            Ok(answers) => interactive = interactive.with_answers(answers),
+            Err(err) => command
+                .error(
+                    clap::error::ErrorKind::Io,
+                    format!("cannot open answers {}: {err}", answers.display()),
+                )
+                .exit(),
+        }
+    }
+    Box::new(interactive)
+}
+
+fn parse_progress_log(
+    matches: &clap::ArgMatches,
+    memory_budget: Option<&Arc<MemoryBudget>>,
+) -> Option<Arc<ProgressLog>> {
+    let every_files = matches.get_one::<u64>("progressfiles").copied();
+    let interval = matches.get_one::<Duration>("progressinterval").copied();
+    let live = matches.get_flag("progress");
+    (every_files.is_some() || interval.is_some() || live).then(|| {
+        let progress = ProgressLog::new(every_files, interval)
+            .with_live(live)
+            .with_memory_budget(memory_budget.cloned());
+        Arc::new(progress)
+    })
+}
+
+fn parse_seen_db(command: &mut clap::Command, matches: &clap::ArgMatches) -> Option<SeenDb> {
+    let seen_db = matches.get_one::<PathBuf>("seendb")?;
+    match SeenDb::open(seen_db) {
+        Ok(seen_db) => Some(seen_db),
+        Err(err) => command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot open seen database {}: {err}", seen_db.display()),
+            )
+            .exit(),
     }
+}
 
-    input_source
+/// the simulation on the manifest, which can only filter the files by their names and order them by what the
+/// manifest records
+fn parse_simulation(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    errors: &Arc<ErrorSummary>,
+) -> Option<Simulation> {
+    let manifest = matches.get_one::<PathBuf>("simulateon")?;
+    let file_filter = parse_file_filter(&DirScope::whole(matches), errors);
+    if !file_filter.1.is_empty() {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "the size, time and readability filters need the files and cannot be simulated",
+            )
+            .exit()
+    }
+    let variants = get_set_order_options();
+    let needs_files = |order: &&String| {
+        variants
+            .iter()
+            .any(|(name, _, implementation)| name == *order && implementation.needs_files())
+    };
+    let mut orders = matches.get_many::<String>("setorder").into_iter().flatten();
+    let mut keep_rules = matches
+        .get_many::<KeepRule>("keep")
+        .into_iter()
+        .flatten()
+        .zip(matches.get_raw("keep").into_iter().flatten());
+    let unsimulated = orders
+        .find(needs_files)
+        .map(|order| format!("the order {order}"))
+        .or_else(|| {
+            keep_rules
+                .find(|(rule, _)| rule.set_order().needs_files())
+                .map(|(_, rule)| format!("the keep rule {}", rule.to_string_lossy()))
+        });
+    if let Some(order) = unsimulated {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("{order} needs the files and cannot be simulated"),
+            )
+            .exit()
+    }
+    let algorithm = parse_hash_algorithm(matches);
+    Some(Simulation::new(manifest.clone(), file_filter).with_hash_algorithm(algorithm))
+}
+
+/// where the consumers write the results: stdout, nowhere or the sink given by --out
+fn parse_results_output(command: &mut clap::Command, matches: &clap::ArgMatches) -> ResultsOutput {
+    if matches.get_flag("quietresults") {
+        return ResultsOutput::Discard;
+    }
+    let Some(sink) = matches.get_one::<OutSink>("out") else {
+        return ResultsOutput::Console;
+    };
+    match sink.open() {
+        Ok(sink) => ResultsOutput::sink(sink),
+        Err(err) => command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot open output {sink}: {err}"),
+            )
+            .exit(),
+    }
+}
+
+fn parse_hash_cache(command: &mut clap::Command, matches: &clap::ArgMatches) -> Option<HashCache> {
+    let cache = matches.get_one::<PathBuf>("hashcache")?;
+    match HashCache::open(cache, parse_hash_algorithm(matches).name()) {
+        Ok(cache) => Some(cache),
+        Err(err) => command
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("cannot open hash cache {}: {err}", cache.display()),
+            )
+            .exit(),
+    }
+}
+
+/// the directories of the host mounted into the container, None if the paths are not translated
+fn parse_path_maps(matches: &clap::ArgMatches) -> Option<Vec<PathMap>> {
+    let maps = matches.get_many::<PathMap>("pathmap")?;
+    Some(maps.cloned().collect())
+}
+
+/// add the filters on the sets given to the consumer
+fn wrap_set_consumer(
+    command: &mut clap::Command,
+    matches: &clap::ArgMatches,
+    mut consumer: Box<dyn FileSetConsumer>,
+    seen_db: Option<SeenDb>,
+) -> Box<dyn FileSetConsumer> {
+    if let Some(seen_db) = seen_db {
+        consumer = Box::new(SuppressSeen::new(seen_db, consumer));
+    }
+    if let Some(min_savings) = matches.get_one::<FileSize>("minsetsavings") {
+        consumer = Box::new(SkipSmallSets::new(min_savings.0, consumer));
+    }
+    // the sets are recorded as seen without the allowed duplicates
+    if let Some(allow_dupes) = matches.get_one::<PathBuf>("allowdupes") {
+        let allowed = match AllowedDupes::read(allow_dupes) {
+            Ok(allowed) => allowed,
+            Err(err) => command
+                .error(
+                    clap::error::ErrorKind::Io,
+                    format!(
+                        "cannot read allowed duplicates {}: {err}",
+                        allow_dupes.display()
+                    ),
+                )
+                .exit(),
+        };
+        consumer = Box::new(FilterAllowedDupes::new(allowed, consumer));
+    }
+    // hard links are left out before any other filter sees the set
+    if matches.get_flag("ignorehardlinked") {
+        consumer = Box::new(SkipHardLinked::new(consumer));
+    }
+    consumer
+}
+
+fn parse_symlink_treatment(matches: &clap::ArgMatches) -> SymlinkTreatment {
+    match matches
+        .get_one::<String>("treatsymlinks")
+        .map(String::as_str)
+    {
+        Some("skip") => SymlinkTreatment::Skip,
+        Some("follow") => SymlinkTreatment::Follow,
+        Some("as-files") => SymlinkTreatment::AsFiles,
+        Some(mode) => panic!("invalid symlink treatment {mode}"),
+        None if matches.contains_id("followsymlink") => SymlinkTreatment::Follow,
+        None => SymlinkTreatment::Skip,
+    }
 }
 
 fn get_set_order_options() -> Vec<(&'static str, String, Box<dyn SetOrder>)> {
@@ -501,8 +1831,18 @@ fn get_set_order_options() -> Vec<(&'static str, String, Box<dyn SetOrder>)> {
         ("rmodtime", Box::new(ModTimeSetOrder::new(true)), "Order the files from most recently to least recently modified"),
         ("createtime", Box::new(CreateTimeSetOrder::new(false)), "Order the files from oldest to newest"),
         ("rcreatetime", Box::new(CreateTimeSetOrder::new(true)), "Order the files from newest to oldest"),
+        ("size", Box::new(SizeSetOrder::new(false)), "Order the files from smallest to largest; files with equal content only differ in size when an equality check does not compare the content"),
+        ("rsize", Box::new(SizeSetOrder::new(true)), "Order the files from largest to smallest"),
         ("alphabetic", Box::new(NameAlphabeticSetOrder::new(false)), "Order the files alphabetically ascending(may behave strangely with chars that are not ascii letters or digits)"),
         ("ralphabetic", Box::new(NameAlphabeticSetOrder::new(true)), "Order the files alphabetically descending(risks and side effects of 'alphabetic' apply)"),
+        ("staleness", Box::new(StalenessSetOrder::new(false, StalenessWeights::default())), "Order the files from the most to the least recently modified and used, by their modification and access times weighted by --staleness-weights"),
+        ("rstaleness", Box::new(StalenessSetOrder::new(true, StalenessWeights::default())), "Order the files from the least to the most recently modified and used(risks and side effects of 'staleness' apply)"),
+        ("pathdepth", Box::new(PathDepthSetOrder::new(false)), "Order the files from the shallowest to the deepest directory"),
+        ("rpathdepth", Box::new(PathDepthSetOrder::new(true)), "Order the files from the deepest to the shallowest directory"),
+        ("pathlen", Box::new(PathLengthSetOrder::new(false)), "Order the files from the shortest to the longest full path"),
+        ("rpathlen", Box::new(PathLengthSetOrder::new(true)), "Order the files from the longest to the shortest full path"),
+        ("stable", Box::new(StableSetOrder), "Order the files by their device, inode and canonical path, so that repeated runs choose the same original among files the other orderings consider equal"),
+        ("cleanname", Box::new(CleanNameSetOrder::new()), "Order the files whose names do not look like copies(e.g. 'a (1).txt', 'a - Copy.txt', 'a_copy.txt', 'a.txt~') before those that do"),
         ("as_is", Box::new(NoopSetOrder::new()), "Do not order the files; the order is thus non-deterministic and not reproducible"),
     ];
     let default_order_options = default_order_options
@@ -538,6 +1878,22 @@ fn get_file_consume_action_args() -> Vec<SimpleArgDeclaration<Box<dyn FileConsum
             false,
             Box::<ReplaceWithHardLinkFileAction>::default(),
         ),
+        (
+            "reflink",
+            None,
+            "reflink",
+            String::from("Replace duplicated files with a copy-on-write clone of the original, which keeps its own metadata(btrfs, XFS and APFS)"),
+            false,
+            Box::<ReplaceWithReflinkFileAction>::default(),
+        ),
+        (
+            "trash",
+            None,
+            "trash",
+            String::from("Move duplicated files to the trash or recycle bin, from which they can be restored"),
+            false,
+            Box::new(crate::os::TrashFileAction),
+        ),
     ];
     let os_specific = crate::os::get_file_consumer_simple().into_iter().map(
         |SimpleFileConsumeActionArg {
@@ -565,15 +1921,27 @@ fn get_file_consume_action_args() -> Vec<SimpleArgDeclaration<Box<dyn FileConsum
         .collect::<Vec<_>>()
 }
 
-fn get_file_equals_args() -> Vec<SimpleArgDeclaration<Box<dyn FileEqualsChecker + Send>>> {
-    let default: Vec<(_, _, _, _, _, Box<dyn FileEqualsChecker + Send>)> = vec![(
-        "contenteq",
-        Some('c'),
-        "nocontenteq",
-        String::from("do not compare files byte-by-byte(only by hash)"),
-        true,
-        Box::new(FileContentEquals::new()),
-    )];
+fn get_file_equals_args(
+    compare_buffer: usize,
+) -> Vec<SimpleArgDeclaration<Box<dyn FileEqualsChecker + Send>>> {
+    let default: Vec<(_, _, _, _, _, Box<dyn FileEqualsChecker + Send>)> = vec![
+        (
+            "contenteq",
+            Some('c'),
+            "nocontenteq",
+            String::from("do not compare files byte-by-byte(only by hash)"),
+            true,
+            Box::new(FileContentEquals::new(compare_buffer)),
+        ),
+        (
+            "splitbydevice",
+            None,
+            "split-by-device",
+            String::from("consider files on different devices different files, as they cannot be linked to each other"),
+            false,
+            Box::new(DeviceEquals),
+        ),
+    ];
     let os_specific = crate::os::get_file_equals_simple().into_iter().map(
         |SimpleFileEqualCheckerArg {
              name,
@@ -623,6 +1991,39 @@ fn get_file_name_filters() -> Vec<SimpleArgDeclaration<Box<dyn FileNameFilter +
     default
 }
 
+/// The worker counts picked for `--threads` without a number: hashing is mostly CPU bound, while
+/// comparing the contents of two files is mostly waiting for reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AutoWorkers {
+    cpu: u32,
+    io: u32,
+}
+
+fn auto_worker_counts<'p>(dirs: impl Iterator<Item = &'p Path>) -> AutoWorkers {
+    let kinds = dirs.map(probe_storage_kind).collect::<Vec<_>>();
+    let cpus = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let (cpu, io) = if kinds.contains(&StorageKind::Rotational) {
+        // parallel reads on a spinning disk mostly cause seeking, so one worker hashes while the main thread discovers
+        // and the contents are compared one pair at a time
+        (2, 1)
+    } else {
+        let queue_depth = kinds
+            .iter()
+            .filter_map(|kind| match kind {
+                StorageKind::SolidState { queue_depth } => *queue_depth,
+                _ => None,
+            })
+            .min()
+            .map(|depth| usize::try_from(depth).unwrap_or(usize::MAX).max(1));
+        let io = queue_depth.map_or(cpus, |depth| depth.min(cpus.saturating_mul(2)));
+        (cpus, io)
+    };
+    AutoWorkers {
+        cpu: u32::try_from(cpu).unwrap_or(1),
+        io: u32::try_from(io).unwrap_or(1),
+    }
+}
+
 fn set_order_parser() -> clap::builder::ValueParser {
     let values = get_set_order_options()
         .into_iter()
@@ -632,65 +2033,381 @@ fn set_order_parser() -> clap::builder::ValueParser {
     PossibleValuesParser::new(values).into()
 }
 
-pub fn parse() -> ExecutionPlan {
-    let matches = assemble_command_info().get_matches();
+fn hash_algorithm_parser() -> clap::builder::ValueParser {
+    PossibleValuesParser::new([
+        PossibleValue::new("xxh3").help("fast, but collisions can be crafted(default)"),
+        PossibleValue::new("blake3").help("cryptographic, fast on many cores"),
+        PossibleValue::new("sha256").help("cryptographic, widely used to check downloads"),
+        PossibleValue::new("crc32")
+            .help("only 32 bits, leave the content comparison on for larger scans"),
+    ])
+    .into()
+}
+
+fn parse_hash_algorithm(matches: &clap::ArgMatches) -> HashAlgorithm {
+    let name = matches.get_one::<String>("hashalgorithm").unwrap();
+    HashAlgorithm::from_cli_name(&name.to_lowercase()).expect("invalid hash algorithm")
+}
+
+fn parse_subcommand(matches: &clap::ArgMatches) -> Option<Invocation> {
+    match matches.subcommand()? {
+        ("apply", apply) => {
+            let actions = get_file_consume_action_args()
+                .into_iter()
+                .map(|arg| (arg.long, arg.action))
+                .collect();
+            Some(Invocation::Apply(ApplyPlan {
+                plan: apply.get_one::<PathBuf>("planfile").unwrap().clone(),
+                actions,
+            }))
+        }
+        ("diff", diff) => Some(Invocation::Diff(DiffManifests {
+            a: diff.get_one::<PathBuf>("manifesta").unwrap().clone(),
+            b: diff.get_one::<PathBuf>("manifestb").unwrap().clone(),
+            algorithm: parse_hash_algorithm(diff),
+        })),
+        ("dismiss", dismiss) => Some(Invocation::Dismiss(DismissSet {
+            seen_db: dismiss.get_one::<PathBuf>("seendb").unwrap().clone(),
+            files: dismiss
+                .get_many::<PathBuf>("files")
+                .unwrap()
+                .cloned()
+                .collect(),
+        })),
+        ("find-copies", find_copies) => Some(Invocation::FindCopies(FindCopies {
+            target: find_copies.get_one::<PathBuf>("target").unwrap().clone(),
+            dirs: parse_directories(find_copies),
+        })),
+        (name, _) => panic!("invalid subcommand {name}"),
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+pub fn parse() -> Invocation {
+    let args = std::env::args_os().collect::<Vec<_>>();
+    let mut command = config_file::with_config_defaults(assemble_command_info(), &args);
+    let matches = command
+        .try_get_matches_from_mut(&args)
+        .unwrap_or_else(|err| err.exit());
+    if let Some(invocation) = parse_subcommand(&matches) {
+        return invocation;
+    }
     //let x = matches.get_many::<usize>("oi").unwrap();
 
-    let num_threads = match matches.get_one::<u32>("numthreads") {
-        Some(0) => u32::try_from(
-            std::thread::available_parallelism()
-                .map_or(1, NonZeroUsize::get)
-                .saturating_mul(2),
-        )
-        .unwrap_or(1),
-        Some(num) => *num,
-        None => 1,
+    let auto_workers = matches
+        .get_one::<u32>("numthreads")
+        .filter(|num| **num == 0)
+        .map(|_| auto_worker_counts(directories(&matches).map(PathBuf::as_path)));
+    let num_threads = match (auto_workers, matches.get_one::<u32>("numthreads")) {
+        (Some(workers), _) => workers.cpu,
+        (None, Some(num)) => *num,
+        (None, None) => 1,
     };
+    // the compare pool is only picked on its own where --content-threads would be allowed
+    let content_threads = matches
+        .get_one::<u32>("contentthreads")
+        .copied()
+        .or_else(|| {
+            let pool_allowed = ["stream", "stopafter", "requirecopies", "mirrorto"]
+                .into_iter()
+                .all(|id| {
+                    matches
+                        .value_source(id)
+                        .is_none_or(|source| source == ValueSource::DefaultValue)
+                });
+            auto_workers
+                .filter(|_| pool_allowed)
+                .map(|workers| workers.io)
+        });
 
     let set_ordering = parse_set_order(&matches);
 
-    let file_action: Option<Box<dyn FileConsumeAction + Send>> = get_file_consume_action_args()
-        .into_iter()
-        .find(|arg| matches.get_flag(arg.name))
-        .map(|arg| arg.action);
+    let (file_action_name, file_action) = parse_file_action(&mut command, &matches);
 
-    let file_equals = get_file_equals_args()
-        .into_iter()
-        .filter(|arg| matches.get_flag(arg.name))
-        .map(|arg| arg.action)
-        .collect::<Vec<_>>();
+    let estimate = matches.get_flag("estimate");
+
+    let file_equals = parse_file_equals(&mut command, &matches, estimate);
+
+    let refiner_logic = match matches
+        .get_one::<String>("refinerlogic")
+        .map(String::as_str)
+    {
+        Some("or") => RefinerLogic::Or,
+        _ => RefinerLogic::And,
+    };
+
+    let output = parse_results_output(&mut command, &matches);
+    let mut seen_db = parse_seen_db(&mut command, &matches);
+    let errors = Arc::new(ErrorSummary::new());
 
     let file_set_consumer: Box<dyn FileSetConsumer> = if matches.get_flag("uncond") {
-        Box::new(UnconditionalAction::new(file_action.expect(
-            "file action should be present because of command config",
-        )))
+        Box::new(parse_unconditional_action(
+            &matches,
+            file_action.expect("file action should be present because of command config"),
+        ))
     } else if matches.get_flag("iact") {
-        Box::new(InteractiveEachChoice::for_console(file_action.expect(
-            "file action should be present because of command config",
-        )))
-    } else if let Some(kind) = matches.get_one::<String>("machine_readable") {
-        match kind.as_str() {
-            "pairwise" => Box::new(MachineReadableEach::for_console()),
-            "setwise" => Box::new(MachineReadableSet::for_console()),
-            _ => panic!("invalid maschine-reable-out config {kind}"),
-        }
+        parse_interactive_action(
+            &mut command,
+            &matches,
+            file_action.expect("file action should be present because of command config"),
+            // the interactive choice suppresses the dismissed sets itself
+            seen_db.take(),
+        )
+    } else if matches.get_flag("iactset") {
+        let action = file_action.expect("file action should be present because of command config");
+        Box::new(
+            InteractiveSetChoice::for_console(action)
+                .with_verify_original(verify_original(&matches)),
+        )
+    } else if matches.get_flag("dryrun") {
+        let action = file_action.expect("file action should be present because of command config");
+        let dry_run = DryRun::for_output(&output).with_action(&*action);
+        Box::new(dry_run.simulated(matches.contains_id("simulateon")))
+    } else if let Some(plan) = matches.get_one::<PathBuf>("plan") {
+        let action_name =
+            file_action_name.expect("file action should be present because of command config");
+        parse_plan_writer(&mut command, plan, action_name)
     } else {
-        Box::new(DryRun::for_console())
+        parse_report(&mut command, &matches, &output, estimate, &errors)
     };
 
-    let input_sources = parse_input_source(&matches);
+    // the paths are translated after everything else looked at the files
+    let file_set_consumer: Box<dyn FileSetConsumer> = match parse_path_maps(&matches) {
+        Some(maps) => Box::new(MapToHost::new(maps, file_set_consumer)),
+        None => file_set_consumer,
+    };
+    let file_set_consumer = wrap_set_consumer(&mut command, &matches, file_set_consumer, seen_db);
 
     let ignore_log_set = parse_ignore_log_targets(&matches);
 
-    let dedup_files = matches.get_flag("followsymlink");
+    // followed symlinks may lead to files which are also discovered directly
+    let dedup_files = matches.contains_id("followsymlink")
+        || parse_symlink_treatment(&matches) == SymlinkTreatment::Follow
+        || matches
+            .get_many::<DirOptions>("dir")
+            .is_some_and(|mut dirs| dirs.any(|dir| dir.follow_symlinks));
+
+    let memory_budget = matches.get_one::<FileSize>("maxmemory").map(|size| {
+        let limit = usize::try_from(size.0).unwrap_or(usize::MAX);
+        Arc::new(MemoryBudget::new(limit))
+    });
+    let progress = parse_progress_log(&matches, memory_budget.as_ref());
+
+    let timeout = matches.get_one::<Duration>("timeout").copied();
+
+    let stop_after = matches.get_one::<u64>("stopafter").copied();
+
+    let manifest = parse_manifest(&mut command, &matches);
+
+    // created last, so that no other error leaves the snapshots behind
+    let snapshots = parse_snapshots(&mut command, &matches);
+    let (input_sources, config_warnings) =
+        parse_input_source(&matches, snapshots.as_ref(), &command, &errors);
 
-    ExecutionPlan {
-        file_equals,
-        order_set: set_ordering,
+    // the content comparison is among the checkers of the command line if it is wanted
+    let search = file_equals.into_iter().fold(
+        Config::new()
+            .compare_content(false)
+            .refiner_logic(refiner_logic)
+            .threads(NonZeroU32::new(num_threads).unwrap())
+            .hash_scope(parse_hash_scope(&matches, estimate))
+            .hash_algorithm(parse_hash_algorithm(&matches)),
+        Config::file_equals,
+    );
+    let search = input_sources.into_iter().fold(search, Config::input_source);
+    let search = set_ordering.into_iter().fold(search, Config::order);
+
+    Invocation::Scan(Box::new(ExecutionPlan {
+        search,
         action: file_set_consumer,
-        num_threads: NonZeroU32::new(num_threads).unwrap(),
+        content_threads: content_threads.and_then(NonZeroU32::new),
         ignore_log_set,
-        input_sources,
+        log_timestamps: parse_log_timestamps(&matches),
         dedup_files,
-    }
+        memory_budget,
+        timeout,
+        file_timeout: matches.get_one::<Duration>("filetimeout").copied(),
+        stop_after,
+        manifest,
+        progress,
+        group_hard_links: matches.get_flag("reporthardlinks"),
+        queue_depth: matches
+            .get_one::<u32>("queuedepth")
+            .map(|depth| *depth as usize),
+        snapshots,
+        stream: matches.get_flag("stream"),
+        config_warnings,
+        simulation: parse_simulation(&mut command, &matches, &errors),
+        errors,
+        hash_cache: parse_hash_cache(&mut command, &matches),
+        size_format: parse_size_format(&matches),
+        output,
+    }))
+}
+
+#[test]
+fn test_quiet_flags() {
+    let matches = |args: &[&str]| assemble_command_info().try_get_matches_from(args);
+    let quiet = matches(&["duplis", ".", "--quiet-logs"]).unwrap();
+    assert_eq!(
+        parse_ignore_log_targets(&quiet).len(),
+        get_all_log_targets().len()
+    );
+    assert!(matches(&["duplis", ".", "--quiet-logs", "--loginfo", "+file_changed"]).is_err());
+    assert!(matches(&["duplis", ".", "-d", "-u", "--quiet-results"]).is_ok());
+    assert!(matches(&["duplis", ".", "-d", "-i", "--quiet-results"]).is_err());
+}
+
+#[test]
+fn test_collapse_overlapping_roots() {
+    let [data, photos, music, data2] =
+        ["/data", "/data/photos", "/music", "/data2"].map(PathBuf::from);
+    let (roots, warnings) =
+        collapse_overlapping_roots([&photos, &music, &data, &data2, &music], true);
+    assert_eq!(roots, [&data, &music, &data2]);
+    assert_eq!(
+        warnings,
+        [
+            "/data/photos lies within /data; discovering it once as part of the latter",
+            "/music is given twice; discovering it once",
+        ]
+    );
+    // without recursing only the files directly within the roots are discovered
+    let (roots, warnings) = collapse_overlapping_roots([&data, &photos, &data], false);
+    assert_eq!(roots, [&data, &photos]);
+    assert_eq!(warnings.len(), 1);
+}
+
+#[test]
+fn test_scoped_file_filters() {
+    let filters = |args: &str| {
+        let matches = assemble_command_info()
+            .try_get_matches_from(args.split(' '))
+            .unwrap();
+        parse_scoped_file_filters(&matches, &Arc::default()).unwrap()
+    };
+    let counts = |filter: &FileFilter| (filter.0.len(), filter.1.len());
+
+    let (global, dir_filters) = filters("duplis --nonzero --dir src --extwl rs --dir .");
+    let (names, metadata) = counts(&global);
+    assert_eq!(dir_filters.len(), 2);
+    // only the first directory has the extension filter
+    assert_eq!(counts(&dir_filters[0].1), (names + 1, metadata));
+    assert_eq!(counts(&dir_filters[1].1), (names, metadata));
+    assert_eq!(
+        dir_filters[1].0.path,
+        Path::new(".").canonicalize().unwrap()
+    );
+
+    // a flag given for one directory leaves the others at its default
+    let (global, dir_filters) = filters("duplis --dir src --dir . --nonzero");
+    assert_eq!(counts(&global), counts(&dir_filters[0].1));
+    assert_eq!(counts(&dir_filters[1].1), (names, metadata));
+    let (global, dir_filters) = filters("duplis src");
+    assert_eq!(counts(&global), (names, metadata - 1));
+    assert!(dir_filters.is_empty());
+}
+
+#[test]
+fn test_symlink_mode() {
+    let mode = |args: &[&str]| {
+        let matches = assemble_command_info().try_get_matches_from(args).unwrap();
+        matches.get_one::<String>("followsymlink").cloned()
+    };
+    assert_eq!(mode(&["duplis", "."]), None);
+    assert_eq!(mode(&["duplis", "-s", "."]).as_deref(), Some("all"));
+    assert_eq!(
+        mode(&["duplis", "--symlink=same-device", "."]).as_deref(),
+        Some("same-device")
+    );
+}
+
+#[test]
+fn test_keep_rules() {
+    let root = LinkedPath::root("/");
+    let file = |path: &str| crate::HashedFile {
+        file_version_timestamp: None,
+        content_hash: 0,
+        file_path: LinkedPath::new_child(&root, OsString::from(path)),
+        identity: None,
+        size: None,
+    };
+    let original = |args: &[&str]| {
+        let matches = assemble_command_info().try_get_matches_from(args).unwrap();
+        let mut files = vec![file("a/longer"), file("b/x"), file("c/yy")];
+        for order in &mut parse_set_order(&matches) {
+            order.order(&mut files).unwrap();
+        }
+        files[0].file_path.to_push_buf()
+    };
+    let order = ["duplis", "-o", "ralphabetic"];
+    assert_eq!(
+        original(&[&order[..], &["."]].concat()),
+        PathBuf::from("/c/yy")
+    );
+    // the rules win over the orderings and the first rule over the others
+    let rules = [
+        "--keep",
+        "path_prefix:/no/such/dir",
+        "--keep",
+        "shortest_path",
+    ];
+    assert_eq!(
+        original(&[&order[..], &rules, &["."]].concat()),
+        PathBuf::from("/b/x")
+    );
+    let rules = ["--keep", "path_prefix:/a", "--keep", "shortest_path"];
+    assert_eq!(
+        original(&[&order[..], &rules, &["."]].concat()),
+        PathBuf::from("/a/longer")
+    );
+    assert!(assemble_command_info()
+        .try_get_matches_from(["duplis", "--keep", "largest", "."])
+        .is_err());
+}
+
+#[test]
+fn test_depth_aliases() {
+    let depths = |args: &[&str]| {
+        let matches = assemble_command_info().try_get_matches_from(args).unwrap();
+        (
+            matches.get_one::<u32>("mindepth").copied(),
+            matches.get_one::<u32>("maxdepth").copied(),
+        )
+    };
+    assert_eq!(
+        depths(&["duplis", "-r", "--min-depth", "2", "--max-depth", "3", "."]),
+        (Some(2), Some(3))
+    );
+    assert_eq!(
+        depths(&["duplis", "-r", "--mindepth", "2", "--maxdepth", "3", "."]),
+        (Some(2), Some(3))
+    );
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_simulated_orders() {
+    let unsimulated = get_set_order_options()
+        .into_iter()
+        .filter(|(_, _, order)| order.needs_files())
+        .map(|(name, _, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        unsimulated,
+        [
+            "modtime",
+            "rmodtime",
+            "createtime",
+            "rcreatetime",
+            "size",
+            "rsize",
+            "staleness",
+            "rstaleness",
+            "stable"
+        ]
+    );
+    assert!(KeepRule::Newest.set_order().needs_files());
+    assert!(KeepRule::Oldest.set_order().needs_files());
+    assert!(!KeepRule::ShortestPath.set_order().needs_files());
 }
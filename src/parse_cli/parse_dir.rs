@@ -0,0 +1,66 @@
+use crate::parse_cli::CanonicalPathValueParser;
+use clap::builder::TypedValueParser;
+use clap::Arg;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+/// a directory given by --dir with the discovery options only applying to it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirOptions {
+    pub path: PathBuf,
+    pub recursive: bool,
+    pub follow_symlinks: bool,
+}
+
+/// Parse `PATH[:recursive][:follow-symlinks]`, the path is canonicalized
+#[derive(Clone)]
+pub(crate) struct DirOptionsValueParser;
+
+impl TypedValueParser for DirOptionsValueParser {
+    type Value = DirOptions;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let (path, recursive, follow_symlinks) = split_dir_options(value);
+        Ok(DirOptions {
+            path: CanonicalPathValueParser.parse_ref(cmd, arg, path)?,
+            recursive,
+            follow_symlinks,
+        })
+    }
+}
+
+/// the path and whether it is followed by ':recursive' and ':follow-symlinks' in any order
+fn split_dir_options(value: &OsStr) -> (&OsStr, bool, bool) {
+    let Some(mut path) = value.to_str() else {
+        return (value, false, false);
+    };
+    let (mut recursive, mut follow_symlinks) = (false, false);
+    loop {
+        if let Some(rest) = path.strip_suffix(":recursive") {
+            recursive = true;
+            path = rest;
+        } else if let Some(rest) = path.strip_suffix(":follow-symlinks") {
+            follow_symlinks = true;
+            path = rest;
+        } else {
+            return (OsStr::new(path), recursive, follow_symlinks);
+        }
+    }
+}
+
+#[test]
+fn test_split_dir_options() {
+    let split = |text| split_dir_options(OsStr::new(text));
+    assert_eq!(split("/a"), (OsStr::new("/a"), false, false));
+    assert_eq!(split("/a:recursive"), (OsStr::new("/a"), true, false));
+    assert_eq!(
+        split("C:\\a:follow-symlinks:recursive"),
+        (OsStr::new("C:\\a"), true, true)
+    );
+    assert_eq!(split("/a:other"), (OsStr::new("/a:other"), false, false));
+}
@@ -0,0 +1,67 @@
+use clap::builder::{StringValueParser, TypedValueParser};
+use clap::Arg;
+use std::ffi::OsStr;
+use std::time::Duration;
+
+/// Parse a duration like `90`, `30s`, `15m`, `2h`, `7d` or `1w`; plain numbers are seconds
+#[derive(Clone)]
+pub(crate) struct DurationValueParser;
+
+impl TypedValueParser for DurationValueParser {
+    type Value = Duration;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse_duration(&value).ok_or_else(|| {
+            let arg_text = arg.map_or(String::new(), |arg| {
+                let literal = cmd.get_styles().get_literal();
+                format!(
+                    " for arg '{}{arg}{}'",
+                    literal.render(),
+                    literal.render_reset()
+                )
+            });
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid duration '{value}'{arg_text}: expected a number followed by one of s, m, h, d, w\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+    let scale = match unit.to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(scale).map(Duration::from_secs)
+}
+
+#[test]
+fn test_parse_duration() {
+    let secs = |text| parse_duration(text).as_ref().map(Duration::as_secs);
+    assert_eq!(secs("90"), Some(90));
+    assert_eq!(secs("30s"), Some(30));
+    assert_eq!(secs("15M"), Some(900));
+    assert_eq!(secs("2h"), Some(7200));
+    assert_eq!(secs("1w"), Some(604_800));
+    assert_eq!(secs("h"), None);
+    assert_eq!(secs("3y"), None);
+    assert_eq!(secs("-3s"), None);
+    assert_eq!(secs("18446744073709551615d"), None);
+}
@@ -75,6 +75,112 @@ impl TypedValueParser for FileSizeValueParser {
     }
 }
 
+/// a range of file sizes, the minimum is inclusive and the maximum exclusive
+#[derive(Clone)]
+pub(crate) struct FileSizeRange {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+/// Parse a range `MIN..MAX` of file sizes like [`FileSizeValueParser`], either bound may be left out
+#[derive(Clone)]
+pub(crate) struct FileSizeRangeValueParser;
+
+impl TypedValueParser for FileSizeRangeValueParser {
+    type Value = FileSizeRange;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        let range_err = |reason: &str| invalid_value_err(cmd, arg, "size range", &value, reason);
+        let Some((min, max)) = value.split_once("..") else {
+            return Err(range_err("expected MIN..MAX, MIN.. or ..MAX"));
+        };
+        let parse_bound = |bound: &str| {
+            (!bound.is_empty())
+                .then(|| FileSizeValueParser.parse_ref(cmd, arg, OsStr::new(bound)))
+                .transpose()
+                .map(|size| size.map(|size| size.0))
+        };
+        let range = FileSizeRange {
+            min: parse_bound(min)?,
+            max: parse_bound(max)?,
+        };
+        match (range.min, range.max) {
+            (None, None) => Err(range_err("at least one bound is required")),
+            (Some(min), Some(max)) if min >= max => {
+                Err(range_err("the minimum must be smaller than the maximum"))
+            }
+            _ => Ok(range),
+        }
+    }
+}
+
+/// hash this many evenly spaced blocks of the size from each file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct SampleHash {
+    pub blocks: u64,
+    pub block_size: u64,
+}
+
+/// Parse `NxSIZE`, the size like [`FileSizeValueParser`]; both must be positive
+#[derive(Clone)]
+pub(crate) struct SampleHashValueParser;
+
+impl TypedValueParser for SampleHashValueParser {
+    type Value = SampleHash;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        let sample_err = |reason: &str| invalid_value_err(cmd, arg, "sample", &value, reason);
+        // the size may be hexadecimal, which contains an x itself
+        let Some((blocks, block_size)) = value.split_once(['x', 'X']) else {
+            return Err(sample_err("expected NxSIZE, like 16x64KiB"));
+        };
+        let blocks = blocks
+            .parse::<u64>()
+            .map_err(|err| sample_err(&format!("invalid number of blocks: {err}")))?;
+        let block_size = FileSizeValueParser
+            .parse_ref(cmd, arg, OsStr::new(block_size))?
+            .0;
+        if blocks == 0 || block_size == 0 {
+            return Err(sample_err("at least one block of one byte is required"));
+        }
+        Ok(SampleHash { blocks, block_size })
+    }
+}
+
+fn invalid_value_err(
+    cmd: &clap::Command,
+    arg: Option<&Arg>,
+    what: &str,
+    value: &str,
+    reason: &str,
+) -> clap::Error {
+    let arg_text = arg.map_or(String::new(), |arg| {
+        let literal = cmd.get_styles().get_literal();
+        format!(
+            " for arg '{}{arg}{}'",
+            literal.render(),
+            literal.render_reset()
+        )
+    });
+    clap::Error::raw(
+        ClapErrorKind::InvalidValue,
+        format!("invalid {what} '{value}'{arg_text}: {reason}\n"),
+    )
+    .with_cmd(cmd)
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum ParseIntError {
     Overflow,
@@ -181,3 +287,58 @@ fn test_num_prefix() {
     let result = parse_number_prefix("9Eefx", hexrange, 16).unwrap();
     assert_eq!(result, (0x9Eef, "x"));
 }
+
+#[test]
+fn test_size_range() {
+    let command = clap::Command::new("test")
+        .arg(
+            clap::Arg::new("range")
+                .value_parser(clap::builder::ValueParser::from(FileSizeRangeValueParser))
+                .ignore_case(true),
+        )
+        .no_binary_name(true);
+    let parse = |range: &str| {
+        command
+            .clone()
+            .try_get_matches_from([range])
+            .map(|matches| {
+                let range = matches.get_one::<FileSizeRange>("range").unwrap();
+                (range.min, range.max)
+            })
+    };
+    assert_eq!(
+        parse("1MiB..2GiB").unwrap(),
+        (Some(2u64.pow(20)), Some(2 * 2u64.pow(30)))
+    );
+    assert_eq!(parse("4KiB..").unwrap(), (Some(4096), None));
+    assert_eq!(parse("..0x10").unwrap(), (None, Some(16)));
+    assert!(parse("..").is_err());
+    assert!(parse("10").is_err());
+    assert!(parse("2kb..1kb").is_err());
+    assert!(parse("1kb..1000").is_err());
+    assert!(parse("1xb..").is_err());
+}
+
+#[test]
+fn test_sample_hash() {
+    let command = clap::Command::new("test")
+        .arg(
+            clap::Arg::new("sample")
+                .value_parser(clap::builder::ValueParser::from(SampleHashValueParser))
+                .ignore_case(true),
+        )
+        .no_binary_name(true);
+    let parse = |sample: &str| {
+        command
+            .clone()
+            .try_get_matches_from([sample])
+            .map(|matches| *matches.get_one::<SampleHash>("sample").unwrap())
+    };
+    let sample = |blocks, block_size| SampleHash { blocks, block_size };
+    assert_eq!(parse("16x64KiB").unwrap(), sample(16, 64 * 1024));
+    assert_eq!(parse("4x0x100").unwrap(), sample(4, 0x100));
+    assert_eq!(parse("1X1").unwrap(), sample(1, 1));
+    for invalid in ["16", "x64", "0x64", "16x0", "-1x64", "16x64lb"] {
+        assert!(parse(invalid).is_err(), "{invalid}");
+    }
+}
@@ -0,0 +1,91 @@
+use crate::set_order::{ModTimeSetOrder, PathLengthSetOrder, PathPrefixSetOrder, SetOrder};
+use clap::builder::{StringValueParser, TypedValueParser};
+use clap::Arg;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+/// a rule choosing which file of a set is kept as the original
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum KeepRule {
+    /// the files below the directory
+    PathPrefix(PathBuf),
+    ShortestPath,
+    Newest,
+    Oldest,
+}
+
+impl KeepRule {
+    /// the order putting the files the rule keeps first
+    pub(crate) fn set_order(&self) -> Box<dyn SetOrder + Send> {
+        match self {
+            Self::PathPrefix(prefix) => Box::new(PathPrefixSetOrder::new(prefix.clone())),
+            Self::ShortestPath => Box::new(PathLengthSetOrder::new(false)),
+            Self::Newest => Box::new(ModTimeSetOrder::new(true)),
+            Self::Oldest => Box::new(ModTimeSetOrder::new(false)),
+        }
+    }
+}
+
+/// Parse a keep rule like `path_prefix:/home/me/photos`, `shortest_path`, `newest` or `oldest`
+#[derive(Clone)]
+pub(crate) struct KeepRuleValueParser;
+
+impl TypedValueParser for KeepRuleValueParser {
+    type Value = KeepRule;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse_keep_rule(&value).ok_or_else(|| {
+            let arg_text = arg.map_or(String::new(), |arg| {
+                let literal = cmd.get_styles().get_literal();
+                format!(
+                    " for arg '{}{arg}{}'",
+                    literal.render(),
+                    literal.render_reset()
+                )
+            });
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid keep rule '{value}'{arg_text}: expected path_prefix:DIR, shortest_path, newest or oldest\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+fn parse_keep_rule(text: &str) -> Option<KeepRule> {
+    if let Some(prefix) = text.strip_prefix("path_prefix:") {
+        if prefix.is_empty() {
+            return None;
+        }
+        // the order also matches the canonical paths, so the prefix is kept as given
+        return Some(KeepRule::PathPrefix(PathBuf::from(prefix)));
+    }
+    match text {
+        "shortest_path" => Some(KeepRule::ShortestPath),
+        "newest" => Some(KeepRule::Newest),
+        "oldest" => Some(KeepRule::Oldest),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_keep_rule() {
+    assert_eq!(parse_keep_rule("newest"), Some(KeepRule::Newest));
+    assert_eq!(parse_keep_rule("oldest"), Some(KeepRule::Oldest));
+    assert_eq!(
+        parse_keep_rule("shortest_path"),
+        Some(KeepRule::ShortestPath)
+    );
+    assert_eq!(
+        parse_keep_rule("path_prefix:no/such/dir"),
+        Some(KeepRule::PathPrefix(PathBuf::from("no/such/dir")))
+    );
+    assert_eq!(parse_keep_rule("path_prefix:"), None);
+    assert_eq!(parse_keep_rule("largest"), None);
+}
@@ -7,12 +7,6 @@ use std::num::IntErrorKind;
 #[derive(Clone, Debug)]
 pub struct UNumberParser<T>(PhantomData<T>);
 
-impl UNumberParser<u64> {
-    pub fn u64() -> Self {
-        Self(PhantomData)
-    }
-}
-
 impl UNumberParser<u32> {
     pub fn u32() -> Self {
         Self(PhantomData)
@@ -0,0 +1,36 @@
+use crate::out_sink::OutSink;
+use clap::builder::{StringValueParser, TypedValueParser};
+use clap::Arg;
+use std::ffi::OsStr;
+
+/// Parse a destination for the results like `file:report.txt`, `fd:3` or `tcp:host:port`
+#[derive(Clone)]
+pub(crate) struct OutSinkValueParser;
+
+impl TypedValueParser for OutSinkValueParser {
+    type Value = OutSink;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        OutSink::parse(&value).ok_or_else(|| {
+            let arg_text = arg.map_or(String::new(), |arg| {
+                let literal = cmd.get_styles().get_literal();
+                format!(
+                    " for arg '{}{arg}{}'",
+                    literal.render(),
+                    literal.render_reset()
+                )
+            });
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid output '{value}'{arg_text}: expected file:<PATH>, fd:<N> or tcp:<HOST>:<PORT>\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
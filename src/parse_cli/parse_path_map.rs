@@ -0,0 +1,72 @@
+use crate::util::PathMap;
+use clap::builder::{OsStringValueParser, TypedValueParser};
+use clap::Arg;
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// Parse a directory of the host and where it is mounted in the container like `/srv/data=/data`
+#[derive(Clone)]
+pub(crate) struct PathMapValueParser;
+
+impl TypedValueParser for PathMapValueParser {
+    type Value = PathMap;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = OsStringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse_path_map(&value).ok_or_else(|| {
+            let arg_text = arg.map_or(String::new(), |arg| {
+                let literal = cmd.get_styles().get_literal();
+                format!(
+                    " for arg '{}{arg}{}'",
+                    literal.render(),
+                    literal.render_reset()
+                )
+            });
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!(
+                    "invalid path map '{}'{arg_text}: expected HOST_PREFIX=CONTAINER_PREFIX like /srv/data=/data\n",
+                    value.to_string_lossy()
+                ),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+/// split at the last `=`, so that the host prefix may contain one
+fn parse_path_map(text: &OsStr) -> Option<PathMap> {
+    let text = crate::util::path_bytes(Path::new(text));
+    let split = text.iter().rposition(|byte| *byte == b'=')?;
+    let (host, container) = (&text[..split], &text[split + 1..]);
+    if host.is_empty() || container.is_empty() {
+        return None;
+    }
+    Some(PathMap {
+        host: crate::util::path_from_bytes(host.to_vec()),
+        container: crate::util::path_from_bytes(container.to_vec()),
+    })
+}
+
+#[test]
+fn test_parse_path_map() {
+    use std::path::PathBuf;
+
+    let map = |text: &str| parse_path_map(OsStr::new(text)).map(|map| (map.host, map.container));
+    assert_eq!(
+        map("/srv/data=/data"),
+        Some((PathBuf::from("/srv/data"), PathBuf::from("/data")))
+    );
+    assert_eq!(
+        map("/srv/a=b=/data"),
+        Some((PathBuf::from("/srv/a=b"), PathBuf::from("/data")))
+    );
+    assert_eq!(map("/srv/data"), None);
+    assert_eq!(map("=/data"), None);
+    assert_eq!(map("/srv/data="), None);
+}
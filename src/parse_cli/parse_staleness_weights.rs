@@ -0,0 +1,58 @@
+use crate::set_order::StalenessWeights;
+use clap::builder::{StringValueParser, TypedValueParser};
+use clap::Arg;
+use std::ffi::OsStr;
+
+/// Parse the weights of the modification and the access time like `2:1`, at least one of them not zero
+#[derive(Clone)]
+pub(crate) struct StalenessWeightsValueParser;
+
+impl TypedValueParser for StalenessWeightsValueParser {
+    type Value = StalenessWeights;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        parse_staleness_weights(&value).ok_or_else(|| {
+            let arg_text = arg.map_or(String::new(), |arg| {
+                let literal = cmd.get_styles().get_literal();
+                format!(
+                    " for arg '{}{arg}{}'",
+                    literal.render(),
+                    literal.render_reset()
+                )
+            });
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid weights '{value}'{arg_text}: expected MTIME:ATIME like 2:1, not both zero\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+fn parse_staleness_weights(text: &str) -> Option<StalenessWeights> {
+    let (modified, accessed) = text.split_once(':')?;
+    let weights = StalenessWeights {
+        modified: modified.parse().ok()?,
+        accessed: accessed.parse().ok()?,
+    };
+    (weights.modified > 0 || weights.accessed > 0).then_some(weights)
+}
+
+#[test]
+fn test_parse_staleness_weights() {
+    let weights =
+        |text| parse_staleness_weights(text).map(|weights| (weights.modified, weights.accessed));
+    assert_eq!(weights("1:1"), Some((1, 1)));
+    assert_eq!(weights("3:0"), Some((3, 0)));
+    assert_eq!(weights("0:2"), Some((0, 2)));
+    assert_eq!(weights("0:0"), None);
+    assert_eq!(weights("2"), None);
+    assert_eq!(weights("-1:2"), None);
+    assert_eq!(weights("1:2:3"), None);
+}
@@ -0,0 +1,203 @@
+use crate::parse_cli::parse_duration::parse_duration;
+use clap::builder::{StringValueParser, TypedValueParser};
+use clap::Arg;
+use std::ffi::OsStr;
+use std::time::{Duration, SystemTime};
+
+/// a range of points in time, the start is inclusive and the end exclusive
+#[derive(Clone)]
+pub(crate) struct TimeRange {
+    pub start: Option<SystemTime>,
+    pub end: Option<SystemTime>,
+}
+
+/// Parse a range `START..END` of dates(`2023-01-01`, `2023-01-01T12:30`, in UTC) or durations(`30d`),
+/// which are taken as that long ago; either bound may be left out
+#[derive(Clone)]
+pub(crate) struct TimeRangeValueParser;
+
+impl TypedValueParser for TimeRangeValueParser {
+    type Value = TimeRange;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = StringValueParser::new().parse_ref(cmd, arg, value)?;
+        let range_err = |reason: &str| {
+            let arg_text = arg.map_or(String::new(), |arg| {
+                let literal = cmd.get_styles().get_literal();
+                format!(
+                    " for arg '{}{arg}{}'",
+                    literal.render(),
+                    literal.render_reset()
+                )
+            });
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid time range '{value}'{arg_text}: {reason}\n"),
+            )
+            .with_cmd(cmd)
+        };
+        let Some((start, end)) = value.split_once("..") else {
+            return Err(range_err("expected START..END, START.. or ..END"));
+        };
+        let now = SystemTime::now();
+        let parse_bound = |bound: &str| {
+            if bound.is_empty() {
+                return Ok(None);
+            }
+            parse_date(bound)
+                .or_else(|| parse_duration(bound).and_then(|ago| now.checked_sub(ago)))
+                .map(Some)
+                .ok_or_else(|| {
+                    range_err(&format!(
+                        "'{bound}' is neither a date like 2023-01-31 or 2023-01-31T12:30 nor a duration like 30d"
+                    ))
+                })
+        };
+        let range = TimeRange {
+            start: parse_bound(start)?,
+            end: parse_bound(end)?,
+        };
+        match (range.start, range.end) {
+            (None, None) => Err(range_err("at least one bound is required")),
+            (Some(start), Some(end)) if start >= end => {
+                Err(range_err("the start must be before the end"))
+            }
+            _ => Ok(range),
+        }
+    }
+}
+
+/// parse `YYYY-MM-DD` optionally followed by `THH:MM` or `THH:MM:SS`, in UTC
+pub(crate) fn parse_date(text: &str) -> Option<SystemTime> {
+    let (date, time) = text.split_once(['T', 't', ' ']).unwrap_or((text, ""));
+    let mut date_parts = date.split('-');
+    let (year, month, day) = (date_parts.next()?, date_parts.next()?, date_parts.next()?);
+    if date_parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let year = year.parse::<i64>().ok()?;
+    let month = month
+        .parse::<i64>()
+        .ok()
+        .filter(|month| (1..=12).contains(month))?;
+    let day = day
+        .parse::<i64>()
+        .ok()
+        .filter(|day| *day >= 1 && *day <= days_in_month(year, month))?;
+
+    let mut seconds_of_day = 0;
+    if !time.is_empty() {
+        let limits = [24, 60, 60];
+        let mut parts = time.split(':');
+        for (i, part) in parts.by_ref().take(limits.len()).enumerate() {
+            let value = part
+                .parse::<i64>()
+                .ok()
+                .filter(|value| part.len() == 2 && *value < limits[i])?;
+            seconds_of_day += value * [3600, 60, 1][i];
+        }
+        // the minutes are required, further parts are not allowed
+        if parts.next().is_some() || !time.contains(':') {
+            return None;
+        }
+    }
+
+    let seconds = days_from_civil(year, month, day) * 24 * 60 * 60 + seconds_of_day;
+    let offset = Duration::from_secs(seconds.unsigned_abs());
+    if seconds >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(offset)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(offset)
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// days since 1970-01-01 of the date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // count years from march so that the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[test]
+fn test_parse_date() {
+    let secs = |text| {
+        parse_date(text).map(|time| match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(after) => i128::from(after.as_secs()),
+            Err(before) => -i128::from(before.duration().as_secs()),
+        })
+    };
+    assert_eq!(secs("1970-01-01"), Some(0));
+    assert_eq!(secs("2000-03-01"), Some(951_868_800));
+    assert_eq!(secs("2024-02-29T12:30"), Some(1_709_209_800));
+    assert_eq!(secs("2024-02-29T12:30:15"), Some(1_709_209_815));
+    assert_eq!(secs("1969-12-31T23:59:59"), Some(-1));
+    assert_eq!(secs("2023-02-29"), None);
+    assert_eq!(secs("2023-13-01"), None);
+    assert_eq!(secs("2023-1-01"), None);
+    assert_eq!(secs("2023-01-01T24:00"), None);
+    assert_eq!(secs("2023-01-01T12"), None);
+    assert_eq!(secs("30d"), None);
+}
+
+#[test]
+fn test_time_range() {
+    let command = clap::Command::new("test")
+        .arg(
+            clap::Arg::new("range")
+                .value_parser(clap::builder::ValueParser::from(TimeRangeValueParser)),
+        )
+        .no_binary_name(true);
+    let parse = |range: &str| {
+        command
+            .clone()
+            .try_get_matches_from([range])
+            .map(|matches| {
+                let range = matches.get_one::<TimeRange>("range").unwrap();
+                (range.start, range.end)
+            })
+    };
+    let date = |text| parse_date(text).unwrap();
+    assert_eq!(
+        parse("2023-01-01..2023-02-01T12:30").unwrap(),
+        (Some(date("2023-01-01")), Some(date("2023-02-01T12:30")))
+    );
+    assert_eq!(
+        parse("2023-01-01..").unwrap(),
+        (Some(date("2023-01-01")), None)
+    );
+    // a duration is taken as that long ago
+    let (start, end) = parse("..30d").unwrap();
+    let ago = SystemTime::now().duration_since(end.unwrap()).unwrap();
+    assert_eq!(start, None);
+    let day = Duration::from_hours(24);
+    assert!(ago >= 30 * day && ago < 31 * day);
+    assert!(parse("30d..1d").is_ok());
+    for invalid in [
+        "..",
+        "2023-01-01",
+        "2023-02-01..2023-01-01",
+        "1d..30d",
+        "now..",
+    ] {
+        assert!(parse(invalid).is_err(), "{invalid}");
+    }
+}
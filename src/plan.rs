@@ -0,0 +1,156 @@
+use crate::error_handling::AlreadyReportedError;
+use crate::file_action::FileConsumeAction;
+use crate::util::count_of;
+use crate::{content_matches, HashAlgorithm, Recoverable};
+use std::io::BufRead;
+use std::path::Path;
+
+/// written at the top of every plan, lines starting with '#' are ignored when applying
+pub static PLAN_HEADER: &str = "# duplis plan: delete the lines of actions which should not be executed, then run `duplis apply <FILE>`\n# action\tcontent hash\tfile\toriginal\n";
+
+/// the hashes of plans are computed with it, whichever algorithm the scan was hashed with
+pub const PLAN_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Xxh3;
+
+/// one intended action of a plan, written as a tab separated line
+pub struct PlanLine<'a> {
+    /// the long name of the action flag, like 'delete'
+    pub action: &'a str,
+    /// the hash of the content both files had when the plan was written
+    pub hash: u128,
+    pub file: &'a str,
+    pub original: &'a str,
+}
+
+impl<'a> PlanLine<'a> {
+    pub fn write_to(&self, write: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(
+            write,
+            "{}\t{:032x}\t{}\t{}",
+            self.action, self.hash, self.file, self.original
+        )
+    }
+
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let action = fields.next()?;
+        let hash = u128::from_str_radix(fields.next()?, 16).ok()?;
+        let file = fields.next()?;
+        let original = fields.next()?;
+        if fields.next().is_some() || file.is_empty() || original.is_empty() {
+            return None;
+        }
+        Some(Self {
+            action,
+            hash,
+            file,
+            original,
+        })
+    }
+}
+
+/// returns true if the path can be written into a plan line
+pub fn plan_can_represent(path: &str) -> bool {
+    !path.contains(['\t', '\n', '\r'])
+}
+
+/// execute the remaining lines of the plan, but only if both files still have the content they had when the plan was written;
+/// the malformed lines and the lines of unknown actions are skipped, but fail the plan once it is done
+pub fn apply_plan(
+    plan: &Path,
+    actions: &mut [(&'static str, Box<dyn FileConsumeAction + Send>)],
+) -> Result<(), AlreadyReportedError> {
+    let plan_file = std::fs::File::open(plan).map_err(|err| {
+        log::error!(
+            target: crate::error_handling::CONFIG_ERR_TARGET,
+            "cannot open plan {}: {err}",
+            plan.display()
+        );
+        AlreadyReportedError
+    })?;
+    let mut skipped = 0;
+    for (line_number, line) in std::io::BufReader::new(plan_file).lines().enumerate() {
+        let line = line.map_err(|err| {
+            log::error!(
+                target: crate::error_handling::CONFIG_ERR_TARGET,
+                "cannot read plan {}: {err}",
+                plan.display()
+            );
+            AlreadyReportedError
+        })?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some(planned) = PlanLine::parse(line.trim_end_matches('\r')) else {
+            log::error!(target: crate::error_handling::FORMAT_ERR_TARGET, "line {} of plan {} is malformed; skipping it", line_number + 1, plan.display());
+            skipped += 1;
+            continue;
+        };
+        let Some((_, action)) = actions.iter_mut().find(|(name, _)| *name == planned.action) else {
+            log::error!(target: crate::error_handling::FORMAT_ERR_TARGET, "unknown action '{}' in line {} of plan {}; skipping it", planned.action, line_number + 1, plan.display());
+            skipped += 1;
+            continue;
+        };
+        let (file, original) = (Path::new(planned.file), Path::new(planned.original));
+        if !content_unchanged(file, planned.hash) || !content_unchanged(original, planned.hash) {
+            continue;
+        }
+        if let Err(Recoverable::Fatal(AlreadyReportedError)) = action.consume(file, Some(original))
+        {
+            log::error!(
+                target: crate::error_handling::FILE_SET_ERR_TARGET,
+                "aborting the plan due to previous error"
+            );
+            return Err(AlreadyReportedError);
+        }
+    }
+    if skipped > 0 {
+        log::error!(
+            target: crate::error_handling::FORMAT_ERR_TARGET,
+            "skipped {} of plan {}",
+            count_of(skipped, "line"),
+            plan.display()
+        );
+        return Err(AlreadyReportedError);
+    }
+    Ok(())
+}
+
+fn content_unchanged(path: &Path, expected: u128) -> bool {
+    match content_matches(path, expected, PLAN_HASH_ALGORITHM) {
+        Ok(true) => true,
+        Ok(false) => {
+            log::warn!(
+                target: crate::error_handling::FILE_CHANGED_TARGET,
+                "file {} changed since the plan was written; skipping its action",
+                path.display()
+            );
+            false
+        }
+        Err(AlreadyReportedError) => false,
+    }
+}
+
+#[test]
+fn test_plan_line() {
+    let mut written = Vec::new();
+    let line = PlanLine {
+        action: "delete",
+        hash: 0xab,
+        file: "/a/b c",
+        original: "/a/d",
+    };
+    line.write_to(&mut written).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert_eq!(
+        written,
+        "delete\t000000000000000000000000000000ab\t/a/b c\t/a/d\n"
+    );
+    let parsed = PlanLine::parse(written.trim_end()).unwrap();
+    assert_eq!(
+        (parsed.action, parsed.hash, parsed.file, parsed.original),
+        ("delete", 0xab, "/a/b c", "/a/d")
+    );
+    assert!(PlanLine::parse("delete\tzz\t/a\t/b").is_none());
+    assert!(PlanLine::parse("delete\tab\t/a").is_none());
+    assert!(PlanLine::parse("delete\tab\t/a\t/b\t/c").is_none());
+}
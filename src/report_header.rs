@@ -0,0 +1,67 @@
+use crate::util::format_timestamp;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// describes the scan a report was written by, so that archived reports explain themselves
+pub struct ReportHeader {
+    pub started: SystemTime,
+    pub roots: Vec<PathBuf>,
+    pub from_stdin: bool,
+    /// the names of the orderings, most important first
+    pub orderings: Vec<String>,
+    /// the arguments of the invocation without the program name, they include the filters
+    pub arguments: Vec<OsString>,
+}
+
+impl ReportHeader {
+    /// write the header as lines starting with '#', the whole header is terminated by `record_end`
+    pub fn write_to(&self, write: &mut impl std::io::Write, record_end: u8) -> std::io::Result<()> {
+        writeln!(write, "# duplis {} report", env!("CARGO_PKG_VERSION"))?;
+        writeln!(write, "# started: {}", format_timestamp(self.started))?;
+        for root in &self.roots {
+            writeln!(write, "# root: {}", root.display())?;
+        }
+        if self.from_stdin {
+            writeln!(write, "# root: files read from stdin")?;
+        }
+        writeln!(write, "# ordering: {}", self.orderings.join(","))?;
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|argument| argument.to_string_lossy())
+            .collect::<Vec<_>>();
+        write!(write, "# arguments: {}", arguments.join(" "))?;
+        write.write_all(&[record_end])
+    }
+}
+
+#[test]
+fn test_report_header() {
+    let header = ReportHeader {
+        started: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_709_209_815),
+        roots: vec![PathBuf::from("/a"), PathBuf::from("/b")],
+        from_stdin: true,
+        orderings: vec![String::from("cleanname"), String::from("modtime")],
+        arguments: vec![OsString::from("-r"), OsString::from("--wout=setwise")],
+    };
+    let mut written = Vec::new();
+    header.write_to(&mut written, b'\0').unwrap();
+    let expected = format!(
+        "# duplis {} report\n# started: 2024-02-29T12:30:15Z\n# root: /a\n# root: /b\n# root: files read from stdin\n# ordering: cleanname,modtime\n# arguments: -r --wout=setwise\0",
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(String::from_utf8(written).unwrap(), expected);
+    assert_eq!(
+        format_timestamp(SystemTime::UNIX_EPOCH),
+        "1970-01-01T00:00:00Z"
+    );
+    assert_eq!(
+        format_timestamp(SystemTime::UNIX_EPOCH - std::time::Duration::from_secs(1)),
+        "1969-12-31T23:59:59Z"
+    );
+    assert_eq!(
+        format_timestamp(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(951_868_801)),
+        "2000-03-01T00:00:01Z"
+    );
+}
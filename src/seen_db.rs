@@ -0,0 +1,114 @@
+use crate::plan::plan_can_represent;
+use crate::util::path_bytes;
+use crate::DuplicateSet;
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// the sets the user dismissed, each line starts with the key of one set or with `id ` and the id of a
+/// set skipped whatever its files
+pub struct SeenDb {
+    seen: HashSet<u128>,
+    skipped: HashSet<u128>,
+    append: std::fs::File,
+}
+
+impl SeenDb {
+    /// read the dismissed sets, the file is created if it does not exist yet
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut seen = HashSet::new();
+        let mut skipped = HashSet::new();
+        for line in std::io::BufReader::new(&file).lines() {
+            let line = line?;
+            if let Some(id) = line.strip_prefix("id ") {
+                if let Ok(id) = u128::from_str_radix(id, 16) {
+                    skipped.insert(id);
+                }
+                continue;
+            }
+            let key = line.split('\t').next().unwrap_or_default();
+            if let Ok(key) = u128::from_str_radix(key, 16) {
+                seen.insert(key);
+            }
+        }
+        Ok(Self {
+            seen,
+            skipped,
+            append: file,
+        })
+    }
+
+    pub fn is_dismissed(&self, paths: &[PathBuf]) -> bool {
+        self.seen.contains(&set_key(paths))
+    }
+
+    /// whether the set is dismissed, either as the exact files or by its id
+    pub fn suppresses(&self, set: &DuplicateSet) -> bool {
+        self.skipped.contains(&set.id) || self.is_dismissed(&set.paths())
+    }
+
+    /// remember the set by its id, it stays skipped as long as the content of its files does not change
+    pub fn skip(&mut self, id: u128) -> std::io::Result<()> {
+        if !self.skipped.insert(id) {
+            return Ok(());
+        }
+        writeln!(self.append, "id {id:032x}")
+    }
+
+    /// remember the exact set, the paths follow the key if they can be represented
+    pub fn dismiss(&mut self, paths: &[PathBuf]) -> std::io::Result<()> {
+        let key = set_key(paths);
+        if !self.seen.insert(key) {
+            return Ok(());
+        }
+        let mut line = format!("{key:032x}");
+        let readable = paths
+            .iter()
+            .map(|path| path.to_str().filter(|path| plan_can_represent(path)))
+            .collect::<Option<Vec<_>>>();
+        for path in readable.into_iter().flatten() {
+            line.push('\t');
+            line.push_str(path);
+        }
+        line.push('\n');
+        self.append.write_all(line.as_bytes())
+    }
+}
+
+/// identifies a set by its members regardless of their order
+fn set_key(paths: &[PathBuf]) -> u128 {
+    let mut paths = paths
+        .iter()
+        .map(|path| path_bytes(path))
+        .collect::<Vec<_>>();
+    paths.sort_unstable();
+    let mut hasher = xxhash_rust::xxh3::Xxh3::default();
+    for path in paths {
+        hasher.write(&path);
+        hasher.write_u8(0);
+    }
+    hasher.digest128()
+}
+
+#[test]
+fn test_set_key() {
+    let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+    assert_eq!(
+        set_key(&paths(&["/a", "/b"])),
+        set_key(&paths(&["/b", "/a"]))
+    );
+    assert_ne!(
+        set_key(&paths(&["/a", "/b"])),
+        set_key(&paths(&["/a", "/b", "/c"]))
+    );
+    assert_ne!(
+        set_key(&paths(&["/ab", "/c"])),
+        set_key(&paths(&["/a", "/bc"]))
+    );
+}
@@ -1,15 +1,53 @@
-use crate::error_handling::AlreadyReportedError;
-use crate::file_action::FileConsumeAction;
-use crate::util::{path_contains_comma, ChoiceInputReader};
+use crate::allow_dupes::AllowedDupes;
+use crate::answers::Answers;
+use crate::error_handling::{AlreadyReportedError, ErrorSummary};
+use crate::file_action::{original_is_usable, FileConsumeAction, FileConsumeResult};
+use crate::os::{console_output, ConsoleOutput};
+use crate::out_sink::ResultsOutput;
+use crate::plan::{plan_can_represent, PlanLine, PLAN_HASH_ALGORITHM, PLAN_HEADER};
+use crate::report_header::ReportHeader;
+use crate::seen_db::SeenDb;
+use crate::util::{
+    count_of, format_size, format_timestamp, path_bytes, path_contains_comma, ChoiceInputReader,
+    LinkedPath, PathMap, SizeFormat,
+};
 use crate::{
-    handle_file_op, in_err_map, out_err_map, report_file_missing, HashedFile, Recoverable,
+    content_matches, handle_file_op, handle_metadata_op, hash_content, in_err_map, out_err_map,
+    report_file_missing, DuplicateSet, HashAlgorithm, HashedFile, Recoverable,
 };
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+macro_rules! warn_path_not_plannable {
+    ($path: expr) => {
+        log::warn!(
+            target: crate::error_handling::FORMAT_ERR_TARGET,
+            "path {} is not valid unicode or contains a tab or newline and cannot be written into a plan",
+            $path.display()
+        );
+    };
+}
 
 pub trait FileSetConsumer {
     /// first element of set is the 'original',
-    /// the set is a least of size 2
-    fn consume_set(&mut self, set: Vec<HashedFile>) -> Result<(), AlreadyReportedError>;
+    /// the set is a least of size 2 unless the consumer consumes lone files
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError>;
+    /// returns true if consuming a set may modify the file system
+    fn is_destructive(&self) -> bool {
+        false
+    }
+    /// returns true if the files without any duplicate are consumed as sets of their own
+    fn consumes_lone_files(&self) -> bool {
+        false
+    }
+    /// called once after the last set has been consumed
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        Ok(())
+    }
 }
 
 /// execute given [`FileConsumeAction`] without user input
@@ -17,6 +55,10 @@ pub struct UnconditionalAction {
     running_buf: PathBuf,
     original_buf: PathBuf,
     action: Box<dyn FileConsumeAction>,
+    /// hash the original again with the algorithm of the scan before acting on its set
+    verify_original: Option<HashAlgorithm>,
+    /// runs the actions on several threads if present
+    pool: Option<ActionPool>,
 }
 
 /// execute given [`FileConsumeAction`] after asking user
@@ -25,54 +67,281 @@ pub struct InteractiveEachChoice<R, W> {
     original_buf: PathBuf,
     choice_buf: String,
     action: Box<dyn FileConsumeAction>,
+    /// hash the original again with the algorithm of the scan before asking about its set
+    verify_original: Option<HashAlgorithm>,
+    /// the answer to an empty line, empty lines are rejected if none
+    default_answer: Option<bool>,
+    /// the user may dismiss a set for future runs if present
+    seen_db: Option<SeenDb>,
+    /// answers are replayed from and recorded into it if present
+    answers: Option<Answers>,
+    read: R,
+    write: W,
+}
+
+/// how an interactive answer dismissed a set for future runs
+#[derive(Copy, Clone)]
+enum SetDismissal {
+    /// the exact files of the set, recorded by their paths
+    Files,
+    /// the set whatever its files, recorded by its id
+    Set,
+}
+
+/// list the files of each set and execute given [`FileConsumeAction`] on the files the user does not keep,
+/// the first kept file is the original
+pub struct InteractiveSetChoice<R, W> {
+    running_buf: PathBuf,
+    original_buf: PathBuf,
+    choice_buf: String,
+    action: Box<dyn FileConsumeAction>,
+    /// hash the original again with the algorithm of the scan before acting on its set
+    verify_original: Option<HashAlgorithm>,
     read: R,
     write: W,
 }
 
+/// skip the sets the user dismissed in earlier runs
+pub struct SuppressSeen {
+    seen_db: SeenDb,
+    inner: Box<dyn FileSetConsumer>,
+}
+
+/// skip the sets which would not free enough space, hard links to an earlier member free no space
+pub struct SkipSmallSets {
+    min_savings: u64,
+    path_buf: PathBuf,
+    inner: Box<dyn FileSetConsumer>,
+}
+
+/// leave out the files which are hard links to an earlier member, skip the sets with a single file left
+pub struct SkipHardLinked {
+    inner: Box<dyn FileSetConsumer>,
+}
+
+/// write a header in front of the output of the consumer, even if there are no sets
+pub struct WithReportHeader<W> {
+    header: Option<ReportHeader>,
+    record_end: u8,
+    write: W,
+    inner: Box<dyn FileSetConsumer>,
+}
+
+/// give the consumer the paths of the files on the host instead of in the container, the paths outside the
+/// mounted directories stay as they are
+pub struct MapToHost {
+    maps: Vec<PathMap>,
+    path_buf: PathBuf,
+    inner: Box<dyn FileSetConsumer>,
+}
+
+/// leave out the files which are intentional duplicates of a file before them in the set
+pub struct FilterAllowedDupes {
+    allowed: AllowedDupes,
+    inner: Box<dyn FileSetConsumer>,
+}
+
 /// simply print all files that would be affected by an action
 pub struct DryRun<W> {
     path_buf: PathBuf,
+    /// the short name of the chosen action and whether it requires an original, deletion if none was chosen
+    action: Option<(String, bool)>,
+    /// the files are not accessed, they may not exist
+    simulated: bool,
+    write: W,
+}
+
+/// what the consumed sets add up to, logged once the consumer finished
+pub struct SetSummary {
+    sets: u64,
+    duplicates: u64,
+    /// the space the duplicates take up apart from hard links to an earlier file of their set, None if
+    /// the size of a set is not known
+    reclaimable: Option<u64>,
+    size_format: SizeFormat,
+}
+
+/// sum up the space that could at most be reclaimed, the sets are not verified by content
+pub struct EstimateReport<W> {
+    compared_bytes: u64,
+    sets: u64,
+    duplicates: u64,
+    reclaimable: u64,
+    path_buf: PathBuf,
+    write: W,
+}
+
+/// sum up the duplicates and their bytes per extension
+pub struct ExtensionReport<W> {
+    /// the duplicates and their bytes by lowercase extension, the empty string means no extension
+    extensions: BTreeMap<String, (u64, u64)>,
+    path_buf: PathBuf,
+    size_format: SizeFormat,
+    write: W,
+}
+
+/// report the groups of paths linking to the same file and the space the links already save
+pub struct HardLinkReport<W> {
+    groups: u64,
+    saved: u64,
+    path_buf: PathBuf,
+    size_format: SizeFormat,
+    write: W,
+}
+
+/// report the files with fewer than the required copies, counting every other file of its set as a copy
+/// unless it is a hard link to a file counted already
+pub struct CopyAudit<W> {
+    required: usize,
+    /// the files reported so far
+    lacking: u64,
+    /// the audit fails if any file never reached it
+    errors: Option<Arc<ErrorSummary>>,
+    path_buf: PathBuf,
+    write: W,
+}
+
+/// build a mirror of the files below the target directory by their full paths, every file of a set is a
+/// hard link to its original, so the mirror holds each content once and the scanned files stay untouched
+pub struct MirrorTree<W> {
+    target: PathBuf,
+    /// the files and the distinct contents mirrored so far
+    files: u64,
+    contents: u64,
+    path_buf: PathBuf,
+    original_buf: PathBuf,
+    write: W,
+}
+
+/// write the intended actions into a plan, which can be reviewed and then executed by `duplis apply`
+pub struct PlanWriter<W> {
+    action_name: &'static str,
+    path_buf: PathBuf,
+    write: W,
+}
+
+/// render the sets grouped under their common ancestor directories as an indented tree
+pub struct TreeReport<W> {
+    sets: BTreeMap<PathBuf, Vec<(u64, Vec<PathBuf>)>>,
+    size_format: SizeFormat,
     write: W,
 }
 
+/// a handle to a dedicated thread writing the output of consumers which may run concurrently,
+/// everything written through one handle between two flushes is written in one piece
+pub struct SyncWriterSink {
+    buf: Vec<u8>,
+    queue: Option<flume::Sender<Vec<u8>>>,
+    /// the last handle to be dropped waits for the writer to finish
+    writer: Option<Arc<std::thread::JoinHandle<()>>>,
+}
+
+/// write the duplicates as NUL terminated paths for `xargs -0 rm --` in batches of limited size,
+/// a `# batch N` record starts each batch and a `# keep ORIGINAL` record each set
+pub struct RmBatch<W> {
+    batch_bytes: usize,
+    batches: u64,
+    /// the bytes of the paths in the current batch
+    written: usize,
+    path_buf: PathBuf,
+    writer: W,
+}
+
 pub struct MachineReadableEach<W> {
     written_before: bool,
     writer: W,
     path_bufs: (PathBuf, PathBuf),
+    /// the records start with the id of their set
+    set_ids: bool,
+    /// every path is followed by the id of its device
+    device_ids: bool,
+    /// every path is followed by the size and modification time of its file, the sizes in this format
+    file_details: Option<SizeFormat>,
 }
 pub struct MachineReadableSet<W> {
     written_before: bool,
     writer: W,
     path_bufs: (PathBuf, PathBuf),
+    /// the records start with the id of their set
+    set_ids: bool,
+    /// every path is followed by the id of its device
+    device_ids: bool,
+    /// every path is followed by the size and modification time of its file, the sizes in this format
+    file_details: Option<SizeFormat>,
 }
 
-impl Default for DryRun<std::io::Stdout> {
-    fn default() -> Self {
+impl SetSummary {
+    pub fn new(size_format: SizeFormat) -> Self {
         Self {
-            path_buf: PathBuf::new(),
-            write: std::io::stdout(),
+            sets: 0,
+            duplicates: 0,
+            reclaimable: Some(0),
+            size_format,
+        }
+    }
+
+    /// count the set, a lone file is no set
+    pub fn add(&mut self, set: &DuplicateSet) {
+        if set.files.len() < 2 {
+            return;
         }
+        let separate_copies = find_hard_links(&set.files)[1..]
+            .iter()
+            .filter(|hard_link| hard_link.is_none())
+            .count() as u64;
+        self.sets += 1;
+        self.duplicates += set.files.len() as u64 - 1;
+        self.reclaimable = self
+            .reclaimable
+            .zip(set.file_size())
+            .map(|(reclaimable, size)| reclaimable + separate_copies * size);
+    }
+
+    /// like `2 duplicates in 1 set, 2.9 KiB reclaimable`
+    pub fn line(&self) -> String {
+        let reclaimable = self
+            .reclaimable
+            .map_or(String::from("an unknown size"), |reclaimable| {
+                self.size_format.format(reclaimable)
+            });
+        format!(
+            "{} in {}, {reclaimable} reclaimable",
+            count_of(self.duplicates, "duplicate"),
+            count_of(self.sets, "set")
+        )
+    }
+
+    pub fn report(&self) {
+        log::info!(target: crate::error_handling::SUMMARY_TARGET, "{}", self.line());
     }
 }
 
 impl<W> DryRun<W> {
-    pub fn new() -> Self
-    where
-        Self: Default,
-    {
-        Self::default()
-    }
     pub fn new_with(write: W) -> Self {
         Self {
             path_buf: PathBuf::new(),
+            action: None,
+            simulated: false,
             write,
         }
     }
+
+    /// report what the action would do instead of deleting
+    pub fn with_action(mut self, action: &dyn FileConsumeAction) -> Self {
+        self.action = Some((action.short_name().into_owned(), action.requires_original()));
+        self
+    }
+
+    /// report the sets without checking whether the originals are usable
+    pub fn simulated(mut self, simulated: bool) -> Self {
+        self.simulated = simulated;
+        self
+    }
 }
 
-impl DryRun<std::io::Stdout> {
-    pub fn for_console() -> Self {
-        Self::new()
+impl DryRun<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new_with(output.writer())
     }
 }
 
@@ -87,25 +356,51 @@ macro_rules! warn_path_contains_comma {
 }
 
 impl<W: std::io::Write> FileSetConsumer for DryRun<W> {
-    fn consume_set(&mut self, set: Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let mut set = set.files;
+        if let (Some((_, true)), false) = (&self.action, self.simulated) {
+            // the action would skip the originals it cannot link to
+            while let Some(file) = set.first() {
+                file.file_path.write_full_to_buf(&mut self.path_buf);
+                if original_is_usable(&self.path_buf) {
+                    break;
+                }
+                set.remove(0);
+            }
+            if set.len() < 2 {
+                return Ok(());
+            }
+        }
+        let hard_links = find_hard_links(&set);
         set[0].file_path.write_full_to_buf(&mut self.path_buf);
-        write!(
-            self.write,
-            "keeping {}, dry-deleting ",
-            self.path_buf.display()
-        )
+        match &self.action {
+            Some((short_name, _)) => write!(
+                self.write,
+                "keeping {}, would {short_name}: ",
+                self.path_buf.display()
+            ),
+            None => write!(
+                self.write,
+                "keeping {}, dry-deleting ",
+                self.path_buf.display()
+            ),
+        }
         .map_err(out_err_map!())?;
         let mut write_sep = false;
-        for file in &set[1..] {
+        for (file, hard_link) in set[1..].iter().zip(&hard_links[1..]) {
             if write_sep {
                 write!(self.write, ", ").map_err(out_err_map!())?;
             }
             write_sep = true;
             file.file_path.write_full_to_buf(&mut self.path_buf);
             write!(self.write, "{}", self.path_buf.display()).map_err(out_err_map!())?;
+            if let Some(linked) = hard_link {
+                set[*linked].file_path.write_full_to_buf(&mut self.path_buf);
+                write!(self.write, " (hard link of {})", self.path_buf.display())
+                    .map_err(out_err_map!())?;
+            }
         }
-        writeln!(self.write).map_err(out_err_map!())?;
-        Ok(())
+        writeln!(self.write).map_err(out_err_map!())
     }
 }
 
@@ -115,22 +410,63 @@ impl UnconditionalAction {
             running_buf: PathBuf::new(),
             original_buf: PathBuf::new(),
             action,
+            verify_original: None,
+            pool: None,
         }
     }
+
+    pub fn with_verify_original(mut self, verify_original: Option<HashAlgorithm>) -> Self {
+        self.verify_original = verify_original;
+        self
+    }
+
+    /// execute the actions on this many threads; actions which cannot be cloned run on the current thread
+    pub fn with_action_threads(mut self, threads: NonZeroU32) -> Self {
+        self.pool = None;
+        if threads.get() > 1 {
+            self.pool = ActionPool::new(&*self.action, threads);
+        }
+        self
+    }
 }
 
 impl FileSetConsumer for UnconditionalAction {
-    fn consume_set(&mut self, mut set: Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+    fn is_destructive(&self) -> bool {
+        true
+    }
+
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let mut set = set.files;
+        if self.pool.as_ref().is_some_and(ActionPool::is_aborted) {
+            log::error!(
+                target: crate::error_handling::FILE_SET_ERR_TARGET,
+                "aborting '{}' due to previous error",
+                self.action.short_name()
+            );
+            return Err(AlreadyReportedError);
+        }
         let original_buf = loop {
-            let Some(file) = set.get(0) else { return Ok(()) };
+            let Some(file) = set.first() else { return Ok(()) };
             file.file_path.write_full_to_buf(&mut self.original_buf);
-            if self.original_buf.exists() {
-                break &self.original_buf;
-            } else {
+            if !self.original_buf.exists() {
                 report_file_missing!(&self.original_buf);
-                set.remove(0);
+            } else if !self.action.requires_original() || original_is_usable(&self.original_buf) {
+                break &self.original_buf;
             }
+            set.remove(0);
         };
+        if let Some(algorithm) = self.verify_original {
+            if !original_unchanged(original_buf, set[0].content_hash, algorithm) {
+                return Ok(());
+            }
+        }
+        if let Some(pool) = &self.pool {
+            let original = Arc::new(original_buf.clone());
+            for file in &set[1..] {
+                pool.submit(file.file_path.to_push_buf(), original.clone());
+            }
+            return Ok(());
+        }
         for file in &set[1..] {
             file.file_path.write_full_to_buf(&mut self.running_buf);
             if !self.running_buf.exists() {
@@ -138,7 +474,7 @@ impl FileSetConsumer for UnconditionalAction {
                 continue;
             }
             if let Err(Recoverable::Fatal(AlreadyReportedError {})) =
-                self.action.consume(&self.running_buf, Some(original_buf))
+                act_on(self.action.as_mut(), &self.running_buf, Some(original_buf))
             {
                 log::error!(
                     target: crate::error_handling::FILE_SET_ERR_TARGET,
@@ -146,15 +482,122 @@ impl FileSetConsumer for UnconditionalAction {
                     self.action.short_name()
                 );
                 return Err(AlreadyReportedError);
-            };
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        match self.pool.take() {
+            Some(pool) => pool.join(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// a file and the original of its set
+type ActionJob = (PathBuf, Arc<PathBuf>);
+
+/// runs file actions on worker threads; the actions on the files of one directory all run on the same
+/// worker in the order they were submitted, so that they never race each other
+struct ActionPool {
+    workers: Vec<(flume::Sender<ActionJob>, std::thread::JoinHandle<()>)>,
+    aborted: Arc<AtomicBool>,
+    short_name: String,
+}
+
+impl ActionPool {
+    /// None if the action cannot be cloned or no thread could be spawned
+    fn new(action: &dyn FileConsumeAction, threads: NonZeroU32) -> Option<Self> {
+        let aborted = Arc::new(AtomicBool::new(false));
+        let mut workers = Vec::new();
+        for t in 0..threads.get() {
+            let mut action = action.try_clone()?;
+            // keep the backlog in check while the sets are still coming in
+            let (jobs_send, jobs_rev) = flume::bounded::<ActionJob>(128);
+            let aborted = aborted.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("file_action_worker_{t}"))
+                .spawn(move || {
+                    for (path, original) in jobs_rev {
+                        if aborted.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        if !path.exists() {
+                            report_file_missing!(&path);
+                            continue;
+                        }
+                        if let Err(Recoverable::Fatal(AlreadyReportedError {})) =
+                            act_on(action.as_mut(), &path, Some(&original))
+                        {
+                            aborted.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            match thread {
+                Ok(thread) => workers.push((jobs_send, thread)),
+                Err(err) => {
+                    log::error!(target: crate::error_handling::CONFIG_ERR_TARGET, "threading not supported on this platform; please do not use the action threads option({err})");
+                    break;
+                }
+            }
+        }
+        (!workers.is_empty()).then(|| Self {
+            workers,
+            aborted,
+            short_name: action.short_name().into_owned(),
+        })
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    fn submit(&self, path: PathBuf, original: Arc<PathBuf>) {
+        let directory = path.parent().unwrap_or(&path);
+        let worker = xxhash_rust::xxh3::xxh3_64(&path_bytes(directory)) % self.workers.len() as u64;
+        let worker = usize::try_from(worker).expect("worker index is below the number of workers");
+        // a worker only hangs up by panicking, which is reported on join
+        let _ = self.workers[worker].0.send((path, original));
+    }
+
+    /// wait for all submitted actions to finish
+    fn join(mut self) -> Result<(), AlreadyReportedError> {
+        self.join_workers();
+        if self.is_aborted() {
+            log::error!(
+                target: crate::error_handling::FILE_SET_ERR_TARGET,
+                "aborting '{}' due to previous error",
+                self.short_name
+            );
+            return Err(AlreadyReportedError);
         }
         Ok(())
     }
+
+    fn join_workers(&mut self) {
+        let threads = std::mem::take(&mut self.workers)
+            .into_iter()
+            .map(|(_, thread)| thread)
+            .collect::<Vec<_>>();
+        for thread in threads {
+            if thread.join().is_err() {
+                self.aborted.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Drop for ActionPool {
+    fn drop(&mut self) {
+        // the submitted actions are still completed if the run is aborted
+        self.join_workers();
+    }
 }
 
-impl InteractiveEachChoice<std::io::Stdin, std::io::Stdout> {
+impl InteractiveEachChoice<std::io::Stdin, ConsoleOutput> {
     pub fn for_console(action: Box<dyn FileConsumeAction>) -> Self {
-        Self::new(std::io::stdin(), std::io::stdout(), action)
+        Self::new(std::io::stdin(), console_output(), action)
     }
 }
 
@@ -165,106 +608,1266 @@ impl<R, W> InteractiveEachChoice<R, W> {
             original_buf: PathBuf::new(),
             choice_buf: String::new(),
             action,
+            verify_original: None,
+            default_answer: None,
+            seen_db: None,
+            answers: None,
             read,
             write,
         }
     }
+
+    pub fn with_verify_original(mut self, verify_original: Option<HashAlgorithm>) -> Self {
+        self.verify_original = verify_original;
+        self
+    }
+
+    pub fn with_default_answer(mut self, default_answer: Option<bool>) -> Self {
+        self.default_answer = default_answer;
+        self
+    }
+
+    pub fn with_seen_db(mut self, seen_db: SeenDb) -> Self {
+        self.seen_db = Some(seen_db);
+        self
+    }
+
+    pub fn with_answers(mut self, answers: Answers) -> Self {
+        self.answers = Some(answers);
+        self
+    }
+
+    /// the recorded answer for the current file, None if the user has to be asked
+    fn replay_answer(&mut self) -> Option<bool> {
+        self.answers.as_mut()?.replay(&self.running_buf)
+    }
+
+    fn record_answer(&mut self, answer: bool) -> Result<(), AlreadyReportedError> {
+        let Some(answers) = &mut self.answers else { return Ok(()) };
+        match answers.record(&self.running_buf, answer) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                log::warn!(
+                    target: crate::error_handling::INTERACTION_ERR_TARGET,
+                    "{} cannot be recorded as an answer",
+                    self.running_buf.display()
+                );
+                Ok(())
+            }
+            Err(err) => {
+                log::error!(
+                    target: crate::error_handling::INTERACTION_ERR_TARGET,
+                    "cannot record the answer: {err}"
+                );
+                Err(AlreadyReportedError)
+            }
+        }
+    }
+
+    fn dismiss_set(
+        &mut self,
+        dismissal: SetDismissal,
+        id: u128,
+        paths: &[PathBuf],
+    ) -> Result<(), AlreadyReportedError> {
+        let Some(seen_db) = &mut self.seen_db else { return Ok(()) };
+        let recorded = match dismissal {
+            SetDismissal::Files => seen_db.dismiss(paths),
+            SetDismissal::Set => seen_db.skip(id),
+        };
+        recorded.map_err(|err| {
+            log::error!(
+                target: crate::error_handling::INTERACTION_ERR_TARGET,
+                "cannot record the dismissed set: {err}"
+            );
+            AlreadyReportedError
+        })
+    }
+}
+
+/// act on the file once the buffered log is written, so that the records leading up to a change of the file are
+/// not lost if the run ends during it
+fn act_on(
+    action: &mut (impl FileConsumeAction + ?Sized),
+    path: &Path,
+    original: Option<&Path>,
+) -> FileConsumeResult {
+    log::logger().flush();
+    action.consume(path, original)
+}
+
+/// Ctrl+C does not end the read of an answer, so an answer read after it is not acted on
+fn answer_not_interrupted(interrupted_before: bool) -> Result<(), AlreadyReportedError> {
+    if !interrupted_before && crate::util::is_interrupted() {
+        log::warn!(
+            target: crate::error_handling::INTERACTION_ERR_TARGET,
+            "interrupted; the answer is not acted on"
+        );
+        return Err(AlreadyReportedError);
+    }
+    Ok(())
+}
+
+impl<R: ChoiceInputReader, W: std::io::Write> InteractiveEachChoice<R, W> {
+    /// ask whether to execute the action on the current file, the dismissal if the user dismissed the set
+    fn ask(&mut self) -> Result<Result<bool, SetDismissal>, AlreadyReportedError> {
+        let default_hint = match self.default_answer {
+            Some(true) => " [Y/n]",
+            Some(false) => " [y/N]",
+            None => "",
+        };
+        // the records about the file come before the question
+        log::logger().flush();
+        writeln!(
+            self.write,
+            "{} {}?{default_hint}",
+            self.action.short_name().as_ref(),
+            self.running_buf.display()
+        )
+        .map_err(out_err_map!())?;
+        let interrupted_before = crate::util::is_interrupted();
+        let answer = loop {
+            self.write.flush().map_err(out_err_map!())?;
+            self.choice_buf.clear();
+            self.read
+                .read_remaining(&mut self.choice_buf)
+                .map_err(in_err_map!())?;
+            answer_not_interrupted(interrupted_before)?;
+            if self.choice_buf.is_empty() {
+                log::error!(
+                    target: crate::error_handling::INTERACTION_ERR_TARGET,
+                    "cannot accept input in interactive mode since the input is closed"
+                );
+                return Err(AlreadyReportedError);
+            }
+            let choice = self.choice_buf.trim();
+
+            if let (true, Some(default_answer)) = (choice.is_empty(), self.default_answer) {
+                break default_answer;
+            } else if choice.eq_ignore_ascii_case("y") | choice.eq_ignore_ascii_case("yes") {
+                break true;
+            } else if choice.eq_ignore_ascii_case("n") | choice.eq_ignore_ascii_case("no") {
+                break false;
+            } else if self.seen_db.is_some()
+                && (choice.eq_ignore_ascii_case("i") | choice.eq_ignore_ascii_case("ignore"))
+            {
+                return Ok(Err(SetDismissal::Files));
+            } else if self.seen_db.is_some() && choice.eq_ignore_ascii_case("x") {
+                return Ok(Err(SetDismissal::Set));
+            } else if self.seen_db.is_some() {
+                writeln!(
+                    self.write,
+                    "unrecognised answer; only y(es), n(o), i(gnore these files in future runs) and x(never ask about the set again) are accepted"
+                )
+                .map_err(out_err_map!())?;
+            } else {
+                writeln!(
+                    self.write,
+                    "unrecognised answer; only y(es) and n(o) are accepted"
+                )
+                .map_err(out_err_map!())?;
+            }
+        };
+        self.record_answer(answer)?;
+        Ok(Ok(answer))
+    }
 }
 
 impl<R: ChoiceInputReader, W: std::io::Write> FileSetConsumer for InteractiveEachChoice<R, W> {
-    fn consume_set(&mut self, mut set: Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
-        let original_buf = loop {
-            let Some(file) = set.get(0) else { return Ok(()) };
+    fn is_destructive(&self) -> bool {
+        true
+    }
+
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let seen_paths = match &self.seen_db {
+            Some(seen_db) => {
+                if seen_db.suppresses(&set) {
+                    return Ok(());
+                }
+                set.paths()
+            }
+            None => Vec::new(),
+        };
+        let id = set.id;
+        let mut set = set.files;
+        loop {
+            let Some(file) = set.first() else { return Ok(()) };
             file.file_path.write_full_to_buf(&mut self.original_buf);
-            if self.original_buf.exists() {
-                break &self.original_buf;
-            } else {
+            if !self.original_buf.exists() {
                 report_file_missing!(&self.original_buf);
-                set.remove(0);
+            } else if !self.action.requires_original() || original_is_usable(&self.original_buf) {
+                break;
             }
-        };
+            set.remove(0);
+        }
+        if let Some(algorithm) = self.verify_original {
+            if !original_unchanged(&self.original_buf, set[0].content_hash, algorithm) {
+                return Ok(());
+            }
+        }
         for file in &set[1..] {
             file.file_path.write_full_to_buf(&mut self.running_buf);
             if !self.running_buf.exists() {
                 report_file_missing!(&self.running_buf);
                 continue;
             }
-            writeln!(
-                self.write,
-                "{} {}?",
-                self.action.short_name().as_ref(),
-                self.running_buf.display()
-            )
-            .map_err(out_err_map!())?;
-            let execute_action = loop {
-                self.write.flush().map_err(out_err_map!())?;
-                self.choice_buf.clear();
-                self.read
-                    .read_remaining(&mut self.choice_buf)
-                    .map_err(in_err_map!())?;
-                if self.choice_buf.is_empty() {
-                    log::error!(
-                        target: crate::error_handling::INTERACTION_ERR_TARGET,
-                        "cannot accept input in interactive mode since the input is closed"
-                    );
-                    return Err(AlreadyReportedError);
-                }
-                let choice = self.choice_buf.trim();
-
-                if choice.eq_ignore_ascii_case("y") | choice.eq_ignore_ascii_case("yes") {
-                    break true;
-                } else if choice.eq_ignore_ascii_case("n") | choice.eq_ignore_ascii_case("no") {
-                    break false;
-                } else {
-                    writeln!(
-                        self.write,
-                        "unrecognised answer; only y(es) and n(o) are accepted"
-                    )
-                    .map_err(out_err_map!())?;
-                }
+            let execute_action = match self.replay_answer() {
+                Some(answer) => answer,
+                None => match self.ask()? {
+                    Ok(answer) => answer,
+                    // never ask about this set again
+                    Err(dismissal) => return self.dismiss_set(dismissal, id, &seen_paths),
+                },
             };
 
             if execute_action {
-                if let Err(Recoverable::Fatal(AlreadyReportedError {})) =
-                    self.action.consume(&self.running_buf, Some(original_buf))
-                {
+                if let Err(Recoverable::Fatal(AlreadyReportedError {})) = act_on(
+                    self.action.as_mut(),
+                    &self.running_buf,
+                    Some(&self.original_buf),
+                ) {
                     log::error!(
                         target: crate::error_handling::FILE_SET_ERR_TARGET,
                         "aborting '{}' due to previous error",
                         self.action.short_name()
                     );
                     return Err(AlreadyReportedError);
-                };
+                }
             }
         }
         Ok(())
     }
 }
 
-impl<W: std::io::Write> MachineReadableEach<W> {
-    pub fn new(writer: W) -> Self {
+impl InteractiveSetChoice<std::io::Stdin, ConsoleOutput> {
+    pub fn for_console(action: Box<dyn FileConsumeAction>) -> Self {
+        Self::new(std::io::stdin(), console_output(), action)
+    }
+}
+
+impl<R, W> InteractiveSetChoice<R, W> {
+    pub fn new(read: R, write: W, action: Box<dyn FileConsumeAction>) -> Self {
         Self {
-            written_before: false,
-            writer,
-            path_bufs: (PathBuf::new(), PathBuf::new()),
+            running_buf: PathBuf::new(),
+            original_buf: PathBuf::new(),
+            choice_buf: String::new(),
+            action,
+            verify_original: None,
+            read,
+            write,
         }
     }
+
+    pub fn with_verify_original(mut self, verify_original: Option<HashAlgorithm>) -> Self {
+        self.verify_original = verify_original;
+        self
+    }
 }
 
-impl MachineReadableEach<std::io::Stdout> {
-    pub fn for_console() -> Self {
-        Self::new(std::io::stdout())
+/// the files to keep by the numbers of the answer like `1,3` or `1 3`, None if a number is not a file
+fn parse_kept_files(choice: &str, files: usize) -> Option<Vec<bool>> {
+    let mut keep = vec![false; files];
+    for number in choice
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|number| !number.is_empty())
+    {
+        let number = number.parse::<usize>().ok()?;
+        *keep.get_mut(number.checked_sub(1)?)? = true;
     }
+    keep.contains(&true).then_some(keep)
 }
 
-impl<W: std::io::Write> FileSetConsumer for MachineReadableEach<W> {
-    fn consume_set(&mut self, mut set: Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
-        let (orig_path, tmp_path) = &mut self.path_bufs;
-        let Some(orig_path) = find_nocomma_original(&mut set, orig_path) else { return Ok(()) };
-        for file in &set[1..] {
-            file.file_path.write_full_to_buf(tmp_path);
+impl<R: ChoiceInputReader, W: std::io::Write> InteractiveSetChoice<R, W> {
+    /// ask which of the listed files to keep
+    fn ask(&mut self, files: usize) -> Result<Vec<bool>, AlreadyReportedError> {
+        writeln!(
+            self.write,
+            "which files to keep, the others are {}? [like 1,3, all or none]",
+            self.action.short_name().as_ref()
+        )
+        .map_err(out_err_map!())?;
+        let interrupted_before = crate::util::is_interrupted();
+        loop {
+            self.write.flush().map_err(out_err_map!())?;
+            self.choice_buf.clear();
+            self.read
+                .read_remaining(&mut self.choice_buf)
+                .map_err(in_err_map!())?;
+            answer_not_interrupted(interrupted_before)?;
+            if self.choice_buf.is_empty() {
+                log::error!(
+                    target: crate::error_handling::INTERACTION_ERR_TARGET,
+                    "cannot accept input in interactive mode since the input is closed"
+                );
+                return Err(AlreadyReportedError);
+            }
+            let choice = self.choice_buf.trim();
 
-            let tmp_path = handle_file_op!(std::fs::canonicalize(&*tmp_path), tmp_path, continue);
+            if choice.eq_ignore_ascii_case("a") | choice.eq_ignore_ascii_case("all") {
+                return Ok(vec![true; files]);
+            } else if choice.eq_ignore_ascii_case("none") && self.action.requires_original() {
+                writeln!(
+                    self.write,
+                    "'{}' needs an original; at least one file has to be kept",
+                    self.action.short_name().as_ref()
+                )
+                .map_err(out_err_map!())?;
+            } else if choice.eq_ignore_ascii_case("none") {
+                return Ok(vec![false; files]);
+            } else if let Some(keep) = parse_kept_files(choice, files) {
+                return Ok(keep);
+            } else {
+                writeln!(
+                    self.write,
+                    "unrecognised answer; only the numbers of the files to keep(from 1 to {files}), all and none are accepted"
+                )
+                .map_err(out_err_map!())?;
+            }
+        }
+    }
+}
+
+impl<R: ChoiceInputReader, W: std::io::Write> FileSetConsumer for InteractiveSetChoice<R, W> {
+    fn is_destructive(&self) -> bool {
+        true
+    }
+
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let mut set = set.files;
+        set.retain(|file| {
+            file.file_path.write_full_to_buf(&mut self.running_buf);
+            let exists = self.running_buf.exists();
+            if !exists {
+                report_file_missing!(&self.running_buf);
+            }
+            exists
+        });
+        if set.len() < 2 {
+            return Ok(());
+        }
+        // the records about the set come before the question
+        log::logger().flush();
+        for (i, file) in set.iter().enumerate() {
+            file.file_path.write_full_to_buf(&mut self.running_buf);
+            writeln!(self.write, "[{}] {}", i + 1, self.running_buf.display())
+                .map_err(out_err_map!())?;
+        }
+        let keep = self.ask(set.len())?;
+        let original = keep.iter().position(|keep| *keep);
+        if let Some(original) = original.map(|i| &set[i]) {
+            original.file_path.write_full_to_buf(&mut self.original_buf);
+            if self.action.requires_original() && !original_is_usable(&self.original_buf) {
+                return Ok(());
+            }
+            if let Some(algorithm) = self.verify_original {
+                if !original_unchanged(&self.original_buf, original.content_hash, algorithm) {
+                    return Ok(());
+                }
+            }
+        }
+        let original = original.map(|_| self.original_buf.as_path());
+        for (file, _) in set.iter().zip(keep).filter(|(_, keep)| !keep) {
+            file.file_path.write_full_to_buf(&mut self.running_buf);
+            if let Err(Recoverable::Fatal(AlreadyReportedError {})) =
+                act_on(self.action.as_mut(), &self.running_buf, original)
+            {
+                log::error!(
+                    target: crate::error_handling::FILE_SET_ERR_TARGET,
+                    "aborting '{}' due to previous error",
+                    self.action.short_name()
+                );
+                return Err(AlreadyReportedError);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// whether the original still has the content it was hashed with, warns if not
+fn original_unchanged(original: &Path, content_hash: u128, algorithm: HashAlgorithm) -> bool {
+    match content_matches(original, content_hash, algorithm) {
+        Ok(true) => true,
+        Ok(false) => {
+            log::warn!(
+                target: crate::error_handling::FILE_CHANGED_TARGET,
+                "original {} changed since it was hashed; skipping its set",
+                original.display()
+            );
+            false
+        }
+        Err(AlreadyReportedError) => false,
+    }
+}
+
+impl SuppressSeen {
+    pub fn new(seen_db: SeenDb, inner: Box<dyn FileSetConsumer>) -> Self {
+        Self { seen_db, inner }
+    }
+}
+
+impl FileSetConsumer for SuppressSeen {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        if self.seen_db.suppresses(&set) {
+            return Ok(());
+        }
+        self.inner.consume_set(set)
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.inner.is_destructive()
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.inner.finish()
+    }
+}
+
+impl SkipHardLinked {
+    pub fn new(inner: Box<dyn FileSetConsumer>) -> Self {
+        Self { inner }
+    }
+}
+
+impl FileSetConsumer for SkipHardLinked {
+    fn consume_set(&mut self, mut set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let hard_links = find_hard_links(&set.files);
+        set.files = std::mem::take(&mut set.files)
+            .into_iter()
+            .zip(hard_links)
+            .filter_map(|(file, hard_link)| hard_link.is_none().then_some(file))
+            .collect();
+        if set.files.len() < 2 {
+            return Ok(());
+        }
+        self.inner.consume_set(set)
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.inner.is_destructive()
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.inner.finish()
+    }
+}
+
+impl SkipSmallSets {
+    pub fn new(min_savings: u64, inner: Box<dyn FileSetConsumer>) -> Self {
+        Self {
+            min_savings,
+            path_buf: PathBuf::new(),
+            inner,
+        }
+    }
+}
+
+impl FileSetConsumer for SkipSmallSets {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let Some(size) = set_file_size(&set, &mut self.path_buf) else { return Ok(()) };
+        let separate_copies = find_hard_links(&set.files)[1..]
+            .iter()
+            .filter(|hard_link| hard_link.is_none())
+            .count() as u64;
+        if separate_copies.saturating_mul(size) < self.min_savings {
+            return Ok(());
+        }
+        self.inner.consume_set(set)
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.inner.is_destructive()
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.inner.finish()
+    }
+}
+
+impl<W: std::io::Write> WithReportHeader<W> {
+    pub fn new(
+        header: ReportHeader,
+        record_end: u8,
+        write: W,
+        inner: Box<dyn FileSetConsumer>,
+    ) -> Self {
+        Self {
+            header: Some(header),
+            record_end,
+            write,
+            inner,
+        }
+    }
+
+    fn write_header(&mut self) -> Result<(), AlreadyReportedError> {
+        let Some(header) = self.header.take() else { return Ok(()) };
+        header
+            .write_to(&mut self.write, self.record_end)
+            .and_then(|()| self.write.flush())
+            .map_err(out_err_map!())
+    }
+}
+
+impl WithReportHeader<Box<dyn Write + Send>> {
+    pub fn for_output(
+        output: &ResultsOutput,
+        header: ReportHeader,
+        record_end: u8,
+        inner: Box<dyn FileSetConsumer>,
+    ) -> Self {
+        Self::new(header, record_end, output.writer(), inner)
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for WithReportHeader<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        self.write_header()?;
+        self.inner.consume_set(set)
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.inner.is_destructive()
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.write_header()?;
+        self.inner.finish()
+    }
+}
+
+impl MapToHost {
+    pub fn new(maps: Vec<PathMap>, inner: Box<dyn FileSetConsumer>) -> Self {
+        Self {
+            maps,
+            path_buf: PathBuf::new(),
+            inner,
+        }
+    }
+}
+
+impl FileSetConsumer for MapToHost {
+    fn consume_set(&mut self, mut set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        for file in &mut set.files {
+            file.file_path.write_full_to_buf(&mut self.path_buf);
+            // the first directory the file is below decides
+            if let Some(host) = self.maps.iter().find_map(|map| map.to_host(&self.path_buf)) {
+                file.file_path = (*LinkedPath::from_path_buf(&host)).clone();
+            }
+        }
+        self.inner.consume_set(set)
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.inner.is_destructive()
+    }
+
+    fn consumes_lone_files(&self) -> bool {
+        self.inner.consumes_lone_files()
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.inner.finish()
+    }
+}
+
+impl FilterAllowedDupes {
+    pub fn new(allowed: AllowedDupes, inner: Box<dyn FileSetConsumer>) -> Self {
+        Self { allowed, inner }
+    }
+}
+
+impl FileSetConsumer for FilterAllowedDupes {
+    fn consume_set(&mut self, mut set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let paths = set.paths();
+        let mut kept = Vec::with_capacity(paths.len());
+        let mut kept_paths: Vec<&Path> = Vec::with_capacity(paths.len());
+        for (file, path) in std::mem::take(&mut set.files).into_iter().zip(&paths) {
+            if kept_paths
+                .iter()
+                .any(|kept_path| self.allowed.is_allowed(kept_path, path))
+            {
+                continue;
+            }
+            kept_paths.push(path);
+            kept.push(file);
+        }
+        if kept.len() < 2 {
+            return Ok(());
+        }
+        set.files = kept;
+        self.inner.consume_set(set)
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.inner.is_destructive()
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.inner.finish()
+    }
+}
+
+impl<W> EstimateReport<W> {
+    /// `compared_bytes` is the amount of leading bytes the files were compared by
+    pub fn new(compared_bytes: u64, write: W) -> Self {
+        Self {
+            compared_bytes,
+            sets: 0,
+            duplicates: 0,
+            reclaimable: 0,
+            path_buf: PathBuf::new(),
+            write,
+        }
+    }
+}
+
+impl EstimateReport<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput, compared_bytes: u64) -> Self {
+        Self::new(compared_bytes, output.writer())
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for EstimateReport<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let Some(size) = set_file_size(&set, &mut self.path_buf) else { return Ok(()) };
+        let set = set.files;
+        let duplicates = set.len() as u64 - 1;
+        let separate_copies = find_hard_links(&set)[1..]
+            .iter()
+            .filter(|hard_link| hard_link.is_none())
+            .count() as u64;
+        self.sets += 1;
+        self.duplicates += duplicates;
+        self.reclaimable += separate_copies * size;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        writeln!(
+            self.write,
+            "estimate: at most {} bytes reclaimable from {} in {}(files were only compared by size and their first {} bytes)",
+            self.reclaimable,
+            count_of(self.duplicates, "duplicate"),
+            count_of(self.sets, "set"),
+            self.compared_bytes
+        )
+        .map_err(out_err_map!())
+    }
+}
+
+impl<W> ExtensionReport<W> {
+    pub fn new(write: W) -> Self {
+        Self {
+            extensions: BTreeMap::new(),
+            path_buf: PathBuf::new(),
+            size_format: SizeFormat::default(),
+            write,
+        }
+    }
+
+    pub fn with_size_format(mut self, size_format: SizeFormat) -> Self {
+        self.size_format = size_format;
+        self
+    }
+}
+
+impl ExtensionReport<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new(output.writer())
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for ExtensionReport<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let Some(size) = set_file_size(&set, &mut self.path_buf) else { return Ok(()) };
+        let set = set.files;
+        let hard_links = find_hard_links(&set);
+        for (file, hard_link) in set[1..].iter().zip(&hard_links[1..]) {
+            let extension = Path::new(file.file_path.file_name())
+                .extension()
+                .map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase());
+            let (duplicates, bytes) = self.extensions.entry(extension).or_default();
+            *duplicates += 1;
+            // removing a hard link does not free any space
+            if hard_link.is_none() {
+                *bytes += size;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        let mut extensions = self.extensions.iter().collect::<Vec<_>>();
+        // the largest waste first, stable to keep equal ones alphabetic
+        extensions.sort_by(|(_, (_, bytes1)), (_, (_, bytes2))| bytes2.cmp(bytes1));
+        writeln!(
+            self.write,
+            "{:<12} {:>10} {:>12}",
+            "extension", "duplicates", "size"
+        )
+        .map_err(out_err_map!())?;
+        for (extension, (duplicates, bytes)) in extensions {
+            let extension = if extension.is_empty() {
+                "(none)"
+            } else {
+                extension
+            };
+            writeln!(
+                self.write,
+                "{extension:<12} {duplicates:>10} {:>12}",
+                self.size_format.format(*bytes)
+            )
+            .map_err(out_err_map!())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> HardLinkReport<W> {
+    pub fn new(write: W) -> Self {
+        Self {
+            groups: 0,
+            saved: 0,
+            path_buf: PathBuf::new(),
+            size_format: SizeFormat::default(),
+            write,
+        }
+    }
+
+    pub fn with_size_format(mut self, size_format: SizeFormat) -> Self {
+        self.size_format = size_format;
+        self
+    }
+}
+
+impl HardLinkReport<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new(output.writer())
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for HardLinkReport<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let Some(size) = set_file_size(&set, &mut self.path_buf) else { return Ok(()) };
+        let set = set.files;
+        self.groups += 1;
+        self.saved += size * (set.len() as u64 - 1);
+        write!(
+            self.write,
+            "{} file linked as ",
+            self.size_format.format(size)
+        )
+        .map_err(out_err_map!())?;
+        for (i, file) in set.iter().enumerate() {
+            file.file_path.write_full_to_buf(&mut self.path_buf);
+            let separator = if i == 0 { "" } else { ", " };
+            write!(self.write, "{separator}{}", self.path_buf.display()).map_err(out_err_map!())?;
+        }
+        writeln!(self.write).map_err(out_err_map!())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        writeln!(
+            self.write,
+            "{} files with several links, saving {}",
+            self.groups,
+            self.size_format.format(self.saved)
+        )
+        .map_err(out_err_map!())?;
+        self.write.flush().map_err(out_err_map!())
+    }
+}
+
+impl<W> CopyAudit<W> {
+    pub fn new(required: usize, write: W) -> Self {
+        Self {
+            required,
+            lacking: 0,
+            errors: None,
+            path_buf: PathBuf::new(),
+            write,
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Arc<ErrorSummary>) -> Self {
+        self.errors = Some(errors);
+        self
+    }
+}
+
+impl CopyAudit<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput, required: usize) -> Self {
+        Self::new(required, output.writer())
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for CopyAudit<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let distinct_files = find_hard_links(&set.files)
+            .into_iter()
+            .filter(Option::is_none)
+            .count();
+        let copies = distinct_files.saturating_sub(1);
+        if copies >= self.required {
+            return Ok(());
+        }
+        for file in &set.files {
+            self.lacking += 1;
+            file.file_path.write_full_to_buf(&mut self.path_buf);
+            writeln!(
+                self.write,
+                "{}: {copies} of {} copies",
+                self.path_buf.display(),
+                self.required
+            )
+            .map_err(out_err_map!())?;
+        }
+        Ok(())
+    }
+
+    fn consumes_lone_files(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        writeln!(
+            self.write,
+            "{} with fewer than {} copies",
+            count_of(self.lacking, "file"),
+            self.required
+        )
+        .map_err(out_err_map!())?;
+        self.write.flush().map_err(out_err_map!())?;
+        let skipped = self.errors.as_ref().map_or(0, |errors| errors.skipped());
+        if skipped > 0 {
+            log::error!(
+                target: crate::error_handling::FILE_SET_ERR_TARGET,
+                "the audit is incomplete: skipped {}, which may lack copies as well",
+                count_of(skipped, "file")
+            );
+            return Err(AlreadyReportedError);
+        }
+        Ok(())
+    }
+}
+
+impl<W> MirrorTree<W> {
+    pub fn new(target: PathBuf, write: W) -> Self {
+        Self {
+            target,
+            files: 0,
+            contents: 0,
+            path_buf: PathBuf::new(),
+            original_buf: PathBuf::new(),
+            write,
+        }
+    }
+}
+
+impl MirrorTree<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput, target: PathBuf) -> Self {
+        Self::new(target, output.writer())
+    }
+}
+
+/// the path of the file in the mirror, its full path below the target; None if it would leave the target
+fn mirror_path(target: &Path, file: &Path) -> Option<PathBuf> {
+    let mut mirrored = target.to_path_buf();
+    for component in file.components() {
+        match component {
+            Component::Normal(name) => mirrored.push(name),
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => return None,
+        }
+    }
+    Some(mirrored)
+}
+
+impl<W: std::io::Write> FileSetConsumer for MirrorTree<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let Some(original) = set.files.first() else { return Ok(()) };
+        original.file_path.write_full_to_buf(&mut self.original_buf);
+        self.contents += 1;
+        for file in &set.files {
+            file.file_path.write_full_to_buf(&mut self.path_buf);
+            let Some(mirrored) = mirror_path(&self.target, &self.path_buf) else {
+                log::warn!(
+                    target: crate::error_handling::FILE_SET_ERR_TARGET,
+                    "{} leaves the mirror by its path; skipping it",
+                    self.path_buf.display()
+                );
+                continue;
+            };
+            if let Some(parent) = mirrored.parent() {
+                handle_file_op!(std::fs::create_dir_all(parent), parent, continue);
+            }
+            handle_file_op!(
+                std::fs::hard_link(&self.original_buf, &mirrored),
+                mirrored,
+                continue
+            );
+            self.files += 1;
+        }
+        Ok(())
+    }
+
+    fn consumes_lone_files(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        writeln!(
+            self.write,
+            "mirrored {} files with {} distinct contents into {}",
+            self.files,
+            self.contents,
+            self.target.display()
+        )
+        .map_err(out_err_map!())?;
+        self.write.flush().map_err(out_err_map!())
+    }
+}
+
+impl<W: std::io::Write> PlanWriter<W> {
+    pub fn new(action_name: &'static str, mut write: W) -> std::io::Result<Self> {
+        write.write_all(PLAN_HEADER.as_bytes())?;
+        Ok(Self {
+            action_name,
+            path_buf: PathBuf::new(),
+            write,
+        })
+    }
+
+    /// the canonical path if it can be written into a plan
+    fn plan_path(&mut self, file: &HashedFile) -> Option<String> {
+        file.file_path.write_full_to_buf(&mut self.path_buf);
+        let path = handle_file_op!(self.path_buf.canonicalize(), self.path_buf, return None);
+        match path.into_os_string().into_string() {
+            Ok(path) if plan_can_represent(&path) => Some(path),
+            Ok(path) => {
+                warn_path_not_plannable!(Path::new(&path));
+                None
+            }
+            Err(path) => {
+                warn_path_not_plannable!(Path::new(&path));
+                None
+            }
+        }
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for PlanWriter<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let mut set = set.files;
+        let (original, hash) = loop {
+            let Some(first) = set.first() else { return Ok(()); };
+            let Some(original) = self.plan_path(first) else {
+                set.remove(0);
+                continue;
+            };
+            if let Ok(hash) = hash_content(Path::new(&original), PLAN_HASH_ALGORITHM) {
+                break (original, hash);
+            }
+            set.remove(0);
+        };
+        for file in &set[1..] {
+            let Some(file) = self.plan_path(file) else { continue; };
+            let line = PlanLine {
+                action: self.action_name,
+                hash,
+                file: &file,
+                original: &original,
+            };
+            line.write_to(&mut self.write).map_err(out_err_map!())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        self.write.flush().map_err(out_err_map!())
+    }
+}
+
+impl<W> TreeReport<W> {
+    pub fn new(write: W) -> Self {
+        Self {
+            sets: BTreeMap::new(),
+            size_format: SizeFormat::default(),
+            write,
+        }
+    }
+
+    pub fn with_size_format(mut self, size_format: SizeFormat) -> Self {
+        self.size_format = size_format;
+        self
+    }
+}
+
+impl TreeReport<Box<dyn Write + Send>> {
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new(output.writer())
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for TreeReport<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let mut path_buf = PathBuf::new();
+        let Some(size) = set_file_size(&set, &mut path_buf) else { return Ok(()) };
+        let paths = set.paths();
+        let mut ancestor = paths[0]
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        for path in &paths[1..] {
+            while !path.starts_with(&ancestor) && ancestor.pop() {}
+        }
+        self.sets.entry(ancestor).or_default().push((size, paths));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), AlreadyReportedError> {
+        // the ancestors are sorted, so descendants directly follow their ancestor
+        let mut open: Vec<&Path> = Vec::new();
+        for (ancestor, sets) in &self.sets {
+            while open
+                .last()
+                .is_some_and(|parent| !ancestor.starts_with(parent))
+            {
+                open.pop();
+            }
+            let indent = open.len() * 2;
+            let shown = open
+                .last()
+                .and_then(|parent| ancestor.strip_prefix(parent).ok())
+                .unwrap_or(ancestor);
+            let shown = if shown.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                shown
+            };
+            writeln!(self.write, "{:indent$}{}", "", shown.display()).map_err(out_err_map!())?;
+            for (size, paths) in sets {
+                writeln!(
+                    self.write,
+                    "{:indent$}  {} copies of {}",
+                    "",
+                    paths.len(),
+                    self.size_format.format(*size)
+                )
+                .map_err(out_err_map!())?;
+                for (i, path) in paths.iter().enumerate() {
+                    let relative = path.strip_prefix(ancestor).unwrap_or(path);
+                    let marker = if i == 0 { " (original)" } else { "" };
+                    writeln!(
+                        self.write,
+                        "{:indent$}    {}{marker}",
+                        "",
+                        relative.display()
+                    )
+                    .map_err(out_err_map!())?;
+                }
+            }
+            open.push(ancestor);
+        }
+        Ok(())
+    }
+}
+
+impl SyncWriterSink {
+    pub fn new<W: Write + Send + 'static>(mut write: W) -> std::io::Result<Self> {
+        let (queue, chunks) = flume::unbounded::<Vec<u8>>();
+        let writer = std::thread::Builder::new()
+            .name(String::from("output_writer"))
+            .spawn(move || {
+                let written = chunks
+                    .iter()
+                    .try_for_each(|chunk| write.write_all(&chunk))
+                    .and_then(|()| write.flush());
+                // the handles notice the failure once their next chunk cannot be queued
+                if let Err(err) = written {
+                    log::error!(
+                        target: crate::error_handling::INTERACTION_ERR_TARGET,
+                        "cannot write out: {err}"
+                    );
+                }
+            })?;
+        Ok(Self {
+            buf: Vec::new(),
+            queue: Some(queue),
+            writer: Some(Arc::new(writer)),
+        })
+    }
+
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new(output.writer()).expect("failed to spawn the output writer thread")
+    }
+}
+
+impl Clone for SyncWriterSink {
+    fn clone(&self) -> Self {
+        Self {
+            buf: Vec::new(),
+            queue: self.queue.clone(),
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl Write for SyncWriterSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::take(&mut self.buf);
+        self.queue
+            .as_ref()
+            .and_then(|queue| queue.send(chunk).ok())
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Drop for SyncWriterSink {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        // the writer stops once all queues are dropped
+        drop(self.queue.take());
+        if let Some(writer) = self.writer.take().and_then(Arc::into_inner) {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl<W: std::io::Write> RmBatch<W> {
+    /// a set is only split if it alone has more than `batch_bytes` bytes of paths
+    pub fn new(batch_bytes: usize, writer: W) -> Self {
+        Self {
+            batch_bytes,
+            batches: 0,
+            written: 0,
+            path_buf: PathBuf::new(),
+            writer,
+        }
+    }
+
+    fn write_record(&mut self, prefix: &[u8], path: &Path) -> Result<(), AlreadyReportedError> {
+        self.writer.write_all(prefix).map_err(out_err_map!())?;
+        self.writer
+            .write_all(&path_bytes(path))
+            .map_err(out_err_map!())?;
+        self.writer.write_all(b"\0").map_err(out_err_map!())
+    }
+
+    fn start_batch(&mut self) -> Result<(), AlreadyReportedError> {
+        self.batches += 1;
+        self.written = 0;
+        write!(self.writer, "# batch {}\0", self.batches).map_err(out_err_map!())
+    }
+}
+
+impl RmBatch<SyncWriterSink> {
+    pub fn for_output(output: &ResultsOutput, batch_bytes: usize) -> Self {
+        Self::new(batch_bytes, SyncWriterSink::for_output(output))
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for RmBatch<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let set = set.files;
+        let original = set[0].file_path.to_push_buf();
+        // without the original, the duplicates must not be deleted
+        let canonical_original = handle_file_op!(original.canonicalize(), original, return Ok(()));
+        let mut duplicates = Vec::with_capacity(set.len() - 1);
+        for file in &set[1..] {
+            file.file_path.write_full_to_buf(&mut self.path_buf);
+            let canonical = handle_file_op!(self.path_buf.canonicalize(), self.path_buf, continue);
+            // a symlink to the original would remove the original
+            if canonical == canonical_original {
+                log::warn!(
+                    target: crate::error_handling::FILE_SET_ERR_TARGET,
+                    "{} leads to the original {}; it is left out of the batch",
+                    self.path_buf.display(),
+                    original.display()
+                );
+                continue;
+            }
+            // the path as discovered, rm removes a symlink itself rather than what it leads to
+            duplicates.push(self.path_buf.clone());
+        }
+        let set_bytes = duplicates
+            .iter()
+            .map(|path| path.as_os_str().len() + 1)
+            .sum::<usize>();
+        if self.batches == 0 || (self.written > 0 && self.written + set_bytes > self.batch_bytes) {
+            self.start_batch()?;
+        }
+        self.write_record(b"# keep ", &original)?;
+        for duplicate in &duplicates {
+            if self.written > 0 && self.written + duplicate.as_os_str().len() + 1 > self.batch_bytes
+            {
+                self.start_batch()?;
+                self.write_record(b"# keep ", &original)?;
+            }
+            self.write_record(b"", duplicate)?;
+            self.written += duplicate.as_os_str().len() + 1;
+        }
+        // hand out the set in one piece
+        self.writer.flush().map_err(out_err_map!())
+    }
+}
+
+impl<W: std::io::Write> MachineReadableEach<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            written_before: false,
+            writer,
+            path_bufs: (PathBuf::new(), PathBuf::new()),
+            set_ids: false,
+            device_ids: false,
+            file_details: None,
+        }
+    }
+
+    /// start every record with the id of its set
+    pub fn with_set_ids(mut self) -> Self {
+        self.set_ids = true;
+        self
+    }
+
+    /// follow every path by the id of its device
+    pub fn with_device_ids(mut self) -> Self {
+        self.device_ids = true;
+        self
+    }
+
+    /// follow every path by the size of its file and its modification time, see [`file_detail_fields`]
+    pub fn with_file_details(mut self, size_format: SizeFormat) -> Self {
+        self.file_details = Some(size_format);
+        self
+    }
+}
+
+impl MachineReadableEach<SyncWriterSink> {
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new(SyncWriterSink::for_output(output))
+    }
+}
+
+impl<W: std::io::Write> FileSetConsumer for MachineReadableEach<W> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let set_id = set.id;
+        let mut set = set.files;
+        let (orig_path, tmp_path) = &mut self.path_bufs;
+        let Some(orig_path) = find_nocomma_original(&mut set, orig_path) else { return Ok(()) };
+        let orig_details = self
+            .file_details
+            .map(|size_format| file_detail_fields(&set[0], &orig_path, size_format));
+        for file in &set[1..] {
+            file.file_path.write_full_to_buf(tmp_path);
+
+            let tmp_path = handle_file_op!(std::fs::canonicalize(&*tmp_path), tmp_path, continue);
             if path_contains_comma(&tmp_path) {
                 warn_path_contains_comma!(&tmp_path);
                 continue;
@@ -272,17 +1875,42 @@ impl<W: std::io::Write> FileSetConsumer for MachineReadableEach<W> {
             if self.written_before {
                 writeln!(self.writer).map_err(out_err_map!())?;
             }
-            write!(
-                self.writer,
-                "{},{}",
-                orig_path.display(),
-                tmp_path.display()
-            )
+            if self.set_ids {
+                write!(self.writer, "{set_id:032x},").map_err(out_err_map!())?;
+            }
+            if self.device_ids {
+                write!(
+                    self.writer,
+                    "{},{}",
+                    orig_path.display(),
+                    device_field(&orig_path)
+                )
+            } else {
+                write!(self.writer, "{}", orig_path.display())
+            }
             .map_err(out_err_map!())?;
+            if let Some(orig_details) = &orig_details {
+                write!(self.writer, ",{orig_details}").map_err(out_err_map!())?;
+            }
+            if self.device_ids {
+                write!(
+                    self.writer,
+                    ",{},{}",
+                    tmp_path.display(),
+                    device_field(&tmp_path)
+                )
+            } else {
+                write!(self.writer, ",{}", tmp_path.display())
+            }
+            .map_err(out_err_map!())?;
+            if let Some(size_format) = self.file_details {
+                let details = file_detail_fields(file, &tmp_path, size_format);
+                write!(self.writer, ",{details}").map_err(out_err_map!())?;
+            }
             self.written_before = true;
         }
-
-        Ok(())
+        // hand out the set in one piece
+        self.writer.flush().map_err(out_err_map!())
     }
 }
 
@@ -292,18 +1920,41 @@ impl<W: std::io::Write> MachineReadableSet<W> {
             written_before: false,
             writer,
             path_bufs: (PathBuf::new(), PathBuf::new()),
+            set_ids: false,
+            device_ids: false,
+            file_details: None,
         }
     }
+
+    /// start every record with the id of its set
+    pub fn with_set_ids(mut self) -> Self {
+        self.set_ids = true;
+        self
+    }
+
+    /// follow every path by the id of its device
+    pub fn with_device_ids(mut self) -> Self {
+        self.device_ids = true;
+        self
+    }
+
+    /// follow every path by the size of its file and its modification time, see [`file_detail_fields`]
+    pub fn with_file_details(mut self, size_format: SizeFormat) -> Self {
+        self.file_details = Some(size_format);
+        self
+    }
 }
 
-impl MachineReadableSet<std::io::Stdout> {
-    pub fn for_console() -> Self {
-        Self::new(std::io::stdout())
+impl MachineReadableSet<SyncWriterSink> {
+    pub fn for_output(output: &ResultsOutput) -> Self {
+        Self::new(SyncWriterSink::for_output(output))
     }
 }
 
 impl<W: std::io::Write> FileSetConsumer for MachineReadableSet<W> {
-    fn consume_set(&mut self, mut set: Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+    fn consume_set(&mut self, set: DuplicateSet) -> Result<(), AlreadyReportedError> {
+        let set_id = set.id;
+        let mut set = set.files;
         let (orig_path, tmp_path) = &mut self.path_bufs;
         let mut first = true;
         let Some(orig_path) = find_nocomma_original(&mut set, orig_path) else { return Ok(()) };
@@ -317,25 +1968,93 @@ impl<W: std::io::Write> FileSetConsumer for MachineReadableSet<W> {
                 warn_path_contains_comma!(&tmp_path);
                 continue;
             }
-            let empty_path = PathBuf::new();
-            let prev_path = if first { &orig_path } else { &empty_path };
-            write!(
-                self.writer,
-                "{},{}",
-                prev_path.display(),
-                tmp_path.display()
-            )
-            .map_err(out_err_map!())?;
+            if self.set_ids && first {
+                write!(self.writer, "{set_id:032x},").map_err(out_err_map!())?;
+            }
+            // the set is a single record starting with its original
+            if first {
+                write!(self.writer, "{}", orig_path.display()).map_err(out_err_map!())?;
+                if self.device_ids {
+                    write!(self.writer, ",{}", device_field(&orig_path)).map_err(out_err_map!())?;
+                }
+                if let Some(size_format) = self.file_details {
+                    let details = file_detail_fields(&set[0], &orig_path, size_format);
+                    write!(self.writer, ",{details}").map_err(out_err_map!())?;
+                }
+            }
+            write!(self.writer, ",{}", tmp_path.display()).map_err(out_err_map!())?;
+            if self.device_ids {
+                write!(self.writer, ",{}", device_field(&tmp_path)).map_err(out_err_map!())?;
+            }
+            if let Some(size_format) = self.file_details {
+                let details = file_detail_fields(file, &tmp_path, size_format);
+                write!(self.writer, ",{details}").map_err(out_err_map!())?;
+            }
             first = false;
             self.written_before = true;
         }
-        Ok(())
+        // hand out the set in one piece
+        self.writer.flush().map_err(out_err_map!())
+    }
+}
+
+/// the id of the device the file lies on as the field following its path, empty if it cannot be read
+fn device_field(path: &Path) -> String {
+    let device = handle_metadata_op!(crate::os::file_device(path), path, return String::new());
+    device.to_string()
+}
+
+/// the size of the file in bytes and its modification time in RFC 3339 UTC as the fields following its path,
+/// as recorded while hashing; `SizeFormat::Both` adds the size with binary prefixes after the bytes. The fields
+/// are left empty if they cannot be read
+fn file_detail_fields(file: &HashedFile, path: &Path, size_format: SizeFormat) -> String {
+    let empty_fields = String::from(if size_format == SizeFormat::Both {
+        ",,"
+    } else {
+        ","
+    });
+    let (size, modified) = if let Some(size) = file.size() {
+        (size, file.modified())
+    } else {
+        let metadata = handle_metadata_op!(path.metadata(), path, return empty_fields);
+        (metadata.len(), metadata.modified().ok())
+    };
+    let modified = modified.map(format_timestamp).unwrap_or_default();
+    if size_format == SizeFormat::Both {
+        format!("{size},{},{modified}", format_size(size))
+    } else {
+        format!("{size},{modified}")
     }
 }
 
+/// the size of the files of the set as recorded while hashing, read from the first file if it was not recorded;
+/// none if it cannot be read, which is reported
+pub(crate) fn set_file_size(set: &DuplicateSet, path_buf: &mut PathBuf) -> Option<u64> {
+    if let Some(size) = set.file_size() {
+        return Some(size);
+    }
+    set.files[0].file_path.write_full_to_buf(path_buf);
+    Some(handle_metadata_op!(path_buf.metadata(), path_buf, return None).len())
+}
+
+/// for each member of the set, the index of the first member which is the same file, if it is a hard link to an earlier member
+fn find_hard_links(set: &[HashedFile]) -> Vec<Option<usize>> {
+    let mut first_links = HashMap::new();
+    set.iter()
+        .enumerate()
+        .map(|(i, file)| match first_links.entry(file.identity?) {
+            std::collections::hash_map::Entry::Occupied(first) => Some(*first.get()),
+            std::collections::hash_map::Entry::Vacant(first) => {
+                first.insert(i);
+                None
+            }
+        })
+        .collect()
+}
+
 fn find_nocomma_original(set: &mut Vec<HashedFile>, orig_path: &mut PathBuf) -> Option<PathBuf> {
     let buf = loop {
-        let Some(first) = set.get(0) else { return None };
+        let first = set.first()?;
         first.file_path.write_full_to_buf(orig_path);
         let orig_path = handle_file_op!(orig_path.canonicalize(), orig_path, {
             set.remove(0);
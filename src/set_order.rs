@@ -1,10 +1,21 @@
 use crate::error_handling::AlreadyReportedError;
-use crate::{handle_file_op, HashedFile};
-use std::path::PathBuf;
-use std::time::SystemTime;
+use crate::{handle_metadata_op, HashedFile};
+use std::cmp::Reverse;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub trait SetOrder: DynCloneSetOrder {
+    /// sort the files, the first is the original of the set
+    /// # Errors
+    /// if the files cannot be ordered, the reason is reported and the files keep the order of the
+    /// earlier orders
     fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError>;
+    /// whether the order looks at the files themselves instead of only their paths, so that it cannot order
+    /// the sets of a simulation
+    fn needs_files(&self) -> bool {
+        false
+    }
 }
 
 crate::dyn_clone_impl!(DynCloneSetOrder, crate::set_order::SetOrder);
@@ -38,8 +49,49 @@ pub struct ModTimeSetOrder(MetadataSetOrder<SystemTime>);
 /// sort set by file creation timestamp
 #[derive(Default, Clone)]
 pub struct CreateTimeSetOrder(MetadataSetOrder<SystemTime>);
+/// sort set by file size, smallest first
+#[derive(Default, Clone)]
+pub struct SizeSetOrder(MetadataSetOrder<u64>);
 #[derive(Default, Clone)]
 pub struct SymlinkSetOrder(MetadataSetOrder<bool>);
+/// sort set by the weighted sum of the modification and access timestamps, the most recently modified and
+/// used file first
+#[derive(Clone)]
+pub struct StalenessSetOrder {
+    order: MetadataSetOrder<Reverse<u128>>,
+    weights: StalenessWeights,
+}
+/// how much the modification and the access time count in the staleness of a file
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StalenessWeights {
+    pub modified: u32,
+    pub accessed: u32,
+}
+/// sort files whose names look like copies(`a (1).txt`, `a - Copy.txt`, `a_copy.txt`, `a.txt~`) after the others
+#[derive(Default, Clone)]
+pub struct CleanNameSetOrder;
+/// sort set by the device, the inode and the canonical path of the files, so that the same original is
+/// chosen every run among files the other orders consider equal
+#[derive(Default, Clone)]
+pub struct StableSetOrder;
+/// sort the files below the directory before the others
+#[derive(Clone)]
+pub struct PathPrefixSetOrder {
+    prefix: PathBuf,
+    /// matched against the canonical paths, so that the relative paths given on stdin match an absolute
+    /// prefix and the other way round
+    canonical_prefix: Option<PathBuf>,
+}
+/// sort set by the length of the full path of the files, shortest first
+#[derive(Default, Clone)]
+pub struct PathLengthSetOrder {
+    reverse: bool,
+}
+/// sort set by the number of directories above the files, shallowest first
+#[derive(Default, Clone)]
+pub struct PathDepthSetOrder {
+    reverse: bool,
+}
 /// sort set by file name
 #[derive(Default, Clone)]
 pub struct NameAlphabeticSetOrder {
@@ -71,7 +123,7 @@ impl<T> Default for MetadataSetOrder<T> {
 }
 
 impl<F: Ord> MetadataSetOrder<F> {
-    fn order(
+    pub(crate) fn order(
         &mut self,
         files: &mut Vec<HashedFile>,
         key_extract: impl Fn(std::fs::Metadata) -> Result<F, AlreadyReportedError>,
@@ -83,7 +135,7 @@ impl<F: Ord> MetadataSetOrder<F> {
 
             // remove data from set if access error
             let metadata =
-                handle_file_op!(self.path_buf.symlink_metadata(), self.path_buf, continue);
+                handle_metadata_op!(self.path_buf.symlink_metadata(), self.path_buf, continue);
 
             let key = key_extract(metadata)?;
             self.file_buf.push((key, file_data));
@@ -116,6 +168,10 @@ impl SetOrder for ModTimeSetOrder {
             })
         })
     }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
 }
 impl_new_rev!(CreateTimeSetOrder, this, this.0);
 
@@ -131,12 +187,201 @@ impl SetOrder for CreateTimeSetOrder {
             })
         })
     }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
+}
+
+impl_new_rev!(SizeSetOrder, this, this.0);
+
+impl SetOrder for SizeSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        self.0.order(files, |md| Ok(md.len()))
+    }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
+}
+
+impl Default for StalenessWeights {
+    fn default() -> Self {
+        Self {
+            modified: 1,
+            accessed: 1,
+        }
+    }
+}
+
+impl StalenessSetOrder {
+    pub fn new(reverse: bool, weights: StalenessWeights) -> Self {
+        let order = MetadataSetOrder {
+            reverse,
+            ..MetadataSetOrder::default()
+        };
+        Self { order, weights }
+    }
+}
+
+impl SetOrder for StalenessSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        let weights = self.weights;
+        self.order.order(files, |md| {
+            let time_err = |err: std::io::Error| {
+                log::error!(
+                    target: crate::error_handling::CONFIG_ERR_TARGET,
+                    "cannot access modification or access time on current platform: {err}"
+                );
+                AlreadyReportedError
+            };
+            // the times before the epoch count as the epoch
+            let secs = |time: SystemTime| {
+                let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                u128::from(since_epoch.as_secs())
+            };
+            let modified = secs(md.modified().map_err(time_err)?);
+            let accessed = secs(md.accessed().map_err(time_err)?);
+            Ok(Reverse(
+                u128::from(weights.modified) * modified + u128::from(weights.accessed) * accessed,
+            ))
+        })
+    }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
 }
 
 impl SetOrder for SymlinkSetOrder {
     fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
         self.0.order(files, |md| Ok(md.is_symlink()))
     }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
+}
+
+impl CleanNameSetOrder {
+    pub fn new() -> Self {
+        CleanNameSetOrder
+    }
+}
+
+impl SetOrder for CleanNameSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        // stable in case there are multiple sorters
+        files.sort_by_cached_key(|file| looks_like_copy(file.file_path.file_name()));
+        Ok(())
+    }
+}
+
+fn looks_like_copy(name: &OsStr) -> bool {
+    let name = name.to_string_lossy().to_lowercase();
+    if name.ends_with('~') {
+        return true;
+    }
+    let stem = Path::new(&name)
+        .file_stem()
+        .map_or(name.as_str(), |stem| stem.to_str().unwrap_or_default());
+    // `a (1)`, `a(2)`, `a - Copy (3)`
+    let without_counter = stem.strip_suffix(')').and_then(|stem| {
+        stem.trim_end_matches(|c: char| c.is_ascii_digit())
+            .strip_suffix('(')
+    });
+    if let Some(without_counter) = without_counter {
+        if !without_counter.trim_end().is_empty() {
+            return true;
+        }
+    }
+    // `a copy 2` as created by macOS
+    let stem = stem
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end();
+    stem.starts_with("copy of ")
+        || [" copy", "-copy", "_copy"]
+            .iter()
+            .any(|suffix| stem.ends_with(suffix))
+}
+
+impl SetOrder for StableSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        let identity = |file: &HashedFile| {
+            file.identity
+                .map(|identity| (identity.device, identity.index))
+        };
+        // stable in case there are multiple sorters
+        files.sort_by_key(identity);
+        // the canonical paths only tell apart the files of the same identity, like the hard links to a file
+        for tied in files.chunk_by_mut(|file1, file2| identity(file1) == identity(file2)) {
+            if tied.len() > 1 {
+                tied.sort_by_cached_key(|file| {
+                    let path = file.file_path.to_push_buf();
+                    path.canonicalize().unwrap_or(path)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn needs_files(&self) -> bool {
+        true
+    }
+}
+
+impl PathPrefixSetOrder {
+    pub fn new(prefix: PathBuf) -> Self {
+        let canonical_prefix = prefix.canonicalize().ok();
+        Self {
+            prefix,
+            canonical_prefix,
+        }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.prefix)
+            || self.canonical_prefix.as_ref().is_some_and(|prefix| {
+                path.canonicalize()
+                    .is_ok_and(|path| path.starts_with(prefix))
+            })
+    }
+}
+
+impl SetOrder for PathPrefixSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        // stable in case there are multiple sorters
+        files.sort_by_cached_key(|file| !self.contains(&file.file_path.to_push_buf()));
+        Ok(())
+    }
+}
+
+impl_new_rev!(PathLengthSetOrder, this, this);
+
+/// sort the files by the key of their full paths, the largest key first if reversed
+fn sort_by_path_key(files: &mut [HashedFile], reverse: bool, key: impl Fn(&Path) -> usize) {
+    // stable in case there are multiple sorters
+    if reverse {
+        files.sort_by_cached_key(|file| Reverse(key(&file.file_path.to_push_buf())));
+    } else {
+        files.sort_by_cached_key(|file| key(&file.file_path.to_push_buf()));
+    }
+}
+
+impl SetOrder for PathLengthSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        sort_by_path_key(files, self.reverse, |path| path.as_os_str().len());
+        Ok(())
+    }
+}
+
+impl_new_rev!(PathDepthSetOrder, this, this);
+
+impl SetOrder for PathDepthSetOrder {
+    fn order(&mut self, files: &mut Vec<HashedFile>) -> Result<(), AlreadyReportedError> {
+        sort_by_path_key(files, self.reverse, |path| path.components().count());
+        Ok(())
+    }
 }
 
 impl_new_rev!(NameAlphabeticSetOrder, this, this);
@@ -0,0 +1,61 @@
+use crate::duplicate_set::{DuplicateSet, SetProvenance};
+use crate::error_handling::AlreadyReportedError;
+use crate::file_filters::FileFilter;
+use crate::file_set_refiner::{FileSetRefiners, RefinerLogic};
+use crate::manifest::read_manifest;
+use crate::util::LinkedPath;
+use crate::{course_set_key, HashAlgorithm, HashedFile};
+use std::path::{Path, PathBuf};
+
+/// the files of a manifest in place of the files themselves; the files are grouped by their recorded hash
+/// and only filtered by their names, so the sets can be ordered and acted on without touching any file
+pub struct Simulation {
+    manifest: PathBuf,
+    file_filter: FileFilter,
+    /// the manifest has to be hashed with it
+    hash_algorithm: HashAlgorithm,
+}
+
+impl Simulation {
+    pub fn new(manifest: PathBuf, file_filter: FileFilter) -> Self {
+        Self {
+            manifest,
+            file_filter,
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    pub fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// the sets of the manifest with the ids a run on the files would give them, sorted by the id so that
+    /// every simulation of the manifest hands on the sets in the same order
+    pub fn sets(mut self) -> Result<Vec<DuplicateSet>, AlreadyReportedError> {
+        let mut no_refiners = FileSetRefiners::new(Box::new([]), RefinerLogic::And);
+        let mut sets = Vec::new();
+        for (hash, files) in read_manifest(&self.manifest, self.hash_algorithm.name())? {
+            let mut set = Vec::with_capacity(files.len());
+            for file in files {
+                let file = Path::new(&file);
+                let file_path = LinkedPath::from_path_buf(file);
+                if self.file_filter.filter_name(&file_path, file) {
+                    set.push(HashedFile {
+                        file_version_timestamp: None,
+                        content_hash: hash,
+                        file_path: (*file_path).clone(),
+                        identity: None,
+                        size: None,
+                    });
+                }
+            }
+            if set.len() > 1 {
+                let key = course_set_key(hash, &mut no_refiners, Path::new(""))?;
+                sets.push(DuplicateSet::from_key(key, set, SetProvenance::Manifest));
+            }
+        }
+        sets.sort_unstable_by_key(|set| set.id);
+        Ok(sets)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::util::LinkedPath;
+use crate::HashedFile;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+/// snapshots of the directories to scan, created and removed by commands of the user;
+/// the files are discovered and hashed in the snapshots, but reported and acted on in the live directories
+pub struct Snapshots {
+    /// the live directory and the directory the snapshot of it is mounted at
+    mounted: Vec<(PathBuf, PathBuf)>,
+    /// run with the live directory and the snapshot directory once the scan is done
+    cleanup_cmd: Option<CleanupCommand>,
+}
+
+type CleanupCommand = Box<dyn Fn(&Path, &Path) -> Command + Send>;
+
+/// run the command by the shell of the platform, the arguments are passed to it
+fn shell_command(cmd: &str, args: &[&Path]) -> Command {
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(cmd);
+        command
+    };
+    #[cfg(not(windows))]
+    let mut command = {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd).arg("sh");
+        command
+    };
+    command.args(args);
+    command
+}
+
+impl Snapshots {
+    /// run the command for each directory, it has to print the directory the snapshot of it is mounted at
+    pub fn create(
+        create_cmd: &str,
+        cleanup_cmd: Option<String>,
+        dirs: &[PathBuf],
+    ) -> Result<Self, String> {
+        let cleanup_cmd = cleanup_cmd.map(|cmd| -> CleanupCommand {
+            Box::new(move |live, snapshot| shell_command(&cmd, &[live, snapshot]))
+        });
+        Self::create_with(
+            |dir| Ok(shell_command(create_cmd, &[dir])),
+            cleanup_cmd,
+            dirs,
+        )
+    }
+
+    /// create a volume shadow copy for each directory
+    #[cfg(windows)]
+    pub fn create_vss(dirs: &[PathBuf]) -> Result<Self, String> {
+        use crate::os::{vss_cleanup_command, vss_create_command};
+        Self::create_with(
+            vss_create_command,
+            Some(Box::new(vss_cleanup_command)),
+            dirs,
+        )
+    }
+
+    fn create_with(
+        create_cmd: impl Fn(&Path) -> Result<Command, String>,
+        cleanup_cmd: Option<CleanupCommand>,
+        dirs: &[PathBuf],
+    ) -> Result<Self, String> {
+        // the snapshots created so far are removed on drop if a later one fails
+        let mut snapshots = Self {
+            mounted: Vec::with_capacity(dirs.len()),
+            cleanup_cmd,
+        };
+        for dir in dirs {
+            let output = create_cmd(dir)?
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .output()
+                .map_err(|err| format!("cannot run the snapshot command: {err}"))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "the snapshot command failed for {}({})",
+                    dir.display(),
+                    output.status
+                ));
+            }
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let snapshot = PathBuf::from(stdout.trim_end_matches(['\n', '\r']));
+            // the discovered paths start with the snapshot as printed, so it is not canonicalized
+            if let Err(err) = snapshot.metadata() {
+                return Err(format!(
+                    "cannot access the snapshot of {} at {}: {err}",
+                    dir.display(),
+                    snapshot.display()
+                ));
+            }
+            snapshots.mounted.push((dir.clone(), snapshot));
+        }
+        Ok(snapshots)
+    }
+
+    /// the root to discover the files of the live directory in
+    pub fn snapshot_dir(&self, dir: &Path) -> Option<Arc<LinkedPath>> {
+        self.mounted
+            .iter()
+            .find(|(live, _)| live == dir)
+            .map(|(_, snapshot)| LinkedPath::from_path_buf(snapshot))
+    }
+
+    /// the live path of the file in a snapshot
+    fn live_path(&self, path: &Path) -> Option<PathBuf> {
+        self.mounted.iter().find_map(|(live, snapshot)| {
+            let relative = path.strip_prefix(snapshot).ok()?;
+            Some(live.join(relative))
+        })
+    }
+
+    /// replace the paths of the files in the snapshots with their live paths
+    pub fn live_files(&self, mut set: Vec<HashedFile>) -> Vec<HashedFile> {
+        let mut path_buf = PathBuf::new();
+        for file in &mut set {
+            file.file_path.write_full_to_buf(&mut path_buf);
+            if let Some(live) = self.live_path(&path_buf) {
+                file.file_path = (*LinkedPath::from_path_buf(&live)).clone();
+            }
+        }
+        set
+    }
+}
+
+impl Drop for Snapshots {
+    fn drop(&mut self) {
+        let Some(cleanup_cmd) = &self.cleanup_cmd else {
+            return;
+        };
+        for (live, snapshot) in &self.mounted {
+            let status = cleanup_cmd(live, snapshot).stdin(Stdio::null()).status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => log::error!(
+                    target: crate::error_handling::CONFIG_ERR_TARGET,
+                    "the snapshot cleanup command failed for {}({status})",
+                    snapshot.display()
+                ),
+                Err(err) => log::error!(
+                    target: crate::error_handling::CONFIG_ERR_TARGET,
+                    "cannot run the snapshot cleanup command: {err}"
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::util::{path_bytes, path_from_bytes, LinkedPath, MemoryBudget};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The size classes which did not fit into the memory budget, appended to a temporary file as the size, the
+/// length of the path and the path of each of their files; the file is removed once dropped
+pub struct SpilledClasses {
+    path: PathBuf,
+    write: Option<BufWriter<File>>,
+    /// the number of files of each spilled class and the memory they take once read back
+    classes: BTreeMap<u64, (usize, usize)>,
+    /// set once writing failed, the files which would follow are skipped
+    failed: bool,
+    path_buf: PathBuf,
+}
+
+impl SpilledClasses {
+    pub fn new() -> Self {
+        static SPILLED: AtomicUsize = AtomicUsize::new(0);
+        // the process id tells the spill files of concurrent runs apart
+        let name = format!(
+            "duplis-{}-{}.spill",
+            std::process::id(),
+            SPILLED.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        Self {
+            path,
+            write: None,
+            classes: BTreeMap::new(),
+            failed: false,
+            path_buf: PathBuf::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// whether writing failed, no more files are spilled
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// the number of files spilled of the class of the size
+    pub fn files(&self, size: u64) -> usize {
+        self.classes.get(&size).map_or(0, |(files, _)| *files)
+    }
+
+    /// appends the file to its class on disk, false if it cannot be written; the first failure is reported
+    pub fn spill(&mut self, size: u64, file: &LinkedPath) -> bool {
+        if self.failed {
+            return false;
+        }
+        file.write_full_to_buf(&mut self.path_buf);
+        if let Err(err) = self.write_record(size) {
+            log::error!(
+                target: crate::error_handling::DISCOVERY_ERR_TARGET,
+                "cannot spill the size classes beyond --max-memory to {}: {err}; the files which do not fit are skipped",
+                self.path.display()
+            );
+            self.failed = true;
+            return false;
+        }
+        let class = self.classes.entry(size).or_default();
+        class.0 += 1;
+        class.1 += MemoryBudget::file_cost(file);
+        true
+    }
+
+    fn write_record(&mut self, size: u64) -> std::io::Result<()> {
+        let write = match &mut self.write {
+            Some(write) => write,
+            None => self.write.insert(BufWriter::new(File::create(&self.path)?)),
+        };
+        let path = path_bytes(&self.path_buf);
+        write.write_all(&size.to_le_bytes())?;
+        write.write_all(&(path.len() as u64).to_le_bytes())?;
+        write.write_all(&path)
+    }
+
+    /// the sizes of the classes of more than one file, as many at once as fit into the limit along with
+    /// each other, and the memory they take; a class which alone exceeds the limit is on its own
+    pub fn batches(&self, limit: usize) -> Vec<(Vec<u64>, usize)> {
+        let mut batches: Vec<(Vec<u64>, usize)> = Vec::new();
+        let classes = self.classes.iter().filter(|(_, (files, _))| *files > 1);
+        for (&size, &(_, cost)) in classes {
+            match batches.last_mut() {
+                Some((sizes, batch_cost)) if batch_cost.saturating_add(cost) <= limit => {
+                    sizes.push(size);
+                    *batch_cost += cost;
+                }
+                _ => batches.push((vec![size], cost)),
+            }
+        }
+        batches
+    }
+
+    /// reads the files of the classes of the sizes back, in the order of the sizes
+    /// # Errors
+    /// if the spill file cannot be read
+    pub fn read_classes(&mut self, sizes: &[u64]) -> std::io::Result<Vec<Vec<LinkedPath>>> {
+        if let Some(mut write) = self.write.take() {
+            write.flush()?;
+        }
+        let mut classes: HashMap<u64, Vec<LinkedPath>> =
+            sizes.iter().map(|size| (*size, Vec::new())).collect();
+        // a record cut short by a failed write ends the file
+        let records = self.classes.values().map(|(files, _)| files).sum::<usize>();
+        let mut read = BufReader::new(File::open(&self.path)?);
+        let mut parent: Option<(PathBuf, Arc<LinkedPath>)> = None;
+        for _ in 0..records {
+            let size = read_u64(&mut read)?;
+            let len = usize::try_from(read_u64(&mut read)?).map_err(std::io::Error::other)?;
+            let mut path = vec![0; len];
+            read.read_exact(&mut path)?;
+            if let Some(class) = classes.get_mut(&size) {
+                class.push(linked_path(&path_from_bytes(path), &mut parent));
+            }
+        }
+        Ok(sizes
+            .iter()
+            .filter_map(|size| classes.remove(size))
+            .collect())
+    }
+}
+
+impl Default for SpilledClasses {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for SpilledClasses {
+    fn drop(&mut self) {
+        if self.write.take().is_some() || !self.classes.is_empty() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn read_u64(read: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    read.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// the path, which shares its parent with the path before if they lie in the same directory
+fn linked_path(path: &Path, parent: &mut Option<(PathBuf, Arc<LinkedPath>)>) -> LinkedPath {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return LinkedPath::clone(&LinkedPath::from_path_buf(path));
+    };
+    let parent = match parent {
+        Some((last, linked)) if last == dir => linked,
+        _ => {
+            &parent
+                .insert((dir.to_path_buf(), LinkedPath::from_path_buf(dir)))
+                .1
+        }
+    };
+    LinkedPath::new_child(parent, name.to_owned())
+}
+
+#[test]
+fn test_spilled_classes() {
+    let root = LinkedPath::root("spill");
+    let dir = Arc::new(LinkedPath::new_child(&root, "dir".into()));
+    let file = |name: &str| LinkedPath::new_child(&dir, name.into());
+    let cost = MemoryBudget::file_cost(&file("a"));
+
+    let mut spilled = SpilledClasses::new();
+    for (size, name) in [
+        (3, "a"),
+        (1, "b"),
+        (3, "c"),
+        (2, "d"),
+        (1, "e"),
+        (4, "f"),
+        (4, "g"),
+    ] {
+        assert!(spilled.spill(size, &file(name)));
+    }
+    assert_eq!(spilled.files(3), 2);
+    assert_eq!(spilled.files(2), 1);
+    // the class of a single file is left out, the classes are batched by the limit
+    let batches = spilled.batches(cost * 4);
+    assert_eq!(batches, [(vec![1, 3], cost * 4), (vec![4], cost * 2)]);
+    let classes = spilled.read_classes(&batches[0].0).unwrap();
+    assert_eq!(
+        classes,
+        [vec![file("b"), file("e")], vec![file("a"), file("c")]]
+    );
+    assert_eq!(
+        spilled.batches(cost),
+        [
+            (vec![1], cost * 2),
+            (vec![3], cost * 2),
+            (vec![4], cost * 2)
+        ]
+    );
+
+    let path = spilled.path.clone();
+    assert!(path.exists());
+    drop(spilled);
+    assert!(!path.exists());
+}
@@ -1,6 +1,9 @@
-use std::ffi::OsString;
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 #[macro_export]
 macro_rules! dyn_clone_impl {
     ($dcname: ident, $tname: path) => {
@@ -27,7 +30,7 @@ impl ChoiceInputReader for std::io::Stdin {
     }
 }
 
-impl<'a> ChoiceInputReader for &'a [u8] {
+impl ChoiceInputReader for &[u8] {
     fn read_remaining(&mut self, buf: &mut String) -> std::io::Result<()> {
         use std::io::BufRead;
         self.read_line(buf).map(|_| ())
@@ -39,6 +42,7 @@ impl<'a> ChoiceInputReader for &'a [u8] {
 #[allow(clippy::derived_hash_with_manual_eq)]
 pub struct LinkedPath(Option<Arc<LinkedPath>>, OsString);
 impl LinkedPath {
+    #[must_use]
     pub fn new_child(parent: &Arc<LinkedPath>, segment: OsString) -> Self {
         Self(Some(parent.clone()), segment)
     }
@@ -55,12 +59,22 @@ impl LinkedPath {
         buf.push(&self.1);
     }
 
+    /// the last segment of the path
+    #[must_use]
+    pub fn file_name(&self) -> &OsStr {
+        &self.1
+    }
+
+    #[must_use]
     pub fn to_push_buf(&self) -> PathBuf {
         let mut path_buf = PathBuf::new();
         self.push_full_to_buf(&mut path_buf);
         path_buf
     }
 
+    /// # Panics
+    /// if the path is empty
+    #[must_use]
     pub fn from_path_buf(buf: &Path) -> Arc<Self> {
         buf.iter()
             .map(ToOwned::to_owned)
@@ -68,6 +82,7 @@ impl LinkedPath {
             .expect("empty path")
     }
 
+    #[must_use]
     pub fn root(dir: &str) -> Arc<Self> {
         Arc::new(Self(None, OsString::from(dir)))
     }
@@ -79,6 +94,36 @@ impl PartialEq for LinkedPath {
     }
 }
 
+/// a directory of the host mounted into the container duplis runs in
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PathMap {
+    pub host: PathBuf,
+    pub container: PathBuf,
+}
+
+impl PathMap {
+    /// the path on the host of the path in the container, None if it is not below the mounted directory
+    #[must_use]
+    pub fn to_host(&self, path: &Path) -> Option<PathBuf> {
+        Self::replace_prefix(path, &self.container, &self.host)
+    }
+
+    /// the path in the container of the path on the host, None if it is not below the mounted directory
+    #[must_use]
+    pub fn to_container(&self, path: &Path) -> Option<PathBuf> {
+        Self::replace_prefix(path, &self.host, &self.container)
+    }
+
+    fn replace_prefix(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+        let relative = path.strip_prefix(from).ok()?;
+        // joining an empty path would append a separator
+        if relative.as_os_str().is_empty() {
+            return Some(to.to_path_buf());
+        }
+        Some(to.join(relative))
+    }
+}
+
 pub fn path_contains_comma(path: &Path) -> bool {
     #[cfg(unix)]
     return {
@@ -89,10 +134,113 @@ pub fn path_contains_comma(path: &Path) -> bool {
     return { path.as_os_str().to_string_lossy().contains(',') };
 }
 
+/// the raw bytes of the path where the platform allows it
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    return {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(path.as_os_str().as_bytes())
+    };
+    #[cfg(not(unix))]
+    return match path.as_os_str().to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    };
+}
+
+/// the path made of the raw bytes where the platform allows it, the inverse of `path_bytes`
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    #[cfg(unix)]
+    return {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_vec(bytes))
+    };
+    #[cfg(not(unix))]
+    return PathBuf::from(String::from_utf8_lossy(&bytes).into_owned());
+}
+
+/// format a byte count with binary prefixes, like `9.7 KiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut scale = 1024u64;
+    let mut unit = 0;
+    while unit + 1 < UNITS.len() && bytes / scale >= 1024 {
+        scale *= 1024;
+        unit += 1;
+    }
+    let tenths = (bytes % scale) * 10 / scale;
+    format!("{}.{tenths} {}", bytes / scale, UNITS[unit])
+}
+
+/// the count followed by the noun, in plural unless the count is one, like `1 set` or `3 sets`
+pub fn count_of(count: u64, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// how the reports write the sizes of files
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SizeFormat {
+    /// with binary prefixes, like `9.7 KiB`
+    #[default]
+    Human,
+    /// the exact bytes, like `9933`
+    Bytes,
+    /// the exact bytes followed by the size with binary prefixes, like `9933 (9.7 KiB)`
+    Both,
+}
+
+impl SizeFormat {
+    #[must_use]
+    pub fn format(self, bytes: u64) -> String {
+        match self {
+            SizeFormat::Human => format_size(bytes),
+            SizeFormat::Bytes => bytes.to_string(),
+            SizeFormat::Both => format!("{bytes} ({})", format_size(bytes)),
+        }
+    }
+}
+
+/// format the point in time like `2023-01-31T12:30:15Z`, in UTC
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => i64::try_from(after.as_secs()).unwrap_or(i64::MAX),
+        Err(before) => i64::try_from(before.duration().as_secs()).map_or(i64::MIN, |secs| -secs),
+    };
+    let (days, seconds_of_day) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    // convert the days since 1970-01-01 to a date, counting years from march so that the leap day is last
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
 /// Used to temporarily append a segment to a path, while guaranteeing, that that segment is popped off again
 pub struct TemporarySegmentToken<'a>(pub &'a mut PathBuf);
 
-impl<'a> Drop for TemporarySegmentToken<'a> {
+impl Drop for TemporarySegmentToken<'_> {
     fn drop(&mut self) {
         self.0.pop();
     }
@@ -102,3 +250,581 @@ pub fn push_to_path<'a>(path: &'a mut PathBuf, segment: &OsString) -> TemporaryS
     path.push(segment);
     TemporarySegmentToken(path)
 }
+
+/// An approximate ceiling on the memory held by the discovered files: the paths in the discovery queue, the
+/// size classes they are sorted into and the files placed into their sets. Once it is reached, charging waits
+/// for the work in flight, like sorting the queued paths into their classes or hashing a class, which makes
+/// room by spilling classes to disk or by releasing the files without duplicates
+pub struct MemoryBudget {
+    state: Mutex<BudgetState>,
+    /// notified whenever memory is released or work is done
+    room: Condvar,
+    limit: usize,
+}
+
+#[derive(Default)]
+struct BudgetState {
+    used: usize,
+    work_in_flight: usize,
+    dropped: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::default(),
+            room: Condvar::new(),
+            limit,
+        }
+    }
+
+    /// the approximate memory a discovered file occupies until its set is consumed
+    pub fn file_cost(path: &LinkedPath) -> usize {
+        std::mem::size_of::<crate::HashedFile>() + path.1.len()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// charges the amount once it fits into the budget or no work is left in flight which could make room
+    /// for it, the budget is exceeded then
+    pub fn charge(&self, amount: usize) {
+        let mut state = self.state();
+        while state.work_in_flight > 0 && state.used.saturating_add(amount) > self.limit {
+            state = self
+                .room
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.used = state.used.saturating_add(amount);
+    }
+
+    pub fn release(&self, amount: usize) {
+        let mut state = self.state();
+        state.used = state.used.saturating_sub(amount);
+        self.room.notify_all();
+    }
+
+    /// work which may make room once done was handed on, like a path to sort into its size class
+    pub fn start_work(&self) {
+        self.state().work_in_flight += 1;
+    }
+
+    pub fn finish_work(&self) {
+        let mut state = self.state();
+        state.work_in_flight = state.work_in_flight.saturating_sub(1);
+        self.room.notify_all();
+    }
+
+    /// files which neither fit into the budget nor could be spilled to disk were skipped
+    pub fn files_dropped(&self, files: usize) {
+        self.state().dropped += files;
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.state().dropped
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.used() > self.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.state().used
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// the charged memory and the limit, like `12.0 MiB/64.0 MiB`
+    fn usage(&self) -> String {
+        let size = |bytes: usize| format_size(u64::try_from(bytes).unwrap_or(u64::MAX));
+        format!("{}/{}", size(self.used()), size(self.limit))
+    }
+}
+
+/// Why discovery and hashing were cut short
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    Timeout = 1,
+    EnoughSets = 2,
+    /// the user pressed Ctrl+C
+    Interrupted = 3,
+}
+
+impl StopReason {
+    /// the sets found so far may lack some of their files
+    pub fn leaves_sets_incomplete(self) -> bool {
+        matches!(self, StopReason::Timeout | StopReason::Interrupted)
+    }
+}
+
+/// the stop signal of the running scan, which the interrupt handler stops
+static INTERRUPT_TARGET: OnceLock<Arc<StopSignal>> = OnceLock::new();
+
+/// records an interrupt of the running scan, returns true if it was interrupted before; only touches
+/// atomics, so that it can be called from a signal handler
+pub fn interrupt() -> bool {
+    INTERRUPT_TARGET.get().is_some_and(|stop| stop.interrupt())
+}
+
+/// whether the user pressed Ctrl+C during the running scan
+pub fn is_interrupted() -> bool {
+    INTERRUPT_TARGET.get().is_some_and(|stop| stop.is_interrupted())
+}
+
+/// Tells discovery and hashing to stop early, shared between all threads
+pub struct StopSignal {
+    deadline: Option<Instant>,
+    stop_after_sets: Option<u64>,
+    confirmed_sets: AtomicU64,
+    reason: AtomicU8,
+    /// recorded separately, as the run may have stopped for another reason before
+    interrupted: AtomicBool,
+    discovery_stop_reported: AtomicBool,
+}
+
+impl StopSignal {
+    pub fn new(deadline: Option<Instant>, stop_after_sets: Option<u64>) -> Self {
+        Self {
+            deadline,
+            stop_after_sets,
+            confirmed_sets: AtomicU64::new(0),
+            reason: AtomicU8::new(0),
+            interrupted: AtomicBool::new(false),
+            discovery_stop_reported: AtomicBool::new(false),
+        }
+    }
+
+    /// stop once the user presses Ctrl+C, a second Ctrl+C ends the program immediately; only the first
+    /// signal of the program is registered
+    pub fn stop_on_interrupt(self: &Arc<Self>) {
+        if INTERRUPT_TARGET.set(self.clone()).is_ok() {
+            crate::os::install_interrupt_handler();
+        }
+    }
+
+    /// returns true if it was interrupted before
+    fn interrupt(&self) -> bool {
+        self.stop(StopReason::Interrupted);
+        self.interrupted.swap(true, Ordering::Relaxed)
+    }
+
+    /// log why discovery skips the remaining files, only the first call logs
+    pub fn report_discovery_stopped(&self) {
+        if self.discovery_stop_reported.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        match self.reason() {
+            Some(StopReason::Timeout) => log::warn!(
+                target: crate::error_handling::DISCOVERY_ERR_TARGET,
+                "timeout exceeded; all further files are skipped"
+            ),
+            Some(StopReason::Interrupted) => log::warn!(
+                target: crate::error_handling::DISCOVERY_ERR_TARGET,
+                "interrupted; all further files are skipped"
+            ),
+            Some(StopReason::EnoughSets) | None => log::info!(
+                target: crate::error_handling::DISCOVERY_ERR_TARGET,
+                "found enough duplicate sets; all further files are skipped"
+            ),
+        }
+    }
+
+    pub fn stop_after_sets(&self) -> Option<u64> {
+        self.stop_after_sets
+    }
+
+    /// the user pressed Ctrl+C, even if the run stopped for another reason before
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// the first reason to stop, only the first call to `stop` is recorded
+    pub fn stop(&self, reason: StopReason) {
+        let _ = self
+            .reason
+            .compare_exchange(0, reason as u8, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    pub fn reason(&self) -> Option<StopReason> {
+        match self.reason.load(Ordering::Relaxed) {
+            1 => Some(StopReason::Timeout),
+            2 => Some(StopReason::EnoughSets),
+            3 => Some(StopReason::Interrupted),
+            _ => None,
+        }
+    }
+
+    /// also stops if the deadline has passed
+    pub fn should_stop(&self) -> bool {
+        if self.reason().is_some() {
+            return true;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.stop(StopReason::Timeout);
+            return true;
+        }
+        false
+    }
+
+    /// count a set that received its second file
+    pub fn set_confirmed(&self) {
+        let confirmed = self.confirmed_sets.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.stop_after_sets.is_some_and(|max| confirmed >= max) {
+            self.stop(StopReason::EnoughSets);
+        }
+    }
+}
+
+/// logs the number of hashed files and bytes every so many files or after an interval, shared between all
+/// threads; the interval is only checked whenever a file has been hashed. The share of the discovered bytes
+/// which has been hashed and the time left are estimated by bytes, so that a few large files at the end do
+/// not stall the estimate
+pub struct ProgressLog {
+    every_files: Option<u64>,
+    interval: Option<Duration>,
+    started: Instant,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    /// the bytes of the files whose hash was taken from the cache instead
+    cached_bytes: AtomicU64,
+    /// the bytes which will be hashed of the files discovery has handed on so far
+    discovered_bytes: AtomicU64,
+    discovered_files: AtomicU64,
+    /// the number of files and the time since the start of the last record
+    last_record: Mutex<(u64, Duration)>,
+    /// the progress is also shown in a line on stderr while hashing if true
+    live: bool,
+    /// stops the thread rendering the live line once dropped and the thread, once started
+    live_render: Mutex<Option<(flume::Sender<()>, std::thread::JoinHandle<()>)>>,
+    /// the memory budget whose usage is shown along with the progress
+    memory_budget: Option<Arc<MemoryBudget>>,
+}
+
+/// how often the live line is redrawn
+const LIVE_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// a writer which keeps a status line below everything written through it: the line is cleared before
+/// each write and drawn again once the written text ends a line, so that the log records never run into it
+pub struct StatusLineWriter<W> {
+    shared: Arc<Mutex<StatusLine<W>>>,
+}
+
+struct StatusLine<W> {
+    write: W,
+    line: Option<String>,
+    /// whether the line is drawn right now, with the cursor at its start
+    drawn: bool,
+    /// whether the last write ended in the middle of a line, the status line waits for its end
+    mid_line: bool,
+}
+
+impl<W> Clone for StatusLineWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<W: std::io::Write> StatusLineWriter<W> {
+    pub fn new(write: W) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(StatusLine {
+                write,
+                line: None,
+                drawn: false,
+                mid_line: false,
+            })),
+        }
+    }
+
+    /// replace the status line, none clears it
+    pub fn set_line(&self, line: Option<String>) {
+        let Ok(mut status) = self.shared.lock() else { return };
+        status.line = line;
+        if !status.mid_line {
+            let _ = status.redraw();
+        }
+    }
+}
+
+impl<W: std::io::Write> StatusLine<W> {
+    fn redraw(&mut self) -> std::io::Result<()> {
+        match &self.line {
+            Some(line) => write!(self.write, "\x1b[K{line}\r")?,
+            None if self.drawn => write!(self.write, "\x1b[K")?,
+            None => {}
+        }
+        self.drawn = self.line.is_some();
+        self.write.flush()
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for StatusLineWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let Ok(mut status) = self.shared.lock() else {
+            return Err(std::io::Error::other("the status line is poisoned"));
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if status.drawn {
+            write!(status.write, "\x1b[K")?;
+            status.drawn = false;
+        }
+        status.write.write_all(buf)?;
+        status.mid_line = !buf.ends_with(b"\n");
+        if !status.mid_line {
+            status.redraw()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let Ok(mut status) = self.shared.lock() else { return Ok(()) };
+        status.write.flush()
+    }
+}
+
+impl ProgressLog {
+    pub fn new(every_files: Option<u64>, interval: Option<Duration>) -> Self {
+        Self {
+            every_files,
+            interval,
+            started: Instant::now(),
+            files: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            cached_bytes: AtomicU64::new(0),
+            discovered_bytes: AtomicU64::new(0),
+            discovered_files: AtomicU64::new(0),
+            last_record: Mutex::new((0, Duration::ZERO)),
+            live: false,
+            live_render: Mutex::new(None),
+            memory_budget: None,
+        }
+    }
+
+    /// show the progress in a line on stderr while hashing, if stderr is a terminal
+    pub fn with_live(mut self, live: bool) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// show the memory charged to the budget and its limit
+    pub fn with_memory_budget(mut self, budget: Option<Arc<MemoryBudget>>) -> Self {
+        self.memory_budget = budget;
+        self
+    }
+
+    /// the usage of the memory budget, if there is one, like `, memory 12.0 MiB/64.0 MiB`
+    fn memory_usage(&self) -> String {
+        self.memory_budget.as_ref().map_or(String::new(), |budget| {
+            format!(", memory {}", budget.usage())
+        })
+    }
+
+    /// start redrawing the live line below the log records on stderr until hashing is finished, if it is to
+    /// be shown
+    pub fn start_live<W: std::io::Write + Send + 'static>(
+        self: &Arc<Self>,
+        stderr: &StatusLineWriter<W>,
+    ) {
+        use std::io::IsTerminal;
+        if !self.live || !std::io::stderr().is_terminal() {
+            return;
+        }
+        let stderr = stderr.clone();
+        let (stop_render, render_stopped) = flume::bounded::<()>(0);
+        // the progress is only borrowed so that dropping it ends the thread
+        let progress = Arc::downgrade(self);
+        let thread = std::thread::Builder::new()
+            .name(String::from("live_progress"))
+            .spawn(move || {
+                while let Err(flume::RecvTimeoutError::Timeout) =
+                    render_stopped.recv_timeout(LIVE_PROGRESS_INTERVAL)
+                {
+                    let Some(progress) = progress.upgrade() else { break };
+                    stderr.set_line(Some(progress.live_line()));
+                }
+                stderr.set_line(None);
+            });
+        if let (Ok(thread), Ok(mut live_render)) = (thread, self.live_render.lock()) {
+            *live_render = Some((stop_render, thread));
+        }
+    }
+
+    /// count a file discovery handed on, of which this many bytes will be hashed
+    pub fn file_discovered(&self, bytes: u64) {
+        self.discovered_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.discovered_files.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// count a file whose hash was taken from the cache instead of hashing this many bytes
+    pub fn file_cached(&self, bytes: u64) {
+        self.cached_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.file_hashed(0);
+    }
+
+    pub fn file_hashed(&self, bytes: u64) {
+        let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+        let bytes = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        // another thread is writing a record right now
+        let Ok(mut last_record) = self.last_record.try_lock() else { return };
+        let elapsed = self.started.elapsed();
+        let due = self
+            .every_files
+            .is_some_and(|every| files >= last_record.0 + every)
+            || self
+                .interval
+                .is_some_and(|interval| elapsed >= last_record.1 + interval);
+        if due {
+            *last_record = (files, elapsed);
+            self.record("hashed", files, bytes, elapsed);
+        }
+    }
+
+    /// stop the live line and log the totals once hashing is done
+    pub fn finish(&self) {
+        let live_render = self
+            .live_render
+            .lock()
+            .ok()
+            .and_then(|mut live| live.take());
+        if let Some((stop_render, thread)) = live_render {
+            drop(stop_render);
+            let _ = thread.join();
+        }
+        let files = self.files.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        self.record("finished hashing", files, bytes, self.started.elapsed());
+    }
+
+    /// the hashed files and bytes of the discovered ones, the time left and the memory used, like
+    /// `hashed 120/300 files, 1.2 GiB (40%, 85.0 MiB/s), about 21s left, memory 12.0 MiB/64.0 MiB`
+    pub fn live_line(&self) -> String {
+        let files = self.files.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let discovered_files = self.discovered_files.load(Ordering::Relaxed);
+        let per_second = bytes_per_second(bytes, self.started.elapsed());
+        let discovered = self.discovered_bytes.load(Ordering::Relaxed);
+        let done = bytes + self.cached_bytes.load(Ordering::Relaxed);
+        let estimate = match progress_estimate(done, discovered, per_second) {
+            Some((percent, Some(left))) => format!(
+                " ({percent}%, {}/s), about {}s left",
+                format_size(per_second),
+                left.as_secs()
+            ),
+            Some((percent, None)) => format!(" ({percent}%)"),
+            None => String::new(),
+        };
+        format!(
+            "hashed {files}/{discovered_files} files, {}{estimate}{}",
+            format_size(bytes),
+            self.memory_usage()
+        )
+    }
+
+    fn record(&self, what: &str, files: u64, bytes: u64, elapsed: Duration) {
+        log::info!(
+            target: crate::error_handling::PROGRESS_TARGET,
+            "{}",
+            self.record_line(what, files, bytes, elapsed)
+        );
+    }
+
+    /// like `hashed 120 files with 1.2 GiB in 14.1s (85.0 MiB/s), 40% of 3.0 GiB discovered, about 21s left,
+    /// memory 12.0 MiB/64.0 MiB`
+    fn record_line(&self, what: &str, files: u64, bytes: u64, elapsed: Duration) -> String {
+        let per_second = bytes_per_second(bytes, elapsed);
+        let discovered = self.discovered_bytes.load(Ordering::Relaxed);
+        let done = bytes + self.cached_bytes.load(Ordering::Relaxed);
+        let estimate = match progress_estimate(done, discovered, per_second) {
+            Some((percent, Some(left))) => {
+                format!(
+                    ", {percent}% of {} discovered, about {}s left",
+                    format_size(discovered),
+                    left.as_secs()
+                )
+            }
+            Some((percent, None)) => {
+                format!(", {percent}% of {} discovered", format_size(discovered))
+            }
+            None => String::new(),
+        };
+        format!(
+            "{what} {files} files with {} in {:.1}s ({}/s){estimate}{}",
+            format_size(bytes),
+            elapsed.as_secs_f64(),
+            format_size(per_second),
+            self.memory_usage()
+        )
+    }
+}
+
+fn bytes_per_second(bytes: u64, elapsed: Duration) -> u64 {
+    let per_second = u128::from(bytes) * 1000 / elapsed.as_millis().max(1);
+    u64::try_from(per_second).unwrap_or(u64::MAX)
+}
+
+/// the percentage of the discovered bytes which are done and the time left at the current rate; none
+/// if nothing was discovered yet
+pub fn progress_estimate(
+    done: u64,
+    discovered: u64,
+    per_second: u64,
+) -> Option<(u64, Option<Duration>)> {
+    if discovered == 0 {
+        return None;
+    }
+    let done = done.min(discovered);
+    let percent = u128::from(done) * 100 / u128::from(discovered);
+    let left =
+        (per_second > 0).then(|| Duration::from_secs((discovered - done).div_ceil(per_second)));
+    Some((u64::try_from(percent).unwrap_or(100), left))
+}
+
+#[test]
+fn test_progress_records() {
+    let records = |progress: &ProgressLog| progress.last_record.lock().unwrap().0;
+    // a record every two files
+    let progress = ProgressLog::new(Some(2), None);
+    progress.file_hashed(1000);
+    assert_eq!(records(&progress), 0);
+    progress.file_cached(1000);
+    assert_eq!(records(&progress), 2);
+    progress.file_hashed(1000);
+    assert_eq!(records(&progress), 2);
+
+    let progress = ProgressLog::new(None, Some(Duration::ZERO));
+    progress.file_hashed(1000);
+    assert_eq!(records(&progress), 1);
+    let progress = ProgressLog::new(None, None);
+    progress.file_hashed(1000);
+    assert_eq!(records(&progress), 0);
+
+    progress.file_discovered(4096);
+    progress.file_cached(1024);
+    assert_eq!(
+        progress.record_line("hashed", 2, 2048, Duration::from_secs(2)),
+        "hashed 2 files with 2.0 KiB in 2.0s (1.0 KiB/s), 75% of 4.0 KiB discovered, about 1s left"
+    );
+
+    let budget = Arc::new(MemoryBudget::new(64 << 20));
+    budget.charge(12 << 20);
+    let progress = ProgressLog::new(None, None).with_memory_budget(Some(budget));
+    assert_eq!(
+        progress.record_line("hashed", 0, 0, Duration::from_secs(1)),
+        "hashed 0 files with 0 B in 1.0s (0 B/s), memory 12.0 MiB/64.0 MiB"
+    );
+    assert_eq!(
+        progress.live_line(),
+        "hashed 0/0 files, 0 B, memory 12.0 MiB/64.0 MiB"
+    );
+}